@@ -5,6 +5,8 @@ import { Download, Lock, Shield, X } from "lucide-react";
 import styles from "./HUD.module.css";
 import { useStore } from "@/store/useStore";
 
+type ExportFormat = "signed" | "kml" | "kmz" | "gpx";
+
 const ExportModal: React.FC = () => {
   const showExport = useStore((state) => state.showExport);
   const setShowExport = useStore((state) => state.setShowExport);
@@ -13,6 +15,8 @@ const ExportModal: React.FC = () => {
   const [downloading, setDownloading] = useState(false);
   const [token, setToken] = useState<string | null>(null);
   const [trackId, setTrackId] = useState("track_0");
+  const [format, setFormat] = useState<ExportFormat>("signed");
+  const [fileName, setFileName] = useState<string | null>(null);
   const [error, setError] = useState<string | null>(null);
   const [watermarkTs] = useState(() => new Date().toISOString());
 
@@ -28,11 +32,51 @@ const ExportModal: React.FC = () => {
 
   if (!showExport) return null;
 
+  const actorHeaders = {
+    "Content-Type": "application/json",
+    "x-harpy-role": userRole,
+    "x-harpy-scopes": userRole === "ADMIN" ? "graph:query,graph:query:advanced,graph:export" : "graph:query",
+    "x-harpy-actor-id": "hud-operator",
+    "x-harpy-attrs": JSON.stringify({}),
+  };
+
+  const handleFileExport = async (graphUrl: string, fileFormat: Exclude<ExportFormat, "signed">) => {
+    const trackIds = trackId
+      .split(",")
+      .map((id) => id.trim())
+      .filter((id) => id.length > 0);
+    const response = await fetch(`${graphUrl}/graph/export/tracks`, {
+      method: "POST",
+      body: JSON.stringify({ track_ids: trackIds, format: fileFormat }),
+      headers: actorHeaders,
+    });
+
+    if (!response.ok) {
+      const body = await response.text();
+      throw new Error(body || `Export request failed (${response.status})`);
+    }
+
+    const name = `harpy-tracks.${fileFormat}`;
+    const url = URL.createObjectURL(await response.blob());
+    const link = document.createElement("a");
+    link.href = url;
+    link.download = name;
+    link.click();
+    URL.revokeObjectURL(url);
+    setFileName(name);
+  };
+
   const handleExport = async () => {
     setDownloading(true);
     setError(null);
+    setToken(null);
+    setFileName(null);
     try {
       const graphUrl = process.env.NEXT_PUBLIC_GRAPH_URL || "http://localhost:8083";
+      if (format !== "signed") {
+        await handleFileExport(graphUrl, format);
+        return;
+      }
       const response = await fetch(`${graphUrl}/graph/export`, {
         method: "POST",
         body: JSON.stringify({
@@ -45,13 +89,7 @@ const ExportModal: React.FC = () => {
           watermark: `HARPY-${userRole}-${watermarkTs}`,
           expires_in_secs: 900,
         }),
-        headers: {
-          "Content-Type": "application/json",
-          "x-harpy-role": userRole,
-          "x-harpy-scopes": userRole === "ADMIN" ? "graph:query,graph:query:advanced,graph:export" : "graph:query",
-          "x-harpy-actor-id": "hud-operator",
-          "x-harpy-attrs": JSON.stringify({}),
-        },
+        headers: actorHeaders,
       });
 
       if (!response.ok) {
@@ -91,7 +129,18 @@ const ExportModal: React.FC = () => {
                 <Lock size={12} />
                 <span>WATERMARK: {userRole}{" // "}{watermarkTs}</span>
               </div>
-              <label className={styles.label}>TRACK_ID</label>
+              <label className={styles.label}>FORMAT</label>
+              <select
+                className={styles.select}
+                value={format}
+                onChange={(e) => setFormat(e.target.value as ExportFormat)}
+              >
+                <option value="signed">SIGNED_TOKEN</option>
+                <option value="kml">KML (GOOGLE EARTH)</option>
+                <option value="kmz">KMZ</option>
+                <option value="gpx">GPX</option>
+              </select>
+              <label className={styles.label}>{format === "signed" ? "TRACK_ID" : "TRACK_IDS (COMMA-SEPARATED)"}</label>
               <input
                 className={styles.input}
                 value={trackId}
@@ -101,11 +150,11 @@ const ExportModal: React.FC = () => {
               {error ? <span className={styles.critical}>{error}</span> : null}
             </div>
 
-            {token ? (
+            {token || fileName ? (
               <div className={styles.resultsArea}>
-                <label className={styles.label}>SIGNED_TOKEN</label>
+                <label className={styles.label}>{token ? "SIGNED_TOKEN" : "FILE"}</label>
                 <div className={styles.resultsContent}>
-                  <pre className={styles.jsonBlock}>{token}</pre>
+                  <pre className={styles.jsonBlock}>{token ?? fileName}</pre>
                 </div>
                 <button 
                   className={styles.runButton} 
@@ -59,6 +59,24 @@ interface DecodedTrackBatchStatsMessage {
 
 interface DecodedSubscriptionAckMessage {
   type: "SUBSCRIPTION_ACK";
+  seq?: number;
+  ack: {
+    subscriptionId: string;
+    success: boolean;
+    resumeToken: string;
+    resumed: boolean;
+    replayComplete: boolean;
+  };
+}
+
+interface DecodedPlaybackSessionMessage {
+  type: "PLAYBACK_SESSION_STATE";
+  session: {
+    sessionId: string;
+    currentTsMs: number;
+    speed: number;
+    isPlaying: boolean;
+  };
 }
 
 interface DecodedLinkUpsertMessage {
@@ -79,8 +97,8 @@ interface RelayDebugSnapshotResponse {
     connected_clients?: number;
     playback_clients?: number;
     backpressure_totals?: {
-      track_batches_dropped?: number;
       track_batches_sent?: number;
+      track_updates_dropped?: number;
       high_priority_sent?: number;
     };
   };
@@ -108,6 +126,40 @@ const resolveStreamMode = (): StreamMode => {
   return "hybrid";
 };
 
+// Deflated frames and compact track batches unless NEXT_PUBLIC_WS_COMPACT=false
+const resolveWireOptions = (): { compression: string; encoding: string } => {
+  if ((process.env.NEXT_PUBLIC_WS_COMPACT || "true").toLowerCase() === "false") {
+    return { compression: "", encoding: "" };
+  }
+  return { compression: "deflate", encoding: "compact" };
+};
+
+// Reconnects carry the previous session's token so the relay restores its subscriptions
+const connectUrl = (
+  wsUrl: string,
+  wire: { compression: string; encoding: string },
+  resumeToken: string,
+  lastSeq: number,
+): string => {
+  const params = new URLSearchParams();
+  if (wire.compression) {
+    params.set("compression", wire.compression);
+  }
+  if (wire.encoding) {
+    params.set("encoding", wire.encoding);
+  }
+  if (resumeToken) {
+    params.set("resume_token", resumeToken);
+    params.set("last_seq", String(lastSeq));
+  }
+  const query = params.toString();
+  if (!query) {
+    return wsUrl;
+  }
+  const separator = wsUrl.includes("?") ? "&" : "?";
+  return `${wsUrl}${separator}${query}`;
+};
+
 const resolveRelayDebugSnapshotUrl = (): string => {
   const envUrl = process.env.NEXT_PUBLIC_RELAY_DEBUG_SNAPSHOT_URL;
   if (envUrl) {
@@ -148,6 +200,31 @@ const isSubscriptionAckMessage = (data: unknown): data is DecodedSubscriptionAck
   return message.type === "SUBSCRIPTION_ACK";
 };
 
+const isPlaybackSessionMessage = (data: unknown): data is DecodedPlaybackSessionMessage => {
+  if (typeof data !== "object" || data === null) {
+    return false;
+  }
+  const message = data as { type?: string; session?: unknown };
+  return message.type === "PLAYBACK_SESSION_STATE" && typeof message.session === "object" && message.session !== null;
+};
+
+// Shared playback session to join, from ?playback_session= in the page URL
+const initialPlaybackSessionId = (): string => {
+  if (typeof window === "undefined") {
+    return "";
+  }
+  return new URLSearchParams(window.location.search).get("playback_session") ?? "";
+};
+
+const encodePlaybackControl = (control: harpy.v1.IPlaybackControl): Uint8Array =>
+  harpy.v1.Envelope.encode(
+    harpy.v1.Envelope.create({
+      schemaVersion: "1.0.0",
+      serverTsMs: Date.now(),
+      playbackControl: control,
+    }),
+  ).finish();
+
 const isLinkUpsertMessage = (data: unknown): data is DecodedLinkUpsertMessage => {
   if (typeof data !== "object" || data === null) {
     return false;
@@ -179,6 +256,8 @@ const mapUiLayersToProto = (activeLayers: string[]): number[] => {
     mapped.add(harpy.v1.LayerType.LAYER_TYPE_AIRCRAFT);
     mapped.add(harpy.v1.LayerType.LAYER_TYPE_SATELLITE);
   }
+  // The alert panel is always shown; alerts are only fanned out to subscribers of this layer.
+  mapped.add(harpy.v1.LayerType.LAYER_TYPE_ALERT);
   return Array.from(mapped);
 };
 
@@ -273,6 +352,8 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
   const isLive = useStore((state) => state.isLive);
   const isPlaying = useStore((state) => state.isPlaying);
   const currentTimeMs = useStore((state) => state.currentTimeMs);
+  const setIsPlaying = useStore((state) => state.setIsPlaying);
+  const setCurrentTimeMs = useStore((state) => state.setCurrentTimeMs);
   const layersRef = useRef<string[]>(layers);
   const isLiveRef = useRef<boolean>(isLive);
   const currentTimeRef = useRef<number>(currentTimeMs);
@@ -289,6 +370,11 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
   
   // WebSocket reference
   const socketRef = useRef<WebSocket | null>(null);
+  // Relay session to resume after a reconnect
+  const resumeTokenRef = useRef<string>("");
+  const lastSeqRef = useRef<number>(0);
+  // Shared playback session followed in playback mode; pause, seek and speed drive everyone in it
+  const playbackSessionIdRef = useRef<string>(initialPlaybackSessionId());
   const streamMode = resolveStreamMode();
   const useWebSocket = streamMode !== "offline";
   const useMockStreamer = streamMode !== "online";
@@ -320,6 +406,7 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
             playback: {
               startTsMs: Math.max(0, endTsMs - 60 * 60 * 1000),
               endTsMs,
+              sessionId: playbackSessionIdRef.current,
             },
           });
 
@@ -719,6 +806,10 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
     // Connect Pipeline: wsDecode -> trackIndex -> cluster -> pack -> main
     wsDecodeWorker.current.onmessage = (e: MessageEvent<unknown>) => {
       setLastMessageTsMs(Date.now());
+      const seq = (e.data as { seq?: unknown } | null)?.seq;
+      if (typeof seq === "number" && seq > lastSeqRef.current) {
+        lastSeqRef.current = seq;
+      }
       if (isProviderStatusMessage(e.data)) {
         updateProviderStatus(e.data.status);
       } else if (isAlertUpsertMessage(e.data)) {
@@ -737,9 +828,30 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
         const total = throughputWindowRef.current.reduce((sum, entry) => sum + entry.count, 0);
         setThroughputStats(total / 5, e.data.count);
       } else if (isSubscriptionAckMessage(e.data)) {
+        const { resumeToken, resumed, replayComplete } = e.data.ack;
+        if (resumeToken && resumeToken !== resumeTokenRef.current) {
+          resumeTokenRef.current = resumeToken;
+          if (!resumed) {
+            lastSeqRef.current = e.data.seq ?? 0;
+          }
+        }
+        if (resumed && !replayComplete) {
+          console.warn("[WS] Session resumed but some alerts were no longer buffered.");
+        }
         if (lastSubscriptionSentAtRef.current > 0) {
           setWsRttMs(Date.now() - lastSubscriptionSentAtRef.current);
         }
+      } else if (isPlaybackSessionMessage(e.data)) {
+        const { sessionId, currentTsMs, isPlaying: sessionPlaying } = e.data.session;
+        if (sessionId !== playbackSessionIdRef.current) {
+          playbackSessionIdRef.current = sessionId;
+          // Make the page URL shareable so others can join the session
+          const url = new URL(window.location.href);
+          url.searchParams.set("playback_session", sessionId);
+          window.history.replaceState(null, "", url.toString());
+        }
+        setCurrentTimeMs(currentTsMs);
+        setIsPlaying(sessionPlaying);
       } else if (isLinkUpsertMessage(e.data)) {
         upsertLink(e.data.link);
       } else {
@@ -866,6 +978,7 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
     // Setup Real WebSocket
     const wsUrl = process.env.NEXT_PUBLIC_WS_URL || "ws://localhost:8080/ws";
     const relayDebugSnapshotUrl = resolveRelayDebugSnapshotUrl();
+    const wireOptions = resolveWireOptions();
     let reconnectTimeout: ReturnType<typeof setTimeout> | null = null;
     let connectAttempt = 0;
 
@@ -873,9 +986,12 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
       connectAttempt += 1;
       const attempt = connectAttempt;
       setConnectionStatus("CONNECTING");
-      const socket = new WebSocket(wsUrl);
+      const socket = new WebSocket(
+        connectUrl(wsUrl, wireOptions, resumeTokenRef.current, lastSeqRef.current),
+      );
       socketRef.current = socket;
       socket.binaryType = "arraybuffer";
+      wsDecodeWorker.current?.postMessage({ type: "WS_CONNECT", ...wireOptions });
 
       socket.onopen = () => {
         if (disposed || socket !== socketRef.current) {
@@ -893,7 +1009,7 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
 
       socket.onmessage = (event) => {
         if (event.data instanceof ArrayBuffer) {
-          wsDecodeWorker.current?.postMessage(event.data, [event.data]);
+          wsDecodeWorker.current?.postMessage({ type: "WS_FRAME", buffer: event.data }, [event.data]);
         }
       };
 
@@ -991,7 +1107,7 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
             return acc;
           }, {});
           setRelayDebugStats({
-            dropped: body.relay?.backpressure_totals?.track_batches_dropped ?? 0,
+            dropped: body.relay?.backpressure_totals?.track_updates_dropped ?? 0,
             sent: body.relay?.backpressure_totals?.track_batches_sent ?? 0,
             highPriority: body.relay?.backpressure_totals?.high_priority_sent ?? 0,
             connectedClients: body.relay?.connected_clients ?? 0,
@@ -1041,6 +1157,8 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
     setConnectionStatus,
     setAlertsPerSec,
     setCameraPose,
+    setCurrentTimeMs,
+    setIsPlaying,
     setLastMessageTsMs,
     setRelayDebugStats,
     setRenderedTrackStats,
@@ -1093,11 +1211,29 @@ const CesiumViewer: React.FC<CesiumViewerProps> = ({ ionToken }) => {
     if (isLive || isPlaying) {
       return;
     }
-    if (socketRef.current?.readyState === WebSocket.OPEN) {
-      sendSubscription(socketRef.current, layers, false, currentTimeMs);
+    const socket = socketRef.current;
+    if (socket?.readyState !== WebSocket.OPEN) {
+      return;
+    }
+    if (playbackSessionIdRef.current) {
+      // Seeking a shared session moves everyone; repeating the current position is a no-op
+      socket.send(encodePlaybackControl({ sessionId: playbackSessionIdRef.current, seekTsMs: currentTimeMs }));
+    } else {
+      sendSubscription(socket, layers, false, currentTimeMs);
     }
   }, [currentTimeMs, isLive, isPlaying, layers, sendSubscription, useWebSocket]);
 
+  // Pausing or resuming locally pauses or resumes the shared playback session
+  useEffect(() => {
+    if (!useWebSocket || isLive || !playbackSessionIdRef.current) {
+      return;
+    }
+    const socket = socketRef.current;
+    if (socket?.readyState === WebSocket.OPEN) {
+      socket.send(encodePlaybackControl({ sessionId: playbackSessionIdRef.current, playing: isPlaying }));
+    }
+  }, [isLive, isPlaying, useWebSocket]);
+
   useEffect(() => {
     if (!viewerInstance.current) return;
     updateVisionMode(viewerInstance.current.scene, visionMode);
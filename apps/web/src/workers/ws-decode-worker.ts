@@ -4,74 +4,192 @@ import { harpy } from "@harpy/shared-types";
 
 const workerCtx: DedicatedWorkerGlobalScope = self as unknown as DedicatedWorkerGlobalScope;
 
+// Whether the current socket was opened with compression=deflate (see harpy-relay wire.rs)
+let deflate = false;
+
+// Last state of each track decoded from compact batches on the current socket
+interface CompactTrackState {
+  kind: number;
+  latE7: number;
+  lonE7: number;
+  altDm: number;
+  headingCdeg: number;
+  speedCms: number;
+  tsMs: number;
+  providerId: string;
+  meta: Record<string, string>;
+}
+const compactTracks = new Map<string, CompactTrackState>();
+
+// Inflation is async; chain frames so they are handled in arrival order
+let frameChain: Promise<void> = Promise.resolve();
+
+const inflate = async (data: ArrayBuffer): Promise<Uint8Array> => {
+  const stream = new Blob([data]).stream().pipeThrough(new DecompressionStream("deflate-raw"));
+  return new Uint8Array(await new Response(stream).arrayBuffer());
+};
+
+const emptyTrackState = (): CompactTrackState => ({
+  kind: 0,
+  latE7: 0,
+  lonE7: 0,
+  altDm: 0,
+  headingCdeg: 0,
+  speedCms: 0,
+  tsMs: 0,
+  providerId: "",
+  meta: {},
+});
+
+// Apply compact differences and rebuild full TrackDelta objects
+const expandCompactBatch = (batch: harpy.v1.ICompactTrackBatch) => {
+  const deltas = [];
+  for (const delta of batch.deltas || []) {
+    const id = delta.id ?? "";
+    const previous = delta.keyframe ? undefined : compactTracks.get(id);
+    if (!delta.keyframe && !previous) {
+      // Differences against a state we never saw cannot be applied
+      continue;
+    }
+    const state = previous ?? emptyTrackState();
+    if (delta.kind != null) state.kind = delta.kind;
+    state.latE7 += Number(delta.latE7 ?? 0);
+    state.lonE7 += Number(delta.lonE7 ?? 0);
+    state.altDm += Number(delta.altDm ?? 0);
+    state.headingCdeg += Number(delta.headingCdeg ?? 0);
+    state.speedCms += Number(delta.speedCms ?? 0);
+    state.tsMs += Number(delta.tsMs ?? 0);
+    if (delta.providerId != null) state.providerId = delta.providerId;
+    Object.assign(state.meta, delta.meta ?? {});
+    for (const key of delta.metaRemoved ?? []) {
+      delete state.meta[key];
+    }
+    compactTracks.set(id, state);
+    deltas.push({
+      id,
+      kind: state.kind,
+      position: { lat: state.latE7 / 1e7, lon: state.lonE7 / 1e7, alt: state.altDm / 10 },
+      heading: state.headingCdeg / 100,
+      speed: state.speedCms / 100,
+      tsMs: state.tsMs,
+      providerId: state.providerId,
+      meta: { ...state.meta },
+    });
+  }
+  return deltas;
+};
+
+const decodeFrame = (uint8: Uint8Array) => {
+  try {
+    const envelope = harpy.v1.Envelope.decode(uint8);
+    // Relay session sequence number, echoed back as last_seq when resuming
+    const seq = Number(envelope.seq ?? 0);
+    
+    // Dispatch based on payload
+    if (envelope.trackDeltaBatch || envelope.compactTrackBatch) {
+      const deltas = envelope.compactTrackBatch
+        ? expandCompactBatch(envelope.compactTrackBatch)
+        : envelope.trackDeltaBatch?.deltas || [];
+      workerCtx.postMessage({ type: "TRACK_DELTA_BATCH", deltas });
+      workerCtx.postMessage({ type: "TRACK_BATCH_STATS", count: deltas.length, serverTsMs: Number(envelope.serverTsMs ?? 0), seq });
+    } else if (envelope.alertUpsert) {
+      const severityName = harpy.v1.AlertSeverity[envelope.alertUpsert.severity ?? 0] ?? "ALERT_SEVERITY_UNSPECIFIED";
+      // Map alert to plain object
+      const alert = {
+        id: envelope.alertUpsert.id,
+        title: envelope.alertUpsert.title,
+        description: envelope.alertUpsert.description,
+        severity: severityName,
+        tsMs: Number(envelope.alertUpsert.tsMs),
+        evidenceLinkIds: envelope.alertUpsert.evidenceLinkIds || []
+      };
+      workerCtx.postMessage({ type: "ALERT_UPSERT", alert, seq });
+    } else if (envelope.providerStatus) {
+      const circuitName = harpy.v1.CircuitState[envelope.providerStatus.circuitState ?? 0] ?? "CIRCUIT_STATE_UNSPECIFIED";
+      const freshnessName = harpy.v1.Freshness[envelope.providerStatus.freshness ?? 0] ?? "FRESHNESS_UNSPECIFIED";
+      const lastSuccess = Number(envelope.providerStatus.lastSuccessTsMs ?? 0);
+      const latencyMs = lastSuccess > 0 ? Math.max(0, Date.now() - lastSuccess) : 0;
+      // Map protobuf to our store structure (if names differ)
+      workerCtx.postMessage({ 
+        type: "PROVIDER_STATUS", 
+        seq,
+        status: {
+          providerId: envelope.providerStatus.providerId,
+          circuitState: circuitName,
+          freshness: freshnessName,
+          latencyMs,
+          lastSuccessTsMs: lastSuccess,
+          failureCount: envelope.providerStatus.failureCount ?? 0,
+        } 
+      });
+    } else if (envelope.subscriptionAck) {
+      workerCtx.postMessage({
+        type: "SUBSCRIPTION_ACK",
+        seq,
+        ack: {
+          subscriptionId: envelope.subscriptionAck.subscriptionId,
+          success: envelope.subscriptionAck.success,
+          error: envelope.subscriptionAck.error,
+          resumeToken: envelope.subscriptionAck.resumeToken ?? "",
+          resumed: envelope.subscriptionAck.resumed ?? false,
+          replayComplete: envelope.subscriptionAck.replayComplete ?? false,
+        },
+      });
+    } else if (envelope.playbackSessionState) {
+      const session = envelope.playbackSessionState;
+      workerCtx.postMessage({
+        type: "PLAYBACK_SESSION_STATE",
+        seq,
+        session: {
+          sessionId: session.sessionId ?? "",
+          subscriptionId: session.subscriptionId ?? "",
+          startTsMs: Number(session.startTsMs ?? 0),
+          endTsMs: Number(session.endTsMs ?? 0),
+          currentTsMs: Number(session.currentTsMs ?? 0),
+          speed: session.speed ?? 1,
+          isPlaying: session.isPlaying ?? false,
+        },
+      });
+    } else if (envelope.linkUpsert) {
+      workerCtx.postMessage({
+        type: "LINK_UPSERT",
+        seq,
+        link: {
+          id: envelope.linkUpsert.id,
+          fromType: harpy.v1.NodeType[envelope.linkUpsert.from?.nodeType ?? 0] ?? "NODE_TYPE_UNSPECIFIED",
+          fromId: envelope.linkUpsert.from?.nodeId ?? "",
+          rel: envelope.linkUpsert.rel ?? "",
+          toType: harpy.v1.NodeType[envelope.linkUpsert.to?.nodeType ?? 0] ?? "NODE_TYPE_UNSPECIFIED",
+          toId: envelope.linkUpsert.to?.nodeId ?? "",
+          tsMs: Number(envelope.linkUpsert.tsMs ?? 0),
+        },
+      });
+    }
+  } catch (err) {
+    console.error("[WS-DECODE-WORKER] Decode Error:", err);
+  }
+};
+
 workerCtx.onmessage = (event: MessageEvent) => {
   const { data } = event;
-  
+
   if (data instanceof ArrayBuffer) {
-    try {
-      const uint8 = new Uint8Array(data);
-      const envelope = harpy.v1.Envelope.decode(uint8);
-      
-      // Dispatch based on payload
-      if (envelope.trackDeltaBatch) {
-        const deltas = envelope.trackDeltaBatch.deltas || [];
-        workerCtx.postMessage({ type: "TRACK_DELTA_BATCH", deltas });
-        workerCtx.postMessage({ type: "TRACK_BATCH_STATS", count: deltas.length, serverTsMs: Number(envelope.serverTsMs ?? 0) });
-      } else if (envelope.alertUpsert) {
-        const severityName = harpy.v1.AlertSeverity[envelope.alertUpsert.severity ?? 0] ?? "ALERT_SEVERITY_UNSPECIFIED";
-        // Map alert to plain object
-        const alert = {
-          id: envelope.alertUpsert.id,
-          title: envelope.alertUpsert.title,
-          description: envelope.alertUpsert.description,
-          severity: severityName,
-          tsMs: Number(envelope.alertUpsert.tsMs),
-          evidenceLinkIds: envelope.alertUpsert.evidenceLinkIds || []
-        };
-        workerCtx.postMessage({ type: "ALERT_UPSERT", alert });
-      } else if (envelope.providerStatus) {
-        const circuitName = harpy.v1.CircuitState[envelope.providerStatus.circuitState ?? 0] ?? "CIRCUIT_STATE_UNSPECIFIED";
-        const freshnessName = harpy.v1.Freshness[envelope.providerStatus.freshness ?? 0] ?? "FRESHNESS_UNSPECIFIED";
-        const lastSuccess = Number(envelope.providerStatus.lastSuccessTsMs ?? 0);
-        const latencyMs = lastSuccess > 0 ? Math.max(0, Date.now() - lastSuccess) : 0;
-        // Map protobuf to our store structure (if names differ)
-        workerCtx.postMessage({ 
-          type: "PROVIDER_STATUS", 
-          status: {
-            providerId: envelope.providerStatus.providerId,
-            circuitState: circuitName,
-            freshness: freshnessName,
-            latencyMs,
-            lastSuccessTsMs: lastSuccess,
-            failureCount: envelope.providerStatus.failureCount ?? 0,
-          } 
-        });
-      } else if (envelope.subscriptionAck) {
-        workerCtx.postMessage({
-          type: "SUBSCRIPTION_ACK",
-          ack: {
-            subscriptionId: envelope.subscriptionAck.subscriptionId,
-            success: envelope.subscriptionAck.success,
-            error: envelope.subscriptionAck.error,
-          },
-        });
-      } else if (envelope.linkUpsert) {
-        workerCtx.postMessage({
-          type: "LINK_UPSERT",
-          link: {
-            id: envelope.linkUpsert.id,
-            fromType: harpy.v1.NodeType[envelope.linkUpsert.from?.nodeType ?? 0] ?? "NODE_TYPE_UNSPECIFIED",
-            fromId: envelope.linkUpsert.from?.nodeId ?? "",
-            rel: envelope.linkUpsert.rel ?? "",
-            toType: harpy.v1.NodeType[envelope.linkUpsert.to?.nodeType ?? 0] ?? "NODE_TYPE_UNSPECIFIED",
-            toId: envelope.linkUpsert.to?.nodeId ?? "",
-            tsMs: Number(envelope.linkUpsert.tsMs ?? 0),
-          },
-        });
-      }
-    } catch (err) {
-      console.error("[WS-DECODE-WORKER] Decode Error:", err);
+    // Locally generated frames (mock streamer) are never compressed
+    decodeFrame(new Uint8Array(data));
+  } else if (data?.type === "WS_CONNECT") {
+    // A new socket starts with fresh compact state
+    deflate = data.compression === "deflate";
+    compactTracks.clear();
+  } else if (data?.type === "WS_FRAME" && data.buffer instanceof ArrayBuffer) {
+    const buffer: ArrayBuffer = data.buffer;
+    if (!deflate) {
+      decodeFrame(new Uint8Array(buffer));
+      return;
     }
+    frameChain = frameChain
+      .then(() => inflate(buffer))
+      .then(decodeFrame)
+      .catch((err) => console.error("[WS-DECODE-WORKER] Inflate Error:", err));
   }
 };
 
@@ -0,0 +1,141 @@
+//! Aircraft registry lookup
+//!
+//! Maps ICAO24 transponder addresses to registration, type and operator from a
+//! local CSV such as the OpenSky aircraft database. Column names are matched
+//! case-insensitively against the common export headers.
+
+use super::csv::{field, CsvTable};
+use super::{set_meta, TrackEnricher, STAGE_AIRCRAFT_REGISTRY};
+use crate::error::Result;
+use harpy_proto::harpy::v1::{TrackDelta, TrackKind};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AircraftRecord {
+    pub registration: String,
+    /// ICAO type designator, e.g. `B738`
+    pub type_code: String,
+    pub model: String,
+    pub operator: String,
+}
+
+pub struct AircraftRegistry {
+    by_icao24: HashMap<String, AircraftRecord>,
+}
+
+impl AircraftRegistry {
+    pub fn load(path: &str) -> Result<Self> {
+        let registry = Self::from_table(&CsvTable::read(path)?);
+        tracing::info!(
+            "Aircraft registry {} has {} entries",
+            path,
+            registry.by_icao24.len()
+        );
+        Ok(registry)
+    }
+
+    pub fn from_table(table: &CsvTable) -> Self {
+        let icao24 = table.column(&["icao24", "icao", "hex"]);
+        let registration = table.column(&["registration", "reg"]);
+        let type_code = table.column(&["typecode", "icaoaircrafttype", "type"]);
+        let model = table.column(&["model", "manufacturername"]);
+        let operator = table.column(&["operator", "operatorname", "owner"]);
+        let owner = table.column(&["owner"]);
+
+        let by_icao24 = table
+            .rows()
+            .iter()
+            .filter_map(|row| {
+                let key = field(row, icao24).to_ascii_lowercase();
+                if key.is_empty() {
+                    return None;
+                }
+                let operator = match field(row, operator) {
+                    "" => field(row, owner),
+                    operator => operator,
+                };
+                Some((
+                    key,
+                    AircraftRecord {
+                        registration: field(row, registration).to_string(),
+                        type_code: field(row, type_code).to_string(),
+                        model: field(row, model).to_string(),
+                        operator: operator.to_string(),
+                    },
+                ))
+            })
+            .collect();
+        Self { by_icao24 }
+    }
+
+    pub fn lookup(&self, icao24: &str) -> Option<&AircraftRecord> {
+        self.by_icao24.get(&icao24.trim().to_ascii_lowercase())
+    }
+}
+
+impl TrackEnricher for AircraftRegistry {
+    fn name(&self) -> &'static str {
+        STAGE_AIRCRAFT_REGISTRY
+    }
+
+    fn enrich(&self, track: &mut TrackDelta) {
+        if track.kind != TrackKind::Aircraft as i32 {
+            return;
+        }
+        let Some(record) = track
+            .meta
+            .get("icao24")
+            .and_then(|icao24| self.lookup(icao24))
+        else {
+            return;
+        };
+        let record = record.clone();
+        set_meta(track, "registration", record.registration);
+        set_meta(track, "aircraft_type", record.type_code);
+        set_meta(track, "aircraft_model", record.model);
+        set_meta(track, "operator", record.operator);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_enriches_aircraft_by_icao24() {
+        let table = CsvTable::parse(
+            "icao24,registration,manufacturername,model,typecode,operator,owner\n\
+             4CA7B5,EI-DCL,Boeing,737-8AS,B738,Ryanair,\n\
+             a0b1c2,N123AB,Cessna,172S,C172,,\"Flying Club, LLC\"\n",
+        )
+        .unwrap();
+        let registry = AircraftRegistry::from_table(&table);
+
+        let mut track = TrackDelta {
+            id: "OPENSKY-4ca7b5".to_string(),
+            kind: TrackKind::Aircraft as i32,
+            meta: HashMap::from([("icao24".to_string(), "4ca7b5".to_string())]),
+            ..Default::default()
+        };
+        registry.enrich(&mut track);
+        assert_eq!(track.meta["registration"], "EI-DCL");
+        assert_eq!(track.meta["aircraft_type"], "B738");
+        assert_eq!(track.meta["aircraft_model"], "737-8AS");
+        assert_eq!(track.meta["operator"], "Ryanair");
+
+        // Owner stands in for a missing operator
+        assert_eq!(
+            registry.lookup("A0B1C2").unwrap().operator,
+            "Flying Club, LLC"
+        );
+
+        // Other kinds are left alone
+        let mut vessel = TrackDelta {
+            kind: TrackKind::Vessel as i32,
+            meta: HashMap::from([("icao24".to_string(), "4ca7b5".to_string())]),
+            ..Default::default()
+        };
+        registry.enrich(&mut vessel);
+        assert_eq!(vessel.meta.len(), 1);
+    }
+}
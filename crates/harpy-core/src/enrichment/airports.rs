@@ -0,0 +1,216 @@
+//! Nearest-airport attribution
+//!
+//! Tags aircraft with the closest airport from a local CSV in the OurAirports
+//! `airports.csv` layout, within a maximum distance. Airports are bucketed by
+//! whole degree so a lookup only scans the surrounding cells.
+
+use super::csv::{field, CsvTable};
+use super::{set_meta, TrackEnricher, STAGE_NEAREST_AIRPORT};
+use crate::error::Result;
+use harpy_proto::harpy::v1::{TrackDelta, TrackKind};
+use std::collections::HashMap;
+
+/// Search radius used when `ENRICH_AIRPORT_MAX_KM` is unset
+pub const DEFAULT_MAX_DISTANCE_KM: f64 = 50.0;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+const KM_PER_DEGREE_LAT: f64 = 111.32;
+
+/// OurAirports types that are not airfields aircraft are tracked around
+const SKIPPED_TYPES: &[&str] = &["closed", "heliport", "balloonport", "seaplane_base"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Airport {
+    /// ICAO/GPS code (`ident`)
+    pub ident: String,
+    pub iata: String,
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+pub struct NearestAirport {
+    max_distance_km: f64,
+    cells: HashMap<(i32, i32), Vec<Airport>>,
+}
+
+impl NearestAirport {
+    pub fn load(path: &str, max_distance_km: f64) -> Result<Self> {
+        let airports = Self::from_table(&CsvTable::read(path)?, max_distance_km);
+        tracing::info!(
+            "Airport file {} has {} airports",
+            path,
+            airports.cells.values().map(Vec::len).sum::<usize>()
+        );
+        Ok(airports)
+    }
+
+    pub fn from_table(table: &CsvTable, max_distance_km: f64) -> Self {
+        let ident = table.column(&["ident", "icao", "gps_code"]);
+        let iata = table.column(&["iata_code", "iata"]);
+        let name = table.column(&["name"]);
+        let kind = table.column(&["type"]);
+        let lat = table.column(&["latitude_deg", "latitude", "lat"]);
+        let lon = table.column(&["longitude_deg", "longitude", "lon"]);
+
+        let mut cells: HashMap<(i32, i32), Vec<Airport>> = HashMap::new();
+        for row in table.rows() {
+            if SKIPPED_TYPES.contains(&field(row, kind)) {
+                continue;
+            }
+            let (Ok(lat), Ok(lon)) = (
+                field(row, lat).parse::<f64>(),
+                field(row, lon).parse::<f64>(),
+            ) else {
+                continue;
+            };
+            let ident = field(row, ident);
+            if ident.is_empty() {
+                continue;
+            }
+            cells.entry(cell(lat, lon)).or_default().push(Airport {
+                ident: ident.to_string(),
+                iata: field(row, iata).to_string(),
+                name: field(row, name).to_string(),
+                lat,
+                lon,
+            });
+        }
+        Self {
+            max_distance_km,
+            cells,
+        }
+    }
+
+    /// Closest airport within the maximum distance, with its distance in km
+    pub fn nearest(&self, lat: f64, lon: f64) -> Option<(&Airport, f64)> {
+        if !lat.is_finite() || !lon.is_finite() {
+            return None;
+        }
+        let lat_cells = (self.max_distance_km / KM_PER_DEGREE_LAT).ceil() as i32;
+        // Degrees of longitude shrink towards the poles; clamp before it blows up
+        let km_per_degree_lon = KM_PER_DEGREE_LAT * lat.to_radians().cos().max(0.01);
+        let lon_cells = ((self.max_distance_km / km_per_degree_lon).ceil() as i32).min(180);
+        let (cell_lat, cell_lon) = cell(lat, lon);
+
+        let mut best: Option<(&Airport, f64)> = None;
+        for d_lat in -lat_cells..=lat_cells {
+            for d_lon in -lon_cells..=lon_cells {
+                // Wrap across the antimeridian
+                let wrapped_lon = (cell_lon + d_lon + 180).rem_euclid(360) - 180;
+                let Some(airports) = self.cells.get(&(cell_lat + d_lat, wrapped_lon)) else {
+                    continue;
+                };
+                for airport in airports {
+                    let km = haversine_km(lat, lon, airport.lat, airport.lon);
+                    if km <= self.max_distance_km && best.map_or(true, |(_, b)| km < b) {
+                        best = Some((airport, km));
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+impl TrackEnricher for NearestAirport {
+    fn name(&self) -> &'static str {
+        STAGE_NEAREST_AIRPORT
+    }
+
+    fn enrich(&self, track: &mut TrackDelta) {
+        if track.kind != TrackKind::Aircraft as i32 {
+            return;
+        }
+        let Some(position) = track.position.as_ref() else {
+            return;
+        };
+        let Some((airport, km)) = self.nearest(position.lat, position.lon) else {
+            return;
+        };
+        let (ident, iata, name) = (
+            airport.ident.clone(),
+            airport.iata.clone(),
+            airport.name.clone(),
+        );
+        set_meta(track, "nearest_airport", ident);
+        set_meta(track, "nearest_airport_iata", iata);
+        set_meta(track, "nearest_airport_name", name);
+        set_meta(track, "nearest_airport_km", format!("{km:.1}"));
+    }
+}
+
+fn cell(lat: f64, lon: f64) -> (i32, i32) {
+    (lat.floor() as i32, lon.floor() as i32)
+}
+
+fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let delta_lat = (lat2 - lat1).to_radians();
+    let delta_lon = (lon2 - lon1).to_radians();
+    let a = (delta_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (delta_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harpy_proto::harpy::v1::Position;
+
+    fn airports(max_km: f64) -> NearestAirport {
+        let table = CsvTable::parse(
+            "\"id\",\"ident\",\"type\",\"name\",\"latitude_deg\",\"longitude_deg\",\"iata_code\"\n\
+             1,KSFO,large_airport,\"San Francisco International Airport\",37.6188,-122.3750,SFO\n\
+             2,KOAK,large_airport,\"Metropolitan Oakland International Airport\",37.7213,-122.2208,OAK\n\
+             3,XXXX,closed,\"Old Field\",37.8044,-122.2712,\n\
+             4,NZCH,large_airport,\"Christchurch International Airport\",-43.4894,172.5320,CHC\n\
+             5,NFFN,large_airport,\"Nadi International Airport\",-17.7554,177.4434,NAN\n",
+        )
+        .unwrap();
+        NearestAirport::from_table(&table, max_km)
+    }
+
+    #[test]
+    fn test_nearest_airport_within_radius() {
+        let airports = airports(50.0);
+        // Downtown Oakland: OAK is ~10 km, SFO ~23 km, the closed field 0 km
+        let (airport, km) = airports.nearest(37.8044, -122.2712).unwrap();
+        assert_eq!(airport.ident, "KOAK");
+        assert!((km - 10.0).abs() < 1.5, "{km}");
+
+        assert!(airports.nearest(0.0, 0.0).is_none());
+        assert!(self::airports(5.0).nearest(37.8044, -122.2712).is_none());
+    }
+
+    #[test]
+    fn test_nearest_airport_across_antimeridian() {
+        let airports = airports(300.0);
+        let (airport, _) = airports.nearest(-17.7554, -179.9).unwrap();
+        assert_eq!(airport.ident, "NFFN");
+    }
+
+    #[test]
+    fn test_enrich_only_aircraft() {
+        let airports = airports(50.0);
+        let mut track = TrackDelta {
+            kind: TrackKind::Aircraft as i32,
+            position: Some(Position {
+                lat: 37.62,
+                lon: -122.38,
+                alt: 300.0,
+            }),
+            ..Default::default()
+        };
+        airports.enrich(&mut track);
+        assert_eq!(track.meta["nearest_airport"], "KSFO");
+        assert_eq!(track.meta["nearest_airport_iata"], "SFO");
+
+        let mut ground = TrackDelta {
+            kind: TrackKind::Ground as i32,
+            ..track.clone()
+        };
+        ground.meta.clear();
+        airports.enrich(&mut ground);
+        assert!(ground.meta.is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+//! Boundary attribution
+//!
+//! Names the region a track is over from an offline GeoJSON file of
+//! `Polygon`/`MultiPolygon` features: countries (e.g. Natural Earth admin 0)
+//! or exclusive economic zones (e.g. the Marine Regions EEZ layer).
+
+use super::{set_meta, TrackEnricher};
+use crate::error::{HarpyError, Result};
+use harpy_proto::harpy::v1::TrackDelta;
+use serde_json::Value;
+
+/// `[min_lon, min_lat, max_lon, max_lat]`
+type BBox = [f64; 4];
+
+struct Polygon {
+    bbox: BBox,
+    /// Outer ring then holes, as `(lon, lat)`
+    rings: Vec<Vec<(f64, f64)>>,
+}
+
+struct Region {
+    name: String,
+    bbox: BBox,
+    polygons: Vec<Polygon>,
+}
+
+pub struct BoundaryAttribution {
+    stage: &'static str,
+    meta_key: &'static str,
+    regions: Vec<Region>,
+}
+
+impl BoundaryAttribution {
+    /// Load `path`, naming each feature by its `property`
+    pub fn load(
+        stage: &'static str,
+        meta_key: &'static str,
+        path: &str,
+        property: &str,
+    ) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| HarpyError::Config(format!("Failed to read {}: {}", path, e)))?;
+        let geojson: Value = serde_json::from_str(&text)
+            .map_err(|e| HarpyError::Config(format!("{} is not valid GeoJSON: {}", path, e)))?;
+        let boundaries = Self::from_geojson(stage, meta_key, &geojson, property);
+        tracing::info!(
+            "Boundary file {} has {} regions for {}",
+            path,
+            boundaries.regions.len(),
+            meta_key
+        );
+        Ok(boundaries)
+    }
+
+    pub fn from_geojson(
+        stage: &'static str,
+        meta_key: &'static str,
+        geojson: &Value,
+        property: &str,
+    ) -> Self {
+        let regions = geojson["features"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|feature| {
+                let name = match &feature["properties"][property] {
+                    Value::String(name) => name.clone(),
+                    Value::Null => return None,
+                    other => other.to_string(),
+                };
+                let geometry = &feature["geometry"];
+                let polygons: Vec<Polygon> = match geometry["type"].as_str() {
+                    Some("Polygon") => parse_polygon(&geometry["coordinates"])
+                        .into_iter()
+                        .collect(),
+                    Some("MultiPolygon") => geometry["coordinates"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(parse_polygon)
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                if polygons.is_empty() {
+                    return None;
+                }
+                let bbox = polygons
+                    .iter()
+                    .map(|p| p.bbox)
+                    .reduce(union)
+                    .expect("at least one polygon");
+                Some(Region {
+                    name,
+                    bbox,
+                    polygons,
+                })
+            })
+            .collect();
+        Self {
+            stage,
+            meta_key,
+            regions,
+        }
+    }
+
+    /// Name of the first region containing the point
+    pub fn locate(&self, lat: f64, lon: f64) -> Option<&str> {
+        self.regions
+            .iter()
+            .filter(|region| in_bbox(&region.bbox, lon, lat))
+            .find(|region| {
+                region
+                    .polygons
+                    .iter()
+                    .any(|polygon| in_bbox(&polygon.bbox, lon, lat) && contains(polygon, lon, lat))
+            })
+            .map(|region| region.name.as_str())
+    }
+}
+
+impl TrackEnricher for BoundaryAttribution {
+    fn name(&self) -> &'static str {
+        self.stage
+    }
+
+    fn enrich(&self, track: &mut TrackDelta) {
+        let Some(position) = track.position.as_ref() else {
+            return;
+        };
+        if let Some(name) = self.locate(position.lat, position.lon) {
+            let name = name.to_string();
+            set_meta(track, self.meta_key, name);
+        }
+    }
+}
+
+fn parse_polygon(coordinates: &Value) -> Option<Polygon> {
+    let rings: Vec<Vec<(f64, f64)>> = coordinates
+        .as_array()?
+        .iter()
+        .map(|ring| {
+            ring.as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|point| Some((point[0].as_f64()?, point[1].as_f64()?)))
+                .collect::<Vec<_>>()
+        })
+        .filter(|ring| ring.len() >= 3)
+        .collect();
+    let outer = rings.first()?;
+    let bbox = outer.iter().fold(
+        [f64::MAX, f64::MAX, f64::MIN, f64::MIN],
+        |[min_lon, min_lat, max_lon, max_lat], &(lon, lat)| {
+            [
+                min_lon.min(lon),
+                min_lat.min(lat),
+                max_lon.max(lon),
+                max_lat.max(lat),
+            ]
+        },
+    );
+    Some(Polygon { bbox, rings })
+}
+
+fn union(a: BBox, b: BBox) -> BBox {
+    [
+        a[0].min(b[0]),
+        a[1].min(b[1]),
+        a[2].max(b[2]),
+        a[3].max(b[3]),
+    ]
+}
+
+fn in_bbox(bbox: &BBox, lon: f64, lat: f64) -> bool {
+    lon >= bbox[0] && lat >= bbox[1] && lon <= bbox[2] && lat <= bbox[3]
+}
+
+/// Even-odd ray casting over every ring, so holes fall out
+fn contains(polygon: &Polygon, lon: f64, lat: f64) -> bool {
+    let mut inside = false;
+    for ring in &polygon.rings {
+        let mut j = ring.len() - 1;
+        for i in 0..ring.len() {
+            let (xi, yi) = ring[i];
+            let (xj, yj) = ring[j];
+            if (yi > lat) != (yj > lat) && lon < (xj - xi) * (lat - yi) / (yj - yi) + xi {
+                inside = !inside;
+            }
+            j = i;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harpy_proto::harpy::v1::Position;
+
+    fn boundaries() -> BoundaryAttribution {
+        let geojson = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "properties": { "ISO_A3": "AAA" },
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [
+                            [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0], [0.0, 0.0]],
+                            [[4.0, 4.0], [6.0, 4.0], [6.0, 6.0], [4.0, 6.0], [4.0, 4.0]]
+                        ]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": { "ISO_A3": "BBB" },
+                    "geometry": {
+                        "type": "MultiPolygon",
+                        "coordinates": [
+                            [[[4.5, 4.5], [5.5, 4.5], [5.5, 5.5], [4.5, 5.5], [4.5, 4.5]]],
+                            [[[20.0, -5.0], [25.0, -5.0], [22.5, 0.0], [20.0, -5.0]]]
+                        ]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": { "NAME": "unnamed" },
+                    "geometry": { "type": "Point", "coordinates": [1.0, 1.0] }
+                }
+            ]
+        });
+        BoundaryAttribution::from_geojson("country", "country", &geojson, "ISO_A3")
+    }
+
+    #[test]
+    fn test_locate_handles_holes_and_multipolygons() {
+        let boundaries = boundaries();
+        assert_eq!(boundaries.regions.len(), 2);
+        assert_eq!(boundaries.locate(1.0, 1.0), Some("AAA"));
+        // Inside AAA's hole, covered by BBB's island
+        assert_eq!(boundaries.locate(5.0, 5.0), Some("BBB"));
+        assert_eq!(boundaries.locate(4.2, 4.2), None);
+        assert_eq!(boundaries.locate(-3.0, 22.5), Some("BBB"));
+        assert_eq!(boundaries.locate(50.0, 50.0), None);
+    }
+
+    #[test]
+    fn test_enrich_sets_meta_key() {
+        let mut track = TrackDelta {
+            position: Some(Position {
+                lat: 2.0,
+                lon: 8.0,
+                alt: 0.0,
+            }),
+            ..Default::default()
+        };
+        boundaries().enrich(&mut track);
+        assert_eq!(track.meta["country"], "AAA");
+    }
+}
@@ -0,0 +1,94 @@
+//! Minimal CSV reading for enrichment reference files
+//!
+//! Handles a header row, quoted fields with embedded commas and doubled
+//! quotes, and both `,` and `\r\n` line endings. Quoted fields spanning lines
+//! are not supported; registry and airport exports do not use them.
+
+use crate::error::{HarpyError, Result};
+use std::collections::HashMap;
+
+/// Rows of a CSV file, addressed by header name
+pub struct CsvTable {
+    columns: HashMap<String, usize>,
+    rows: Vec<Vec<String>>,
+}
+
+impl CsvTable {
+    pub fn read(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| HarpyError::Config(format!("Failed to read {}: {}", path, e)))?;
+        Self::parse(&text).ok_or_else(|| HarpyError::Config(format!("{} has no header row", path)))
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header = split_line(lines.next()?.trim_start_matches('\u{feff}'));
+        let columns = header
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| (name.trim().to_ascii_lowercase(), index))
+            .collect();
+        Some(Self {
+            columns,
+            rows: lines.map(split_line).collect(),
+        })
+    }
+
+    /// Index of the first of `names` present in the header
+    pub fn column(&self, names: &[&str]) -> Option<usize> {
+        names
+            .iter()
+            .find_map(|name| self.columns.get(*name).copied())
+    }
+
+    pub fn rows(&self) -> &[Vec<String>] {
+        &self.rows
+    }
+}
+
+/// Field `index` of `row`, trimmed, or "" when missing
+pub fn field(row: &[String], index: Option<usize>) -> &str {
+    index
+        .and_then(|index| row.get(index))
+        .map_or("", |value| value.trim())
+}
+
+fn split_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    fields.push(current);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quoted_fields() {
+        let table = CsvTable::parse(
+            "\u{feff}icao24,Operator,model\r\n\"a1b2c3\",\"Acme, Inc.\",\"Boeing \"\"747\"\"\"\r\n\nff00ff,,\n",
+        )
+        .unwrap();
+        let operator = table.column(&["operatorname", "operator"]);
+        assert_eq!(operator, Some(1));
+        assert_eq!(table.rows().len(), 2);
+        assert_eq!(field(&table.rows()[0], operator), "Acme, Inc.");
+        assert_eq!(field(&table.rows()[0], Some(2)), "Boeing \"747\"");
+        assert_eq!(field(&table.rows()[1], operator), "");
+        assert_eq!(field(&table.rows()[1], Some(7)), "");
+    }
+}
@@ -0,0 +1,284 @@
+//! Track enrichment
+//!
+//! Enrichers add attributes to `TrackDelta.meta` from local reference data
+//! after a provider fetch and before tracks are stored or fanned out. Which
+//! stages run is configured per provider with `ENRICHMENT_STAGES`:
+//!
+//! ```text
+//! ENRICHMENT_STAGES="opensky=aircraft_registry,country,eez,nearest_airport;*=country"
+//! ```
+//!
+//! `*` applies to providers without their own entry. Each stage loads its data
+//! once, from the file named by its own variable (see [`Enrichers::from_env`]);
+//! stages whose file is unset or unreadable are skipped with a warning.
+//! Enrichers never overwrite a meta key the provider already set.
+
+pub mod aircraft_registry;
+pub mod airports;
+pub mod boundaries;
+mod csv;
+
+use harpy_proto::harpy::v1::TrackDelta;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub use aircraft_registry::AircraftRegistry;
+pub use airports::NearestAirport;
+pub use boundaries::BoundaryAttribution;
+
+/// Stage names accepted in `ENRICHMENT_STAGES`
+pub const STAGE_AIRCRAFT_REGISTRY: &str = "aircraft_registry";
+pub const STAGE_COUNTRY: &str = "country";
+pub const STAGE_EEZ: &str = "eez";
+pub const STAGE_NEAREST_AIRPORT: &str = "nearest_airport";
+
+/// One enrichment stage
+pub trait TrackEnricher: Send + Sync {
+    /// Stage name, as used in `ENRICHMENT_STAGES`
+    fn name(&self) -> &'static str;
+
+    /// Add attributes to `track.meta`
+    fn enrich(&self, track: &mut TrackDelta);
+}
+
+/// Insert `value` under `key` unless the provider already set it
+pub(crate) fn set_meta(track: &mut TrackDelta, key: &str, value: impl Into<String>) {
+    let value = value.into();
+    if value.is_empty() {
+        return;
+    }
+    track.meta.entry(key.to_string()).or_insert(value);
+}
+
+/// Stages run in order over every fetched batch
+#[derive(Clone, Default)]
+pub struct EnrichmentPipeline {
+    stages: Vec<Arc<dyn TrackEnricher>>,
+}
+
+impl EnrichmentPipeline {
+    pub fn new(stages: Vec<Arc<dyn TrackEnricher>>) -> Self {
+        Self { stages }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn stage_names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    pub fn enrich(&self, tracks: &mut [TrackDelta]) {
+        for track in tracks {
+            for stage in &self.stages {
+                stage.enrich(track);
+            }
+        }
+    }
+}
+
+/// Loaded stages and the per-provider stage lists
+#[derive(Clone, Default)]
+pub struct Enrichers {
+    available: HashMap<&'static str, Arc<dyn TrackEnricher>>,
+    stages: StageSpec,
+}
+
+impl Enrichers {
+    pub fn new(available: Vec<Arc<dyn TrackEnricher>>, stages: StageSpec) -> Self {
+        Self {
+            available: available
+                .into_iter()
+                .map(|stage| (stage.name(), stage))
+                .collect(),
+            stages,
+        }
+    }
+
+    /// Load the stages named in `ENRICHMENT_STAGES` from their data files:
+    ///
+    /// - `aircraft_registry`: `ENRICH_AIRCRAFT_REGISTRY_CSV`
+    /// - `country`: `ENRICH_COUNTRY_GEOJSON` (name from `ENRICH_COUNTRY_PROPERTY`)
+    /// - `eez`: `ENRICH_EEZ_GEOJSON` (name from `ENRICH_EEZ_PROPERTY`)
+    /// - `nearest_airport`: `ENRICH_AIRPORTS_CSV`, within `ENRICH_AIRPORT_MAX_KM`
+    pub fn from_env() -> Self {
+        let stages = StageSpec::parse(&std::env::var("ENRICHMENT_STAGES").unwrap_or_default());
+        let wanted = stages.all_stages();
+        let path = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let property = |name: &str, default: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| default.to_string())
+        };
+
+        let mut available: Vec<Arc<dyn TrackEnricher>> = Vec::new();
+        for stage in wanted {
+            let loaded: Result<Arc<dyn TrackEnricher>, String> = match stage.as_str() {
+                STAGE_AIRCRAFT_REGISTRY => match path("ENRICH_AIRCRAFT_REGISTRY_CSV") {
+                    Some(file) => AircraftRegistry::load(&file)
+                        .map(|s| Arc::new(s) as Arc<dyn TrackEnricher>)
+                        .map_err(|e| e.to_string()),
+                    None => Err("ENRICH_AIRCRAFT_REGISTRY_CSV is not set".to_string()),
+                },
+                STAGE_COUNTRY => match path("ENRICH_COUNTRY_GEOJSON") {
+                    Some(file) => BoundaryAttribution::load(
+                        STAGE_COUNTRY,
+                        "country",
+                        &file,
+                        &property("ENRICH_COUNTRY_PROPERTY", "ISO_A3"),
+                    )
+                    .map(|s| Arc::new(s) as Arc<dyn TrackEnricher>)
+                    .map_err(|e| e.to_string()),
+                    None => Err("ENRICH_COUNTRY_GEOJSON is not set".to_string()),
+                },
+                STAGE_EEZ => match path("ENRICH_EEZ_GEOJSON") {
+                    Some(file) => BoundaryAttribution::load(
+                        STAGE_EEZ,
+                        "eez",
+                        &file,
+                        &property("ENRICH_EEZ_PROPERTY", "GEONAME"),
+                    )
+                    .map(|s| Arc::new(s) as Arc<dyn TrackEnricher>)
+                    .map_err(|e| e.to_string()),
+                    None => Err("ENRICH_EEZ_GEOJSON is not set".to_string()),
+                },
+                STAGE_NEAREST_AIRPORT => match path("ENRICH_AIRPORTS_CSV") {
+                    Some(file) => {
+                        let max_km = std::env::var("ENRICH_AIRPORT_MAX_KM")
+                            .ok()
+                            .and_then(|v| v.parse::<f64>().ok())
+                            .unwrap_or(airports::DEFAULT_MAX_DISTANCE_KM);
+                        NearestAirport::load(&file, max_km)
+                            .map(|s| Arc::new(s) as Arc<dyn TrackEnricher>)
+                            .map_err(|e| e.to_string())
+                    }
+                    None => Err("ENRICH_AIRPORTS_CSV is not set".to_string()),
+                },
+                other => Err(format!("unknown stage '{}'", other)),
+            };
+            match loaded {
+                Ok(enricher) => {
+                    tracing::info!("Loaded enrichment stage {}", enricher.name());
+                    available.push(enricher);
+                }
+                Err(e) => tracing::warn!("Skipping enrichment stage {}: {}", stage, e),
+            }
+        }
+        Self::new(available, stages)
+    }
+
+    /// Pipeline configured for `provider_id`, limited to the stages that loaded
+    pub fn pipeline_for(&self, provider_id: &str) -> EnrichmentPipeline {
+        EnrichmentPipeline::new(
+            self.stages
+                .for_provider(provider_id)
+                .iter()
+                .filter_map(|name| self.available.get(name.as_str()).cloned())
+                .collect(),
+        )
+    }
+}
+
+/// Parsed `ENRICHMENT_STAGES`: `provider=stage,stage;provider=...;*=stage`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StageSpec {
+    by_provider: HashMap<String, Vec<String>>,
+    default: Vec<String>,
+}
+
+impl StageSpec {
+    pub fn parse(spec: &str) -> Self {
+        let mut parsed = Self::default();
+        for entry in spec.split(';') {
+            let Some((provider, stages)) = entry.split_once('=') else {
+                if !entry.trim().is_empty() {
+                    tracing::warn!("Ignoring ENRICHMENT_STAGES entry without '=': {}", entry);
+                }
+                continue;
+            };
+            let stages: Vec<String> = stages
+                .split(',')
+                .map(|stage| stage.trim().to_ascii_lowercase())
+                .filter(|stage| !stage.is_empty())
+                .collect();
+            match provider.trim() {
+                "*" => parsed.default = stages,
+                provider => {
+                    parsed.by_provider.insert(provider.to_string(), stages);
+                }
+            }
+        }
+        parsed
+    }
+
+    pub fn for_provider(&self, provider_id: &str) -> &[String] {
+        self.by_provider.get(provider_id).unwrap_or(&self.default)
+    }
+
+    /// Every stage named anywhere, once each
+    fn all_stages(&self) -> Vec<String> {
+        let mut all: Vec<String> = self
+            .by_provider
+            .values()
+            .chain(std::iter::once(&self.default))
+            .flatten()
+            .cloned()
+            .collect();
+        all.sort();
+        all.dedup();
+        all
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Tag(&'static str);
+
+    impl TrackEnricher for Tag {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn enrich(&self, track: &mut TrackDelta) {
+            set_meta(track, self.0, "yes");
+        }
+    }
+
+    #[test]
+    fn test_stage_spec_per_provider_with_default() {
+        let spec =
+            StageSpec::parse(" opensky = aircraft_registry, Country ; *=country;;celestrak-gp=");
+        assert_eq!(
+            spec.for_provider("opensky"),
+            ["aircraft_registry", "country"]
+        );
+        assert_eq!(spec.for_provider("mock-adsb"), ["country"]);
+        assert!(spec.for_provider("celestrak-gp").is_empty());
+        assert_eq!(spec.all_stages(), vec!["aircraft_registry", "country"]);
+    }
+
+    #[test]
+    fn test_pipeline_runs_loaded_stages_without_overwriting() {
+        let enrichers = Enrichers::new(
+            vec![Arc::new(Tag("country")), Arc::new(Tag("eez"))],
+            StageSpec::parse("opensky=country,nearest_airport,eez"),
+        );
+        // nearest_airport is configured but did not load
+        let pipeline = enrichers.pipeline_for("opensky");
+        assert_eq!(pipeline.stage_names(), vec!["country", "eez"]);
+        assert!(enrichers.pipeline_for("mock-adsb").is_empty());
+
+        let mut tracks = vec![TrackDelta {
+            id: "a1".to_string(),
+            meta: HashMap::from([("country".to_string(), "provider".to_string())]),
+            ..Default::default()
+        }];
+        pipeline.enrich(&mut tracks);
+        assert_eq!(tracks[0].meta["country"], "provider");
+        assert_eq!(tracks[0].meta["eez"], "yes");
+    }
+}
@@ -0,0 +1,100 @@
+//! Follow-Track Filters
+//!
+//! Pins specific track ids or callsign patterns to a subscription so they are
+//! delivered regardless of viewport or layer. Node and relay match live tracks
+//! here; the relay also loads trails for followed tracks from Postgres.
+
+use harpy_proto::harpy::v1::{FollowFilter, TrackDelta};
+
+/// Whether the filter pins anything at all
+pub fn is_active(filter: &FollowFilter) -> bool {
+    !filter.track_ids.is_empty() || !filter.callsign_patterns.is_empty()
+}
+
+/// Whether a track is pinned by the filter
+pub fn follows(filter: &FollowFilter, track: &TrackDelta) -> bool {
+    filter.track_ids.contains(&track.id)
+        || track
+            .meta
+            .get("callsign")
+            .is_some_and(|callsign| follows_callsign(filter, callsign))
+}
+
+/// Whether a callsign matches one of the filter's patterns
+pub fn follows_callsign(filter: &FollowFilter, callsign: &str) -> bool {
+    let callsign = callsign.trim();
+    filter
+        .callsign_patterns
+        .iter()
+        .any(|pattern| glob_match(pattern.trim(), callsign))
+}
+
+/// Case-insensitive glob match supporting `*` (any run) and `?` (one char)
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0usize, 0usize);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn track(id: &str, callsign: Option<&str>) -> TrackDelta {
+        let mut meta = HashMap::new();
+        if let Some(callsign) = callsign {
+            meta.insert("callsign".to_string(), callsign.to_string());
+        }
+        TrackDelta {
+            id: id.to_string(),
+            meta,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("UAL*", "ual123"));
+        assert!(glob_match("*123", "UAL123"));
+        assert!(glob_match("U?L1*3", "UAL123"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("UAL?", "UAL12"));
+        assert!(!glob_match("DAL*", "UAL123"));
+    }
+
+    #[test]
+    fn test_follows_by_id_and_callsign() {
+        let filter = FollowFilter {
+            track_ids: vec!["adsb-abc123".to_string()],
+            callsign_patterns: vec!["MCK00?".to_string()],
+            trail_ms: 0,
+        };
+        assert!(is_active(&filter));
+        assert!(follows(&filter, &track("adsb-abc123", None)));
+        assert!(follows(&filter, &track("adsb-other", Some("MCK007  "))));
+        assert!(!follows(&filter, &track("adsb-other", Some("MCK017"))));
+        assert!(!follows(&filter, &track("adsb-other", None)));
+        assert!(!is_active(&FollowFilter::default()));
+    }
+}
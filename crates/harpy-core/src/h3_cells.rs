@@ -0,0 +1,268 @@
+//! H3 cells for spatial indexing
+//!
+//! `tracks.h3_index` and `track_deltas.h3_index` hold the H3 cell of each
+//! position at the storage resolution (`H3_RESOLUTION`, default 8). Viewport
+//! queries cover the area with cells at the coarsest resolution that keeps the
+//! cover small; every descendant of a coarse cell at the storage resolution
+//! lies in one contiguous `h3_index` range, so the cover becomes a few btree
+//! range scans.
+
+use h3o::{CellIndex, LatLng, Resolution};
+use std::collections::BTreeSet;
+
+/// Storage resolution used when `H3_RESOLUTION` is unset (matches fusion's default)
+pub const DEFAULT_H3_RESOLUTION: u8 = 8;
+
+/// Largest cover, in cells, turned into a predicate; larger areas are not worth filtering
+const MAX_COVER_CELLS: usize = 96;
+/// Target cover size before the k-ring is added, used to pick the resolution
+const TARGET_COVER_CELLS: f64 = 24.0;
+const EARTH_RADIUS_KM: f64 = 6371.0;
+const KM_PER_DEGREE: f64 = 111.32;
+
+/// Bits holding the resolution of an H3 index
+const RESOLUTION_SHIFT: u32 = 52;
+const RESOLUTION_MASK: u64 = 0xF << RESOLUTION_SHIFT;
+
+/// Storage resolution from `H3_RESOLUTION`
+pub fn storage_resolution() -> Resolution {
+    std::env::var("H3_RESOLUTION")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .and_then(|v| Resolution::try_from(v).ok())
+        .unwrap_or_else(|| {
+            Resolution::try_from(DEFAULT_H3_RESOLUTION).expect("default resolution is valid")
+        })
+}
+
+/// `h3_index` value for a position, or `None` for invalid coordinates
+pub fn cell_index(lat: f64, lon: f64, resolution: Resolution) -> Option<i64> {
+    let cell = LatLng::new(lat, lon).ok()?.to_cell(resolution);
+    Some(u64::from(cell) as i64)
+}
+
+/// Inclusive `h3_index` ranges covering a bounding box, or `None` when the box
+/// is too large for a cell predicate to help.
+///
+/// `min_lon > max_lon` is a box crossing the antimeridian.
+pub fn cover_bbox(
+    min_lat: f64,
+    min_lon: f64,
+    max_lat: f64,
+    max_lon: f64,
+    storage: Resolution,
+) -> Option<Vec<(i64, i64)>> {
+    if ![min_lat, min_lon, max_lat, max_lon]
+        .iter()
+        .all(|v| v.is_finite())
+        || min_lat > max_lat
+    {
+        return None;
+    }
+    let min_lat = min_lat.clamp(-90.0, 90.0);
+    let max_lat = max_lat.clamp(-90.0, 90.0);
+    let lon_span = if min_lon <= max_lon {
+        max_lon - min_lon
+    } else {
+        max_lon + 360.0 - min_lon
+    };
+    if lon_span >= 360.0 {
+        return None;
+    }
+
+    let area_km2 = EARTH_RADIUS_KM.powi(2)
+        * lon_span.to_radians()
+        * (max_lat.to_radians().sin() - min_lat.to_radians().sin()).abs();
+
+    // Finest resolution first: the first one with a small enough cover wins.
+    for resolution in Resolution::range(Resolution::Zero, storage).rev() {
+        if area_km2 / resolution.area_km2() > TARGET_COVER_CELLS {
+            continue;
+        }
+        let cells = sample_cells(min_lat, min_lon, max_lat, lon_span, resolution);
+        if cells.len() <= MAX_COVER_CELLS {
+            return Some(
+                cells
+                    .into_iter()
+                    .map(|c| storage_range(c, storage))
+                    .collect(),
+            );
+        }
+    }
+    None
+}
+
+/// Inclusive `h3_index` ranges covering a circle of `radius_m` around a point
+pub fn cover_radius(
+    lat: f64,
+    lon: f64,
+    radius_m: f64,
+    storage: Resolution,
+) -> Option<Vec<(i64, i64)>> {
+    if !radius_m.is_finite() || radius_m < 0.0 {
+        return None;
+    }
+    let dlat = radius_m / 1000.0 / KM_PER_DEGREE;
+    let dlon = dlat / lat.to_radians().cos().max(0.01);
+    if dlon >= 180.0 {
+        return None;
+    }
+    cover_bbox(
+        lat - dlat,
+        wrap_lon(lon - dlon),
+        lat + dlat,
+        wrap_lon(lon + dlon),
+        storage,
+    )
+}
+
+/// SQL predicate restricting `column` to the cover; rows not yet indexed still match
+///
+/// The ranges are integers produced by this module, so the predicate is safe
+/// to splice into a query.
+pub fn sql_predicate(column: &str, ranges: &[(i64, i64)]) -> String {
+    let mut clauses: Vec<String> = ranges
+        .iter()
+        .map(|(lo, hi)| format!("{} BETWEEN {} AND {}", column, lo, hi))
+        .collect();
+    clauses.push(format!("{} IS NULL", column));
+    format!("({})", clauses.join(" OR "))
+}
+
+/// Resolution encoded in an `h3_index` value
+pub fn index_resolution(index: i64) -> u8 {
+    ((index as u64 & RESOLUTION_MASK) >> RESOLUTION_SHIFT) as u8
+}
+
+/// Cells at `resolution` sampled across the box, widened by one ring so cells
+/// clipped between samples are included
+fn sample_cells(
+    min_lat: f64,
+    min_lon: f64,
+    max_lat: f64,
+    lon_span: f64,
+    resolution: Resolution,
+) -> BTreeSet<CellIndex> {
+    let step_deg = resolution.edge_length_km() / KM_PER_DEGREE / 2.0;
+    let mut cells = BTreeSet::new();
+
+    let mut lat = min_lat;
+    loop {
+        let lon_step = step_deg / lat.to_radians().cos().max(0.01);
+        let mut offset = 0.0;
+        loop {
+            if let Ok(point) = LatLng::new(lat, wrap_lon(min_lon + offset)) {
+                cells.insert(point.to_cell(resolution));
+            }
+            if offset >= lon_span {
+                break;
+            }
+            offset = (offset + lon_step).min(lon_span);
+        }
+        if lat >= max_lat {
+            break;
+        }
+        lat = (lat + step_deg).min(max_lat);
+    }
+
+    cells
+        .iter()
+        .flat_map(|cell| cell.grid_disk::<Vec<_>>(1))
+        .collect()
+}
+
+/// Inclusive range of the descendants of `cell` at the storage resolution.
+///
+/// Digits below the cell's own resolution run 0..=6 in its descendants, and
+/// the resolution field sits above all digits, so the smallest and largest
+/// descendants bound exactly that set among indexes at the storage resolution.
+fn storage_range(cell: CellIndex, storage: Resolution) -> (i64, i64) {
+    let own = u8::from(cell.resolution());
+    let storage = u8::from(storage).max(own);
+    let base = (u64::from(cell) & !RESOLUTION_MASK) | (u64::from(storage) << RESOLUTION_SHIFT);
+
+    let (mut lo, mut hi) = (base, base);
+    for digit in own + 1..=storage {
+        let shift = u32::from(15 - digit) * 3;
+        lo &= !(0b111 << shift);
+        hi = (hi & !(0b111 << shift)) | (0b110 << shift);
+    }
+    (lo as i64, hi as i64)
+}
+
+fn wrap_lon(lon: f64) -> f64 {
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covered(ranges: &[(i64, i64)], index: i64) -> bool {
+        ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&index))
+    }
+
+    #[test]
+    fn test_storage_range_holds_descendants_only() {
+        let storage = Resolution::Eight;
+        let point = LatLng::new(37.77, -122.42).unwrap();
+        let coarse = point.to_cell(Resolution::Five);
+        let (lo, hi) = storage_range(coarse, storage);
+
+        for child in coarse.children(storage) {
+            assert!((lo..=hi).contains(&(u64::from(child) as i64)));
+        }
+        let neighbour = coarse
+            .grid_disk::<Vec<_>>(1)
+            .into_iter()
+            .find(|c| *c != coarse)
+            .unwrap();
+        let (n_lo, n_hi) = storage_range(neighbour, storage);
+        assert!(n_hi < lo || n_lo > hi);
+        assert_eq!(index_resolution(lo), 8);
+    }
+
+    #[test]
+    fn test_cover_bbox_contains_points_inside() {
+        let storage = Resolution::Eight;
+        let ranges = cover_bbox(37.0, -123.0, 38.0, -122.0, storage).expect("cover");
+        assert!(ranges.len() <= MAX_COVER_CELLS);
+        for (lat, lon) in [
+            (37.0, -123.0),
+            (37.5, -122.5),
+            (38.0, -122.0),
+            (37.99, -122.01),
+        ] {
+            assert!(covered(&ranges, cell_index(lat, lon, storage).unwrap()));
+        }
+        assert!(!covered(
+            &ranges,
+            cell_index(40.0, -100.0, storage).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_cover_bbox_across_dateline() {
+        let storage = Resolution::Seven;
+        let ranges = cover_bbox(-1.0, 179.5, 1.0, -179.5, storage).expect("cover");
+        assert!(covered(&ranges, cell_index(0.0, 179.9, storage).unwrap()));
+        assert!(covered(&ranges, cell_index(0.0, -179.9, storage).unwrap()));
+        assert!(!covered(&ranges, cell_index(0.0, 0.0, storage).unwrap()));
+    }
+
+    #[test]
+    fn test_world_viewport_has_no_cover() {
+        assert!(cover_bbox(-90.0, -180.0, 90.0, 180.0, Resolution::Eight).is_none());
+    }
+
+    #[test]
+    fn test_cover_radius_and_predicate() {
+        let storage = Resolution::Eight;
+        let ranges = cover_radius(51.5, -0.12, 10_000.0, storage).expect("cover");
+        assert!(covered(&ranges, cell_index(51.55, -0.1, storage).unwrap()));
+
+        let sql = sql_predicate("t.h3_index", &ranges[..1]);
+        assert!(sql.starts_with("(t.h3_index BETWEEN "));
+        assert!(sql.ends_with(" OR t.h3_index IS NULL)"));
+    }
+}
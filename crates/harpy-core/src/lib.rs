@@ -1,5 +1,13 @@
 pub mod config;
+pub mod enrichment;
 pub mod error;
+pub mod follow;
+pub mod h3_cells;
+pub mod provider_status;
+pub mod redis_contract;
+pub mod streams;
+pub mod track_filter;
+pub mod track_wire;
 pub mod types;
 
 pub use error::HarpyError;
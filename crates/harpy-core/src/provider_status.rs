@@ -0,0 +1,158 @@
+//! Provider status events
+//!
+//! harpy-ingest keeps the latest status of each provider under
+//! `provider:status:{id}` and appends a protobuf `Envelope` carrying a
+//! `ProviderStatus` to `PROVIDER_STATUS_STREAM` whenever the status changes.
+
+use crate::error::{HarpyError, Result};
+use crate::track_wire::{decode_envelope, SCHEMA_VERSION};
+use harpy_proto::harpy::v1::{
+    envelope::Payload, CircuitState, Envelope, Freshness, ProviderStatus,
+};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+/// Stream carrying provider status transitions
+pub const PROVIDER_STATUS_STREAM: &str = "providers:updates";
+
+/// Prefix of the keys holding each provider's latest status as JSON
+pub const PROVIDER_STATUS_KEY_PREFIX: &str = "provider:status:";
+
+/// Key holding a provider's latest status
+pub fn status_key(provider_id: &str) -> String {
+    format!("{}{}", PROVIDER_STATUS_KEY_PREFIX, provider_id)
+}
+
+/// Whether `next` differs from `previous` in anything but timestamps
+pub fn is_transition(previous: Option<&ProviderStatus>, next: &ProviderStatus) -> bool {
+    let Some(previous) = previous else {
+        return true;
+    };
+    previous.circuit_state != next.circuit_state
+        || previous.freshness != next.freshness
+        || previous.failure_count != next.failure_count
+        || previous.error_message != next.error_message
+        || previous.meta != next.meta
+}
+
+/// Encode a status change for `PROVIDER_STATUS_STREAM`
+pub fn encode_event(status: &ProviderStatus, server_ts_ms: u64) -> Vec<u8> {
+    Envelope {
+        schema_version: SCHEMA_VERSION.to_string(),
+        server_ts_ms,
+        seq: 0,
+        payload: Some(Payload::ProviderStatus(status.clone())),
+    }
+    .encode_to_vec()
+}
+
+/// Decode a status change read from `PROVIDER_STATUS_STREAM`
+pub fn decode_event(bytes: &[u8]) -> Result<ProviderStatus> {
+    match decode_envelope(bytes)?.payload {
+        Some(Payload::ProviderStatus(status)) => Ok(status),
+        _ => Err(HarpyError::Protobuf(
+            "envelope does not carry a ProviderStatus".to_string(),
+        )),
+    }
+}
+
+/// JSON stored under `provider:status:{id}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderStatusRecord {
+    pub provider_id: String,
+    pub circuit_state: String,
+    pub freshness: String,
+    /// When ingest last wrote the record
+    pub last_update_ts_ms: u64,
+    /// Whether the most recent fetch succeeded
+    pub last_success: bool,
+    #[serde(default)]
+    pub last_success_ts_ms: u64,
+    #[serde(default)]
+    pub failure_count: u32,
+    #[serde(default)]
+    pub error_message: Option<String>,
+}
+
+impl ProviderStatusRecord {
+    pub fn new(status: &ProviderStatus, last_update_ts_ms: u64) -> Self {
+        Self {
+            provider_id: status.provider_id.clone(),
+            circuit_state: status.circuit_state().as_str_name().to_string(),
+            freshness: status.freshness().as_str_name().to_string(),
+            last_update_ts_ms,
+            last_success: status.failure_count == 0,
+            last_success_ts_ms: status.last_success_ts_ms,
+            failure_count: status.failure_count,
+            error_message: status.error_message.clone(),
+        }
+    }
+
+    pub fn to_proto(&self) -> ProviderStatus {
+        ProviderStatus {
+            provider_id: self.provider_id.clone(),
+            circuit_state: CircuitState::from_str_name(&self.circuit_state)
+                .unwrap_or(CircuitState::Unspecified) as i32,
+            freshness: Freshness::from_str_name(&self.freshness).unwrap_or(Freshness::Unspecified)
+                as i32,
+            last_success_ts_ms: self.last_success_ts_ms,
+            failure_count: self.failure_count,
+            error_message: self.error_message.clone(),
+            meta: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(circuit_state: CircuitState, failure_count: u32) -> ProviderStatus {
+        ProviderStatus {
+            provider_id: "adsb-opensky".to_string(),
+            circuit_state: circuit_state as i32,
+            freshness: Freshness::Fresh as i32,
+            last_success_ts_ms: 1_000,
+            failure_count,
+            error_message: (failure_count > 0).then(|| "HTTP 429".to_string()),
+            meta: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_transitions_ignore_timestamps() {
+        let closed = status(CircuitState::Closed, 0);
+        assert!(is_transition(None, &closed));
+
+        let mut later = closed.clone();
+        later.last_success_ts_ms = 2_000;
+        assert!(!is_transition(Some(&closed), &later));
+
+        assert!(is_transition(Some(&closed), &status(CircuitState::Open, 1)));
+        assert!(is_transition(
+            Some(&status(CircuitState::Open, 1)),
+            &status(CircuitState::Open, 2)
+        ));
+    }
+
+    #[test]
+    fn test_event_and_record_round_trip() {
+        let open = status(CircuitState::Open, 3);
+        assert_eq!(decode_event(&encode_event(&open, 5)).unwrap(), open);
+
+        let record = ProviderStatusRecord::new(&open, 5);
+        assert_eq!(record.circuit_state, "CIRCUIT_STATE_OPEN");
+        assert!(!record.last_success);
+        assert_eq!(record.to_proto(), open);
+    }
+
+    #[test]
+    fn test_legacy_record_decodes() {
+        let legacy = r#"{"provider_id":"tle","circuit_state":"CIRCUIT_STATE_CLOSED","freshness":"FRESHNESS_FRESH","last_update_ts_ms":9,"last_success":true}"#;
+        let record: ProviderStatusRecord = serde_json::from_str(legacy).unwrap();
+        let status = record.to_proto();
+        assert_eq!(status.circuit_state(), CircuitState::Closed);
+        assert_eq!(status.failure_count, 0);
+        assert_eq!(status.error_message, None);
+    }
+}
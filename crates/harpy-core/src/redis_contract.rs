@@ -0,0 +1,296 @@
+//! Redis message contract for alerts and links.
+//!
+//! Published by harpy-fusion on the `alerts:updates` / `links:updates` Redis
+//! streams and consumed by harpy-relay. Enum values serialize to the same strings stored in the
+//! `alerts` and `links` tables.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Current contract version. Consumers reject messages with a newer version.
+pub const CONTRACT_VERSION: u32 = 1;
+
+/// Redis stream carrying `AlertMessage` payloads
+pub const ALERTS_STREAM: &str = "alerts:updates";
+/// Redis stream carrying `LinkMessage` payloads
+pub const LINKS_STREAM: &str = "links:updates";
+
+/// Messages published before versioning carried the same fields as v1.
+fn legacy_version() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AlertSeverity {
+    Info,
+    Medium,
+    Warning,
+    Critical,
+}
+
+impl AlertSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "INFO",
+            Self::Medium => "MEDIUM",
+            Self::Warning => "WARNING",
+            Self::Critical => "CRITICAL",
+        }
+    }
+
+    /// Parse the value stored in `alerts.severity`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "INFO" => Some(Self::Info),
+            "MEDIUM" => Some(Self::Medium),
+            "WARNING" => Some(Self::Warning),
+            "CRITICAL" => Some(Self::Critical),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AlertStatus {
+    Active,
+    Acknowledged,
+    Resolved,
+}
+
+impl AlertStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Active => "ACTIVE",
+            Self::Acknowledged => "ACKNOWLEDGED",
+            Self::Resolved => "RESOLVED",
+        }
+    }
+
+    /// Parse the value stored in `alerts.status`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ACTIVE" => Some(Self::Active),
+            "ACKNOWLEDGED" => Some(Self::Acknowledged),
+            "RESOLVED" => Some(Self::Resolved),
+            _ => None,
+        }
+    }
+}
+
+/// Ontology node type at either end of a link
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NodeKind {
+    Track,
+    Sensor,
+    Detection,
+    Alert,
+}
+
+impl NodeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Track => "Track",
+            Self::Sensor => "Sensor",
+            Self::Detection => "Detection",
+            Self::Alert => "Alert",
+        }
+    }
+}
+
+/// WGS84 position of a track when an alert or link was raised
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Track an alert was raised on, with its position at the time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvidenceTrack {
+    pub track_id: String,
+    pub position: GeoPoint,
+    /// Trimmed `callsign` meta of the track, so callsign follows can match
+    #[serde(default)]
+    pub callsign: Option<String>,
+}
+
+/// Alert upsert published on `ALERTS_STREAM`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertMessage {
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+    pub id: String,
+    pub severity: AlertSeverity,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub ts_ms: u64,
+    pub status: AlertStatus,
+    #[serde(default)]
+    pub evidence_link_ids: Vec<String>,
+    /// Tracks the alert concerns; empty when the alert has no location
+    #[serde(default)]
+    pub evidence_tracks: Vec<EvidenceTrack>,
+    #[serde(default)]
+    pub meta: Map<String, Value>,
+}
+
+/// Link upsert published on `LINKS_STREAM`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkMessage {
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+    pub id: String,
+    pub from_type: NodeKind,
+    pub from_id: String,
+    pub rel: String,
+    pub to_type: NodeKind,
+    pub to_id: String,
+    pub ts_ms: u64,
+    /// Position of the `from` node when it is a track
+    #[serde(default)]
+    pub from_position: Option<GeoPoint>,
+    /// Position of the `to` node when it is a track
+    #[serde(default)]
+    pub to_position: Option<GeoPoint>,
+    #[serde(default)]
+    pub meta: Map<String, Value>,
+}
+
+/// Decode a contract message, rejecting versions newer than this build understands
+pub fn decode<T>(payload: &str) -> Result<T, String>
+where
+    T: for<'de> Deserialize<'de> + Versioned,
+{
+    let message: T = serde_json::from_str(payload).map_err(|e| e.to_string())?;
+    if message.version() > CONTRACT_VERSION {
+        return Err(format!(
+            "unsupported contract version {} (max {})",
+            message.version(),
+            CONTRACT_VERSION
+        ));
+    }
+    Ok(message)
+}
+
+/// Messages carrying a contract version
+pub trait Versioned {
+    fn version(&self) -> u32;
+}
+
+impl Versioned for AlertMessage {
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+impl Versioned for LinkMessage {
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+/// Flatten JSON meta into string values; non-string values keep their JSON text
+pub fn flatten_meta(meta: &Map<String, Value>) -> std::collections::HashMap<String, String> {
+    meta.iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_alert_round_trip() {
+        let alert = AlertMessage {
+            version: CONTRACT_VERSION,
+            id: "alert-1".to_string(),
+            severity: AlertSeverity::Medium,
+            title: "Convergence".to_string(),
+            description: "two tracks".to_string(),
+            ts_ms: 1_700_000_000_000,
+            status: AlertStatus::Active,
+            evidence_link_ids: vec!["link-1".to_string()],
+            evidence_tracks: vec![EvidenceTrack {
+                track_id: "track-1".to_string(),
+                position: GeoPoint {
+                    lat: 51.5,
+                    lon: -0.1,
+                },
+                callsign: Some("BAW12".to_string()),
+            }],
+            meta: json!({"rule": "h3_convergence", "track_count": 2})
+                .as_object()
+                .cloned()
+                .unwrap(),
+        };
+        let payload = serde_json::to_string(&alert).unwrap();
+        assert!(payload.contains("\"severity\":\"MEDIUM\""));
+        assert_eq!(decode::<AlertMessage>(&payload).unwrap(), alert);
+    }
+
+    #[test]
+    fn test_legacy_link_without_version_decodes() {
+        let payload = json!({
+            "id": "link-1",
+            "from_type": "Alert",
+            "from_id": "alert-1",
+            "rel": "is_evidenced_by",
+            "to_type": "Track",
+            "to_id": "track-1",
+            "ts_ms": 5,
+            "meta": {"distance_m": 12.5}
+        })
+        .to_string();
+        let link = decode::<LinkMessage>(&payload).unwrap();
+        assert_eq!(link.version, 1);
+        assert_eq!(link.from_type, NodeKind::Alert);
+        assert_eq!(link.to_type, NodeKind::Track);
+        assert_eq!(link.to_position, None);
+        assert_eq!(flatten_meta(&link.meta).get("distance_m").unwrap(), "12.5");
+    }
+
+    #[test]
+    fn test_enum_strings_round_trip() {
+        for severity in [
+            AlertSeverity::Info,
+            AlertSeverity::Medium,
+            AlertSeverity::Warning,
+            AlertSeverity::Critical,
+        ] {
+            assert_eq!(AlertSeverity::parse(severity.as_str()), Some(severity));
+        }
+        for status in [
+            AlertStatus::Active,
+            AlertStatus::Acknowledged,
+            AlertStatus::Resolved,
+        ] {
+            assert_eq!(AlertStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(AlertStatus::parse("active"), None);
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let payload = json!({
+            "version": CONTRACT_VERSION + 1,
+            "id": "alert-1",
+            "severity": "INFO",
+            "title": "t",
+            "ts_ms": 0,
+            "status": "ACTIVE"
+        })
+        .to_string();
+        assert!(decode::<AlertMessage>(&payload).is_err());
+    }
+}
@@ -0,0 +1,424 @@
+//! Redis Streams transport
+//!
+//! Producers append payloads with `XADD ... MAXLEN ~`; consumers read
+//! through a consumer group, acknowledge each entry after it is handled and
+//! resume from the group's last delivered id after a restart. Entries trimmed
+//! by `MAXLEN` before a consumer read them are reported as gaps.
+
+use redis::streams::{StreamMaxlen, StreamReadOptions, StreamReadReply};
+use redis::AsyncCommands;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Stream carrying track batches from harpy-ingest (see `track_wire`)
+pub const TRACKS_STREAM: &str = "tracks:updates";
+
+/// Entry field holding the payload
+pub const PAYLOAD_FIELD: &str = "payload";
+
+/// Approximate number of entries retained per stream
+pub const DEFAULT_MAXLEN: usize = 10_000;
+
+/// Entries read per `XREADGROUP`
+const READ_COUNT: usize = 64;
+/// How long a read blocks waiting for new entries
+const READ_BLOCK_MS: usize = 5_000;
+/// Pending entries idle this long are claimed from consumers that went away
+const CLAIM_MIN_IDLE_MS: u64 = 60_000;
+/// Upper bound on the reconnect backoff
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Stream retention from `REDIS_STREAM_MAXLEN`
+pub fn maxlen_from_env() -> usize {
+    std::env::var("REDIS_STREAM_MAXLEN")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|maxlen| *maxlen > 0)
+        .unwrap_or(DEFAULT_MAXLEN)
+}
+
+/// Append a payload to a stream, trimming it to roughly `maxlen` entries
+pub async fn publish<C: AsyncCommands>(
+    conn: &mut C,
+    stream: &str,
+    maxlen: usize,
+    payload: &[u8],
+) -> redis::RedisResult<String> {
+    conn.xadd_maxlen(
+        stream,
+        StreamMaxlen::Approx(maxlen),
+        "*",
+        &[(PAYLOAD_FIELD, payload)],
+    )
+    .await
+}
+
+/// This process's consumer name from `STREAM_CONSUMER` or `HOSTNAME`
+pub fn consumer_name() -> Option<String> {
+    std::env::var("STREAM_CONSUMER")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .filter(|name| !name.trim().is_empty())
+}
+
+/// Consumer group membership for one stream
+#[derive(Debug, Clone)]
+pub struct ConsumerConfig {
+    pub stream: String,
+    pub group: String,
+    pub consumer: String,
+}
+
+impl ConsumerConfig {
+    /// Build a config, taking the consumer name from `STREAM_CONSUMER` or `HOSTNAME`
+    ///
+    /// The name must be stable across restarts for a consumer to resume its
+    /// own pending entries.
+    pub fn new(stream: &str, group: &str) -> Self {
+        let consumer = consumer_name().unwrap_or_else(|| group.to_string());
+        Self {
+            stream: stream.to_string(),
+            group: group.to_string(),
+            consumer,
+        }
+    }
+}
+
+/// Counters for one stream consumer
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    pub stream: String,
+    /// Entries handled and acknowledged
+    pub processed: AtomicU64,
+    /// Connections re-established after an error
+    pub reconnects: AtomicU64,
+    /// Times entries were trimmed before this consumer read them
+    pub gaps: AtomicU64,
+    /// Total span of trimmed history, in milliseconds of stream time
+    pub gap_ms: AtomicU64,
+}
+
+impl StreamStats {
+    pub fn new(stream: &str) -> Self {
+        Self {
+            stream: stream.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Render the counters in Prometheus text format under `prefix`
+    pub fn render_prometheus(stats: &[&StreamStats], prefix: &str) -> String {
+        type Counter = fn(&StreamStats) -> u64;
+        let counters: [(&str, &str, Counter); 4] = [
+            (
+                "stream_entries_processed_total",
+                "Stream entries handled and acknowledged",
+                |s| s.processed.load(Ordering::Relaxed),
+            ),
+            (
+                "stream_reconnects_total",
+                "Stream consumer reconnects",
+                |s| s.reconnects.load(Ordering::Relaxed),
+            ),
+            (
+                "stream_gaps_total",
+                "Times stream entries were trimmed before being read",
+                |s| s.gaps.load(Ordering::Relaxed),
+            ),
+            (
+                "stream_gap_ms_total",
+                "Stream time lost to trimming in milliseconds",
+                |s| s.gap_ms.load(Ordering::Relaxed),
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, help, value) in counters {
+            out.push_str(&format!("# HELP {prefix}_{name} {help}\n"));
+            out.push_str(&format!("# TYPE {prefix}_{name} counter\n"));
+            for stat in stats {
+                out.push_str(&format!(
+                    "{prefix}_{name}{{stream=\"{}\"}} {}\n",
+                    stat.stream,
+                    value(stat)
+                ));
+            }
+        }
+        out
+    }
+
+    fn record_gap(&self, span_ms: u64) {
+        self.gaps.fetch_add(1, Ordering::Relaxed);
+        self.gap_ms.fetch_add(span_ms, Ordering::Relaxed);
+    }
+}
+
+/// Parse a stream entry id (`<ms>-<seq>`)
+fn parse_id(id: &str) -> Option<(u64, u64)> {
+    let (ms, seq) = id.split_once('-')?;
+    Some((ms.parse().ok()?, seq.parse().ok()?))
+}
+
+/// Span in ms of trimmed history newer than `last_read`, if any
+///
+/// `max_deleted` is the stream's `max-deleted-entry-id`; anything trimmed up to
+/// it and after `last_read` was never seen by the consumer.
+fn gap_since(last_read: &str, max_deleted: &str) -> Option<u64> {
+    let last_read = parse_id(last_read)?;
+    let max_deleted = parse_id(max_deleted)?;
+    (max_deleted > last_read).then(|| max_deleted.0.saturating_sub(last_read.0))
+}
+
+/// Consume a stream forever, reconnecting with backoff on any error
+///
+/// `handler` receives each entry's payload; the entry is acknowledged once the
+/// handler returns.
+pub async fn run_consumer<F, Fut>(
+    client: redis::Client,
+    config: ConsumerConfig,
+    stats: &StreamStats,
+    mut handler: F,
+) where
+    F: FnMut(Vec<u8>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        match consume(&client, &config, stats, &mut handler, &mut backoff).await {
+            Ok(()) => tracing::warn!("Stream {} consumer stopped, restarting", config.stream),
+            Err(e) => tracing::warn!(
+                "Stream {} consumer failed: {}, reconnecting in {:?}",
+                config.stream,
+                e,
+                backoff
+            ),
+        }
+        stats.reconnects.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn consume<F, Fut>(
+    client: &redis::Client,
+    config: &ConsumerConfig,
+    stats: &StreamStats,
+    handler: &mut F,
+    backoff: &mut Duration,
+) -> redis::RedisResult<()>
+where
+    F: FnMut(Vec<u8>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut conn = client.get_async_connection().await?;
+    ensure_group(&mut conn, config).await?;
+    claim_abandoned(&mut conn, config).await?;
+    *backoff = Duration::from_secs(1);
+
+    let mut last_read = group_last_delivered(&mut conn, config)
+        .await?
+        .unwrap_or_else(|| "0-0".to_string());
+    check_gap(&mut conn, config, stats, &mut last_read).await?;
+
+    tracing::info!(
+        "Consuming stream {} as {}/{} from {}",
+        config.stream,
+        config.group,
+        config.consumer,
+        last_read
+    );
+
+    // Entries delivered to this consumer before a restart but never acknowledged
+    let mut pending_cursor = Some("0".to_string());
+    loop {
+        let read_id = pending_cursor.as_deref().unwrap_or(">");
+        let mut options = StreamReadOptions::default()
+            .group(&config.group, &config.consumer)
+            .count(READ_COUNT);
+        if pending_cursor.is_none() {
+            options = options.block(READ_BLOCK_MS);
+        }
+        let reply: Option<StreamReadReply> = conn
+            .xread_options(&[&config.stream], &[read_id], &options)
+            .await?;
+        let entries: Vec<_> = reply
+            .map(|reply| reply.keys.into_iter().flat_map(|key| key.ids).collect())
+            .unwrap_or_default();
+
+        if pending_cursor.is_some() {
+            match entries.last() {
+                Some(entry) => pending_cursor = Some(entry.id.clone()),
+                None => {
+                    pending_cursor = None;
+                    continue;
+                }
+            }
+        }
+
+        let full_batch = entries.len() == READ_COUNT;
+        for entry in entries {
+            match entry.get::<Vec<u8>>(PAYLOAD_FIELD) {
+                Some(payload) => handler(payload).await,
+                // Pending entries trimmed before they were re-read come back empty
+                None => stats.record_gap(0),
+            }
+            conn.xack::<_, _, _, ()>(&config.stream, &config.group, &[&entry.id])
+                .await?;
+            stats.processed.fetch_add(1, Ordering::Relaxed);
+            if parse_id(&entry.id) > parse_id(&last_read) {
+                last_read = entry.id;
+            }
+        }
+
+        // A full batch means the consumer is behind and trimming may overtake it
+        if full_batch {
+            check_gap(&mut conn, config, stats, &mut last_read).await?;
+        }
+    }
+}
+
+/// Create the consumer group at the end of the stream unless it exists
+async fn ensure_group(
+    conn: &mut redis::aio::Connection,
+    config: &ConsumerConfig,
+) -> redis::RedisResult<()> {
+    match conn
+        .xgroup_create_mkstream::<_, _, _, ()>(&config.stream, &config.group, "$")
+        .await
+    {
+        Ok(()) => Ok(()),
+        Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Take over entries left pending by consumers that stopped
+async fn claim_abandoned(
+    conn: &mut redis::aio::Connection,
+    config: &ConsumerConfig,
+) -> redis::RedisResult<()> {
+    redis::cmd("XAUTOCLAIM")
+        .arg(&config.stream)
+        .arg(&config.group)
+        .arg(&config.consumer)
+        .arg(CLAIM_MIN_IDLE_MS)
+        .arg("0")
+        .arg("COUNT")
+        .arg(READ_COUNT * 16)
+        .arg("JUSTID")
+        .query_async::<_, redis::Value>(conn)
+        .await
+        .map(|_| ())
+}
+
+/// The group's `last-delivered-id` from `XINFO GROUPS`
+async fn group_last_delivered(
+    conn: &mut redis::aio::Connection,
+    config: &ConsumerConfig,
+) -> redis::RedisResult<Option<String>> {
+    let groups: Vec<Vec<redis::Value>> = redis::cmd("XINFO")
+        .arg("GROUPS")
+        .arg(&config.stream)
+        .query_async(conn)
+        .await?;
+    for group in groups {
+        if info_field(&group, "name").as_deref() == Some(config.group.as_str()) {
+            return Ok(info_field(&group, "last-delivered-id"));
+        }
+    }
+    Ok(None)
+}
+
+/// Record a gap if entries newer than `last_read` have been trimmed
+///
+/// Relies on `max-deleted-entry-id`, reported by Redis 7 and later.
+async fn check_gap(
+    conn: &mut redis::aio::Connection,
+    config: &ConsumerConfig,
+    stats: &StreamStats,
+    last_read: &mut String,
+) -> redis::RedisResult<()> {
+    let info: Vec<redis::Value> = redis::cmd("XINFO")
+        .arg("STREAM")
+        .arg(&config.stream)
+        .query_async(conn)
+        .await?;
+    let Some(max_deleted) = info_field(&info, "max-deleted-entry-id") else {
+        return Ok(());
+    };
+    if let Some(span_ms) = gap_since(last_read, &max_deleted) {
+        tracing::warn!(
+            "Stream {} trimmed entries up to {} before {} read them ({} ms after {})",
+            config.stream,
+            max_deleted,
+            config.group,
+            span_ms,
+            last_read
+        );
+        stats.record_gap(span_ms);
+        *last_read = max_deleted;
+    }
+    Ok(())
+}
+
+/// Look up a string field in a flat `XINFO` key/value reply
+fn info_field(reply: &[redis::Value], name: &str) -> Option<String> {
+    reply.chunks_exact(2).find_map(|pair| {
+        let key: String = redis::from_redis_value(&pair[0]).ok()?;
+        if key != name {
+            return None;
+        }
+        match &pair[1] {
+            redis::Value::Int(value) => Some(value.to_string()),
+            value => redis::from_redis_value(value).ok(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gap_since() {
+        assert_eq!(gap_since("1000-0", "1500-2"), Some(500));
+        assert_eq!(gap_since("1000-1", "1000-3"), Some(0));
+        assert_eq!(gap_since("1500-0", "1000-0"), None);
+        assert_eq!(gap_since("1000-0", "1000-0"), None);
+        // A stream that never trimmed reports 0-0
+        assert_eq!(gap_since("0-0", "0-0"), None);
+        assert_eq!(gap_since("garbage", "1-0"), None);
+    }
+
+    #[test]
+    fn test_info_field() {
+        let reply = vec![
+            redis::Value::Data(b"name".to_vec()),
+            redis::Value::Data(b"harpy-relay".to_vec()),
+            redis::Value::Data(b"pending".to_vec()),
+            redis::Value::Int(3),
+            redis::Value::Data(b"last-delivered-id".to_vec()),
+            redis::Value::Data(b"1700-4".to_vec()),
+        ];
+        assert_eq!(info_field(&reply, "name").as_deref(), Some("harpy-relay"));
+        assert_eq!(info_field(&reply, "pending").as_deref(), Some("3"));
+        assert_eq!(
+            info_field(&reply, "last-delivered-id").as_deref(),
+            Some("1700-4")
+        );
+        assert_eq!(info_field(&reply, "missing"), None);
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let stats = StreamStats::new(TRACKS_STREAM);
+        stats.processed.store(7, Ordering::Relaxed);
+        stats.record_gap(250);
+        let text = StreamStats::render_prometheus(&[&stats], "harpy_relay");
+        assert!(text
+            .contains("harpy_relay_stream_entries_processed_total{stream=\"tracks:updates\"} 7\n"));
+        assert!(text.contains("harpy_relay_stream_gaps_total{stream=\"tracks:updates\"} 1\n"));
+        assert!(text.contains("harpy_relay_stream_gap_ms_total{stream=\"tracks:updates\"} 250\n"));
+    }
+}
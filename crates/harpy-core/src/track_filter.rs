@@ -0,0 +1,241 @@
+//! Track Filters
+//!
+//! Provider, altitude, speed and meta predicates applied on top of a
+//! subscription's viewport and layers. Node and relay validate and evaluate
+//! filters here for live fanout; the relay translates the same filter to SQL
+//! for playback queries.
+
+use harpy_proto::harpy::v1::{MetaOp, MetaPredicate, TrackDelta, TrackFilter};
+
+/// Maximum number of meta predicates per filter
+const MAX_META_PREDICATES: usize = 16;
+
+/// Reject filters that cannot be evaluated consistently
+pub fn validate(filter: &TrackFilter) -> Result<(), String> {
+    if let (Some(min), Some(max)) = (filter.min_alt, filter.max_alt) {
+        if min > max {
+            return Err(format!("min_alt {} exceeds max_alt {}", min, max));
+        }
+    }
+    if let (Some(min), Some(max)) = (filter.min_speed, filter.max_speed) {
+        if min > max {
+            return Err(format!("min_speed {} exceeds max_speed {}", min, max));
+        }
+    }
+    if filter.meta.len() > MAX_META_PREDICATES {
+        return Err(format!(
+            "too many meta predicates (max {})",
+            MAX_META_PREDICATES
+        ));
+    }
+
+    for predicate in &filter.meta {
+        if predicate.key.trim().is_empty() {
+            return Err("meta predicate key is empty".to_string());
+        }
+        match MetaOp::try_from(predicate.op).unwrap_or(MetaOp::Unspecified) {
+            MetaOp::Unspecified => {
+                return Err(format!("meta predicate on {} has no op", predicate.key));
+            }
+            MetaOp::Exists => {}
+            _ if predicate.values.is_empty() => {
+                return Err(format!("meta predicate on {} has no values", predicate.key));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Whether a track satisfies every predicate of the filter
+pub fn passes(filter: &TrackFilter, track: &TrackDelta) -> bool {
+    if !filter.provider_allow.is_empty() && !filter.provider_allow.contains(&track.provider_id) {
+        return false;
+    }
+    if filter.provider_deny.contains(&track.provider_id) {
+        return false;
+    }
+
+    if filter.min_alt.is_some() || filter.max_alt.is_some() {
+        let Some(alt) = track.position.as_ref().map(|p| p.alt) else {
+            return false;
+        };
+        if !within(alt, filter.min_alt, filter.max_alt) {
+            return false;
+        }
+    }
+    if !within(track.speed, filter.min_speed, filter.max_speed) {
+        return false;
+    }
+
+    filter
+        .meta
+        .iter()
+        .all(|predicate| meta_passes(predicate, track.meta.get(predicate.key.trim())))
+}
+
+fn within(value: f64, min: Option<f64>, max: Option<f64>) -> bool {
+    min.map_or(true, |min| value >= min) && max.map_or(true, |max| value <= max)
+}
+
+fn meta_passes(predicate: &MetaPredicate, value: Option<&String>) -> bool {
+    let value = value.map(|v| v.trim().to_lowercase());
+    let mut values = predicate.values.iter().map(|v| v.trim().to_lowercase());
+
+    match MetaOp::try_from(predicate.op).unwrap_or(MetaOp::Unspecified) {
+        MetaOp::Exists => value.is_some(),
+        MetaOp::Equals => value.is_some_and(|value| values.next() == Some(value)),
+        MetaOp::In => value.is_some_and(|value| values.any(|v| v == value)),
+        MetaOp::NotIn => value.map_or(true, |value| !values.any(|v| v == value)),
+        MetaOp::Prefix => value.is_some_and(|value| values.any(|v| value.starts_with(&v))),
+        MetaOp::Unspecified => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harpy_proto::harpy::v1::Position;
+    use std::collections::HashMap;
+
+    fn track(provider_id: &str, alt: f64, speed: f64, meta: &[(&str, &str)]) -> TrackDelta {
+        TrackDelta {
+            id: "t-1".to_string(),
+            kind: 1,
+            position: Some(Position {
+                lat: 0.0,
+                lon: 0.0,
+                alt,
+            }),
+            speed,
+            provider_id: provider_id.to_string(),
+            meta: meta
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    fn predicate(key: &str, op: MetaOp, values: &[&str]) -> MetaPredicate {
+        MetaPredicate {
+            key: key.to_string(),
+            op: op as i32,
+            values: values.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_provider_allow_and_deny() {
+        let filter = TrackFilter {
+            provider_allow: vec!["adsb-opensky".to_string(), "adsb-mock".to_string()],
+            provider_deny: vec!["adsb-mock".to_string()],
+            ..Default::default()
+        };
+        assert!(passes(&filter, &track("adsb-opensky", 0.0, 0.0, &[])));
+        assert!(!passes(&filter, &track("adsb-mock", 0.0, 0.0, &[])));
+        assert!(!passes(&filter, &track("ais-mock", 0.0, 0.0, &[])));
+    }
+
+    #[test]
+    fn test_altitude_and_speed_bands() {
+        let filter = TrackFilter {
+            min_alt: Some(1000.0),
+            max_alt: Some(12000.0),
+            max_speed: Some(300.0),
+            ..Default::default()
+        };
+        assert!(passes(&filter, &track("p", 5000.0, 200.0, &[])));
+        assert!(!passes(&filter, &track("p", 500.0, 200.0, &[])));
+        assert!(!passes(&filter, &track("p", 5000.0, 350.0, &[])));
+
+        let mut no_position = track("p", 5000.0, 200.0, &[]);
+        no_position.position = None;
+        assert!(!passes(&filter, &no_position));
+    }
+
+    #[test]
+    fn test_meta_predicates() {
+        let squawk = TrackFilter {
+            meta: vec![predicate("squawk", MetaOp::In, &["7500", "7600", "7700"])],
+            ..Default::default()
+        };
+        assert!(passes(
+            &squawk,
+            &track("p", 0.0, 0.0, &[("squawk", "7700")])
+        ));
+        assert!(!passes(
+            &squawk,
+            &track("p", 0.0, 0.0, &[("squawk", "1200")])
+        ));
+        assert!(!passes(&squawk, &track("p", 0.0, 0.0, &[])));
+
+        let callsign = TrackFilter {
+            meta: vec![predicate("callsign", MetaOp::Prefix, &["ual"])],
+            ..Default::default()
+        };
+        assert!(passes(
+            &callsign,
+            &track("p", 0.0, 0.0, &[("callsign", "UAL123 ")])
+        ));
+        assert!(!passes(
+            &callsign,
+            &track("p", 0.0, 0.0, &[("callsign", "DAL1")])
+        ));
+
+        let camera = TrackFilter {
+            meta: vec![
+                predicate("sensor_type", MetaOp::Equals, &["realtime_camera"]),
+                predicate("status", MetaOp::NotIn, &["offline"]),
+                predicate("stream_url", MetaOp::Exists, &[]),
+            ],
+            ..Default::default()
+        };
+        assert!(passes(
+            &camera,
+            &track(
+                "p",
+                0.0,
+                0.0,
+                &[
+                    ("sensor_type", "realtime_camera"),
+                    ("stream_url", "rtsp://x")
+                ]
+            )
+        ));
+        assert!(!passes(
+            &camera,
+            &track(
+                "p",
+                0.0,
+                0.0,
+                &[
+                    ("sensor_type", "realtime_camera"),
+                    ("stream_url", "rtsp://x"),
+                    ("status", "OFFLINE")
+                ]
+            )
+        ));
+    }
+
+    #[test]
+    fn test_validate_rejects_inconsistent_filters() {
+        assert!(validate(&TrackFilter::default()).is_ok());
+        assert!(validate(&TrackFilter {
+            min_alt: Some(10.0),
+            max_alt: Some(5.0),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate(&TrackFilter {
+            meta: vec![predicate("squawk", MetaOp::In, &[])],
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate(&TrackFilter {
+            meta: vec![predicate("squawk", MetaOp::Unspecified, &["7700"])],
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
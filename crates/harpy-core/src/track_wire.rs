@@ -0,0 +1,224 @@
+//! Track batch wire format for Redis
+//!
+//! Track batches travel on `TRACKS_STREAM` as protobuf `Envelope` bytes
+//! carrying a `TrackDeltaBatch`. Publishers can switch to JSON with
+//! `REDIS_WIRE_FORMAT=json` to make entries readable in `redis-cli`;
+//! consumers accept either encoding.
+
+use crate::error::{HarpyError, Result};
+use harpy_proto::harpy::v1::{envelope::Payload, Envelope, Position, TrackDelta, TrackDeltaBatch};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Schema version stamped on every envelope published to Redis
+pub const SCHEMA_VERSION: &str = "1.0.0";
+
+/// Encoding used when publishing track batches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireFormat {
+    #[default]
+    Protobuf,
+    Json,
+}
+
+impl WireFormat {
+    /// Format selected by `REDIS_WIRE_FORMAT` (`protobuf` or `json`)
+    pub fn from_env() -> Self {
+        match std::env::var("REDIS_WIRE_FORMAT").as_deref() {
+            Ok("json") => Self::Json,
+            _ => Self::Protobuf,
+        }
+    }
+}
+
+/// JSON form of a TrackDelta, used for the debug wire format and the
+/// `track:{id}` cache keys
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackJson {
+    pub id: String,
+    pub kind: i32,
+    pub position: Option<PositionJson>,
+    pub heading: f64,
+    pub speed: f64,
+    pub ts_ms: u64,
+    pub provider_id: String,
+    #[serde(default)]
+    pub meta: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PositionJson {
+    pub lat: f64,
+    pub lon: f64,
+    pub alt: f64,
+}
+
+impl From<&TrackDelta> for TrackJson {
+    fn from(track: &TrackDelta) -> Self {
+        Self {
+            id: track.id.clone(),
+            kind: track.kind,
+            position: track.position.as_ref().map(|p| PositionJson {
+                lat: p.lat,
+                lon: p.lon,
+                alt: p.alt,
+            }),
+            heading: track.heading,
+            speed: track.speed,
+            ts_ms: track.ts_ms,
+            provider_id: track.provider_id.clone(),
+            meta: track.meta.clone(),
+        }
+    }
+}
+
+impl From<TrackJson> for TrackDelta {
+    fn from(json: TrackJson) -> Self {
+        Self {
+            id: json.id,
+            kind: json.kind,
+            position: json.position.map(|p| Position {
+                lat: p.lat,
+                lon: p.lon,
+                alt: p.alt,
+            }),
+            heading: json.heading,
+            speed: json.speed,
+            ts_ms: json.ts_ms,
+            provider_id: json.provider_id,
+            meta: json.meta,
+        }
+    }
+}
+
+/// Encode a track batch for publishing
+pub fn encode_track_batch(
+    tracks: &[TrackDelta],
+    server_ts_ms: u64,
+    format: WireFormat,
+) -> Result<Vec<u8>> {
+    match format {
+        WireFormat::Protobuf => Ok(Envelope {
+            schema_version: SCHEMA_VERSION.to_string(),
+            server_ts_ms,
+            seq: 0,
+            payload: Some(Payload::TrackDeltaBatch(TrackDeltaBatch {
+                deltas: tracks.to_vec(),
+                ..Default::default()
+            })),
+        }
+        .encode_to_vec()),
+        WireFormat::Json => {
+            let tracks: Vec<TrackJson> = tracks.iter().map(TrackJson::from).collect();
+            serde_json::to_vec(&tracks).map_err(|e| HarpyError::Internal(e.to_string()))
+        }
+    }
+}
+
+/// Decode a track batch published in either wire format
+///
+/// Protobuf envelopes must carry a `TrackDeltaBatch` with a schema version of
+/// the same major version as `SCHEMA_VERSION`.
+pub fn decode_track_batch(bytes: &[u8]) -> Result<Vec<TrackDelta>> {
+    if is_json(bytes) {
+        let tracks: Vec<TrackJson> =
+            serde_json::from_slice(bytes).map_err(|e| HarpyError::Protobuf(e.to_string()))?;
+        return Ok(tracks.into_iter().map(TrackDelta::from).collect());
+    }
+
+    match decode_envelope(bytes)?.payload {
+        Some(Payload::TrackDeltaBatch(batch)) => Ok(batch.deltas),
+        _ => Err(HarpyError::Protobuf(
+            "envelope does not carry a TrackDeltaBatch".to_string(),
+        )),
+    }
+}
+
+/// Decode a protobuf envelope, rejecting other major schema versions
+pub(crate) fn decode_envelope(bytes: &[u8]) -> Result<Envelope> {
+    let envelope = Envelope::decode(bytes).map_err(|e| HarpyError::Protobuf(e.to_string()))?;
+    if major_version(&envelope.schema_version) != major_version(SCHEMA_VERSION) {
+        return Err(HarpyError::Protobuf(format!(
+            "unsupported schema version {:?} (expected {})",
+            envelope.schema_version, SCHEMA_VERSION
+        )));
+    }
+    Ok(envelope)
+}
+
+/// A JSON batch is an array; a protobuf envelope never starts with `[`
+fn is_json(bytes: &[u8]) -> bool {
+    bytes
+        .iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .is_some_and(|b| *b == b'[')
+}
+
+fn major_version(version: &str) -> Option<&str> {
+    version.split('.').next().filter(|major| !major.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(id: &str) -> TrackDelta {
+        TrackDelta {
+            id: id.to_string(),
+            kind: 1,
+            position: Some(Position {
+                lat: 51.5,
+                lon: -0.1,
+                alt: 1200.0,
+            }),
+            heading: 90.0,
+            speed: 210.0,
+            ts_ms: 1_700_000_000_000,
+            provider_id: "adsb-mock".to_string(),
+            meta: HashMap::from([("callsign".to_string(), "MCK001".to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_both_formats() {
+        let tracks = vec![track("a"), track("b")];
+        for format in [WireFormat::Protobuf, WireFormat::Json] {
+            let bytes = encode_track_batch(&tracks, 5, format).unwrap();
+            assert_eq!(decode_track_batch(&bytes).unwrap(), tracks);
+        }
+    }
+
+    #[test]
+    fn test_legacy_json_batch_decodes() {
+        let payload = br#"[{"id":"a","kind":2,"position":null,"heading":0.0,"speed":0.0,"ts_ms":1,"provider_id":"tle"}]"#;
+        let tracks = decode_track_batch(payload).unwrap();
+        assert_eq!(tracks[0].id, "a");
+        assert!(tracks[0].meta.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_other_major_version_and_payload() {
+        let newer = Envelope {
+            schema_version: "2.0.0".to_string(),
+            server_ts_ms: 0,
+            seq: 0,
+            payload: Some(Payload::TrackDeltaBatch(TrackDeltaBatch::default())),
+        };
+        assert!(decode_track_batch(&newer.encode_to_vec()).is_err());
+
+        let minor = Envelope {
+            schema_version: "1.4.0".to_string(),
+            ..newer.clone()
+        };
+        assert!(decode_track_batch(&minor.encode_to_vec()).is_ok());
+
+        let wrong_payload = Envelope {
+            schema_version: SCHEMA_VERSION.to_string(),
+            server_ts_ms: 0,
+            seq: 0,
+            payload: None,
+        };
+        assert!(decode_track_batch(&wrong_payload.encode_to_vec()).is_err());
+    }
+}
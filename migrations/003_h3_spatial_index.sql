@@ -0,0 +1,17 @@
+-- HARPY: H3 spatial index for tracks and track history
+-- Cells are computed by harpy-ingest at H3_RESOLUTION; Postgres has no H3
+-- functions, so existing rows are backfilled by harpy-ingest at startup.
+
+ALTER TABLE track_deltas ADD COLUMN IF NOT EXISTS h3_index BIGINT;
+
+-- Viewport queries scan a few h3_index ranges within a time window
+CREATE INDEX IF NOT EXISTS idx_track_deltas_h3_ts ON track_deltas(h3_index, ts_ms);
+
+-- Backfill progress per table; a table is done once its resolution matches H3_RESOLUTION
+CREATE TABLE IF NOT EXISTS h3_backfill_state (
+    table_name VARCHAR(64) PRIMARY KEY,
+    resolution SMALLINT NOT NULL,
+    last_id TEXT,
+    completed_at TIMESTAMP WITH TIME ZONE,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
@@ -0,0 +1,75 @@
+-- HARPY: Daily time partitioning for track_deltas
+-- Partitions are ranges of ts_ms (UTC days). harpy-ingest's partition manager
+-- creates upcoming days, drops days past retention and downsamples old data.
+
+DO $$
+DECLARE
+    legacy_end BIGINT;
+BEGIN
+    -- Already partitioned: nothing to convert
+    IF EXISTS (
+        SELECT 1 FROM pg_partitioned_table WHERE partrelid = 'track_deltas'::regclass
+    ) THEN
+        RETURN;
+    END IF;
+
+    ALTER TABLE track_deltas RENAME TO track_deltas_legacy;
+    ALTER INDEX IF EXISTS track_deltas_pkey RENAME TO track_deltas_legacy_pkey;
+    ALTER INDEX IF EXISTS idx_track_deltas_track_id RENAME TO idx_track_deltas_legacy_track_id;
+    ALTER INDEX IF EXISTS idx_track_deltas_ts_ms RENAME TO idx_track_deltas_legacy_ts_ms;
+    ALTER INDEX IF EXISTS idx_track_deltas_h3_ts RENAME TO idx_track_deltas_legacy_h3_ts;
+    ALTER INDEX IF EXISTS idx_track_deltas_snapshot RENAME TO idx_track_deltas_legacy_snapshot;
+
+    -- The partition key must be part of the primary key
+    CREATE TABLE track_deltas (
+        id BIGINT NOT NULL DEFAULT nextval('track_deltas_id_seq'),
+        track_id VARCHAR(255) NOT NULL,
+        lat DOUBLE PRECISION NOT NULL,
+        lon DOUBLE PRECISION NOT NULL,
+        alt DOUBLE PRECISION NOT NULL,
+        heading DOUBLE PRECISION,
+        speed DOUBLE PRECISION,
+        ts_ms BIGINT NOT NULL,
+        provider_id VARCHAR(255) NOT NULL,
+        meta JSONB,
+        created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+        snapshot_id VARCHAR(255) REFERENCES snapshots(id) ON DELETE SET NULL,
+        h3_index BIGINT,
+        PRIMARY KEY (id, ts_ms)
+    ) PARTITION BY RANGE (ts_ms);
+
+    ALTER SEQUENCE track_deltas_id_seq OWNED BY track_deltas.id;
+
+    CREATE INDEX idx_track_deltas_track_id ON track_deltas(track_id);
+    CREATE INDEX idx_track_deltas_ts_ms ON track_deltas(ts_ms DESC);
+    CREATE INDEX idx_track_deltas_h3_ts ON track_deltas(h3_index, ts_ms);
+    CREATE INDEX idx_track_deltas_snapshot ON track_deltas(snapshot_id);
+
+    -- Catches rows outside every daily partition (clock skew, late backfills);
+    -- the partition manager moves them out when it creates their day.
+    CREATE TABLE track_deltas_default PARTITION OF track_deltas DEFAULT;
+
+    IF NOT EXISTS (SELECT 1 FROM track_deltas_legacy) THEN
+        DROP TABLE track_deltas_legacy;
+    ELSE
+        -- Existing history becomes one partition ending at tomorrow (UTC), or the
+        -- day after its newest row; it is dropped like any other partition once
+        -- all of it is past retention.
+        SELECT GREATEST(
+            (EXTRACT(EPOCH FROM date_trunc('day', NOW() AT TIME ZONE 'UTC') + INTERVAL '1 day') * 1000)::BIGINT,
+            (MAX(ts_ms) / 86400000 + 1) * 86400000
+        ) INTO legacy_end FROM track_deltas_legacy;
+        EXECUTE format(
+            'ALTER TABLE track_deltas ATTACH PARTITION track_deltas_legacy FOR VALUES FROM (MINVALUE) TO (%s)',
+            legacy_end
+        );
+    END IF;
+END $$;
+
+-- Downsampling progress: everything before compacted_through_ts_ms holds at
+-- most one position per track per minute
+CREATE TABLE IF NOT EXISTS track_delta_downsample_state (
+    id SMALLINT PRIMARY KEY DEFAULT 1 CHECK (id = 1),
+    compacted_through_ts_ms BIGINT NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
@@ -0,0 +1,22 @@
+-- HARPY: Quarantine for provider records rejected by ingest validation
+-- Rows are kept as received (coordinates may be NaN) with the reason they
+-- failed, so bad upstream data can be inspected without reaching the stores.
+
+CREATE TABLE IF NOT EXISTS quarantine (
+    id BIGSERIAL PRIMARY KEY,
+    provider_id VARCHAR(255) NOT NULL,
+    track_id VARCHAR(255) NOT NULL,
+    reason_code VARCHAR(64) NOT NULL,
+    detail TEXT,
+    kind VARCHAR(50),
+    lat DOUBLE PRECISION,
+    lon DOUBLE PRECISION,
+    alt DOUBLE PRECISION,
+    ts_ms BIGINT,
+    record JSONB NOT NULL DEFAULT '{}',
+    quarantined_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_quarantine_provider_time ON quarantine(provider_id, quarantined_at DESC);
+CREATE INDEX IF NOT EXISTS idx_quarantine_reason ON quarantine(reason_code, quarantined_at DESC);
+CREATE INDEX IF NOT EXISTS idx_quarantine_track ON quarantine(track_id);
@@ -0,0 +1,35 @@
+-- HARPY: Open-data catalog
+-- Dataset listings from AidData, the GEE community catalog and Harvard
+-- Dataverse with their published coverage. The bounding box columns are all
+-- NULL when a source publishes no coverage; west_lon > east_lon crosses the
+-- antimeridian. `search` is written by ingest from title, tags, publisher and
+-- description (tags cannot feed a generated column).
+
+CREATE TABLE IF NOT EXISTS catalog_datasets (
+    id VARCHAR(512) PRIMARY KEY,
+    source VARCHAR(64) NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    publisher TEXT NOT NULL DEFAULT '',
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    dataset_url TEXT,
+    license TEXT,
+    attributes JSONB NOT NULL DEFAULT '{}',
+    west_lon DOUBLE PRECISION,
+    south_lat DOUBLE PRECISION,
+    east_lon DOUBLE PRECISION,
+    north_lat DOUBLE PRECISION,
+    published_at TIMESTAMPTZ,
+    search TSVECTOR NOT NULL DEFAULT ''::tsvector,
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT catalog_bbox_complete CHECK (
+        (west_lon IS NULL AND south_lat IS NULL AND east_lon IS NULL AND north_lat IS NULL)
+        OR (west_lon IS NOT NULL AND south_lat IS NOT NULL AND east_lon IS NOT NULL AND north_lat IS NOT NULL)
+    )
+);
+
+CREATE INDEX IF NOT EXISTS idx_catalog_source ON catalog_datasets(source);
+CREATE INDEX IF NOT EXISTS idx_catalog_search ON catalog_datasets USING GIN(search);
+CREATE INDEX IF NOT EXISTS idx_catalog_lat ON catalog_datasets(south_lat, north_lat)
+    WHERE south_lat IS NOT NULL;
@@ -12,6 +12,8 @@ use axum::{
 };
 use dashmap::DashMap;
 use h3o::{LatLng, Resolution};
+use harpy_core::redis_contract::{AlertSeverity, AlertStatus, EvidenceTrack, GeoPoint, NodeKind};
+use harpy_core::streams::{StreamStats, TRACKS_STREAM};
 use harpy_core::types::HealthResponse;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -38,6 +40,7 @@ struct AppState {
     h3_resolution: u8,
     dedup_ttl_ms: i64,
     dedup_cache: Arc<DashMap<String, i64>>,
+    stream_stats: Arc<StreamStats>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -59,31 +62,33 @@ struct TrackObservation {
     ts_ms: i64,
     provider_id: String,
     #[serde(default)]
-    #[allow(dead_code)]
     meta: Value,
 }
 
 #[derive(Debug, Serialize, Clone)]
 struct AlertUpsertRecord {
     id: String,
-    severity: String,
+    severity: AlertSeverity,
     title: String,
     description: String,
     ts_ms: i64,
-    status: String,
+    status: AlertStatus,
     evidence_link_ids: Vec<String>,
+    evidence_tracks: Vec<EvidenceTrack>,
     meta: Value,
 }
 
 #[derive(Debug, Serialize, Clone)]
 struct LinkUpsertRecord {
     id: String,
-    from_type: String,
+    from_type: NodeKind,
     from_id: String,
     rel: String,
-    to_type: String,
+    to_type: NodeKind,
     to_id: String,
     ts_ms: i64,
+    from_position: Option<GeoPoint>,
+    to_position: Option<GeoPoint>,
     meta: Value,
 }
 
@@ -184,6 +189,7 @@ async fn main() -> anyhow::Result<()> {
         h3_resolution,
         dedup_ttl_ms,
         dedup_cache: Arc::new(DashMap::new()),
+        stream_stats: Arc::new(StreamStats::new(TRACKS_STREAM)),
     };
 
     // Clone for Redis consumer before moving into router
@@ -192,6 +198,7 @@ async fn main() -> anyhow::Result<()> {
 
     let app = Router::new()
         .route("/health", get(health_check))
+        .route("/metrics", get(metrics_handler))
         .route("/fusion/config", get(fusion_config))
         .route("/fusion/rules", get(rules_status))
         .route("/fusion/ingest", post(fusion_ingest))
@@ -235,6 +242,10 @@ async fn health_check() -> Json<HealthResponse> {
     })
 }
 
+async fn metrics_handler(State(state): State<AppState>) -> String {
+    StreamStats::render_prometheus(&[&state.stream_stats], "harpy_fusion")
+}
+
 async fn fusion_config(State(state): State<AppState>) -> Json<FusionConfigResponse> {
     Json(FusionConfigResponse {
         h3_resolution: state.h3_resolution,
@@ -356,6 +367,27 @@ async fn persist_fusion_outputs(
 ) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
 
+    // Links first: alert_evidence references them
+    for link in links {
+        sqlx::query(
+            "INSERT INTO links (id, from_type, from_id, rel, to_type, to_id, ts_ms, meta)\
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)\
+             ON CONFLICT (id) DO UPDATE\
+             SET ts_ms = EXCLUDED.ts_ms,\
+                 meta = EXCLUDED.meta",
+        )
+        .bind(&link.id)
+        .bind(link.from_type.as_str())
+        .bind(&link.from_id)
+        .bind(&link.rel)
+        .bind(link.to_type.as_str())
+        .bind(&link.to_id)
+        .bind(link.ts_ms)
+        .bind(&link.meta)
+        .execute(tx.as_mut())
+        .await?;
+    }
+
     for alert in alerts {
         sqlx::query(
             "INSERT INTO alerts (id, severity, title, description, ts_ms, status, meta)\
@@ -370,11 +402,11 @@ async fn persist_fusion_outputs(
                  updated_at = NOW()",
         )
         .bind(&alert.id)
-        .bind(&alert.severity)
+        .bind(alert.severity.as_str())
         .bind(&alert.title)
         .bind(&alert.description)
         .bind(alert.ts_ms)
-        .bind(&alert.status)
+        .bind(alert.status.as_str())
         .bind(&alert.meta)
         .execute(tx.as_mut())
         .await?;
@@ -405,26 +437,6 @@ async fn persist_fusion_outputs(
         .await?;
     }
 
-    for link in links {
-        sqlx::query(
-            "INSERT INTO links (id, from_type, from_id, rel, to_type, to_id, ts_ms, meta)\
-             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)\
-             ON CONFLICT (id) DO UPDATE\
-             SET ts_ms = EXCLUDED.ts_ms,\
-                 meta = EXCLUDED.meta",
-        )
-        .bind(&link.id)
-        .bind(&link.from_type)
-        .bind(&link.from_id)
-        .bind(&link.rel)
-        .bind(&link.to_type)
-        .bind(&link.to_id)
-        .bind(link.ts_ms)
-        .bind(&link.meta)
-        .execute(tx.as_mut())
-        .await?;
-    }
-
     tx.commit().await?;
     Ok(())
 }
@@ -1,38 +1,19 @@
 //! Redis Consumer for Track Processing
 //!
-//! Consumes tracks from Redis pub/sub and processes them through the fusion engine.
+//! Consumes tracks from the Redis tracks stream and processes them through the
+//! fusion engine.
 
 use crate::rules::RuleEngine;
 use crate::{
     persist_fusion_outputs, AlertUpsertRecord, AppState, LinkUpsertRecord, TrackObservation,
 };
-use futures_util::StreamExt;
-use serde::Deserialize;
+use harpy_core::streams::{self, ConsumerConfig, TRACKS_STREAM};
+use harpy_core::track_wire;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-/// JSON representation of TrackDelta from Redis
-#[derive(Debug, Deserialize)]
-struct TrackDeltaJson {
-    id: String,
-    kind: i32,
-    position: Option<PositionJson>,
-    heading: f64,
-    speed: f64,
-    ts_ms: u64,
-    provider_id: String,
-    #[serde(default)]
-    meta: std::collections::HashMap<String, String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct PositionJson {
-    lat: f64,
-    lon: f64,
-    alt: f64,
-}
-
 pub struct RedisConsumer {
+    client: redis::Client,
     state: AppState,
     rule_engine: Arc<RuleEngine>,
     track_buffer: Arc<Mutex<Vec<TrackObservation>>>,
@@ -46,9 +27,9 @@ impl RedisConsumer {
         rule_engine: Arc<RuleEngine>,
     ) -> anyhow::Result<Self> {
         let client = redis::Client::open(redis_url)?;
-        let _connection = redis::aio::ConnectionManager::new(client).await?;
 
         Ok(Self {
+            client,
             state,
             rule_engine,
             track_buffer: Arc::new(Mutex::new(Vec::new())),
@@ -89,54 +70,25 @@ impl RedisConsumer {
             }
         });
 
-        // Create a new connection for pub/sub
-        let redis_url =
-            std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
-        let client = redis::Client::open(redis_url)?;
-
-        let mut pubsub_conn = loop {
-            match client.get_async_connection().await {
-                Ok(conn) => break conn.into_pubsub(),
-                Err(e) => {
-                    tracing::warn!("Failed to create pubsub connection: {}, retrying...", e);
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                    continue;
+        let group =
+            std::env::var("FUSION_STREAM_GROUP").unwrap_or_else(|_| "harpy-fusion".to_string());
+        streams::run_consumer(
+            self.client.clone(),
+            ConsumerConfig::new(TRACKS_STREAM, &group),
+            &self.state.stream_stats,
+            |payload| async move {
+                if let Err(e) = self.handle_track_batch(payload).await {
+                    tracing::warn!("Failed to handle track batch: {}", e);
                 }
-            }
-        };
-
-        pubsub_conn.subscribe("tracks:updates").await?;
-        tracing::info!("Subscribed to tracks:updates channel");
+            },
+        )
+        .await;
 
-        let mut msg_stream = pubsub_conn.on_message();
-
-        loop {
-            match msg_stream.next().await {
-                Some(msg) => {
-                    let payload: String = match msg.get_payload::<String>() {
-                        Ok(p) => p,
-                        Err(e) => {
-                            tracing::warn!("Failed to get payload: {}", e);
-                            continue;
-                        }
-                    };
-
-                    if let Err(e) = self.handle_track_batch(payload).await {
-                        tracing::warn!("Failed to handle track batch: {}", e);
-                    }
-                }
-                None => {
-                    tracing::warn!("Redis pub/sub stream closed, reconnecting...");
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                }
-            }
-        }
+        Ok(())
     }
 
-    async fn handle_track_batch(&self, payload: String) -> anyhow::Result<()> {
-        let tracks_json: Vec<TrackDeltaJson> = serde_json::from_str(&payload)?;
-
-        let tracks: Vec<TrackObservation> = tracks_json
+    async fn handle_track_batch(&self, payload: Vec<u8>) -> anyhow::Result<()> {
+        let tracks: Vec<TrackObservation> = track_wire::decode_track_batch(&payload)?
             .into_iter()
             .map(|t| TrackObservation {
                 id: t.id,
@@ -1,72 +1,67 @@
 //! Redis Publisher for Fusion Alerts
 //!
-//! Publishes AlertUpsert and LinkUpsert messages to Redis channels
-//! for relay fanout to WebSocket clients.
+//! Appends AlertUpsert and LinkUpsert messages to Redis streams
+//! for relay fanout to WebSocket clients, using the shared
+//! `harpy_core::redis_contract` message types.
 
 use crate::{AlertUpsertRecord, LinkUpsertRecord};
+use harpy_core::redis_contract::{
+    AlertMessage, LinkMessage, ALERTS_STREAM, CONTRACT_VERSION, LINKS_STREAM,
+};
+use harpy_core::streams;
 use redis::AsyncCommands;
 use serde_json::json;
 
 #[derive(Clone)]
 pub struct RedisPublisher {
     client: redis::aio::ConnectionManager,
+    stream_maxlen: usize,
 }
 
 impl RedisPublisher {
     pub async fn new(redis_url: &str) -> anyhow::Result<Self> {
         let client = redis::Client::open(redis_url)?;
         let connection = redis::aio::ConnectionManager::new(client).await?;
-        Ok(Self { client: connection })
+        Ok(Self {
+            client: connection,
+            stream_maxlen: streams::maxlen_from_env(),
+        })
     }
 
-    /// Publish an alert to Redis pub/sub channel
+    /// Append an alert to the alerts Redis stream
     pub async fn publish_alert(&self, alert: &AlertUpsertRecord) -> anyhow::Result<()> {
-        let channel = "alerts:updates";
-
-        let payload = json!({
-            "id": alert.id,
-            "severity": alert.severity,
-            "title": alert.title,
-            "description": alert.description,
-            "ts_ms": alert.ts_ms,
-            "status": alert.status,
-            "evidence_link_ids": alert.evidence_link_ids,
-            "meta": alert.meta,
-        });
+        let payload = serde_json::to_string(&alert_message(alert))?;
 
         let mut client = self.client.clone();
-        client
-            .publish::<_, _, ()>(channel, payload.to_string())
-            .await?;
+        streams::publish(
+            &mut client,
+            ALERTS_STREAM,
+            self.stream_maxlen,
+            payload.as_bytes(),
+        )
+        .await?;
 
         tracing::debug!(
             alert_id = %alert.id,
-            severity = %alert.severity,
+            severity = %alert.severity.as_str(),
             "Published alert to Redis"
         );
 
         Ok(())
     }
 
-    /// Publish a link to Redis pub/sub channel
+    /// Append a link to the links Redis stream
     pub async fn publish_link(&self, link: &LinkUpsertRecord) -> anyhow::Result<()> {
-        let channel = "links:updates";
-
-        let payload = json!({
-            "id": link.id,
-            "from_type": link.from_type,
-            "from_id": link.from_id,
-            "rel": link.rel,
-            "to_type": link.to_type,
-            "to_id": link.to_id,
-            "ts_ms": link.ts_ms,
-            "meta": link.meta,
-        });
+        let payload = serde_json::to_string(&link_message(link))?;
 
         let mut client = self.client.clone();
-        client
-            .publish::<_, _, ()>(channel, payload.to_string())
-            .await?;
+        streams::publish(
+            &mut client,
+            LINKS_STREAM,
+            self.stream_maxlen,
+            payload.as_bytes(),
+        )
+        .await?;
 
         tracing::debug!(
             link_id = %link.id,
@@ -106,9 +101,45 @@ impl RedisPublisher {
     }
 }
 
+/// Convert a fusion alert record to its Redis contract message
+fn alert_message(alert: &AlertUpsertRecord) -> AlertMessage {
+    AlertMessage {
+        version: CONTRACT_VERSION,
+        id: alert.id.clone(),
+        severity: alert.severity,
+        title: alert.title.clone(),
+        description: alert.description.clone(),
+        ts_ms: alert.ts_ms.max(0) as u64,
+        status: alert.status,
+        evidence_link_ids: alert.evidence_link_ids.clone(),
+        evidence_tracks: alert.evidence_tracks.clone(),
+        meta: alert.meta.as_object().cloned().unwrap_or_default(),
+    }
+}
+
+/// Convert a fusion link record to its Redis contract message
+fn link_message(link: &LinkUpsertRecord) -> LinkMessage {
+    LinkMessage {
+        version: CONTRACT_VERSION,
+        id: link.id.clone(),
+        from_type: link.from_type,
+        from_id: link.from_id.clone(),
+        rel: link.rel.clone(),
+        to_type: link.to_type,
+        to_id: link.to_id.clone(),
+        ts_ms: link.ts_ms.max(0) as u64,
+        from_position: link.from_position,
+        to_position: link.to_position,
+        meta: link.meta.as_object().cloned().unwrap_or_default(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use harpy_core::redis_contract::{
+        AlertSeverity, AlertStatus, EvidenceTrack, GeoPoint, NodeKind,
+    };
     use serde_json::json;
 
     // Note: These tests require a running Redis instance
@@ -117,12 +148,17 @@ mod tests {
     fn create_test_alert() -> AlertUpsertRecord {
         AlertUpsertRecord {
             id: "test-alert-001".to_string(),
-            severity: "MEDIUM".to_string(),
+            severity: AlertSeverity::Medium,
             title: "Test Alert".to_string(),
             description: "Test description".to_string(),
             ts_ms: 1000,
-            status: "ACTIVE".to_string(),
+            status: AlertStatus::Active,
             evidence_link_ids: vec!["link-001".to_string()],
+            evidence_tracks: vec![EvidenceTrack {
+                track_id: "track-001".to_string(),
+                position: GeoPoint { lat: 1.0, lon: 2.0 },
+                callsign: None,
+            }],
             meta: json!({"test": true}),
         }
     }
@@ -130,16 +166,34 @@ mod tests {
     fn create_test_link() -> LinkUpsertRecord {
         LinkUpsertRecord {
             id: "test-link-001".to_string(),
-            from_type: "Track".to_string(),
+            from_type: NodeKind::Alert,
             from_id: "track-001".to_string(),
             rel: "associated_with".to_string(),
-            to_type: "Track".to_string(),
+            to_type: NodeKind::Track,
             to_id: "track-002".to_string(),
             ts_ms: 1000,
+            from_position: None,
+            to_position: Some(GeoPoint { lat: 1.0, lon: 2.0 }),
             meta: json!({"test": true}),
         }
     }
 
+    #[test]
+    fn test_contract_messages_preserve_record_fields() {
+        let alert = alert_message(&create_test_alert());
+        assert_eq!(alert.version, CONTRACT_VERSION);
+        assert_eq!(alert.severity, AlertSeverity::Medium);
+        assert_eq!(alert.status, AlertStatus::Active);
+        assert_eq!(alert.evidence_link_ids, vec!["link-001".to_string()]);
+        assert_eq!(alert.evidence_tracks[0].track_id, "track-001");
+        assert_eq!(alert.meta.get("test"), Some(&json!(true)));
+
+        let link = link_message(&create_test_link());
+        assert_eq!(link.from_type, NodeKind::Alert);
+        assert_eq!(link.to_type, NodeKind::Track);
+        assert_eq!(link.to_position, Some(GeoPoint { lat: 1.0, lon: 2.0 }));
+    }
+
     #[tokio::test]
     #[ignore = "Requires Redis"]
     async fn test_publish_alert() {
@@ -7,7 +7,8 @@
 //! - Pattern: Loitering detection (circular patterns)
 
 use crate::{AlertUpsertRecord, LinkUpsertRecord, TrackObservation};
-use serde_json::json;
+use harpy_core::redis_contract::{AlertSeverity, AlertStatus, EvidenceTrack, GeoPoint, NodeKind};
+use serde_json::{json, Value};
 use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicU64, Ordering};
 use uuid::Uuid;
@@ -151,12 +152,14 @@ impl ConvergenceRule {
 
                     let link = LinkUpsertRecord {
                         id: link_id.clone(),
-                        from_type: "Track".to_string(),
+                        from_type: NodeKind::Track,
                         from_id: first.id.clone(),
                         rel: "associated_with".to_string(),
-                        to_type: "Track".to_string(),
+                        to_type: NodeKind::Track,
                         to_id: second.id.clone(),
                         ts_ms: now_ms,
+                        from_position: Some(position(first)),
+                        to_position: Some(position(second)),
                         meta: json!({
                             "rule": "h3_convergence",
                             "cell": cell,
@@ -168,15 +171,16 @@ impl ConvergenceRule {
 
                     let alert = AlertUpsertRecord {
                         id: alert_id.clone(),
-                        severity: "MEDIUM".to_string(),
+                        severity: AlertSeverity::Medium,
                         title: "Multi-Provider Convergence".to_string(),
                         description: format!(
                             "Tracks {} ({}) and {} ({}) converged in H3 cell {} from different providers",
                             first.id, first.provider_id, second.id, second.provider_id, cell
                         ),
                         ts_ms: now_ms,
-                        status: "ACTIVE".to_string(),
+                        status: AlertStatus::Active,
                         evidence_link_ids: vec![link_id.clone()],
+                        evidence_tracks: vec![evidence(first), evidence(second)],
                         meta: json!({
                             "rule": "h3_convergence",
                             "cell": cell,
@@ -188,12 +192,14 @@ impl ConvergenceRule {
 
                     let evidence_link = LinkUpsertRecord {
                         id: Uuid::new_v4().to_string(),
-                        from_type: "Alert".to_string(),
+                        from_type: NodeKind::Alert,
                         from_id: alert_id,
                         rel: "is_evidenced_by".to_string(),
-                        to_type: "Track".to_string(),
+                        to_type: NodeKind::Track,
                         to_id: first.id.clone(),
                         ts_ms: now_ms,
+                        from_position: None,
+                        to_position: Some(position(first)),
                         meta: json!({ "convergence_link": link_id }),
                     };
 
@@ -235,12 +241,14 @@ impl ProximityRule {
 
                     let link = LinkUpsertRecord {
                         id: link_id.clone(),
-                        from_type: "Track".to_string(),
+                        from_type: NodeKind::Track,
                         from_id: first.id.clone(),
                         rel: "near".to_string(),
-                        to_type: "Track".to_string(),
+                        to_type: NodeKind::Track,
                         to_id: second.id.clone(),
                         ts_ms: now_ms,
+                        from_position: Some(position(first)),
+                        to_position: Some(position(second)),
                         meta: json!({
                             "rule": "proximity",
                             "distance_meters": distance,
@@ -251,19 +259,19 @@ impl ProximityRule {
                     let alert = AlertUpsertRecord {
                         id: alert_id.clone(),
                         severity: if distance < 1000.0 {
-                            "CRITICAL"
+                            AlertSeverity::Critical
                         } else {
-                            "WARNING"
-                        }
-                        .to_string(),
+                            AlertSeverity::Warning
+                        },
                         title: "Proximity Alert".to_string(),
                         description: format!(
                             "Tracks {} and {} are {:.0}m apart (threshold: {:.0}m)",
                             first.id, second.id, distance, self.threshold_meters
                         ),
                         ts_ms: now_ms,
-                        status: "ACTIVE".to_string(),
+                        status: AlertStatus::Active,
                         evidence_link_ids: vec![link_id.clone()],
+                        evidence_tracks: vec![evidence(first), evidence(second)],
                         meta: json!({
                             "rule": "proximity",
                             "distance_meters": distance,
@@ -273,12 +281,14 @@ impl ProximityRule {
 
                     let evidence_link = LinkUpsertRecord {
                         id: Uuid::new_v4().to_string(),
-                        from_type: "Alert".to_string(),
+                        from_type: NodeKind::Alert,
                         from_id: alert_id,
                         rel: "is_evidenced_by".to_string(),
-                        to_type: "Track".to_string(),
+                        to_type: NodeKind::Track,
                         to_id: first.id.clone(),
                         ts_ms: now_ms,
+                        from_position: None,
+                        to_position: Some(position(first)),
                         meta: json!({ "proximity_link": link_id }),
                     };
 
@@ -319,9 +329,10 @@ impl AnomalyRule {
                 if speed > speed_threshold_mps {
                     let alert_id = Uuid::new_v4().to_string();
 
+                    let evidence_link = evidence_link(&alert_id, track, now_ms, "anomaly_speed");
                     let alert = AlertUpsertRecord {
                         id: alert_id,
-                        severity: "WARNING".to_string(),
+                        severity: AlertSeverity::Warning,
                         title: "Speed Anomaly".to_string(),
                         description: format!(
                             "Track {} has unusual speed: {:.0} m/s ({:.0} knots)",
@@ -330,8 +341,9 @@ impl AnomalyRule {
                             speed * 1.94384
                         ),
                         ts_ms: now_ms,
-                        status: "ACTIVE".to_string(),
-                        evidence_link_ids: vec![],
+                        status: AlertStatus::Active,
+                        evidence_link_ids: vec![evidence_link.id.clone()],
+                        evidence_tracks: vec![evidence(track)],
                         meta: json!({
                             "rule": "anomaly_speed",
                             "speed_mps": speed,
@@ -342,7 +354,7 @@ impl AnomalyRule {
 
                     results.push(RuleResult::Alert {
                         alert,
-                        links: vec![],
+                        links: vec![evidence_link],
                     });
                 }
             }
@@ -351,9 +363,10 @@ impl AnomalyRule {
             if track.alt > altitude_threshold_meters {
                 let alert_id = Uuid::new_v4().to_string();
 
+                let evidence_link = evidence_link(&alert_id, track, now_ms, "anomaly_altitude");
                 let alert = AlertUpsertRecord {
                     id: alert_id,
-                    severity: "INFO".to_string(),
+                    severity: AlertSeverity::Info,
                     title: "Altitude Anomaly".to_string(),
                     description: format!(
                         "Track {} at unusual altitude: {:.0}m ({:.0}ft)",
@@ -362,8 +375,9 @@ impl AnomalyRule {
                         track.alt * 3.28084
                     ),
                     ts_ms: now_ms,
-                    status: "ACTIVE".to_string(),
-                    evidence_link_ids: vec![],
+                    status: AlertStatus::Active,
+                    evidence_link_ids: vec![evidence_link.id.clone()],
+                    evidence_tracks: vec![evidence(track)],
                     meta: json!({
                         "rule": "anomaly_altitude",
                         "altitude_meters": track.alt,
@@ -374,7 +388,7 @@ impl AnomalyRule {
 
                 results.push(RuleResult::Alert {
                     alert,
-                    links: vec![],
+                    links: vec![evidence_link],
                 });
             }
         }
@@ -408,6 +422,50 @@ impl LoiteringRule {
     }
 }
 
+/// Position of an observed track
+fn position(track: &TrackObservation) -> GeoPoint {
+    GeoPoint {
+        lat: track.lat,
+        lon: track.lon,
+    }
+}
+
+/// Evidence entry locating an alert at an observed track
+fn evidence(track: &TrackObservation) -> EvidenceTrack {
+    EvidenceTrack {
+        track_id: track.id.clone(),
+        position: position(track),
+        callsign: track
+            .meta
+            .get("callsign")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|callsign| !callsign.is_empty())
+            .map(str::to_string),
+    }
+}
+
+/// Link from an alert to the single track it was raised on
+fn evidence_link(
+    alert_id: &str,
+    track: &TrackObservation,
+    now_ms: i64,
+    rule: &str,
+) -> LinkUpsertRecord {
+    LinkUpsertRecord {
+        id: Uuid::new_v4().to_string(),
+        from_type: NodeKind::Alert,
+        from_id: alert_id.to_string(),
+        rel: "is_evidenced_by".to_string(),
+        to_type: NodeKind::Track,
+        to_id: track.id.clone(),
+        ts_ms: now_ms,
+        from_position: None,
+        to_position: Some(position(track)),
+        meta: json!({ "rule": rule }),
+    }
+}
+
 /// Calculate haversine distance between two points in meters
 fn haversine_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
     const R: f64 = 6371e3; // Earth radius in meters
@@ -474,5 +532,24 @@ mod tests {
 
         let results = rule.evaluate(&tracks, 1000);
         assert_eq!(results.len(), 2, "Should detect both anomalies");
+
+        // Anomalies are located at, and linked to, the offending track
+        for RuleResult::Alert { alert, links } in &results {
+            assert_eq!(alert.evidence_tracks.len(), 1);
+            assert_eq!(links.len(), 1);
+            assert_eq!(links[0].from_id, alert.id);
+            assert_eq!(links[0].to_id, alert.evidence_tracks[0].track_id);
+            assert_eq!(alert.evidence_link_ids, vec![links[0].id.clone()]);
+        }
+    }
+
+    #[test]
+    fn test_evidence_carries_trimmed_callsign() {
+        let mut track = create_test_track("A", 37.7749, -122.4194, 100.0, 1000.0);
+        track.meta = json!({ "callsign": "UAL123  " });
+        assert_eq!(evidence(&track).callsign.as_deref(), Some("UAL123"));
+
+        track.meta = json!({ "callsign": " " });
+        assert_eq!(evidence(&track).callsign, None);
     }
 }
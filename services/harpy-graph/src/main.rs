@@ -5,7 +5,8 @@
 
 use axum::{
     extract::State,
-    http::{HeaderMap, StatusCode},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
@@ -22,8 +23,15 @@ use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod queries;
+mod track_export;
 
 use queries::{QueryEngine, QueryResult};
+use track_export::{TrackExportFormat, TrackHistory};
+
+/// Maximum number of tracks in one track export
+const MAX_EXPORT_TRACKS: usize = 50;
+/// Maximum number of positions exported per track
+const MAX_EXPORT_POINTS_PER_TRACK: i64 = 50_000;
 
 #[derive(Clone)]
 struct AppState {
@@ -62,6 +70,14 @@ struct GraphExportRequest {
     expires_in_secs: Option<u64>,
 }
 
+#[derive(Debug, Deserialize)]
+struct TrackExportRequest {
+    track_ids: Vec<String>,
+    start_ts_ms: Option<i64>,
+    end_ts_ms: Option<i64>,
+    format: TrackExportFormat,
+}
+
 #[derive(Debug, Deserialize)]
 struct GraphExportVerifyRequest {
     token: String,
@@ -209,6 +225,7 @@ async fn main() -> anyhow::Result<()> {
         .route("/graph/query", post(graph_query))
         .route("/graph/export", post(graph_export))
         .route("/graph/export/verify", post(graph_export_verify))
+        .route("/graph/export/tracks", post(graph_export_tracks))
         .with_state(state)
         .layer(TraceLayer::new_for_http());
 
@@ -399,6 +416,123 @@ async fn graph_export(
     }))
 }
 
+async fn graph_export_tracks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<TrackExportRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let actor = parse_actor_context(&headers);
+
+    if req.track_ids.is_empty() || req.track_ids.len() > MAX_EXPORT_TRACKS {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "Invalid track export",
+            Some(format!(
+                "track_ids must list between 1 and {} tracks",
+                MAX_EXPORT_TRACKS
+            )),
+        ));
+    }
+
+    // Each track is authorized as its own track_timeline export.
+    let track_params: Vec<Value> = req
+        .track_ids
+        .iter()
+        .map(|track_id| {
+            json!({
+                "track_id": track_id,
+                "start_ts_ms": req.start_ts_ms,
+                "end_ts_ms": req.end_ts_ms,
+            })
+        })
+        .collect();
+    for params in &track_params {
+        if let Err(reason) = authorize_graph_query(&actor, "track_timeline", params, true) {
+            return Err(error_response(
+                StatusCode::FORBIDDEN,
+                "Access denied",
+                Some(reason),
+            ));
+        }
+    }
+
+    let mut histories = Vec::with_capacity(track_params.len());
+    for (track_id, params) in req.track_ids.iter().zip(&track_params) {
+        let QueryResult { rows, total } = state
+            .query_engine
+            .execute(
+                &state.db_pool,
+                "track_timeline",
+                params,
+                MAX_EXPORT_POINTS_PER_TRACK,
+                0,
+            )
+            .await
+            .map_err(|e| {
+                error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Export query execution failed",
+                    Some(e.to_string()),
+                )
+            })?;
+        if total > MAX_EXPORT_POINTS_PER_TRACK {
+            return Err(error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Track history too large",
+                Some(format!(
+                    "track '{}' has {} positions in range (max {}); narrow start_ts_ms/end_ts_ms",
+                    track_id, total, MAX_EXPORT_POINTS_PER_TRACK
+                )),
+            ));
+        }
+        let history = TrackHistory::from_rows(track_id, &rows);
+        if !history.points.is_empty() {
+            histories.push(history);
+        }
+    }
+
+    if histories.is_empty() {
+        return Err(error_response(
+            StatusCode::NOT_FOUND,
+            "No track history",
+            Some("none of the tracks reported positions in range".to_string()),
+        ));
+    }
+
+    let point_count: usize = histories.iter().map(|h| h.points.len()).sum();
+    if let Err(e) = audit_log_query(
+        &state.db_pool,
+        &actor,
+        "graph_export_tracks",
+        "track_timeline",
+        &json!({
+            "track_ids": &req.track_ids,
+            "start_ts_ms": req.start_ts_ms,
+            "end_ts_ms": req.end_ts_ms,
+            "format": req.format.extension(),
+        }),
+        point_count as i64,
+    )
+    .await
+    {
+        tracing::warn!("Failed to write track export audit log: {}", e);
+    }
+
+    let body = track_export::render(req.format, &histories);
+    let disposition = format!(
+        "attachment; filename=\"harpy-tracks.{}\"",
+        req.format.extension()
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, req.format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
 async fn graph_export_verify(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -700,4 +834,22 @@ mod tests {
         );
         assert!(denied.is_err());
     }
+
+    #[test]
+    fn test_track_export_respects_track_prefixes() {
+        let actor = ActorContext {
+            actor_id: "analyst-1".to_string(),
+            role: ActorRole::Viewer,
+            scopes: ["graph:query".to_string(), "graph:export".to_string()]
+                .into_iter()
+                .collect(),
+            attrs: json!({"track_id_prefixes": ["adsb:"]}),
+        };
+
+        let allowed = json!({"track_id": "adsb:abc123"});
+        assert!(authorize_graph_query(&actor, "track_timeline", &allowed, true).is_ok());
+
+        let denied = json!({"track_id": "ais:987"});
+        assert!(authorize_graph_query(&actor, "track_timeline", &denied, true).is_err());
+    }
 }
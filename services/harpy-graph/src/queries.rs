@@ -4,6 +4,7 @@
 //! No arbitrary SQL execution allowed.
 
 use crate::{ParamInfo, TemplateInfo};
+use harpy_core::h3_cells;
 use serde_json::{json, Value};
 use sqlx::{Column, PgPool, Row, TypeInfo};
 use std::collections::HashMap;
@@ -27,6 +28,11 @@ struct QueryTemplate {
     count_sql: Option<String>,
 }
 
+/// Marker in spatial templates replaced by an `h3_index` cover predicate
+const H3_COVER: &str = "{h3_cover}";
+/// Radius used by `find_associated_tracks` when none is given
+const DEFAULT_ASSOCIATION_METERS: f64 = 10_000.0;
+
 #[derive(Clone)]
 struct ParamDef {
     name: String,
@@ -180,6 +186,7 @@ impl QueryEngine {
                           cos(radians(t.lon) - radians(tt.lon)) +
                           sin(radians(tt.lat)) * sin(radians(t.lat)))
                       ) <= COALESCE($2, 10000)
+                      {h3_cover}
                     ORDER BY distance_meters
                     LIMIT $3 OFFSET $4
                 "#
@@ -200,6 +207,7 @@ impl QueryEngine {
                           cos(radians(t.lon) - radians(tt.lon)) +
                           sin(radians(tt.lat)) * sin(radians(t.lat)))
                       ) <= COALESCE($2, 10000)
+                      {h3_cover}
                 "#
                     .to_string(),
                 ),
@@ -241,8 +249,9 @@ impl QueryEngine {
                         CASE a.severity
                             WHEN 'CRITICAL' THEN 1
                             WHEN 'WARNING' THEN 2
-                            WHEN 'INFO' THEN 3
-                            ELSE 4
+                            WHEN 'MEDIUM' THEN 3
+                            WHEN 'INFO' THEN 4
+                            ELSE 5
                         END,
                         a.ts_ms DESC
                     LIMIT $3 OFFSET $4
@@ -395,6 +404,7 @@ impl QueryEngine {
                       AND ($4::float8 IS NULL OR t.lat <= $4)
                       AND ($5::float8 IS NULL OR t.lon >= $5)
                       AND ($6::float8 IS NULL OR t.lon <= $6)
+                      {h3_cover}
                     ORDER BY t.ts_ms DESC
                     LIMIT $7 OFFSET $8
                 "#
@@ -409,6 +419,7 @@ impl QueryEngine {
                       AND ($4::float8 IS NULL OR t.lat <= $4)
                       AND ($5::float8 IS NULL OR t.lon >= $5)
                       AND ($6::float8 IS NULL OR t.lon <= $6)
+                      {h3_cover}
                 "#
                     .to_string(),
                 ),
@@ -496,19 +507,33 @@ impl QueryEngine {
             "find_associated_tracks" => {
                 let track_id = required_string(params, "track_id")?;
                 let max_distance = optional_f64(params, "max_distance_meters")?;
-                let rows = sqlx::query(&template.sql)
+                let target =
+                    sqlx::query_as::<_, (f64, f64)>("SELECT lat, lon FROM tracks WHERE id = $1")
+                        .bind(&track_id)
+                        .fetch_optional(pool)
+                        .await?;
+                let cover = target.and_then(|(lat, lon)| {
+                    h3_cells::cover_radius(
+                        lat,
+                        lon,
+                        max_distance.unwrap_or(DEFAULT_ASSOCIATION_METERS),
+                        h3_cells::storage_resolution(),
+                    )
+                });
+                let rows = sqlx::query(&with_h3_cover(&template.sql, cover.as_deref()))
                     .bind(&track_id)
                     .bind(max_distance)
                     .bind(limit)
                     .bind(offset)
                     .fetch_all(pool)
                     .await?;
-                let total = sqlx::query_scalar::<_, i64>(
+                let total = sqlx::query_scalar::<_, i64>(&with_h3_cover(
                     template
                         .count_sql
                         .as_deref()
                         .ok_or_else(|| anyhow::anyhow!("missing count_sql"))?,
-                )
+                    cover.as_deref(),
+                ))
                 .bind(&track_id)
                 .bind(max_distance)
                 .fetch_one(pool)
@@ -572,8 +597,20 @@ impl QueryEngine {
                 let max_lat = optional_f64(params, "max_lat")?;
                 let min_lon = optional_f64(params, "min_lon")?;
                 let max_lon = optional_f64(params, "max_lon")?;
-
-                let rows = sqlx::query(&template.sql)
+                let cover = match (min_lat, min_lon, max_lat, max_lon) {
+                    (Some(min_lat), Some(min_lon), Some(max_lat), Some(max_lon)) => {
+                        h3_cells::cover_bbox(
+                            min_lat,
+                            min_lon,
+                            max_lat,
+                            max_lon,
+                            h3_cells::storage_resolution(),
+                        )
+                    }
+                    _ => None,
+                };
+
+                let rows = sqlx::query(&with_h3_cover(&template.sql, cover.as_deref()))
                     .bind(id.clone())
                     .bind(kind.clone())
                     .bind(min_lat)
@@ -585,12 +622,13 @@ impl QueryEngine {
                     .fetch_all(pool)
                     .await?;
 
-                let total = sqlx::query_scalar::<_, i64>(
+                let total = sqlx::query_scalar::<_, i64>(&with_h3_cover(
                     template
                         .count_sql
                         .as_deref()
                         .ok_or_else(|| anyhow::anyhow!("missing count_sql"))?,
-                )
+                    cover.as_deref(),
+                ))
                 .bind(id)
                 .bind(kind)
                 .bind(min_lat)
@@ -666,6 +704,15 @@ impl QueryEngine {
     }
 }
 
+/// Fill a template's `{h3_cover}` marker; without a cover the bare lat/lon
+/// filter runs alone
+fn with_h3_cover(sql: &str, cover: Option<&[(i64, i64)]>) -> String {
+    let predicate = cover
+        .map(|ranges| format!("AND {}", h3_cells::sql_predicate("t.h3_index", ranges)))
+        .unwrap_or_default();
+    sql.replace(H3_COVER, &predicate)
+}
+
 fn required_string(params: &Value, key: &str) -> anyhow::Result<String> {
     let value = params
         .get(key)
@@ -745,4 +792,18 @@ mod tests {
         assert!(engine.is_valid_template("get_track_history"));
         assert!(engine.is_valid_template("track_timeline"));
     }
+
+    #[test]
+    fn test_spatial_templates_take_h3_cover() {
+        let engine = QueryEngine::new();
+        let search = &engine.templates["search_tracks"];
+        let sql = with_h3_cover(&search.sql, Some(&[(1, 2)]));
+        assert!(sql.contains("AND (t.h3_index BETWEEN 1 AND 2 OR t.h3_index IS NULL)"));
+
+        let associated = &engine.templates["find_associated_tracks"];
+        for sql in [&associated.sql, associated.count_sql.as_ref().unwrap()] {
+            assert!(sql.contains(H3_COVER));
+            assert!(!with_h3_cover(sql, None).contains(H3_COVER));
+        }
+    }
 }
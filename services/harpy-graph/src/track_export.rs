@@ -0,0 +1,367 @@
+//! Track History Export
+//!
+//! Renders `track_timeline` rows as KML (`gx:Track`), KMZ or GPX so analysts
+//! can load track histories into Google Earth and other GIS tools.
+
+use chrono::{DateTime, SecondsFormat};
+use flate2::{write::DeflateEncoder, Compression, Crc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+/// Output format of a track export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackExportFormat {
+    Kml,
+    Kmz,
+    Gpx,
+}
+
+impl TrackExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Kml => "application/vnd.google-earth.kml+xml",
+            Self::Kmz => "application/vnd.google-earth.kmz",
+            Self::Gpx => "application/gpx+xml",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Kml => "kml",
+            Self::Kmz => "kmz",
+            Self::Gpx => "gpx",
+        }
+    }
+}
+
+/// One reported position of a track
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackPoint {
+    pub ts_ms: i64,
+    pub lat: f64,
+    pub lon: f64,
+    pub alt: f64,
+    pub heading: Option<f64>,
+    pub speed: Option<f64>,
+}
+
+/// A track's positions over the exported range, oldest first
+#[derive(Debug, Clone)]
+pub struct TrackHistory {
+    pub track_id: String,
+    /// Latest `callsign` or `name` meta, else the track id
+    pub name: String,
+    pub provider_id: Option<String>,
+    pub points: Vec<TrackPoint>,
+}
+
+impl TrackHistory {
+    /// Build a history from `track_timeline` rows (newest first, as the template returns them)
+    pub fn from_rows(track_id: &str, rows: &[Value]) -> Self {
+        let name = rows
+            .iter()
+            .find_map(|row| {
+                ["callsign", "name"]
+                    .iter()
+                    .filter_map(|key| row.get("meta")?.get(*key)?.as_str())
+                    .map(str::trim)
+                    .find(|value| !value.is_empty())
+            })
+            .unwrap_or(track_id)
+            .to_string();
+        let provider_id = rows
+            .first()
+            .and_then(|row| row.get("provider_id")?.as_str())
+            .map(ToString::to_string);
+
+        let mut points: Vec<TrackPoint> = rows
+            .iter()
+            .rev()
+            .filter_map(|row| {
+                Some(TrackPoint {
+                    ts_ms: row.get("ts_ms")?.as_i64()?,
+                    lat: row.get("lat")?.as_f64()?,
+                    lon: row.get("lon")?.as_f64()?,
+                    alt: row.get("alt").and_then(Value::as_f64).unwrap_or(0.0),
+                    heading: row.get("heading").and_then(Value::as_f64),
+                    speed: row.get("speed").and_then(Value::as_f64),
+                })
+            })
+            .collect();
+        points.sort_by_key(|point| point.ts_ms);
+
+        Self {
+            track_id: track_id.to_string(),
+            name,
+            provider_id,
+            points,
+        }
+    }
+}
+
+/// Render histories in the requested format
+pub fn render(format: TrackExportFormat, histories: &[TrackHistory]) -> Vec<u8> {
+    match format {
+        TrackExportFormat::Kml => render_kml(histories).into_bytes(),
+        TrackExportFormat::Kmz => kmz(&render_kml(histories)),
+        TrackExportFormat::Gpx => render_gpx(histories).into_bytes(),
+    }
+}
+
+/// KML document with one `gx:Track` placemark per history.
+///
+/// Heading and speed travel as `gx:SimpleArrayData` alongside the coordinates.
+pub fn render_kml(histories: &[TrackHistory]) -> String {
+    let mut out = String::new();
+    out.push_str(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">\n\
+         <Document>\n\
+         <name>HARPY track export</name>\n\
+         <Schema id=\"harpyTrack\">\n\
+         <gx:SimpleArrayField name=\"heading\" type=\"float\"><displayName>Heading (deg)</displayName></gx:SimpleArrayField>\n\
+         <gx:SimpleArrayField name=\"speed\" type=\"float\"><displayName>Speed (m/s)</displayName></gx:SimpleArrayField>\n\
+         </Schema>\n",
+    );
+
+    for history in histories {
+        let _ = writeln!(
+            out,
+            "<Placemark>\n<name>{}</name>\n<description>Track {}{}</description>\n<gx:Track>\n<altitudeMode>absolute</altitudeMode>",
+            escape_xml(&history.name),
+            escape_xml(&history.track_id),
+            history
+                .provider_id
+                .as_deref()
+                .map(|provider| format!(" from {}", escape_xml(provider)))
+                .unwrap_or_default(),
+        );
+        for point in &history.points {
+            let _ = writeln!(out, "<when>{}</when>", iso8601(point.ts_ms));
+        }
+        for point in &history.points {
+            let _ = writeln!(
+                out,
+                "<gx:coord>{} {} {}</gx:coord>",
+                point.lon, point.lat, point.alt
+            );
+        }
+        out.push_str("<ExtendedData>\n<SchemaData schemaUrl=\"#harpyTrack\">\n");
+        write_array_data(
+            &mut out,
+            "heading",
+            history.points.iter().map(|p| p.heading),
+        );
+        write_array_data(&mut out, "speed", history.points.iter().map(|p| p.speed));
+        out.push_str("</SchemaData>\n</ExtendedData>\n</gx:Track>\n</Placemark>\n");
+    }
+
+    out.push_str("</Document>\n</kml>\n");
+    out
+}
+
+/// One `gx:SimpleArrayData` entry per point; missing values stay empty to keep the arrays aligned
+fn write_array_data(out: &mut String, name: &str, values: impl Iterator<Item = Option<f64>>) {
+    let _ = writeln!(out, "<gx:SimpleArrayData name=\"{}\">", name);
+    for value in values {
+        match value {
+            Some(value) => {
+                let _ = writeln!(out, "<gx:value>{}</gx:value>", value);
+            }
+            None => out.push_str("<gx:value/>\n"),
+        }
+    }
+    out.push_str("</gx:SimpleArrayData>\n");
+}
+
+/// GPX 1.1 document with one track (single segment) per history
+pub fn render_gpx(histories: &[TrackHistory]) -> String {
+    let mut out = String::new();
+    out.push_str(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"harpy-graph\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n\
+         <metadata><name>HARPY track export</name></metadata>\n",
+    );
+
+    for history in histories {
+        let _ = writeln!(
+            out,
+            "<trk>\n<name>{}</name>\n<desc>Track {}</desc>",
+            escape_xml(&history.name),
+            escape_xml(&history.track_id)
+        );
+        if let Some(provider) = &history.provider_id {
+            let _ = writeln!(out, "<src>{}</src>", escape_xml(provider));
+        }
+        out.push_str("<trkseg>\n");
+        for point in &history.points {
+            let _ = writeln!(
+                out,
+                "<trkpt lat=\"{}\" lon=\"{}\"><ele>{}</ele><time>{}</time></trkpt>",
+                point.lat,
+                point.lon,
+                point.alt,
+                iso8601(point.ts_ms)
+            );
+        }
+        out.push_str("</trkseg>\n</trk>\n");
+    }
+
+    out.push_str("</gpx>\n");
+    out
+}
+
+/// KMZ archive: a ZIP holding the document as `doc.kml`
+pub fn kmz(kml: &str) -> Vec<u8> {
+    const NAME: &[u8] = b"doc.kml";
+    // 1980-01-01 00:00, the earliest DOS timestamp
+    const DOS_TIME: u16 = 0;
+    const DOS_DATE: u16 = (1 << 5) | 1;
+
+    let mut crc = Crc::new();
+    crc.update(kml.as_bytes());
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    let compressed = encoder
+        .write_all(kml.as_bytes())
+        .and_then(|_| encoder.finish())
+        .unwrap_or_default();
+
+    let mut out = Vec::with_capacity(compressed.len() + 128);
+    let entry = |out: &mut Vec<u8>| {
+        out.extend_from_slice(&20u16.to_le_bytes()); // version needed: deflate
+        out.extend_from_slice(&0u16.to_le_bytes()); // flags
+        out.extend_from_slice(&8u16.to_le_bytes()); // method: deflate
+        out.extend_from_slice(&DOS_TIME.to_le_bytes());
+        out.extend_from_slice(&DOS_DATE.to_le_bytes());
+        out.extend_from_slice(&crc.sum().to_le_bytes());
+        out.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(kml.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(NAME.len() as u16).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+    };
+
+    // Local file header and data
+    out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+    entry(&mut out);
+    out.extend_from_slice(NAME);
+    out.extend_from_slice(&compressed);
+
+    // Central directory
+    let directory_offset = out.len();
+    out.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+    out.extend_from_slice(&20u16.to_le_bytes()); // version made by
+    entry(&mut out);
+    out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    out.extend_from_slice(&0u16.to_le_bytes()); // disk number
+    out.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+    out.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+    out.extend_from_slice(&0u32.to_le_bytes()); // local header offset
+    out.extend_from_slice(NAME);
+    let directory_len = out.len() - directory_offset;
+
+    // End of central directory
+    out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // this disk
+    out.extend_from_slice(&0u16.to_le_bytes()); // directory disk
+    out.extend_from_slice(&1u16.to_le_bytes()); // entries on this disk
+    out.extend_from_slice(&1u16.to_le_bytes()); // entries in total
+    out.extend_from_slice(&(directory_len as u32).to_le_bytes());
+    out.extend_from_slice(&(directory_offset as u32).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    out
+}
+
+fn escape_xml(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn iso8601(ts_ms: i64) -> String {
+    DateTime::from_timestamp_millis(ts_ms)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::DeflateDecoder;
+    use serde_json::json;
+    use std::io::Read;
+
+    fn history() -> TrackHistory {
+        // Newest first, as `track_timeline` returns rows.
+        let rows = vec![
+            json!({"track_id": "a-1", "lat": 11.0, "lon": 21.0, "alt": 900.0, "heading": 90.0,
+                   "speed": null, "ts_ms": 1_700_000_005_000i64, "provider_id": "adsb",
+                   "meta": {"callsign": "R&D <1>"}}),
+            json!({"track_id": "a-1", "lat": 10.0, "lon": 20.0, "alt": 1000.0, "heading": 80.0,
+                   "speed": 200.0, "ts_ms": 1_700_000_000_000i64, "provider_id": "adsb",
+                   "meta": {}}),
+        ];
+        TrackHistory::from_rows("a-1", &rows)
+    }
+
+    #[test]
+    fn test_history_from_rows_is_oldest_first() {
+        let history = history();
+        assert_eq!(history.name, "R&D <1>");
+        assert_eq!(history.provider_id.as_deref(), Some("adsb"));
+        assert_eq!(history.points[0].ts_ms, 1_700_000_000_000);
+        assert_eq!(history.points[1].speed, None);
+    }
+
+    #[test]
+    fn test_kml_gx_track() {
+        let kml = render_kml(&[history()]);
+        assert!(kml.contains("<name>R&amp;D &lt;1&gt;</name>"));
+        let first_when = kml.find("<when>2023-11-14T22:13:20.000Z</when>").unwrap();
+        let second_when = kml.find("<when>2023-11-14T22:13:25.000Z</when>").unwrap();
+        assert!(first_when < second_when);
+        assert!(kml.contains("<gx:coord>20 10 1000</gx:coord>"));
+        assert!(kml.contains("<gx:value>200</gx:value>\n<gx:value/>"));
+    }
+
+    #[test]
+    fn test_gpx_track_points() {
+        let gpx = render_gpx(&[history()]);
+        assert!(gpx.contains(
+            "<trkpt lat=\"10\" lon=\"20\"><ele>1000</ele><time>2023-11-14T22:13:20.000Z</time></trkpt>"
+        ));
+        assert!(gpx.contains("<src>adsb</src>"));
+    }
+
+    #[test]
+    fn test_kmz_holds_kml() {
+        let kml = render_kml(&[history()]);
+        let archive = kmz(&kml);
+        assert_eq!(&archive[..4], b"PK\x03\x04");
+
+        let compressed_len = u32::from_le_bytes(archive[18..22].try_into().unwrap()) as usize;
+        let name_len = u16::from_le_bytes(archive[26..28].try_into().unwrap()) as usize;
+        assert_eq!(&archive[30..30 + name_len], b"doc.kml");
+
+        let data = &archive[30 + name_len..30 + name_len + compressed_len];
+        let mut inflated = String::new();
+        DeflateDecoder::new(data)
+            .read_to_string(&mut inflated)
+            .unwrap();
+        assert_eq!(inflated, kml);
+
+        let end = &archive[archive.len() - 22..];
+        assert_eq!(&end[..4], b"PK\x05\x06");
+    }
+}
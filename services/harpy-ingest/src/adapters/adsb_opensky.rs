@@ -207,6 +207,7 @@ impl OpenSkyProvider {
             let speed = value_as_f64(row.get(9)).unwrap_or(0.0);
 
             let mut meta = HashMap::new();
+            meta.insert("icao24".to_string(), icao24.to_ascii_lowercase());
             if let Some(callsign) = value_as_str(row.get(1)) {
                 meta.insert("callsign".to_string(), callsign.trim().to_string());
             }
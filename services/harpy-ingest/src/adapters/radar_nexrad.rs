@@ -289,6 +289,7 @@ fn parse_station_list(raw: &str) -> Vec<String> {
         .collect()
 }
 
+#[allow(clippy::useless_conversion)]
 fn latest_radar_object_from_xml(xml: &str) -> Option<RadarObject> {
     let keys = extract_tag_values(xml, "Key");
     let timestamps = extract_tag_values(xml, "LastModified");
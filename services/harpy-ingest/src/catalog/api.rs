@@ -0,0 +1,248 @@
+//! Catalog Search API
+//!
+//! `GET /catalog` searches catalogued datasets:
+//! - `q`: full-text search over title, tags, publisher and description
+//! - `bbox=west,south,east,north`: coverage intersects the box (`west > east`
+//!   crosses the antimeridian); `include_unbounded=true` also returns datasets
+//!   without published coverage
+//! - `source`: `aiddata`, `gee` or `dataverse`
+//! - `limit` (default 50, max 500) and `offset`
+//!
+//! `GET /catalog/:id` returns one dataset.
+
+use super::store::{self, CatalogEntry, CatalogSearch};
+use super::BBox;
+use crate::storage::PostgresStore;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Clone)]
+pub struct CatalogApiState {
+    pub postgres: Option<PostgresStore>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CatalogQuery {
+    #[serde(default)]
+    pub q: Option<String>,
+    #[serde(default)]
+    pub bbox: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub include_unbounded: bool,
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CatalogResponse {
+    pub datasets: Vec<CatalogEntry>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CatalogError {
+    pub error: String,
+    pub code: String,
+}
+
+fn error(status: StatusCode, error: impl Into<String>, code: &str) -> Response {
+    (
+        status,
+        Json(CatalogError {
+            error: error.into(),
+            code: code.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+pub async fn search_catalog(
+    State(state): State<CatalogApiState>,
+    Query(query): Query<CatalogQuery>,
+) -> Response {
+    let search = match search_from_query(query) {
+        Ok(search) => search,
+        Err((message, code)) => return error(StatusCode::BAD_REQUEST, message, code),
+    };
+    let Some(postgres) = state.postgres.as_ref() else {
+        return error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Postgres unavailable",
+            "DB_UNAVAILABLE",
+        );
+    };
+
+    match store::search(postgres, &search).await {
+        Ok((datasets, total)) => Json(CatalogResponse {
+            datasets,
+            total,
+            limit: search.limit,
+            offset: search.offset,
+        })
+        .into_response(),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to search catalog: {}", e),
+            "DB_QUERY_FAILED",
+        ),
+    }
+}
+
+pub async fn get_catalog_dataset(
+    State(state): State<CatalogApiState>,
+    Path(id): Path<String>,
+) -> Response {
+    let Some(postgres) = state.postgres.as_ref() else {
+        return error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Postgres unavailable",
+            "DB_UNAVAILABLE",
+        );
+    };
+
+    match store::get_dataset(postgres, &id).await {
+        Ok(Some(entry)) => Json(entry).into_response(),
+        Ok(None) => error(
+            StatusCode::NOT_FOUND,
+            format!("Dataset {} not found", id),
+            "NOT_FOUND",
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to look up dataset: {}", e),
+            "DB_QUERY_FAILED",
+        ),
+    }
+}
+
+/// Validate the query string, or the 400 message and code
+fn search_from_query(query: CatalogQuery) -> Result<CatalogSearch, (String, &'static str)> {
+    let bbox = match query.bbox.as_deref().map(parse_bbox) {
+        Some(Ok(bbox)) => Some(bbox),
+        Some(Err(e)) => return Err((e, "INVALID_BBOX")),
+        None => None,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err((
+            format!("limit must be between 1 and {}", MAX_LIMIT),
+            "INVALID_LIMIT",
+        ));
+    }
+    let offset = query.offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(("offset must not be negative".to_string(), "INVALID_OFFSET"));
+    }
+    let non_empty = |value: Option<String>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+
+    Ok(CatalogSearch {
+        text: non_empty(query.q),
+        source: non_empty(query.source).map(|source| source.to_ascii_lowercase()),
+        bbox,
+        include_unbounded: query.include_unbounded,
+        limit,
+        offset,
+    })
+}
+
+/// Parse `west,south,east,north` in degrees
+fn parse_bbox(raw: &str) -> Result<BBox, String> {
+    let values = raw
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("bbox '{}' is not four numbers", raw))?;
+    let [west_lon, south_lat, east_lon, north_lat] = values[..] else {
+        return Err(format!(
+            "bbox '{}' must be west,south,east,north in degrees",
+            raw
+        ));
+    };
+    if ![west_lon, east_lon]
+        .iter()
+        .all(|lon| (-180.0..=180.0).contains(lon))
+        || ![south_lat, north_lat]
+            .iter()
+            .all(|lat| (-90.0..=90.0).contains(lat))
+    {
+        return Err(format!("bbox '{}' is out of range", raw));
+    }
+    if south_lat > north_lat {
+        return Err(format!("bbox '{}' has south above north", raw));
+    }
+    Ok(BBox {
+        west_lon,
+        south_lat,
+        east_lon,
+        north_lat,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bbox() {
+        assert_eq!(
+            parse_bbox("-77.5, 38.5,-77.0,39"),
+            Ok(BBox {
+                west_lon: -77.5,
+                south_lat: 38.5,
+                east_lon: -77.0,
+                north_lat: 39.0,
+            })
+        );
+        // Crossing the antimeridian is allowed
+        assert!(parse_bbox("170,-20,-170,0").is_ok());
+        assert!(parse_bbox("1,2,3").is_err());
+        assert!(parse_bbox("a,2,3,4").is_err());
+        assert!(parse_bbox("0,10,5,5").is_err());
+        assert!(parse_bbox("0,0,190,5").is_err());
+    }
+
+    #[test]
+    fn test_search_from_query_validates_paging() {
+        let search = search_from_query(CatalogQuery {
+            q: Some("  mangroves ".to_string()),
+            source: Some("GEE".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(search.text.as_deref(), Some("mangroves"));
+        assert_eq!(search.source.as_deref(), Some("gee"));
+        assert_eq!(search.limit, DEFAULT_LIMIT);
+        assert!(search.bbox.is_none());
+
+        let (_, code) = search_from_query(CatalogQuery {
+            limit: Some(MAX_LIMIT + 1),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert_eq!(code, "INVALID_LIMIT");
+        let (_, code) = search_from_query(CatalogQuery {
+            bbox: Some("0,0,1".to_string()),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert_eq!(code, "INVALID_BBOX");
+    }
+}
@@ -0,0 +1,129 @@
+//! Open-Data Catalog
+//!
+//! Periodically pulls dataset listings from the open-data sources into
+//! `catalog_datasets` with their published coverage, and serves them from
+//! `GET /catalog` so analysts can find datasets covering an area of interest.
+//! Catalog entries are datasets, not tracks: they never reach Redis,
+//! `track_deltas` or the relay.
+
+pub mod api;
+pub mod sources;
+pub mod store;
+
+use crate::storage::PostgresStore;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sources::OpenDataCatalog;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Default refresh interval; the upstream catalogs change slowly
+pub const DEFAULT_CATALOG_INTERVAL_SECS: u64 = 86_400;
+
+/// Geographic coverage in degrees
+///
+/// `west_lon > east_lon` means the box crosses the antimeridian.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BBox {
+    pub west_lon: f64,
+    pub south_lat: f64,
+    pub east_lon: f64,
+    pub north_lat: f64,
+}
+
+impl BBox {
+    pub fn centroid(self) -> (f64, f64) {
+        let lat = ((self.north_lat + self.south_lat) / 2.0).clamp(-90.0, 90.0);
+        let lon = if self.west_lon <= self.east_lon {
+            (self.west_lon + self.east_lon) / 2.0
+        } else {
+            let wrapped = (self.west_lon + self.east_lon + 360.0) / 2.0;
+            if wrapped > 180.0 {
+                wrapped - 360.0
+            } else {
+                wrapped
+            }
+        }
+        .clamp(-180.0, 180.0);
+
+        (lat, lon)
+    }
+
+    /// Longitude intervals covered, split at the antimeridian
+    pub fn lon_ranges(self) -> Vec<(f64, f64)> {
+        if self.west_lon <= self.east_lon {
+            vec![(self.west_lon, self.east_lon)]
+        } else {
+            vec![(self.west_lon, 180.0), (-180.0, self.east_lon)]
+        }
+    }
+}
+
+/// One dataset listing, keyed `<source>:<source id>`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CatalogDataset {
+    pub id: String,
+    pub source: String,
+    pub title: String,
+    pub description: String,
+    pub publisher: String,
+    pub tags: Vec<String>,
+    pub dataset_url: Option<String>,
+    pub license: Option<String>,
+    /// `None` when the source publishes no coverage
+    pub bbox: Option<BBox>,
+    pub published_at: Option<DateTime<Utc>>,
+    /// Source-specific fields (GEE asset ID, Dataverse DOI, ...)
+    pub attributes: HashMap<String, String>,
+}
+
+/// Refresh `catalog_datasets` from every enabled source on an interval
+pub async fn run(catalog: OpenDataCatalog, postgres: PostgresStore, interval_secs: u64) {
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+    tracing::info!(
+        "Starting open-data catalog refresh (every {} seconds)",
+        interval_secs
+    );
+
+    loop {
+        interval.tick().await;
+        let datasets = match catalog.fetch().await {
+            Ok(datasets) => datasets,
+            Err(e) => {
+                tracing::error!("Open-data catalog fetch failed: {}", e);
+                continue;
+            }
+        };
+        match store::upsert_datasets(&postgres, &datasets).await {
+            Ok(()) => tracing::info!("Catalogued {} open-data datasets", datasets.len()),
+            Err(e) => tracing::error!("Failed to store catalog datasets: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lon_ranges_split_at_antimeridian() {
+        let pacific = BBox {
+            west_lon: 170.0,
+            south_lat: -20.0,
+            east_lon: -170.0,
+            north_lat: 0.0,
+        };
+        assert_eq!(pacific.lon_ranges(), vec![(170.0, 180.0), (-180.0, -170.0)]);
+        let (lat, lon) = pacific.centroid();
+        assert!((lat + 10.0).abs() < f64::EPSILON);
+        assert!((lon - 180.0).abs() < f64::EPSILON);
+
+        let atlantic = BBox {
+            west_lon: -40.0,
+            south_lat: 0.0,
+            east_lon: -10.0,
+            north_lat: 30.0,
+        };
+        assert_eq!(atlantic.lon_ranges(), vec![(-40.0, -10.0)]);
+    }
+}
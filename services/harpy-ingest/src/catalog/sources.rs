@@ -1,14 +1,17 @@
-use super::Provider;
+//! Open-Data Catalog Sources
+//!
+//! Fetches dataset listings from AidData GeoQuery, the GEE community catalog
+//! and Harvard Dataverse. Only Dataverse publishes coverage; datasets from the
+//! other sources are kept without a bounding box.
+
+use super::{BBox, CatalogDataset};
 use anyhow::Context;
-use async_trait::async_trait;
-use chrono::DateTime;
-use harpy_proto::harpy::v1::{Position, TrackDelta, TrackKind};
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
-const DEFAULT_PROVIDER_ID: &str = "open-data-catalog";
 const DEFAULT_AIDDATA_CATALOG_URL: &str = "https://www.aiddata.org/geoquery/data-documentation";
 const DEFAULT_GEE_CATALOG_URL: &str =
     "https://raw.githubusercontent.com/samapriya/awesome-gee-community-datasets/master/community_datasets.json";
@@ -16,35 +19,12 @@ const DEFAULT_DATAVERSE_SEARCH_URL: &str = "https://dataverse.harvard.edu/api/se
 const DEFAULT_DATAVERSE_QUERY: &str = "*";
 const DEFAULT_DATAVERSE_GEO_POINT: &str = "0,0";
 
-#[derive(Debug, Clone, Copy)]
-struct BBox {
-    west_lon: f64,
-    east_lon: f64,
-    north_lat: f64,
-    south_lat: f64,
-}
-
-impl BBox {
-    fn centroid(self) -> (f64, f64) {
-        let lat = ((self.north_lat + self.south_lat) / 2.0).clamp(-90.0, 90.0);
-        let lon = if self.west_lon <= self.east_lon {
-            (self.west_lon + self.east_lon) / 2.0
-        } else {
-            let wrapped = (self.west_lon + self.east_lon + 360.0) / 2.0;
-            if wrapped > 180.0 {
-                wrapped - 360.0
-            } else {
-                wrapped
-            }
-        }
-        .clamp(-180.0, 180.0);
-
-        (lat, lon)
-    }
-}
+/// Source names stored in `catalog_datasets.source`
+pub const SOURCE_AIDDATA: &str = "aiddata";
+pub const SOURCE_GEE: &str = "gee";
+pub const SOURCE_DATAVERSE: &str = "dataverse";
 
-pub struct OpenDataCatalogProvider {
-    provider_id: String,
+pub struct OpenDataCatalog {
     client: reqwest::Client,
     aiddata_catalog_url: String,
     aiddata_max_items: usize,
@@ -126,7 +106,7 @@ struct DataverseMetadataField {
     value: Value,
 }
 
-impl OpenDataCatalogProvider {
+impl OpenDataCatalog {
     pub fn from_env() -> anyhow::Result<Self> {
         let aiddata_catalog_url = std::env::var("AIDDATA_CATALOG_URL")
             .unwrap_or_else(|_| DEFAULT_AIDDATA_CATALOG_URL.to_string());
@@ -164,7 +144,6 @@ impl OpenDataCatalogProvider {
             .context("failed to build open-data HTTP client")?;
 
         Ok(Self {
-            provider_id: DEFAULT_PROVIDER_ID.to_string(),
             client,
             aiddata_catalog_url,
             aiddata_max_items: env_usize("AIDDATA_MAX_ITEMS", 150),
@@ -183,7 +162,7 @@ impl OpenDataCatalogProvider {
         })
     }
 
-    async fn fetch_aiddata_tracks(&self) -> anyhow::Result<Vec<TrackDelta>> {
+    async fn fetch_aiddata(&self) -> anyhow::Result<Vec<CatalogDataset>> {
         let html = self
             .client
             .get(&self.aiddata_catalog_url)
@@ -196,38 +175,13 @@ impl OpenDataCatalogProvider {
             .await
             .context("failed reading AidData catalog body")?;
 
-        let now = now_ms();
-        let entries = parse_aiddata_entries(&html, self.aiddata_max_items);
-        let tracks = entries
+        Ok(parse_aiddata_entries(&html, self.aiddata_max_items)
             .into_iter()
-            .map(|entry| {
-                let synthetic = synthetic_position(&entry.slug, "aiddata");
-                let mut meta = HashMap::new();
-                meta.insert("catalog_source".to_string(), "AidData GeoQuery".to_string());
-                meta.insert("dataset_title".to_string(), entry.title);
-                meta.insert("description".to_string(), entry.description);
-                meta.insert("publisher".to_string(), entry.publisher);
-                meta.insert("tags".to_string(), entry.tags);
-                meta.insert("dataset_url".to_string(), entry.dataset_url);
-                meta.insert("position_mode".to_string(), "synthetic_catalog".to_string());
-
-                TrackDelta {
-                    id: make_track_id("AIDDATA", &entry.slug),
-                    kind: TrackKind::Ground as i32,
-                    position: Some(synthetic),
-                    heading: 0.0,
-                    speed: 0.0,
-                    ts_ms: now,
-                    provider_id: self.provider_id.clone(),
-                    meta,
-                }
-            })
-            .collect();
-
-        Ok(tracks)
+            .map(aiddata_dataset)
+            .collect())
     }
 
-    async fn fetch_gee_tracks(&self) -> anyhow::Result<Vec<TrackDelta>> {
+    async fn fetch_gee(&self) -> anyhow::Result<Vec<CatalogDataset>> {
         let entries: Vec<GeeCommunityEntry> = self
             .client
             .get(&self.gee_catalog_url)
@@ -240,67 +194,26 @@ impl OpenDataCatalogProvider {
             .await
             .context("failed parsing GEE community catalog JSON")?;
 
-        let now = now_ms();
-        let mut tracks = Vec::with_capacity(entries.len().min(self.gee_max_items));
+        let mut datasets = Vec::with_capacity(entries.len().min(self.gee_max_items));
         let mut seen = HashSet::new();
-
         for entry in entries.into_iter().take(self.gee_max_items) {
-            let key = entry.id.trim();
-            if key.is_empty() || !seen.insert(key.to_string()) {
+            let key = entry.id.trim().to_string();
+            if key.is_empty() || !seen.insert(key) {
                 continue;
             }
-
-            let synthetic = synthetic_position(key, "gee");
-            let mut meta = HashMap::new();
-            meta.insert(
-                "catalog_source".to_string(),
-                "GEE Community Catalog".to_string(),
-            );
-            meta.insert("dataset_title".to_string(), entry.title.clone());
-            meta.insert("dataset_id".to_string(), entry.id.clone());
-            if let Some(group) = entry.thematic_group {
-                meta.insert("thematic_group".to_string(), group);
-            }
-            if let Some(provider) = entry.provider {
-                meta.insert("publisher".to_string(), provider);
-            }
-            if let Some(tags) = entry.tags {
-                meta.insert("tags".to_string(), tags);
-            }
-            if let Some(license) = entry.license {
-                meta.insert("license".to_string(), license);
-            }
-            if let Some(docs) = entry.docs {
-                meta.insert("dataset_url".to_string(), docs);
-            }
-            if let Some(item_type) = entry.item_type {
-                meta.insert("asset_type".to_string(), item_type);
-            }
-            meta.insert("position_mode".to_string(), "synthetic_catalog".to_string());
-
-            tracks.push(TrackDelta {
-                id: make_track_id("GEE", &entry.id),
-                kind: TrackKind::Ground as i32,
-                position: Some(synthetic),
-                heading: 0.0,
-                speed: 0.0,
-                ts_ms: now,
-                provider_id: self.provider_id.clone(),
-                meta,
-            });
+            datasets.push(gee_dataset(entry));
         }
 
-        Ok(tracks)
+        Ok(datasets)
     }
 
-    async fn fetch_dataverse_tracks(&self) -> anyhow::Result<Vec<TrackDelta>> {
-        let mut tracks = Vec::new();
+    async fn fetch_dataverse(&self) -> anyhow::Result<Vec<CatalogDataset>> {
+        let mut datasets = Vec::new();
         let mut start = 0usize;
         let mut scanned_pages = 0usize;
         let mut total_count = usize::MAX;
-        let now = now_ms();
 
-        while tracks.len() < self.dataverse_max_items
+        while datasets.len() < self.dataverse_max_items
             && scanned_pages < self.dataverse_max_pages
             && start < total_count
         {
@@ -340,117 +253,142 @@ impl OpenDataCatalogProvider {
             }
 
             for item in payload.data.items {
-                if tracks.len() >= self.dataverse_max_items {
+                if datasets.len() >= self.dataverse_max_items {
                     break;
                 }
-
-                let Some(track) = self.dataverse_item_to_track(item, now) else {
-                    continue;
-                };
-                tracks.push(track);
+                datasets.push(dataverse_dataset(item));
             }
 
             start = start.saturating_add(self.dataverse_per_page);
             scanned_pages = scanned_pages.saturating_add(1);
         }
 
-        Ok(tracks)
+        Ok(datasets)
     }
 
-    fn dataverse_item_to_track(&self, item: DataverseDatasetItem, ts_ms: u64) -> Option<TrackDelta> {
-        let geospatial_block = item.metadata_blocks.get("geospatial")?;
-        let bbox = extract_bbox_from_geospatial_block(geospatial_block)?;
-        let (lat, lon) = bbox.centroid();
-
-        let mut meta = HashMap::new();
-        meta.insert(
-            "catalog_source".to_string(),
-            "Harvard Dataverse".to_string(),
-        );
-        meta.insert("dataset_title".to_string(), item.name.clone());
-        if !item.global_id.is_empty() {
-            meta.insert("global_id".to_string(), item.global_id.clone());
-        }
-        if let Some(url) = item.url {
-            meta.insert("dataset_url".to_string(), url);
-        }
-        if let Some(publisher) = item.publisher {
-            meta.insert("publisher".to_string(), publisher);
-        }
-        if !item.subjects.is_empty() {
-            meta.insert("subjects".to_string(), item.subjects.join(", "));
-        }
-        if let Some(published) = item.published_at {
-            meta.insert("source_published_at".to_string(), published);
-        }
-
-        meta.insert("bbox_west_lon".to_string(), bbox.west_lon.to_string());
-        meta.insert("bbox_east_lon".to_string(), bbox.east_lon.to_string());
-        meta.insert("bbox_north_lat".to_string(), bbox.north_lat.to_string());
-        meta.insert("bbox_south_lat".to_string(), bbox.south_lat.to_string());
-        meta.insert("position_mode".to_string(), "bbox_centroid".to_string());
-
-        let identity = if item.global_id.is_empty() {
-            item.name
-        } else {
-            item.global_id
-        };
-
-        Some(TrackDelta {
-            id: make_track_id("DATAVERSE", &identity),
-            kind: TrackKind::Ground as i32,
-            position: Some(Position { lat, lon, alt: 0.0 }),
-            heading: 0.0,
-            speed: 0.0,
-            ts_ms,
-            provider_id: self.provider_id.clone(),
-            meta,
-        })
-    }
-}
-
-#[async_trait]
-impl Provider for OpenDataCatalogProvider {
-    async fn fetch(&self) -> anyhow::Result<Vec<TrackDelta>> {
-        let mut tracks = Vec::new();
+    /// Fetch every enabled source
+    ///
+    /// A failing source is logged and skipped; the fetch only fails when every
+    /// enabled source does.
+    pub async fn fetch(&self) -> anyhow::Result<Vec<CatalogDataset>> {
+        let mut datasets = Vec::new();
         let mut errors = Vec::new();
 
         if self.enable_aiddata {
-            match self.fetch_aiddata_tracks().await {
-                Ok(mut result) => tracks.append(&mut result),
+            match self.fetch_aiddata().await {
+                Ok(mut result) => datasets.append(&mut result),
                 Err(e) => errors.push(format!("aiddata={e}")),
             }
         }
 
         if self.enable_gee {
-            match self.fetch_gee_tracks().await {
-                Ok(mut result) => tracks.append(&mut result),
+            match self.fetch_gee().await {
+                Ok(mut result) => datasets.append(&mut result),
                 Err(e) => errors.push(format!("gee={e}")),
             }
         }
 
         if self.enable_dataverse {
-            match self.fetch_dataverse_tracks().await {
-                Ok(mut result) => tracks.append(&mut result),
+            match self.fetch_dataverse().await {
+                Ok(mut result) => datasets.append(&mut result),
                 Err(e) => errors.push(format!("dataverse={e}")),
             }
         }
 
-        if tracks.is_empty() && !errors.is_empty() {
+        if datasets.is_empty() && !errors.is_empty() {
             anyhow::bail!(
                 "open-data catalog fetch failed for all enabled sources: {}",
                 errors.join("; ")
             );
         }
+        if !errors.is_empty() {
+            tracing::warn!("Open-data catalog sources failed: {}", errors.join("; "));
+        }
+
+        Ok(datasets)
+    }
+}
+
+fn aiddata_dataset(entry: AidDataCatalogEntry) -> CatalogDataset {
+    CatalogDataset {
+        id: format!("{SOURCE_AIDDATA}:{}", entry.slug),
+        source: SOURCE_AIDDATA.to_string(),
+        title: entry.title,
+        description: entry.description,
+        publisher: entry.publisher,
+        tags: split_tags(&entry.tags),
+        dataset_url: Some(entry.dataset_url),
+        license: None,
+        bbox: None,
+        published_at: None,
+        attributes: HashMap::new(),
+    }
+}
 
-        Ok(tracks)
+fn gee_dataset(entry: GeeCommunityEntry) -> CatalogDataset {
+    let mut attributes = HashMap::new();
+    attributes.insert("asset_id".to_string(), entry.id.clone());
+    if let Some(group) = entry.thematic_group {
+        attributes.insert("thematic_group".to_string(), group);
+    }
+    if let Some(item_type) = entry.item_type {
+        attributes.insert("asset_type".to_string(), item_type);
     }
 
-    fn provider_id(&self) -> &str {
-        &self.provider_id
+    CatalogDataset {
+        id: format!("{SOURCE_GEE}:{}", entry.id.trim()),
+        source: SOURCE_GEE.to_string(),
+        title: entry.title,
+        description: String::new(),
+        publisher: entry.provider.unwrap_or_default(),
+        tags: entry.tags.as_deref().map(split_tags).unwrap_or_default(),
+        dataset_url: entry.docs,
+        license: entry.license,
+        bbox: None,
+        published_at: None,
+        attributes,
     }
 }
 
+fn dataverse_dataset(item: DataverseDatasetItem) -> CatalogDataset {
+    let bbox = item
+        .metadata_blocks
+        .get("geospatial")
+        .and_then(extract_bbox_from_geospatial_block);
+
+    let mut attributes = HashMap::new();
+    if !item.global_id.is_empty() {
+        attributes.insert("global_id".to_string(), item.global_id.clone());
+    }
+    let identity = if item.global_id.is_empty() {
+        item.name.clone()
+    } else {
+        item.global_id
+    };
+
+    CatalogDataset {
+        id: format!("{SOURCE_DATAVERSE}:{identity}"),
+        source: SOURCE_DATAVERSE.to_string(),
+        title: item.name,
+        description: String::new(),
+        publisher: item.publisher.unwrap_or_default(),
+        tags: item.subjects,
+        dataset_url: item.url,
+        license: None,
+        bbox,
+        published_at: item.published_at.as_deref().and_then(parse_published_at),
+        attributes,
+    }
+}
+
+fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
 fn parse_aiddata_entries(html: &str, max_items: usize) -> Vec<AidDataCatalogEntry> {
     let mut entries = Vec::new();
     let mut seen_slugs = HashSet::new();
@@ -540,12 +478,7 @@ fn extract_bbox_from_geospatial_block(block: &DataverseMetadataBlock) -> Option<
 }
 
 fn extract_nested_value(value: &Value, key: &str) -> Option<f64> {
-    value
-        .get(key)?
-        .get("value")?
-        .as_str()?
-        .parse::<f64>()
-        .ok()
+    value.get(key)?.get("value")?.as_str()?.parse::<f64>().ok()
 }
 
 fn extract_between<'a>(text: &'a str, start: &str, end: &str) -> Option<&'a str> {
@@ -614,39 +547,6 @@ fn parse_lat_lon_pair(raw: &str) -> anyhow::Result<Option<(f64, f64)>> {
     Ok(Some((lat, lon)))
 }
 
-fn synthetic_position(seed: &str, namespace: &str) -> Position {
-    let hash = stable_hash(format!("{namespace}:{seed}").as_bytes());
-
-    let lat_fraction = (hash & 0x0000_FFFF) as f64 / 65_535.0;
-    let lon_fraction = ((hash >> 16) & 0x0000_FFFF) as f64 / 65_535.0;
-
-    Position {
-        lat: (lat_fraction * 150.0) - 75.0,
-        lon: (lon_fraction * 360.0) - 180.0,
-        alt: 0.0,
-    }
-}
-
-fn make_track_id(prefix: &str, value: &str) -> String {
-    format!("{prefix}-{:016x}", stable_hash(value.as_bytes()))
-}
-
-fn stable_hash(bytes: &[u8]) -> u64 {
-    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
-    for byte in bytes {
-        hash ^= u64::from(*byte);
-        hash = hash.wrapping_mul(0x1000_0000_01b3);
-    }
-    hash
-}
-
-fn now_ms() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|duration| duration.as_millis() as u64)
-        .unwrap_or(0)
-}
-
 fn env_usize(name: &str, default: usize) -> usize {
     std::env::var(name)
         .ok()
@@ -664,10 +564,10 @@ fn env_bool(name: &str, default: bool) -> bool {
     }
 }
 
-fn _parse_published_ts_ms(value: &str) -> Option<u64> {
+fn parse_published_at(value: &str) -> Option<DateTime<Utc>> {
     DateTime::parse_from_rfc3339(value)
         .ok()
-        .map(|datetime| datetime.timestamp_millis().max(0) as u64)
+        .map(|datetime| datetime.with_timezone(&Utc))
 }
 
 #[cfg(test)]
@@ -698,7 +598,10 @@ mod tests {
         assert_eq!(entry.slug, "test-dataset");
         assert_eq!(entry.title, "Test & Dataset");
         assert_eq!(entry.publisher, "AidData");
-        assert_eq!(entry.dataset_url, "https://www.aiddata.org/geoquery-datasets/test-dataset");
+        assert_eq!(
+            entry.dataset_url,
+            "https://www.aiddata.org/geoquery-datasets/test-dataset"
+        );
     }
 
     #[test]
@@ -733,11 +636,57 @@ mod tests {
     }
 
     #[test]
-    fn synthetic_position_is_deterministic() {
-        let one = synthetic_position("projects/sat-io/open-datasets/demo", "gee");
-        let two = synthetic_position("projects/sat-io/open-datasets/demo", "gee");
-        assert!((one.lat - two.lat).abs() < f64::EPSILON);
-        assert!((one.lon - two.lon).abs() < f64::EPSILON);
+    fn dataverse_items_keep_their_bbox() {
+        let item: DataverseDatasetItem = serde_json::from_value(serde_json::json!({
+            "name": "Chesapeake Bay Salinity",
+            "global_id": "doi:10.7910/DVN/ABC123",
+            "url": "https://doi.org/10.7910/DVN/ABC123",
+            "published_at": "2023-05-01T12:00:00Z",
+            "subjects": ["Earth and Environmental Sciences"],
+            "metadataBlocks": {
+                "geospatial": {
+                    "fields": [{
+                        "typeName": "geographicBoundingBox",
+                        "value": [{
+                            "westLongitude": { "value": "-77.5" },
+                            "eastLongitude": { "value": "-75.5" },
+                            "northLatitude": { "value": "36.8" },
+                            "southLatitude": { "value": "39.6" }
+                        }]
+                    }]
+                }
+            }
+        }))
+        .expect("valid item");
+
+        let dataset = dataverse_dataset(item);
+        assert_eq!(dataset.id, "dataverse:doi:10.7910/DVN/ABC123");
+        assert_eq!(dataset.source, SOURCE_DATAVERSE);
+        let bbox = dataset.bbox.expect("bbox kept");
+        assert!((bbox.south_lat - 36.8).abs() < f64::EPSILON);
+        assert!((bbox.north_lat - 39.6).abs() < f64::EPSILON);
+        assert_eq!(
+            dataset.published_at.map(|ts| ts.timestamp()),
+            Some(1_682_942_400)
+        );
+    }
+
+    #[test]
+    fn gee_entries_have_no_coverage() {
+        let entry: GeeCommunityEntry = serde_json::from_value(serde_json::json!({
+            "title": "Global Mangrove Watch",
+            "id": "projects/sat-io/open-datasets/GMW",
+            "provider": "Global Mangrove Alliance",
+            "tags": "mangroves, coastal, ",
+            "type": "image_collection"
+        }))
+        .expect("valid entry");
+
+        let dataset = gee_dataset(entry);
+        assert_eq!(dataset.id, "gee:projects/sat-io/open-datasets/GMW");
+        assert!(dataset.bbox.is_none());
+        assert_eq!(dataset.tags, vec!["mangroves", "coastal"]);
+        assert_eq!(dataset.attributes["asset_type"], "image_collection");
     }
 
     #[test]
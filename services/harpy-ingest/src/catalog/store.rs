@@ -0,0 +1,272 @@
+//! Catalog Storage
+//!
+//! Upserts dataset listings into `catalog_datasets` (migration 006) and
+//! searches them by text, source and bounding-box intersection.
+
+use super::{BBox, CatalogDataset};
+use crate::storage::PostgresStore;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{postgres::PgRow, Postgres, QueryBuilder, Row};
+use std::collections::HashMap;
+
+/// Text search configuration shared by the upsert and the query
+const SEARCH_CONFIG: &str = "english";
+
+const SELECT_COLUMNS: &str =
+    "SELECT id, source, title, description, publisher, tags, dataset_url, \
+     license, attributes, west_lon, south_lat, east_lon, north_lat, published_at, \
+     first_seen_at, last_seen_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Centroid {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// A stored dataset as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogEntry {
+    #[serde(flatten)]
+    pub dataset: CatalogDataset,
+    pub centroid: Option<Centroid>,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CatalogSearch {
+    /// Web-search syntax: words, `"phrases"`, `or`, `-excluded`
+    pub text: Option<String>,
+    pub source: Option<String>,
+    /// Datasets whose coverage intersects this box
+    pub bbox: Option<BBox>,
+    /// With `bbox`, also return datasets that publish no coverage
+    pub include_unbounded: bool,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Insert new datasets and refresh existing ones in one transaction
+pub async fn upsert_datasets(
+    postgres: &PostgresStore,
+    datasets: &[CatalogDataset],
+) -> anyhow::Result<()> {
+    if datasets.is_empty() {
+        return Ok(());
+    }
+    let statement = format!(
+        r#"
+        INSERT INTO catalog_datasets (
+            id, source, title, description, publisher, tags, dataset_url, license, attributes,
+            west_lon, south_lat, east_lon, north_lat, published_at, search
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14,
+            setweight(to_tsvector('{config}', $3), 'A')
+                || setweight(to_tsvector('{config}', array_to_string($6, ' ')), 'B')
+                || setweight(to_tsvector('{config}', $5), 'C')
+                || setweight(to_tsvector('{config}', $4), 'D')
+        )
+        ON CONFLICT (id) DO UPDATE SET
+            source = EXCLUDED.source,
+            title = EXCLUDED.title,
+            description = EXCLUDED.description,
+            publisher = EXCLUDED.publisher,
+            tags = EXCLUDED.tags,
+            dataset_url = EXCLUDED.dataset_url,
+            license = EXCLUDED.license,
+            attributes = EXCLUDED.attributes,
+            west_lon = EXCLUDED.west_lon,
+            south_lat = EXCLUDED.south_lat,
+            east_lon = EXCLUDED.east_lon,
+            north_lat = EXCLUDED.north_lat,
+            published_at = EXCLUDED.published_at,
+            search = EXCLUDED.search,
+            last_seen_at = NOW()
+        "#,
+        config = SEARCH_CONFIG
+    );
+
+    let mut tx = postgres.pool.begin().await?;
+    for dataset in datasets {
+        let bbox = dataset.bbox;
+        sqlx::query(&statement)
+            .bind(&dataset.id)
+            .bind(&dataset.source)
+            .bind(&dataset.title)
+            .bind(&dataset.description)
+            .bind(&dataset.publisher)
+            .bind(&dataset.tags)
+            .bind(&dataset.dataset_url)
+            .bind(&dataset.license)
+            .bind(serde_json::to_value(&dataset.attributes)?)
+            .bind(bbox.map(|b| b.west_lon))
+            .bind(bbox.map(|b| b.south_lat))
+            .bind(bbox.map(|b| b.east_lon))
+            .bind(bbox.map(|b| b.north_lat))
+            .bind(dataset.published_at)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Matching datasets, best text match first, and the total match count
+pub async fn search(
+    postgres: &PostgresStore,
+    search: &CatalogSearch,
+) -> anyhow::Result<(Vec<CatalogEntry>, i64)> {
+    let mut qb = QueryBuilder::<Postgres>::new(SELECT_COLUMNS);
+    qb.push(", COUNT(*) OVER () AS total FROM catalog_datasets WHERE TRUE");
+    if let Some(text) = &search.text {
+        qb.push(format!(
+            " AND search @@ websearch_to_tsquery('{}', ",
+            SEARCH_CONFIG
+        ))
+        .push_bind(text.clone())
+        .push(")");
+    }
+    if let Some(source) = &search.source {
+        qb.push(" AND source = ").push_bind(source.clone());
+    }
+    if let Some(bbox) = search.bbox {
+        push_bbox_predicate(&mut qb, bbox, search.include_unbounded);
+    }
+
+    qb.push(" ORDER BY ");
+    if let Some(text) = &search.text {
+        qb.push(format!(
+            "ts_rank(search, websearch_to_tsquery('{}', ",
+            SEARCH_CONFIG
+        ))
+        .push_bind(text.clone())
+        .push(")) DESC, ");
+    }
+    qb.push("title, id LIMIT ")
+        .push_bind(search.limit)
+        .push(" OFFSET ")
+        .push_bind(search.offset);
+
+    let rows = qb.build().fetch_all(&postgres.pool).await?;
+    let total = rows
+        .first()
+        .map(|row| row.try_get::<i64, _>("total"))
+        .transpose()?
+        .unwrap_or(0);
+    let entries = rows.iter().map(entry_from_row).collect::<Result<_, _>>()?;
+    Ok((entries, total))
+}
+
+pub async fn get_dataset(
+    postgres: &PostgresStore,
+    id: &str,
+) -> anyhow::Result<Option<CatalogEntry>> {
+    let row = sqlx::query(&format!(
+        "{} FROM catalog_datasets WHERE id = $1",
+        SELECT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&postgres.pool)
+    .await?;
+    Ok(row.as_ref().map(entry_from_row).transpose()?)
+}
+
+/// Push ` AND ...` matching rows whose box intersects `bbox`
+///
+/// Both the stored and the query box may cross the antimeridian, so each is
+/// compared as up to two longitude intervals.
+fn push_bbox_predicate(qb: &mut QueryBuilder<'_, Postgres>, bbox: BBox, include_unbounded: bool) {
+    qb.push(" AND (");
+    if include_unbounded {
+        qb.push("west_lon IS NULL OR ");
+    }
+    qb.push("(south_lat <= ")
+        .push_bind(bbox.north_lat)
+        .push(" AND north_lat >= ")
+        .push_bind(bbox.south_lat)
+        .push(" AND (");
+    for (i, (min_lon, max_lon)) in bbox.lon_ranges().into_iter().enumerate() {
+        if i > 0 {
+            qb.push(" OR ");
+        }
+        qb.push("(west_lon <= east_lon AND west_lon <= ")
+            .push_bind(max_lon)
+            .push(" AND east_lon >= ")
+            .push_bind(min_lon)
+            .push(") OR (west_lon > east_lon AND (west_lon <= ")
+            .push_bind(max_lon)
+            .push(" OR east_lon >= ")
+            .push_bind(min_lon)
+            .push("))");
+    }
+    qb.push(")))");
+}
+
+fn entry_from_row(row: &PgRow) -> Result<CatalogEntry, sqlx::Error> {
+    let bbox = match (
+        row.try_get::<Option<f64>, _>("west_lon")?,
+        row.try_get::<Option<f64>, _>("south_lat")?,
+        row.try_get::<Option<f64>, _>("east_lon")?,
+        row.try_get::<Option<f64>, _>("north_lat")?,
+    ) {
+        (Some(west_lon), Some(south_lat), Some(east_lon), Some(north_lat)) => Some(BBox {
+            west_lon,
+            south_lat,
+            east_lon,
+            north_lat,
+        }),
+        _ => None,
+    };
+    let attributes: serde_json::Value = row.try_get("attributes")?;
+    let attributes: HashMap<String, String> =
+        serde_json::from_value(attributes).unwrap_or_default();
+
+    Ok(CatalogEntry {
+        dataset: CatalogDataset {
+            id: row.try_get("id")?,
+            source: row.try_get("source")?,
+            title: row.try_get("title")?,
+            description: row.try_get("description")?,
+            publisher: row.try_get("publisher")?,
+            tags: row.try_get("tags")?,
+            dataset_url: row.try_get("dataset_url")?,
+            license: row.try_get("license")?,
+            bbox,
+            published_at: row.try_get("published_at")?,
+            attributes,
+        },
+        centroid: bbox.map(|bbox| {
+            let (lat, lon) = bbox.centroid();
+            Centroid { lat, lon }
+        }),
+        first_seen_at: row.try_get("first_seen_at")?,
+        last_seen_at: row.try_get("last_seen_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bbox_predicate_checks_both_halves_of_a_crossing_box() {
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT 1 FROM catalog_datasets WHERE TRUE");
+        push_bbox_predicate(
+            &mut qb,
+            BBox {
+                west_lon: 170.0,
+                south_lat: -20.0,
+                east_lon: -170.0,
+                north_lat: 0.0,
+            },
+            true,
+        );
+        let sql = qb.sql();
+        assert!(sql.contains("west_lon IS NULL OR (south_lat <= $1 AND north_lat >= $2"));
+        // Two longitude intervals, four binds each
+        assert!(sql.contains("east_lon >= $10"));
+        assert!(!sql.contains("$11"));
+    }
+}
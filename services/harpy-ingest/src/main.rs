@@ -1,9 +1,13 @@
 mod adapters;
+mod catalog;
 mod snapshot;
 mod storage;
+mod validation;
 
 use axum::{routing::get, Json, Router};
+use harpy_core::enrichment::Enrichers;
 use harpy_core::types::HealthResponse;
+use harpy_proto::harpy::v1::{CircuitState, Freshness, ProviderStatus};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -15,8 +19,15 @@ use adapters::{
     seismic_usgs::UsgsSeismicProvider, tle_celestrak::CelesTrakProvider, tle_mock::TleMockProvider,
     weather_nws::NwsWeatherProvider, Provider,
 };
-use snapshot::model::{SnapshotMetadata, Viewport, DEFAULT_SNAPSHOT_INTERVAL_SECS};
-use storage::{PostgresStore, RedisStore};
+use catalog::api::CatalogApiState;
+use catalog::sources::OpenDataCatalog;
+use snapshot::api::SnapshotApiState;
+use snapshot::model::DEFAULT_SNAPSHOT_INTERVAL_SECS;
+use snapshot::storage::{open_storage, SnapshotStorage, StorageBackend};
+use snapshot::writer;
+use storage::partitions::{self, PartitionConfig};
+use storage::{PostgresStore, RedisStore, WriteQueue, WriteQueueConfig};
+use validation::{ValidationConfig, Validator};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -34,11 +45,6 @@ async fn main() -> anyhow::Result<()> {
         .unwrap_or_else(|_| "8081".to_string())
         .parse::<u16>()?;
 
-    // Build router
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .layer(TraceLayer::new_for_http());
-
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     tracing::info!("harpy-ingest listening on {}", addr);
 
@@ -76,6 +82,65 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
+    let snapshot_storage = open_storage(StorageBackend::configured()?)?;
+    let metrics = metrics_exporter_prometheus::PrometheusBuilder::new().install_recorder()?;
+
+    // Build router
+    let app = Router::new()
+        .route("/health", get(health_check))
+        .route("/snapshots/:id", get(snapshot::api::get_snapshot))
+        .with_state(SnapshotApiState {
+            postgres: postgres_store.clone(),
+            storage: snapshot_storage.clone(),
+        })
+        .route(
+            "/catalog",
+            get(catalog::api::search_catalog).with_state(CatalogApiState {
+                postgres: postgres_store.clone(),
+            }),
+        )
+        .route(
+            "/catalog/:id",
+            get(catalog::api::get_catalog_dataset).with_state(CatalogApiState {
+                postgres: postgres_store.clone(),
+            }),
+        )
+        .route(
+            "/metrics",
+            get(move || std::future::ready(metrics.render())),
+        )
+        .layer(TraceLayer::new_for_http());
+
+    // track_deltas needs today's partition before the first write lands
+    let partition_config = PartitionConfig::from_env();
+    if let Some(store) = postgres_store.as_ref() {
+        if let Err(e) = partitions::ensure_partitions(store, partition_config.premake_days).await {
+            tracing::warn!("Failed to create track_deltas partitions: {}", e);
+        }
+        tokio::spawn(partitions::run(store.clone(), partition_config));
+    }
+
+    // Fill in h3_index for rows written before it was populated
+    if let Some(store) = postgres_store.clone() {
+        tokio::spawn(storage::h3_backfill::run(store));
+    }
+
+    // Open-data dataset listings for /catalog
+    if let Some(store) = postgres_store.clone() {
+        if let Some(catalog) = select_open_data_catalog() {
+            let interval_secs = env_u64(
+                "CATALOG_REFRESH_INTERVAL_SECS",
+                catalog::DEFAULT_CATALOG_INTERVAL_SECS,
+            );
+            tokio::spawn(catalog::run(catalog, store, interval_secs));
+        }
+    }
+
+    // All provider writes to Postgres go through one bounded queue
+    let write_queue = postgres_store
+        .clone()
+        .map(|store| WriteQueue::spawn(store, WriteQueueConfig::from_env()));
+
     // Start server
     let server_handle = tokio::spawn(async move {
         let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
@@ -83,6 +148,7 @@ async fn main() -> anyhow::Result<()> {
     });
 
     // Start provider polling loops
+    let enrichers = Enrichers::from_env();
     let (adsb_provider, adsb_interval_secs) = select_adsb_provider();
     let (tle_provider, tle_interval_secs) = select_tle_provider();
     let seismic_provider = select_seismic_provider();
@@ -93,23 +159,31 @@ async fn main() -> anyhow::Result<()> {
         adsb_provider,
         adsb_interval_secs,
         redis_store.clone(),
+        write_queue.clone(),
         postgres_store.clone(),
+        enrichers.clone(),
     ));
     let tle_handle = tokio::spawn(poll_provider(
         tle_provider,
         tle_interval_secs,
         redis_store.clone(),
+        write_queue.clone(),
         postgres_store.clone(),
+        enrichers.clone(),
     ));
     let redis_for_seismic = redis_store.clone();
-    let postgres_for_seismic = postgres_store.clone();
+    let queue_for_seismic = write_queue.clone();
+    let quarantine_for_seismic = postgres_store.clone();
+    let enrichers_for_seismic = enrichers.clone();
     let seismic_handle = tokio::spawn(async move {
         if let Some((provider, interval_secs)) = seismic_provider {
             poll_provider(
                 provider,
                 interval_secs,
                 redis_for_seismic,
-                postgres_for_seismic,
+                queue_for_seismic,
+                quarantine_for_seismic,
+                enrichers_for_seismic,
             )
             .await;
         } else {
@@ -117,14 +191,18 @@ async fn main() -> anyhow::Result<()> {
         }
     });
     let redis_for_weather = redis_store.clone();
-    let postgres_for_weather = postgres_store.clone();
+    let queue_for_weather = write_queue.clone();
+    let quarantine_for_weather = postgres_store.clone();
+    let enrichers_for_weather = enrichers.clone();
     let weather_handle = tokio::spawn(async move {
         if let Some((provider, interval_secs)) = weather_provider {
             poll_provider(
                 provider,
                 interval_secs,
                 redis_for_weather,
-                postgres_for_weather,
+                queue_for_weather,
+                quarantine_for_weather,
+                enrichers_for_weather,
             )
             .await;
         } else {
@@ -132,17 +210,27 @@ async fn main() -> anyhow::Result<()> {
         }
     });
     let redis_for_radar = redis_store.clone();
-    let postgres_for_radar = postgres_store.clone();
+    let queue_for_radar = write_queue.clone();
+    let quarantine_for_radar = postgres_store.clone();
+    let enrichers_for_radar = enrichers.clone();
     let radar_handle = tokio::spawn(async move {
         if let Some((provider, interval_secs)) = radar_provider {
-            poll_provider(provider, interval_secs, redis_for_radar, postgres_for_radar).await;
+            poll_provider(
+                provider,
+                interval_secs,
+                redis_for_radar,
+                queue_for_radar,
+                quarantine_for_radar,
+                enrichers_for_radar,
+            )
+            .await;
         } else {
             futures::future::pending::<()>().await;
         }
     });
 
     // Start periodic snapshot creation job (B2-2)
-    let snapshot_handle = tokio::spawn(snapshot_creation_job(postgres_store));
+    let snapshot_handle = tokio::spawn(snapshot_creation_job(postgres_store, snapshot_storage));
 
     // Wait for all tasks
     tokio::select! {
@@ -162,10 +250,22 @@ async fn poll_provider(
     provider: Arc<dyn Provider>,
     interval_secs: u64,
     mut redis_store: Option<RedisStore>,
-    postgres_store: Option<PostgresStore>,
+    write_queue: Option<WriteQueue>,
+    quarantine: Option<PostgresStore>,
+    enrichers: Enrichers,
 ) {
     let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
     let mut consecutive_failures: u32 = 0;
+    let mut last_status: Option<ProviderStatus> = None;
+    let mut validator = Validator::new(provider.provider_id(), ValidationConfig::from_env());
+    let enrichment = enrichers.pipeline_for(provider.provider_id());
+    if !enrichment.is_empty() {
+        tracing::info!(
+            "Enriching {} with {}",
+            provider.provider_id(),
+            enrichment.stage_names().join(", ")
+        );
+    }
     tracing::info!(
         "Starting provider poll loop: provider={} interval_secs={}",
         provider.provider_id(),
@@ -183,6 +283,25 @@ async fn poll_provider(
                     provider.provider_id()
                 );
 
+                let (mut tracks, rejected) = validator.validate(tracks, now_ms());
+                enrichment.enrich(&mut tracks);
+                if !rejected.is_empty() {
+                    tracing::warn!(
+                        "Quarantined {} of {} records from {}",
+                        rejected.len(),
+                        rejected.len() + tracks.len(),
+                        provider.provider_id()
+                    );
+                    if let Some(store) = quarantine.clone() {
+                        let provider_id = provider.provider_id().to_string();
+                        tokio::spawn(async move {
+                            if let Err(e) = store.insert_quarantine(&provider_id, &rejected).await {
+                                tracing::error!("Failed to write quarantine rows: {}", e);
+                            }
+                        });
+                    }
+                }
+
                 // Store in Redis
                 if let Some(ref mut redis) = redis_store {
                     if let Err(e) = redis.store_tracks(&tracks).await {
@@ -191,27 +310,28 @@ async fn poll_provider(
                     if let Err(e) = redis.publish_track_batch(&tracks).await {
                         tracing::error!("Failed to publish tracks to Redis: {}", e);
                     }
+                    let status = ProviderStatus {
+                        provider_id: provider.provider_id().to_string(),
+                        circuit_state: CircuitState::Closed as i32,
+                        freshness: Freshness::Fresh as i32,
+                        last_success_ts_ms: now_ms(),
+                        failure_count: 0,
+                        error_message: None,
+                        meta: Default::default(),
+                    };
                     if let Err(e) = redis
-                        .update_provider_status(
-                            provider.provider_id(),
-                            "CIRCUIT_STATE_CLOSED",
-                            "FRESHNESS_FRESH",
-                            true,
-                        )
+                        .update_provider_status(last_status.as_ref(), &status)
                         .await
                     {
                         tracing::error!("Failed to update provider status: {}", e);
+                    } else {
+                        last_status = Some(status);
                     }
                 }
 
                 // Store in Postgres
-                if let Some(ref postgres) = postgres_store {
-                    if let Err(e) = postgres.upsert_tracks(&tracks).await {
-                        tracing::error!("Failed to upsert tracks in Postgres: {}", e);
-                    }
-                    if let Err(e) = postgres.store_track_deltas(&tracks).await {
-                        tracing::error!("Failed to store track deltas in Postgres: {}", e);
-                    }
+                if let Some(ref queue) = write_queue {
+                    queue.submit(provider.provider_id(), tracks).await;
                 }
             }
             Err(e) => {
@@ -220,14 +340,25 @@ async fn poll_provider(
 
                 // Update provider status to indicate failure
                 if let Some(ref mut redis) = redis_store {
-                    let _ = redis
-                        .update_provider_status(
-                            provider.provider_id(),
-                            "CIRCUIT_STATE_OPEN",
-                            "FRESHNESS_CRITICAL",
-                            false,
-                        )
-                        .await;
+                    let status = ProviderStatus {
+                        provider_id: provider.provider_id().to_string(),
+                        circuit_state: CircuitState::Open as i32,
+                        freshness: Freshness::Critical as i32,
+                        last_success_ts_ms: last_status
+                            .as_ref()
+                            .map_or(0, |status| status.last_success_ts_ms),
+                        failure_count: consecutive_failures,
+                        error_message: Some(e.to_string()),
+                        meta: Default::default(),
+                    };
+                    if let Err(e) = redis
+                        .update_provider_status(last_status.as_ref(), &status)
+                        .await
+                    {
+                        tracing::error!("Failed to update provider status: {}", e);
+                    } else {
+                        last_status = Some(status);
+                    }
                 }
 
                 // Progressive backoff protects upstream APIs from tight retry loops.
@@ -386,6 +517,27 @@ fn select_radar_provider() -> Option<(Arc<dyn Provider>, u64)> {
     }
 }
 
+fn select_open_data_catalog() -> Option<OpenDataCatalog> {
+    if !env_bool("ENABLE_OPEN_DATA_CATALOG", false) {
+        tracing::info!("Open-data catalog disabled");
+        return None;
+    }
+
+    match OpenDataCatalog::from_env() {
+        Ok(catalog) => {
+            tracing::info!("Using open-data catalog: AidData, GEE community, Dataverse");
+            Some(catalog)
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Failed to initialize open-data catalog: {}. Disabling catalog.",
+                e
+            );
+            None
+        }
+    }
+}
+
 fn env_u64(name: &str, default: u64) -> u64 {
     std::env::var(name)
         .ok()
@@ -403,6 +555,13 @@ fn env_bool(name: &str, default: bool) -> bool {
     }
 }
 
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
 async fn health_check() -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "ok".to_string(),
@@ -411,7 +570,10 @@ async fn health_check() -> Json<HealthResponse> {
 }
 
 /// Periodic snapshot creation job (B2-2)
-async fn snapshot_creation_job(postgres_store: Option<PostgresStore>) {
+async fn snapshot_creation_job(
+    postgres_store: Option<PostgresStore>,
+    storage: Arc<dyn SnapshotStorage>,
+) {
     let interval_secs = std::env::var("SNAPSHOT_INTERVAL_SECS")
         .unwrap_or_else(|_| DEFAULT_SNAPSHOT_INTERVAL_SECS.to_string())
         .parse::<u64>()
@@ -432,112 +594,16 @@ async fn snapshot_creation_job(postgres_store: Option<PostgresStore>) {
             continue;
         };
 
-        match create_snapshot(postgres).await {
-            Ok(snapshot_id) => {
-                tracing::info!("Created snapshot: {}", snapshot_id);
-            }
-            Err(e) => {
-                tracing::error!("Failed to create snapshot: {}", e);
-            }
+        if let Err(e) =
+            writer::create_snapshot(postgres, storage.as_ref(), interval_secs * 1000).await
+        {
+            tracing::error!("Failed to create snapshot: {}", e);
         }
-    }
-}
-
-/// Create a snapshot of current track state
-async fn create_snapshot(postgres: &PostgresStore) -> anyhow::Result<String> {
-    use sqlx::Row;
-    use uuid::Uuid;
-
-    let snapshot_id = format!("snap-{}", Uuid::new_v4().simple());
-    let now_ms = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)?
-        .as_millis() as u64;
-
-    // Get snapshot window (last interval)
-    let interval_ms = DEFAULT_SNAPSHOT_INTERVAL_SECS * 1000;
-    let start_ts_ms = now_ms.saturating_sub(interval_ms);
-    let end_ts_ms = now_ms;
-
-    // Query current tracks from database
-    let tracks = sqlx::query(
-        r#"
-        SELECT id, kind, lat, lon, alt, heading, speed, ts_ms, provider_id, meta
-        FROM tracks
-        WHERE ts_ms >= $1 AND ts_ms <= $2
-        "#,
-    )
-    .bind(start_ts_ms as i64)
-    .bind(end_ts_ms as i64)
-    .fetch_all(&postgres.pool)
-    .await?;
 
-    let track_count = tracks.len();
-
-    if track_count == 0 {
-        tracing::debug!("No tracks to snapshot in time window");
-        return Ok(snapshot_id);
-    }
-
-    // Create snapshot metadata
-    let metadata = SnapshotMetadata::new(
-        snapshot_id.clone(),
-        start_ts_ms,
-        end_ts_ms,
-        format!("snapshots/{}", snapshot_id),
-        "local".to_string(), // TODO: Use configured storage backend
-    )
-    .with_viewport(Viewport::world())
-    .with_meta("format_version", "1.0.0")
-    .with_meta("created_by", "harpy-ingest")
-    .with_track_count(track_count);
-
-    // Insert snapshot metadata into database
-    sqlx::query(
-        r#"
-        INSERT INTO snapshots (id, start_ts_ms, end_ts_ms, track_count, compressed_size_bytes, storage_path, storage_backend, meta)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-        "#
-    )
-    .bind(&metadata.id)
-    .bind(metadata.start_ts_ms as i64)
-    .bind(metadata.end_ts_ms as i64)
-    .bind(metadata.track_count as i32)
-    .bind(metadata.compressed_size_bytes as i64)
-    .bind(&metadata.storage_path)
-    .bind(&metadata.storage_backend)
-    .bind(sqlx::types::Json(&metadata.meta))
-    .execute(&postgres.pool)
-    .await?;
-
-    // Insert track membership records
-    for row in tracks {
-        let track_id: String = row.get("id");
-        let track_kind: String = row.get("kind");
-        let track_ts_ms: i64 = row.get("ts_ms");
-
-        sqlx::query(
-            r#"
-            INSERT INTO snapshot_tracks (snapshot_id, track_id, track_kind, first_ts_ms, last_ts_ms, position_count)
-            VALUES ($1, $2, $3, $4, $5, $6)
-            "#
-        )
-        .bind(&snapshot_id)
-        .bind(&track_id)
-        .bind(&track_kind)
-        .bind(track_ts_ms)
-        .bind(track_ts_ms)
-        .bind(1i32)
-        .execute(&postgres.pool)
-        .await?;
+        match writer::prune_expired(postgres, storage.as_ref()).await {
+            Ok(0) => {}
+            Ok(pruned) => tracing::info!("Pruned {} expired snapshots", pruned),
+            Err(e) => tracing::warn!("Failed to prune expired snapshots: {}", e),
+        }
     }
-
-    tracing::info!(
-        "Created snapshot {} with {} tracks ({} - {})",
-        snapshot_id,
-        track_count,
-        start_ts_ms,
-        end_ts_ms
-    );
-
-    Ok(snapshot_id)
 }
@@ -0,0 +1,220 @@
+//! Snapshot Download API
+//!
+//! `GET /snapshots/:id` streams a stored snapshot blob as-is, or with
+//! `?format=ndjson` decodes it and streams one JSON track state per line.
+
+use super::storage::{decode_blob, SnapshotStorage};
+use crate::storage::PostgresStore;
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::TryStreamExt;
+use harpy_proto::harpy::v1::TrackDelta;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct SnapshotApiState {
+    pub postgres: Option<PostgresStore>,
+    pub storage: Arc<dyn SnapshotStorage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotQuery {
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotError {
+    pub error: String,
+    pub code: String,
+}
+
+fn error(status: StatusCode, error: impl Into<String>, code: &str) -> Response {
+    (
+        status,
+        Json(SnapshotError {
+            error: error.into(),
+            code: code.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+pub async fn get_snapshot(
+    State(state): State<SnapshotApiState>,
+    Path(snapshot_id): Path<String>,
+    Query(query): Query<SnapshotQuery>,
+) -> Response {
+    let ndjson = match query.format.as_deref() {
+        None | Some("blob") => false,
+        Some("ndjson") => true,
+        Some(other) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown format '{}'; use blob or ndjson", other),
+                "INVALID_FORMAT",
+            )
+        }
+    };
+    let Some(postgres) = state.postgres.as_ref() else {
+        return error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Postgres unavailable",
+            "DB_UNAVAILABLE",
+        );
+    };
+
+    let row = sqlx::query(
+        "SELECT storage_path, storage_backend, compressed_size_bytes FROM snapshots WHERE id = $1",
+    )
+    .bind(&snapshot_id)
+    .fetch_optional(&postgres.pool)
+    .await;
+    let row = match row {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            return error(
+                StatusCode::NOT_FOUND,
+                format!("Snapshot {} not found", snapshot_id),
+                "NOT_FOUND",
+            )
+        }
+        Err(e) => {
+            return error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to look up snapshot: {}", e),
+                "DB_QUERY_FAILED",
+            )
+        }
+    };
+    let storage_path: String = row.get("storage_path");
+    let storage_backend: String = row.get("storage_backend");
+    let size_bytes: i64 = row.get("compressed_size_bytes");
+
+    if storage_backend != state.storage.backend().name() || size_bytes == 0 {
+        // Written to another backend, or a pre-2.0 metadata-only snapshot
+        return error(
+            StatusCode::NOT_FOUND,
+            format!("Snapshot {} has no blob in configured storage", snapshot_id),
+            "BLOB_NOT_FOUND",
+        );
+    }
+    let stream = match state.storage.read_stream(&storage_path).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            tracing::warn!("Failed to open snapshot {}: {}", snapshot_id, e);
+            return error(
+                StatusCode::NOT_FOUND,
+                format!("Snapshot {} blob is missing", snapshot_id),
+                "BLOB_NOT_FOUND",
+            );
+        }
+    };
+
+    if !ndjson {
+        return (
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (header::CONTENT_LENGTH, size_bytes.to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}.zst\"", snapshot_id),
+                ),
+            ],
+            Body::from_stream(stream),
+        )
+            .into_response();
+    }
+
+    // The blob is one zstd frame of one protobuf message, so it is decoded
+    // whole; the decoded states are streamed line by line.
+    let data = match stream
+        .try_fold(Vec::new(), |mut data, chunk| async move {
+            data.extend_from_slice(&chunk);
+            Ok(data)
+        })
+        .await
+    {
+        Ok(data) => data,
+        Err(e) => {
+            return error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read snapshot: {}", e),
+                "STORAGE_READ_FAILED",
+            )
+        }
+    };
+    let blob = match decode_blob(&data) {
+        Ok(blob) => blob,
+        Err(e) => {
+            return error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to decode snapshot: {}", e),
+                "CORRUPT_SNAPSHOT",
+            )
+        }
+    };
+    let lines = futures::stream::iter(
+        blob.tracks
+            .into_iter()
+            .map(|track| Ok::<_, std::io::Error>(track_line(&track))),
+    );
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
+
+fn track_line(track: &TrackDelta) -> String {
+    let position = track.position.clone().unwrap_or_default();
+    let mut line = serde_json::json!({
+        "id": track.id,
+        "kind": track.kind().as_str_name(),
+        "lat": position.lat,
+        "lon": position.lon,
+        "alt": position.alt,
+        "heading": track.heading,
+        "speed": track.speed,
+        "ts_ms": track.ts_ms,
+        "provider_id": track.provider_id,
+        "meta": track.meta,
+    })
+    .to_string();
+    line.push('\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harpy_proto::harpy::v1::Position;
+
+    #[test]
+    fn test_track_line_is_one_json_object() {
+        let track = TrackDelta {
+            id: "a1".to_string(),
+            kind: 1,
+            position: Some(Position {
+                lat: 1.0,
+                lon: 2.0,
+                alt: 3.0,
+            }),
+            ts_ms: 42,
+            ..Default::default()
+        };
+        let line = track_line(&track);
+        assert!(line.ends_with('\n'));
+        let value: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(value["kind"], "TRACK_KIND_AIRCRAFT");
+        assert_eq!(value["lat"], 1.0);
+        assert_eq!(value["ts_ms"], 42);
+    }
+}
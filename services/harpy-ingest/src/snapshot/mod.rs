@@ -3,5 +3,9 @@
 //! Handles creation, storage, and retrieval of periodic track snapshots
 //! for DVR time-travel playback functionality.
 
+pub mod api;
 pub mod model;
+pub mod s3;
+pub mod sigv4;
 pub mod storage;
+pub mod writer;
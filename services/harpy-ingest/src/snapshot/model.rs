@@ -48,8 +48,11 @@ pub struct Snapshot {
     pub data: Vec<u8>, // Compressed track data
 }
 
-/// Snapshot format version for compatibility
-pub const SNAPSHOT_FORMAT_VERSION: &str = "1.0.0";
+/// Snapshot format version for compatibility; 1.0.0 snapshots have no blob
+pub const SNAPSHOT_FORMAT_VERSION: &str = "2.0.0";
+
+/// Version byte written after the blob magic
+pub const SNAPSHOT_BLOB_VERSION: u8 = 2;
 
 /// Default snapshot interval in seconds
 pub const DEFAULT_SNAPSHOT_INTERVAL_SECS: u64 = 300; // 5 minutes
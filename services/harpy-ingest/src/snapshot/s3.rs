@@ -0,0 +1,959 @@
+//! S3-Compatible Snapshot Storage
+//!
+//! Stores snapshots in a MinIO/S3 bucket using path-style requests signed
+//! with SigV4. Objects follow the `snapshot_key` layout under `snapshots/`:
+//!
+//! - `snapshots/YYYY/MM/DD/<id>.zst`  — the blob, uploaded in parts above a size threshold
+//! - `snapshots/YYYY/MM/DD/<id>.json` — manifest with metadata and track membership
+//! - `snapshots/ids/<id>`             — blob key, so lookups by ID skip the date walk
+//!
+//! Retention is a bucket lifecycle rule scoped to that prefix rather than a
+//! delete loop here; other rules on the bucket are kept.
+
+use super::model::{Snapshot, SnapshotMetadata};
+use super::sigv4::{self, Credentials, SigningRequest, EMPTY_PAYLOAD_SHA256};
+use super::storage::{BlobStream, SnapshotManifest, SnapshotStorage, StorageBackend};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use chrono::{Datelike, TimeZone};
+use md5::{Digest, Md5};
+use reqwest::{Method, StatusCode, Url};
+
+/// Smallest part S3 accepts, other than the last one
+const MIN_PART_BYTES: usize = 5 * 1024 * 1024;
+
+/// Above this many day prefixes `list` scans the whole bucket instead
+const MAX_LIST_PREFIXES: i64 = 400;
+
+/// Lifecycle rule ID, so re-applying replaces rather than duplicates it
+const RETENTION_RULE_ID: &str = "harpy-snapshot-retention";
+
+/// Prefix of every object written here, and the scope of the retention rule
+pub const KEY_PREFIX: &str = "snapshots/";
+
+/// Index prefix used before keys moved under `KEY_PREFIX`
+const LEGACY_INDEX_PREFIX: &str = "ids/";
+
+/// Upload and retention tuning
+#[derive(Debug, Clone)]
+pub struct S3Options {
+    /// Blobs larger than this use multipart upload
+    pub multipart_threshold: usize,
+    /// Part size for multipart uploads
+    pub part_size: usize,
+    /// Expire snapshots after this many days; 0 keeps them forever
+    pub retention_days: u32,
+}
+
+impl Default for S3Options {
+    fn default() -> Self {
+        Self {
+            multipart_threshold: 16 * 1024 * 1024,
+            part_size: 8 * 1024 * 1024,
+            retention_days: 0,
+        }
+    }
+}
+
+impl S3Options {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let env_usize = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            multipart_threshold: env_usize(
+                "S3_MULTIPART_THRESHOLD_BYTES",
+                defaults.multipart_threshold,
+            ),
+            part_size: env_usize("S3_MULTIPART_PART_BYTES", defaults.part_size).max(MIN_PART_BYTES),
+            retention_days: std::env::var("S3_RETENTION_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.retention_days),
+        }
+    }
+}
+
+pub struct S3SnapshotStorage {
+    backend: StorageBackend,
+    client: reqwest::Client,
+    endpoint: Url,
+    /// `Host` header as reqwest sends it, port included when not the default
+    host: String,
+    bucket: String,
+    credentials: Credentials,
+    options: S3Options,
+}
+
+impl S3SnapshotStorage {
+    pub fn new(backend: StorageBackend, options: S3Options) -> Result<Self> {
+        let StorageBackend::S3 {
+            endpoint,
+            bucket,
+            access_key,
+            secret_key,
+            region,
+        } = &backend
+        else {
+            anyhow::bail!("S3 snapshot storage needs an S3 backend");
+        };
+        let endpoint =
+            Url::parse(endpoint).with_context(|| format!("Invalid S3 endpoint {}", endpoint))?;
+        let host = match (endpoint.host_str(), endpoint.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => anyhow::bail!("S3 endpoint {} has no host", endpoint),
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            host,
+            bucket: bucket.clone(),
+            credentials: Credentials {
+                access_key: access_key.clone(),
+                secret_key: secret_key.clone(),
+                region: region.clone(),
+            },
+            endpoint,
+            options,
+            backend,
+        })
+    }
+
+    /// Create the bucket if missing and merge the retention rule into the
+    /// bucket's lifecycle configuration
+    pub async fn prepare(&self) -> Result<()> {
+        let head = self.send(Method::HEAD, "", &[], &[], Vec::new()).await?;
+        if head.status() == StatusCode::NOT_FOUND {
+            let created = self.send(Method::PUT, "", &[], &[], Vec::new()).await?;
+            check(created, "create bucket").await?;
+            tracing::info!("Created snapshot bucket {}", self.bucket);
+        } else {
+            check(head, "check bucket").await?;
+        }
+
+        if self.options.retention_days > 0 {
+            let existing = self
+                .send(Method::GET, "", &[("lifecycle", "")], &[], Vec::new())
+                .await?;
+            let existing = if existing.status() == StatusCode::NOT_FOUND {
+                // NoSuchLifecycleConfiguration
+                None
+            } else {
+                Some(check(existing, "read lifecycle").await?.text().await?)
+            };
+            let body = lifecycle_xml(existing.as_deref(), self.options.retention_days).into_bytes();
+            let md5 = base64::engine::general_purpose::STANDARD.encode(Md5::digest(&body));
+            let response = self
+                .send(
+                    Method::PUT,
+                    "",
+                    &[("lifecycle", "")],
+                    &[("content-md5", &md5)],
+                    body,
+                )
+                .await?;
+            check(response, "apply lifecycle").await?;
+            tracing::info!(
+                "Snapshots in {} expire after {} days",
+                self.bucket,
+                self.options.retention_days
+            );
+        }
+        Ok(())
+    }
+
+    /// Signed request for `key` in the bucket (the bucket itself when empty)
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response> {
+        let path = if key.is_empty() {
+            format!("/{}", self.bucket)
+        } else {
+            format!("/{}/{}", self.bucket, key)
+        };
+        let payload_sha256 = if body.is_empty() {
+            EMPTY_PAYLOAD_SHA256.to_string()
+        } else {
+            sigv4::sha256_hex(&body)
+        };
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let authorization = sigv4::authorization(
+            &self.credentials,
+            &SigningRequest {
+                method: method.as_str(),
+                host: &self.host,
+                path: &path,
+                query,
+                headers,
+                payload_sha256: &payload_sha256,
+                amz_date: &amz_date,
+            },
+        );
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&sigv4::encode_path(&path));
+        if query.is_empty() {
+            url.set_query(None);
+        } else {
+            url.set_query(Some(&sigv4::canonical_query(query)));
+        }
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_sha256)
+            .header("authorization", authorization);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("S3 request for {} failed", path))
+    }
+
+    async fn put_object(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let response = self.send(Method::PUT, key, &[], &[], data).await?;
+        check(response, key).await.map(drop)
+    }
+
+    /// Object body, or `None` when it does not exist
+    async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let response = self.send(Method::GET, key, &[], &[], Vec::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let data = check(response, key).await?.bytes().await?;
+        Ok(Some(data.to_vec()))
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<()> {
+        let response = self.send(Method::DELETE, key, &[], &[], Vec::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check(response, key).await.map(drop)
+    }
+
+    async fn put_blob(&self, key: &str, data: &[u8]) -> Result<()> {
+        if data.len() <= self.options.multipart_threshold {
+            return self.put_object(key, data.to_vec()).await;
+        }
+
+        let response = self
+            .send(Method::POST, key, &[("uploads", "")], &[], Vec::new())
+            .await?;
+        let body = check(response, key).await?.text().await?;
+        let upload_id = xml_values(&body, "UploadId")
+            .into_iter()
+            .next()
+            .context("CreateMultipartUpload response has no UploadId")?;
+
+        match self.upload_parts(key, &upload_id, data).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                // Parts of an abandoned upload are billed until aborted
+                let abort = self
+                    .send(
+                        Method::DELETE,
+                        key,
+                        &[("uploadId", &upload_id)],
+                        &[],
+                        Vec::new(),
+                    )
+                    .await;
+                if let Err(abort) = abort {
+                    tracing::warn!("Failed to abort upload of {}: {}", key, abort);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn upload_parts(&self, key: &str, upload_id: &str, data: &[u8]) -> Result<()> {
+        let mut parts = Vec::new();
+        for (index, chunk) in data.chunks(self.options.part_size.max(1)).enumerate() {
+            let part_number = (index + 1).to_string();
+            let response = self
+                .send(
+                    Method::PUT,
+                    key,
+                    &[("partNumber", &part_number), ("uploadId", upload_id)],
+                    &[],
+                    chunk.to_vec(),
+                )
+                .await?;
+            let response = check(response, key).await?;
+            let etag = response
+                .headers()
+                .get("etag")
+                .and_then(|v| v.to_str().ok())
+                .context("UploadPart response has no ETag")?
+                .to_string();
+            parts.push((part_number, etag));
+        }
+
+        let response = self
+            .send(
+                Method::POST,
+                key,
+                &[("uploadId", upload_id)],
+                &[],
+                complete_multipart_xml(&parts).into_bytes(),
+            )
+            .await?;
+        let body = check(response, key).await?.text().await?;
+        // CompleteMultipartUpload can fail after the 200 status line is sent
+        if body.contains("<Error>") {
+            anyhow::bail!("Multipart upload of {} failed: {}", key, body);
+        }
+        Ok(())
+    }
+
+    /// Key of the blob `storage_path` points at, if it is in this bucket
+    fn object_key<'a>(&self, storage_path: &'a str) -> Result<&'a str> {
+        storage_path
+            .strip_prefix("s3://")
+            .and_then(|rest| rest.strip_prefix(self.bucket.as_str()))
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|key| !key.is_empty())
+            .with_context(|| {
+                format!(
+                    "Snapshot path {} is not in bucket {}",
+                    storage_path, self.bucket
+                )
+            })
+    }
+
+    /// All keys under `prefix`, following continuation tokens
+    async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = token.as_deref() {
+                query.push(("continuation-token", token));
+            }
+            let response = self.send(Method::GET, "", &query, &[], Vec::new()).await?;
+            let body = check(response, "list objects").await?.text().await?;
+            keys.extend(xml_values(&body, "Key"));
+
+            let truncated = xml_values(&body, "IsTruncated")
+                .first()
+                .is_some_and(|v| v == "true");
+            token = xml_values(&body, "NextContinuationToken")
+                .into_iter()
+                .next();
+            if !truncated || token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+
+    async fn manifest(&self, key: &str) -> Result<Option<SnapshotManifest>> {
+        let Some(data) = self.get_object(key).await? else {
+            return Ok(None);
+        };
+        let manifest =
+            serde_json::from_slice(&data).context("Failed to parse snapshot manifest")?;
+        Ok(Some(manifest))
+    }
+
+    /// Blob key recorded for `snapshot_id`, also under the legacy index
+    async fn blob_key(&self, snapshot_id: &str) -> Result<Option<String>> {
+        let mut data = self.get_object(&index_key(snapshot_id)).await?;
+        if data.is_none() {
+            data = self
+                .get_object(&format!("{}{}", LEGACY_INDEX_PREFIX, snapshot_id))
+                .await?;
+        }
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        Ok(Some(
+            String::from_utf8(data).context("Invalid snapshot index")?,
+        ))
+    }
+}
+
+#[async_trait]
+impl SnapshotStorage for S3SnapshotStorage {
+    async fn store(&self, snapshot: &Snapshot) -> Result<()> {
+        let key = self.object_key(&snapshot.metadata.storage_path)?;
+        self.put_blob(key, &snapshot.data).await?;
+
+        let manifest = serde_json::to_vec(&SnapshotManifest {
+            metadata: snapshot.metadata.clone(),
+            tracks: snapshot.tracks.clone(),
+        })?;
+        self.put_object(&manifest_key(key), manifest).await?;
+        // Written last: a snapshot is only findable by ID once it is complete
+        self.put_object(&index_key(&snapshot.metadata.id), key.as_bytes().to_vec())
+            .await
+    }
+
+    async fn load(&self, snapshot_id: &str) -> Result<Snapshot> {
+        let key = self
+            .blob_key(snapshot_id)
+            .await?
+            .with_context(|| format!("Snapshot {} not found", snapshot_id))?;
+        let manifest = self
+            .manifest(&manifest_key(&key))
+            .await?
+            .with_context(|| format!("Snapshot {} has no manifest", snapshot_id))?;
+        let data = self
+            .get_object(&key)
+            .await?
+            .with_context(|| format!("Snapshot {} blob is missing", snapshot_id))?;
+        Ok(Snapshot {
+            metadata: manifest.metadata,
+            tracks: manifest.tracks,
+            data,
+        })
+    }
+
+    async fn delete(&self, snapshot_id: &str) -> Result<()> {
+        let Some(key) = self.blob_key(snapshot_id).await? else {
+            return Ok(());
+        };
+        self.delete_object(&key).await?;
+        self.delete_object(&manifest_key(&key)).await?;
+        self.delete_object(&format!("{}{}", LEGACY_INDEX_PREFIX, snapshot_id))
+            .await?;
+        self.delete_object(&index_key(snapshot_id)).await
+    }
+
+    async fn exists(&self, snapshot_id: &str) -> Result<bool> {
+        Ok(self.blob_key(snapshot_id).await?.is_some())
+    }
+
+    async fn list(&self, start_ts_ms: u64, end_ts_ms: u64) -> Result<Vec<SnapshotMetadata>> {
+        let mut keys = Vec::new();
+        match day_prefixes(start_ts_ms, end_ts_ms) {
+            Some(prefixes) => {
+                for prefix in prefixes {
+                    keys.extend(self.list_keys(&prefix).await?);
+                }
+            }
+            None => keys = self.list_keys(KEY_PREFIX).await?,
+        }
+
+        let mut listed = Vec::new();
+        for key in keys
+            .iter()
+            .filter(|key| key.ends_with(".json") && !key.starts_with(&index_key("")))
+        {
+            let manifest = match self.manifest(key).await {
+                Ok(Some(manifest)) => manifest,
+                // Expired between listing and reading
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable snapshot manifest {}: {}", key, e);
+                    continue;
+                }
+            };
+            let meta = manifest.metadata;
+            if meta.start_ts_ms <= end_ts_ms && meta.end_ts_ms >= start_ts_ms {
+                listed.push(meta);
+            }
+        }
+        listed.sort_by_key(|meta| meta.start_ts_ms);
+        Ok(listed)
+    }
+
+    async fn read_stream(&self, storage_path: &str) -> Result<BlobStream> {
+        let key = self.object_key(storage_path)?;
+        let response = self.send(Method::GET, key, &[], &[], Vec::new()).await?;
+        let response = check(response, key).await?;
+        let chunks = futures::stream::try_unfold(response, |mut response| async move {
+            let chunk = response.chunk().await.map_err(std::io::Error::other)?;
+            Ok(chunk.map(|chunk| (chunk, response)))
+        });
+        Ok(Box::pin(chunks))
+    }
+
+    fn backend(&self) -> &StorageBackend {
+        &self.backend
+    }
+
+    fn retention_days(&self) -> Option<u32> {
+        (self.options.retention_days > 0).then_some(self.options.retention_days)
+    }
+}
+
+/// Fail on a non-success status, keeping S3's error body for the message
+async fn check(response: reqwest::Response, what: &str) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    anyhow::bail!("S3 {} failed with {}: {}", what, status, body)
+}
+
+fn manifest_key(blob_key: &str) -> String {
+    format!("{}.json", blob_key.strip_suffix(".zst").unwrap_or(blob_key))
+}
+
+fn index_key(snapshot_id: &str) -> String {
+    format!("{}ids/{}", KEY_PREFIX, snapshot_id)
+}
+
+/// `snapshots/YYYY/MM/DD/` prefixes that can hold snapshots overlapping the range.
+///
+/// Keys are dated when the snapshot is written, at or just after its end, so
+/// the walk runs one day past `end_ts_ms`. `None` means the range is too wide
+/// to walk by day.
+fn day_prefixes(start_ts_ms: u64, end_ts_ms: u64) -> Option<Vec<String>> {
+    let day = |ts_ms: u64| {
+        chrono::Utc
+            .timestamp_millis_opt(ts_ms.min(i64::MAX as u64) as i64)
+            .single()
+            .map(|dt| dt.date_naive())
+    };
+    let first = day(start_ts_ms)?;
+    let last = day(end_ts_ms)?.succ_opt()?;
+    if (last - first).num_days() > MAX_LIST_PREFIXES {
+        return None;
+    }
+    Some(
+        first
+            .iter_days()
+            .take_while(|d| *d <= last)
+            .map(|d| {
+                format!(
+                    "{}{}/{:02}/{:02}/",
+                    KEY_PREFIX,
+                    d.year(),
+                    d.month(),
+                    d.day()
+                )
+            })
+            .collect(),
+    )
+}
+
+/// `existing` lifecycle configuration with the retention rule added, or
+/// replacing an earlier version of it
+fn lifecycle_xml(existing: Option<&str>, retention_days: u32) -> String {
+    let mut xml = String::from("<LifecycleConfiguration>");
+    let mut rest = existing.unwrap_or_default();
+    while let Some(start) = rest.find("<Rule>") {
+        let Some(end) = rest[start..].find("</Rule>") else {
+            break;
+        };
+        let rule = &rest[start..start + end + "</Rule>".len()];
+        if xml_values(rule, "ID").first().map(String::as_str) != Some(RETENTION_RULE_ID) {
+            xml.push_str(rule);
+        }
+        rest = &rest[start + rule.len()..];
+    }
+    xml.push_str(&format!(
+        "<Rule><ID>{}</ID><Filter><Prefix>{}</Prefix></Filter>\
+         <Status>Enabled</Status><Expiration><Days>{}</Days></Expiration>\
+         <AbortIncompleteMultipartUpload><DaysAfterInitiation>1</DaysAfterInitiation>\
+         </AbortIncompleteMultipartUpload></Rule></LifecycleConfiguration>",
+        RETENTION_RULE_ID, KEY_PREFIX, retention_days
+    ));
+    xml
+}
+
+fn complete_multipart_xml(parts: &[(String, String)]) -> String {
+    let mut xml = String::from("<CompleteMultipartUpload>");
+    for (number, etag) in parts {
+        xml.push_str(&format!(
+            "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+            number,
+            xml_escape(etag)
+        ));
+    }
+    xml.push_str("</CompleteMultipartUpload>");
+    xml
+}
+
+/// Text of every `<tag>` element; S3 responses are flat enough not to need a parser
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        values.push(xml_unescape(&rest[..end]));
+        rest = &rest[end + close.len()..];
+    }
+    values
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#34;", "\"")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::model::SnapshotTrack;
+    use axum::{
+        body::Bytes,
+        extract::{Query, State},
+        http::{HeaderMap, Method as HttpMethod, StatusCode as HttpStatus, Uri},
+        response::{IntoResponse, Response},
+        Router,
+    };
+    use futures::TryStreamExt;
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::{Arc, Mutex};
+
+    /// Page size of the stand-in's ListObjectsV2, small to exercise continuation
+    const LIST_PAGE: usize = 2;
+
+    #[derive(Default)]
+    struct FakeBucket {
+        created: bool,
+        objects: BTreeMap<String, Vec<u8>>,
+        uploads: HashMap<String, BTreeMap<u32, Vec<u8>>>,
+        completed_uploads: usize,
+        lifecycle: Option<String>,
+    }
+
+    type Fake = Arc<Mutex<FakeBucket>>;
+
+    /// Just enough of the S3 API for one path-style bucket named `harpy`
+    async fn fake_s3(
+        State(fake): State<Fake>,
+        method: HttpMethod,
+        uri: Uri,
+        Query(query): Query<HashMap<String, String>>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        let signed = headers
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("AWS4-HMAC-SHA256 Credential=harpy/"));
+        if !signed || !headers.contains_key("x-amz-date") {
+            return HttpStatus::FORBIDDEN.into_response();
+        }
+
+        let mut fake = fake.lock().unwrap();
+        let Some(rest) = uri.path().strip_prefix("/harpy") else {
+            return HttpStatus::NOT_FOUND.into_response();
+        };
+        let key = rest.trim_start_matches('/').to_string();
+
+        if key.is_empty() {
+            return match method {
+                HttpMethod::HEAD if fake.created => HttpStatus::OK.into_response(),
+                HttpMethod::HEAD => HttpStatus::NOT_FOUND.into_response(),
+                HttpMethod::GET if query.contains_key("lifecycle") => match &fake.lifecycle {
+                    Some(lifecycle) => lifecycle.clone().into_response(),
+                    None => (
+                        HttpStatus::NOT_FOUND,
+                        "<Error><Code>NoSuchLifecycleConfiguration</Code></Error>",
+                    )
+                        .into_response(),
+                },
+                HttpMethod::PUT if query.contains_key("lifecycle") => {
+                    if !headers.contains_key("content-md5") {
+                        return HttpStatus::BAD_REQUEST.into_response();
+                    }
+                    fake.lifecycle = Some(String::from_utf8_lossy(&body).to_string());
+                    HttpStatus::OK.into_response()
+                }
+                HttpMethod::PUT => {
+                    fake.created = true;
+                    HttpStatus::OK.into_response()
+                }
+                HttpMethod::GET => {
+                    let prefix = query.get("prefix").cloned().unwrap_or_default();
+                    let after = query.get("continuation-token").cloned();
+                    let matching: Vec<&String> = fake
+                        .objects
+                        .keys()
+                        .filter(|k| k.starts_with(&prefix))
+                        .filter(|k| after.as_ref().map_or(true, |a| *k > a))
+                        .collect();
+                    let page = &matching[..matching.len().min(LIST_PAGE)];
+                    let mut xml = String::from("<ListBucketResult>");
+                    for key in page {
+                        xml.push_str(&format!("<Contents><Key>{}</Key></Contents>", key));
+                    }
+                    if matching.len() > LIST_PAGE {
+                        xml.push_str(&format!(
+                            "<IsTruncated>true</IsTruncated><NextContinuationToken>{}</NextContinuationToken>",
+                            page[page.len() - 1]
+                        ));
+                    } else {
+                        xml.push_str("<IsTruncated>false</IsTruncated>");
+                    }
+                    xml.push_str("</ListBucketResult>");
+                    xml.into_response()
+                }
+                _ => HttpStatus::METHOD_NOT_ALLOWED.into_response(),
+            };
+        }
+
+        match method {
+            HttpMethod::POST if query.contains_key("uploads") => {
+                let upload_id = format!("upload-{}", fake.uploads.len() + 1);
+                fake.uploads.insert(upload_id.clone(), BTreeMap::new());
+                format!(
+                    "<InitiateMultipartUploadResult><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                    upload_id
+                )
+                .into_response()
+            }
+            HttpMethod::PUT if query.contains_key("uploadId") => {
+                let number: u32 = query["partNumber"].parse().unwrap();
+                let Some(parts) = fake.uploads.get_mut(&query["uploadId"]) else {
+                    return HttpStatus::NOT_FOUND.into_response();
+                };
+                parts.insert(number, body.to_vec());
+                ([("etag", format!("\"part-{}\"", number))], "").into_response()
+            }
+            HttpMethod::POST if query.contains_key("uploadId") => {
+                let Some(parts) = fake.uploads.remove(&query["uploadId"]) else {
+                    return HttpStatus::NOT_FOUND.into_response();
+                };
+                let listed = xml_values(&String::from_utf8_lossy(&body), "PartNumber");
+                if listed.len() != parts.len() {
+                    return "<Error><Code>InvalidPart</Code></Error>".into_response();
+                }
+                fake.objects
+                    .insert(key, parts.into_values().flatten().collect());
+                fake.completed_uploads += 1;
+                "<CompleteMultipartUploadResult/>".into_response()
+            }
+            HttpMethod::DELETE if query.contains_key("uploadId") => {
+                fake.uploads.remove(&query["uploadId"]);
+                HttpStatus::NO_CONTENT.into_response()
+            }
+            HttpMethod::PUT => {
+                fake.objects.insert(key, body.to_vec());
+                HttpStatus::OK.into_response()
+            }
+            HttpMethod::GET => match fake.objects.get(&key) {
+                Some(data) => data.clone().into_response(),
+                None => HttpStatus::NOT_FOUND.into_response(),
+            },
+            HttpMethod::HEAD if fake.objects.contains_key(&key) => HttpStatus::OK.into_response(),
+            HttpMethod::HEAD => HttpStatus::NOT_FOUND.into_response(),
+            HttpMethod::DELETE => {
+                fake.objects.remove(&key);
+                HttpStatus::NO_CONTENT.into_response()
+            }
+            _ => HttpStatus::METHOD_NOT_ALLOWED.into_response(),
+        }
+    }
+
+    async fn start_fake_s3(options: S3Options) -> (S3SnapshotStorage, Fake) {
+        let fake = Fake::default();
+        let app = Router::new().fallback(fake_s3).with_state(fake.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let backend = StorageBackend::S3 {
+            endpoint: format!("http://{}", addr),
+            bucket: "harpy".to_string(),
+            access_key: "harpy".to_string(),
+            secret_key: "secret".to_string(),
+            region: "us-east-1".to_string(),
+        };
+        (S3SnapshotStorage::new(backend, options).unwrap(), fake)
+    }
+
+    fn snapshot(storage: &S3SnapshotStorage, id: &str, end_ts_ms: u64, data: Vec<u8>) -> Snapshot {
+        Snapshot {
+            metadata: SnapshotMetadata::new(
+                id.to_string(),
+                end_ts_ms - 60_000,
+                end_ts_ms,
+                storage.backend().build_path(id),
+                "s3".to_string(),
+            )
+            .with_size_bytes(data.len() as u64),
+            tracks: vec![SnapshotTrack {
+                track_id: "a1".to_string(),
+                track_kind: "aircraft".to_string(),
+                first_ts_ms: end_ts_ms - 60_000,
+                last_ts_ms: end_ts_ms,
+                position_count: 3,
+            }],
+            data,
+        }
+    }
+
+    fn fake_keys(fake: &Fake) -> Vec<String> {
+        fake.lock().unwrap().objects.keys().cloned().collect()
+    }
+
+    fn now_ms() -> u64 {
+        chrono::Utc::now().timestamp_millis() as u64
+    }
+
+    #[tokio::test]
+    async fn test_s3_store_load_list_delete() {
+        let (storage, fake) = start_fake_s3(S3Options::default()).await;
+        storage.prepare().await.unwrap();
+
+        let now = now_ms();
+        for (id, end) in [
+            ("snap-a", now - 120_000),
+            ("snap-b", now),
+            ("snap-c", now - 30_000),
+        ] {
+            storage
+                .store(&snapshot(&storage, id, end, id.as_bytes().to_vec()))
+                .await
+                .unwrap();
+        }
+
+        let loaded = storage.load("snap-b").await.unwrap();
+        assert_eq!(loaded.data, b"snap-b");
+        assert_eq!(loaded.tracks.len(), 1);
+        assert!(storage.exists("snap-a").await.unwrap());
+        assert!(fake_keys(&fake).iter().all(|k| k.starts_with(KEY_PREFIX)));
+
+        // Window catches snap-b and snap-c, across listing pages
+        let listed = storage.list(now - 40_000, now).await.unwrap();
+        let ids: Vec<&str> = listed.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["snap-c", "snap-b"]);
+
+        // Snapshots indexed before keys moved under the prefix are still found
+        let key = storage.blob_key("snap-a").await.unwrap().unwrap();
+        storage
+            .put_object("ids/snap-old", key.into_bytes())
+            .await
+            .unwrap();
+        assert_eq!(storage.load("snap-old").await.unwrap().data, b"snap-a");
+
+        storage.delete("snap-b").await.unwrap();
+        assert!(!storage.exists("snap-b").await.unwrap());
+        assert!(storage.load("snap-b").await.is_err());
+        storage.delete("snap-b").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_s3_multipart_upload_and_stream() {
+        let options = S3Options {
+            multipart_threshold: 1024,
+            part_size: 700,
+            retention_days: 0,
+        };
+        let (storage, fake) = start_fake_s3(options).await;
+        storage.prepare().await.unwrap();
+
+        let data: Vec<u8> = (0..2_500u32).map(|i| (i % 251) as u8).collect();
+        let snap = snapshot(&storage, "snap-big", now_ms(), data.clone());
+        storage.store(&snap).await.unwrap();
+        assert_eq!(fake.lock().unwrap().completed_uploads, 1);
+
+        let streamed = storage
+            .read_stream(&snap.metadata.storage_path)
+            .await
+            .unwrap()
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await
+            .unwrap();
+        assert_eq!(streamed, data);
+
+        // Small blobs skip multipart
+        storage
+            .store(&snapshot(&storage, "snap-small", now_ms(), vec![1; 10]))
+            .await
+            .unwrap();
+        assert_eq!(fake.lock().unwrap().completed_uploads, 1);
+    }
+
+    #[tokio::test]
+    async fn test_s3_prepare_applies_lifecycle() {
+        let options = S3Options {
+            retention_days: 30,
+            ..S3Options::default()
+        };
+        let (storage, fake) = start_fake_s3(options).await;
+        storage.prepare().await.unwrap();
+        // Someone else's rule on the same bucket
+        {
+            let mut fake = fake.lock().unwrap();
+            let lifecycle = fake.lifecycle.take().unwrap().replace(
+                "<LifecycleConfiguration>",
+                "<LifecycleConfiguration><Rule><ID>logs</ID><Filter><Prefix>logs/</Prefix></Filter>\
+                 <Status>Enabled</Status><Expiration><Days>7</Days></Expiration></Rule>",
+            );
+            fake.lifecycle = Some(lifecycle);
+        }
+        // Idempotent against an existing bucket and lifecycle
+        storage.prepare().await.unwrap();
+
+        let fake = fake.lock().unwrap();
+        assert!(fake.created);
+        let lifecycle = fake.lifecycle.as_deref().unwrap();
+        assert!(lifecycle.contains("<Days>30</Days>"));
+        assert!(lifecycle.contains("<Prefix>snapshots/</Prefix>"));
+        assert!(lifecycle.contains("<ID>logs</ID>"));
+        assert_eq!(
+            lifecycle
+                .matches("<ID>harpy-snapshot-retention</ID>")
+                .count(),
+            1
+        );
+        assert_eq!(storage.retention_days(), Some(30));
+    }
+
+    #[test]
+    fn test_day_prefixes_and_keys() {
+        // 2024-02-20T23:00Z to 2024-02-21T01:00Z
+        let prefixes = day_prefixes(1_708_470_000_000, 1_708_477_200_000).unwrap();
+        assert_eq!(
+            prefixes,
+            vec![
+                "snapshots/2024/02/20/",
+                "snapshots/2024/02/21/",
+                "snapshots/2024/02/22/"
+            ]
+        );
+        assert!(day_prefixes(0, 1_708_477_200_000).is_none());
+
+        assert_eq!(
+            manifest_key("2024/02/20/snap-1.zst"),
+            "2024/02/20/snap-1.json"
+        );
+        assert_eq!(
+            xml_values("<a><Key>x&amp;y</Key><Key>z</Key></a>", "Key"),
+            vec!["x&y", "z"]
+        );
+    }
+}
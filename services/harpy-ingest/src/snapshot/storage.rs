@@ -1,14 +1,31 @@
 //! Snapshot Storage Backend
 //!
-//! Implements storage backends for snapshots: MinIO/S3 and local filesystem.
+//! Storage backends for snapshots: MinIO/S3 (see `s3`) and local filesystem.
 
 #![allow(dead_code)]
 
-use super::model::{Snapshot, SnapshotMetadata};
+use super::model::{Snapshot, SnapshotMetadata, SnapshotTrack, SNAPSHOT_BLOB_VERSION};
+use super::s3::{S3Options, S3SnapshotStorage, KEY_PREFIX};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use bytes::Bytes;
 use chrono::Datelike;
-use std::path::PathBuf;
+use futures::Stream;
+use harpy_proto::harpy::v1::SnapshotBlob;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use tokio::io::AsyncReadExt;
+
+/// Leading bytes of every snapshot blob
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"HSNP";
+
+/// Chunk size used when streaming a stored blob
+const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Stored blob bytes, read incrementally
+pub type BlobStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
 
 /// Storage backend trait
 #[async_trait]
@@ -27,6 +44,17 @@ pub trait SnapshotStorage: Send + Sync {
 
     /// List snapshots in a time range
     async fn list(&self, start_ts_ms: u64, end_ts_ms: u64) -> Result<Vec<SnapshotMetadata>>;
+
+    /// Stream the stored blob at `storage_path` (as recorded in `snapshots.storage_path`)
+    async fn read_stream(&self, storage_path: &str) -> Result<BlobStream>;
+
+    /// Backend this storage writes to
+    fn backend(&self) -> &StorageBackend;
+
+    /// Days after which the backend itself expires stored snapshots
+    fn retention_days(&self) -> Option<u32> {
+        None
+    }
 }
 
 /// Storage backend type
@@ -64,6 +92,25 @@ impl StorageBackend {
         })
     }
 
+    /// Backend selected by `SNAPSHOT_STORAGE_BACKEND`; local filesystem by default
+    pub fn configured() -> Result<Self> {
+        match std::env::var("SNAPSHOT_STORAGE_BACKEND").as_deref() {
+            Ok("s3") | Ok("minio") => Self::from_env(),
+            _ => Ok(Self::local(
+                std::env::var("SNAPSHOT_LOCAL_PATH")
+                    .unwrap_or_else(|_| "/var/lib/harpy/snapshots".to_string()),
+            )),
+        }
+    }
+
+    /// Name recorded in `snapshots.storage_backend`
+    pub fn name(&self) -> &'static str {
+        match self {
+            StorageBackend::S3 { .. } => "s3",
+            StorageBackend::Local { .. } => "local",
+        }
+    }
+
     /// Create local filesystem backend
     pub fn local(base_path: impl Into<PathBuf>) -> Self {
         Self::Local {
@@ -75,7 +122,12 @@ impl StorageBackend {
     pub fn build_path(&self, snapshot_id: &str) -> String {
         match self {
             StorageBackend::S3 { bucket, .. } => {
-                format!("s3://{}/{}", bucket, snapshot_key(snapshot_id))
+                format!(
+                    "s3://{}/{}{}",
+                    bucket,
+                    KEY_PREFIX,
+                    snapshot_key(snapshot_id)
+                )
             }
             StorageBackend::Local { base_path } => base_path
                 .join(snapshot_key(snapshot_id))
@@ -85,6 +137,27 @@ impl StorageBackend {
     }
 }
 
+/// Storage for `backend`
+pub fn open_storage(backend: StorageBackend) -> Result<std::sync::Arc<dyn SnapshotStorage>> {
+    Ok(match backend {
+        StorageBackend::Local { base_path } => {
+            std::sync::Arc::new(LocalSnapshotStorage::new(base_path))
+        }
+        backend @ StorageBackend::S3 { .. } => {
+            let storage =
+                std::sync::Arc::new(S3SnapshotStorage::new(backend, S3Options::from_env())?);
+            // Bucket setup retries on the next start; writes fail loudly until then
+            let prepared = storage.clone();
+            tokio::spawn(async move {
+                if let Err(e) = prepared.prepare().await {
+                    tracing::warn!("Failed to prepare snapshot bucket: {}", e);
+                }
+            });
+            storage
+        }
+    })
+}
+
 /// Generate storage key for snapshot
 fn snapshot_key(snapshot_id: &str) -> String {
     // Organize snapshots by date prefix for better listing performance
@@ -110,6 +183,29 @@ pub fn decompress_data(data: &[u8]) -> Result<Vec<u8>> {
     zstd::decode_all(data).context("Failed to decompress snapshot data")
 }
 
+/// Encode a blob: magic, format version, then the zstd-compressed protobuf
+pub fn encode_blob(blob: &SnapshotBlob) -> Result<Vec<u8>> {
+    let compressed = compress_data(&blob.encode_to_vec())?;
+    let mut data = Vec::with_capacity(SNAPSHOT_MAGIC.len() + 1 + compressed.len());
+    data.extend_from_slice(SNAPSHOT_MAGIC);
+    data.push(SNAPSHOT_BLOB_VERSION);
+    data.extend_from_slice(&compressed);
+    Ok(data)
+}
+
+/// Decode a blob written by `encode_blob`
+pub fn decode_blob(data: &[u8]) -> Result<SnapshotBlob> {
+    let body = data
+        .strip_prefix(SNAPSHOT_MAGIC.as_slice())
+        .context("Not a snapshot blob")?;
+    let (&version, compressed) = body.split_first().context("Truncated snapshot blob")?;
+    if version != SNAPSHOT_BLOB_VERSION {
+        anyhow::bail!("Unsupported snapshot blob version {}", version);
+    }
+    SnapshotBlob::decode(decompress_data(compressed)?.as_slice())
+        .context("Failed to decode snapshot blob")
+}
+
 /// Serialize tracks to JSON bytes
 pub fn serialize_tracks(tracks: &[super::model::SnapshotTrack]) -> Result<Vec<u8>> {
     serde_json::to_vec(tracks).context("Failed to serialize tracks")
@@ -120,6 +216,179 @@ pub fn deserialize_tracks(data: &[u8]) -> Result<Vec<super::model::SnapshotTrack
     serde_json::from_slice(data).context("Failed to deserialize tracks")
 }
 
+/// Sidecar written next to each blob so snapshots can be listed and
+/// loaded without Postgres
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SnapshotManifest {
+    pub metadata: SnapshotMetadata,
+    pub tracks: Vec<SnapshotTrack>,
+}
+
+/// Snapshots stored as files under a base directory, laid out as
+/// `YYYY/MM/DD/<id>.zst` with a `<id>.json` manifest alongside
+pub struct LocalSnapshotStorage {
+    backend: StorageBackend,
+    base_path: PathBuf,
+}
+
+impl LocalSnapshotStorage {
+    pub fn new(base_path: impl Into<PathBuf>) -> Self {
+        let base_path = base_path.into();
+        Self {
+            backend: StorageBackend::local(base_path.clone()),
+            base_path,
+        }
+    }
+
+    /// Every manifest path under the base directory
+    async fn manifests(&self) -> Result<Vec<PathBuf>> {
+        let mut found = Vec::new();
+        let mut pending = vec![self.base_path.clone()];
+        while let Some(dir) = pending.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).context("Failed to read snapshot directory"),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                } else if path.extension().is_some_and(|ext| ext == "json") {
+                    found.push(path);
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    async fn find_manifest(&self, snapshot_id: &str) -> Result<Option<PathBuf>> {
+        let file_name = format!("{}.json", snapshot_id);
+        Ok(self.manifests().await?.into_iter().find(|path| {
+            path.file_name()
+                .is_some_and(|name| name == file_name.as_str())
+        }))
+    }
+
+    /// Resolve `storage_path` and refuse anything outside the base directory
+    fn resolve(&self, storage_path: &str) -> Result<PathBuf> {
+        let path = Path::new(storage_path);
+        let relative = path.strip_prefix(&self.base_path).unwrap_or(path);
+        if relative.is_absolute()
+            || relative
+                .components()
+                .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            anyhow::bail!(
+                "Snapshot path {} is outside the storage directory",
+                storage_path
+            );
+        }
+        Ok(self.base_path.join(relative))
+    }
+}
+
+async fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, data)
+        .await
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .with_context(|| format!("Failed to move snapshot into {}", path.display()))
+}
+
+#[async_trait]
+impl SnapshotStorage for LocalSnapshotStorage {
+    async fn store(&self, snapshot: &Snapshot) -> Result<()> {
+        let blob_path = self.resolve(&snapshot.metadata.storage_path)?;
+        if let Some(dir) = blob_path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        write_atomic(&blob_path, &snapshot.data).await?;
+
+        let manifest = serde_json::to_vec(&SnapshotManifest {
+            metadata: snapshot.metadata.clone(),
+            tracks: snapshot.tracks.clone(),
+        })?;
+        write_atomic(&blob_path.with_extension("json"), &manifest).await
+    }
+
+    async fn load(&self, snapshot_id: &str) -> Result<Snapshot> {
+        let manifest_path = self
+            .find_manifest(snapshot_id)
+            .await?
+            .with_context(|| format!("Snapshot {} not found", snapshot_id))?;
+        let manifest: SnapshotManifest =
+            serde_json::from_slice(&tokio::fs::read(&manifest_path).await?)
+                .context("Failed to parse snapshot manifest")?;
+        let data = tokio::fs::read(manifest_path.with_extension("zst")).await?;
+        Ok(Snapshot {
+            metadata: manifest.metadata,
+            tracks: manifest.tracks,
+            data,
+        })
+    }
+
+    async fn delete(&self, snapshot_id: &str) -> Result<()> {
+        if let Some(manifest_path) = self.find_manifest(snapshot_id).await? {
+            for path in [manifest_path.with_extension("zst"), manifest_path] {
+                match tokio::fs::remove_file(&path).await {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e).context("Failed to delete snapshot"),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, snapshot_id: &str) -> Result<bool> {
+        Ok(self.find_manifest(snapshot_id).await?.is_some())
+    }
+
+    async fn list(&self, start_ts_ms: u64, end_ts_ms: u64) -> Result<Vec<SnapshotMetadata>> {
+        let mut listed = Vec::new();
+        for path in self.manifests().await? {
+            let Ok(manifest) =
+                serde_json::from_slice::<SnapshotManifest>(&tokio::fs::read(&path).await?)
+            else {
+                tracing::warn!("Skipping unreadable snapshot manifest {}", path.display());
+                continue;
+            };
+            let meta = manifest.metadata;
+            if meta.start_ts_ms <= end_ts_ms && meta.end_ts_ms >= start_ts_ms {
+                listed.push(meta);
+            }
+        }
+        listed.sort_by_key(|meta| meta.start_ts_ms);
+        Ok(listed)
+    }
+
+    async fn read_stream(&self, storage_path: &str) -> Result<BlobStream> {
+        let path = self.resolve(storage_path)?;
+        let file = tokio::fs::File::open(&path)
+            .await
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let chunks = futures::stream::try_unfold(file, |mut file| async move {
+            let mut buf = vec![0u8; STREAM_CHUNK_BYTES];
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(None);
+            }
+            buf.truncate(read);
+            Ok(Some((Bytes::from(buf), file)))
+        });
+        Ok(Box::pin(chunks))
+    }
+
+    fn backend(&self) -> &StorageBackend {
+        &self.backend
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -141,7 +410,7 @@ mod tests {
             region: "us-east-1".to_string(),
         };
         let path = backend.build_path("snap-001");
-        assert!(path.starts_with("s3://harpy/"));
+        assert!(path.starts_with("s3://harpy/snapshots/"));
         assert!(path.contains("snap-001.zst"));
     }
 
@@ -152,4 +421,69 @@ mod tests {
         let decompressed = decompress_data(&compressed).unwrap();
         assert_eq!(data.to_vec(), decompressed);
     }
+
+    #[test]
+    fn test_blob_roundtrip_and_version_check() {
+        let blob = SnapshotBlob {
+            snapshot_id: "snap-001".to_string(),
+            start_ts_ms: 1000,
+            end_ts_ms: 2000,
+            tracks: vec![harpy_proto::harpy::v1::TrackDelta {
+                id: "a1".to_string(),
+                ts_ms: 1500,
+                ..Default::default()
+            }],
+        };
+        let mut data = encode_blob(&blob).unwrap();
+        assert!(data.starts_with(SNAPSHOT_MAGIC));
+        assert_eq!(decode_blob(&data).unwrap(), blob);
+
+        data[SNAPSHOT_MAGIC.len()] = SNAPSHOT_BLOB_VERSION + 1;
+        assert!(decode_blob(&data).is_err());
+        assert!(decode_blob(b"nope").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_storage_store_load_stream() {
+        use futures::TryStreamExt;
+
+        let base = std::env::temp_dir().join(format!("harpy-snapshots-{}", uuid::Uuid::new_v4()));
+        let storage = LocalSnapshotStorage::new(&base);
+        let metadata = SnapshotMetadata::new(
+            "snap-local".to_string(),
+            1000,
+            2000,
+            storage.backend().build_path("snap-local"),
+            "local".to_string(),
+        );
+        let snapshot = Snapshot {
+            metadata: metadata.clone(),
+            tracks: Vec::new(),
+            data: vec![7u8; STREAM_CHUNK_BYTES + 10],
+        };
+        storage.store(&snapshot).await.unwrap();
+
+        assert!(storage.exists("snap-local").await.unwrap());
+        assert_eq!(
+            storage.load("snap-local").await.unwrap().data,
+            snapshot.data
+        );
+        assert_eq!(storage.list(1500, 3000).await.unwrap().len(), 1);
+        assert!(storage.list(3000, 4000).await.unwrap().is_empty());
+
+        let chunks: Vec<Bytes> = storage
+            .read_stream(&metadata.storage_path)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert!(chunks.len() >= 2);
+        assert_eq!(chunks.concat(), snapshot.data);
+        assert!(storage.read_stream("../etc/passwd").await.is_err());
+
+        storage.delete("snap-local").await.unwrap();
+        assert!(!storage.exists("snap-local").await.unwrap());
+        let _ = tokio::fs::remove_dir_all(&base).await;
+    }
 }
@@ -0,0 +1,339 @@
+//! Snapshot Creation
+//!
+//! Captures the latest state of every track updated in a window as a blob in
+//! snapshot storage, then records its metadata, per-track aggregates and
+//! `delta_index` rows in Postgres.
+
+use super::model::{Snapshot, SnapshotMetadata, SnapshotTrack, Viewport, SNAPSHOT_FORMAT_VERSION};
+use super::storage::{encode_blob, SnapshotStorage};
+use crate::storage::PostgresStore;
+use harpy_proto::harpy::v1::{Position, SnapshotBlob, TrackDelta};
+use sqlx::postgres::PgRow;
+use sqlx::Row;
+use std::collections::HashMap;
+
+/// Where `delta_index` rows point: snapshot blobs hold state, deltas stay in Postgres
+const DELTA_STORAGE_HINT: &str = "postgres:track_deltas";
+
+/// Create a snapshot of tracks updated in the last `interval_ms`.
+///
+/// Returns `None` when no track was updated in the window.
+pub async fn create_snapshot(
+    postgres: &PostgresStore,
+    storage: &dyn SnapshotStorage,
+    interval_ms: u64,
+) -> anyhow::Result<Option<String>> {
+    let snapshot_id = format!("snap-{}", uuid::Uuid::new_v4().simple());
+    let end_ts_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_millis() as u64;
+    let start_ts_ms = end_ts_ms.saturating_sub(interval_ms);
+
+    let rows = sqlx::query(
+        r#"
+        SELECT t.id, t.kind, t.lat, t.lon, t.alt, t.heading, t.speed, t.ts_ms, t.provider_id, t.meta,
+               d.first_ts_ms, d.last_ts_ms, d.position_count
+        FROM tracks t
+        LEFT JOIN (
+            SELECT track_id, MIN(ts_ms) AS first_ts_ms, MAX(ts_ms) AS last_ts_ms,
+                   COUNT(*) AS position_count
+            FROM track_deltas
+            WHERE ts_ms >= $1 AND ts_ms <= $2
+            GROUP BY track_id
+        ) d ON d.track_id = t.id
+        WHERE t.ts_ms >= $1 AND t.ts_ms <= $2
+        ORDER BY t.id
+        "#,
+    )
+    .bind(start_ts_ms as i64)
+    .bind(end_ts_ms as i64)
+    .fetch_all(&postgres.pool)
+    .await?;
+
+    if rows.is_empty() {
+        tracing::debug!("No tracks to snapshot in time window");
+        return Ok(None);
+    }
+
+    let mut states = Vec::with_capacity(rows.len());
+    let mut tracks = Vec::with_capacity(rows.len());
+    let mut delta_counts = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let state = track_from_row(row);
+        let deltas = row.get::<Option<i64>, _>("position_count").map(|count| {
+            (
+                row.get::<i64, _>("first_ts_ms") as u64,
+                row.get::<i64, _>("last_ts_ms") as u64,
+                count as usize,
+            )
+        });
+        delta_counts.push(deltas.map_or(0, |(_, _, count)| count as i32));
+        tracks.push(track_aggregate(&state, row.get("kind"), deltas));
+        states.push(state);
+    }
+
+    let data = encode_blob(&SnapshotBlob {
+        snapshot_id: snapshot_id.clone(),
+        start_ts_ms,
+        end_ts_ms,
+        tracks: states,
+    })?;
+    let backend = storage.backend();
+    let viewport = Viewport::world();
+    let metadata = SnapshotMetadata::new(
+        snapshot_id.clone(),
+        start_ts_ms,
+        end_ts_ms,
+        backend.build_path(&snapshot_id),
+        backend.name().to_string(),
+    )
+    .with_viewport(viewport)
+    .with_meta("format_version", SNAPSHOT_FORMAT_VERSION)
+    .with_meta("compression", super::model::SNAPSHOT_COMPRESSION)
+    .with_meta("created_by", "harpy-ingest")
+    .with_track_count(tracks.len())
+    .with_size_bytes(data.len() as u64);
+
+    let snapshot = Snapshot {
+        metadata,
+        tracks,
+        data,
+    };
+    storage.store(&snapshot).await?;
+
+    if let Err(e) = record_snapshot(postgres, &snapshot, viewport, &delta_counts).await {
+        // Without its metadata row nothing can find the blob again
+        if let Err(cleanup) = storage.delete(&snapshot_id).await {
+            tracing::warn!(
+                "Failed to remove orphaned snapshot {}: {}",
+                snapshot_id,
+                cleanup
+            );
+        }
+        return Err(e);
+    }
+
+    tracing::info!(
+        "Created snapshot {} with {} tracks, {} bytes ({} - {})",
+        snapshot_id,
+        snapshot.tracks.len(),
+        snapshot.metadata.compressed_size_bytes,
+        start_ts_ms,
+        end_ts_ms
+    );
+    Ok(Some(snapshot_id))
+}
+
+/// Drop metadata for snapshots the storage backend has expired.
+///
+/// `snapshot_tracks` rows go with them by cascade; `delta_index` rows point at
+/// `track_deltas`, which has its own retention.
+pub async fn prune_expired(
+    postgres: &PostgresStore,
+    storage: &dyn SnapshotStorage,
+) -> anyhow::Result<u64> {
+    let Some(retention_days) = storage.retention_days() else {
+        return Ok(0);
+    };
+    let cutoff_ms = chrono::Utc::now().timestamp_millis() - retention_days as i64 * 86_400_000;
+    let result = sqlx::query("DELETE FROM snapshots WHERE storage_backend = $1 AND end_ts_ms < $2")
+        .bind(storage.backend().name())
+        .bind(cutoff_ms)
+        .execute(&postgres.pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Insert the snapshot row, its track membership and `delta_index` rows in one transaction
+async fn record_snapshot(
+    postgres: &PostgresStore,
+    snapshot: &Snapshot,
+    viewport: Viewport,
+    delta_counts: &[i32],
+) -> anyhow::Result<()> {
+    let metadata = &snapshot.metadata;
+    let mut tx = postgres.pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO snapshots (id, start_ts_ms, end_ts_ms, track_count, compressed_size_bytes, storage_path, storage_backend,
+                               viewport_min_lat, viewport_min_lon, viewport_max_lat, viewport_max_lon, meta)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        "#,
+    )
+    .bind(&metadata.id)
+    .bind(metadata.start_ts_ms as i64)
+    .bind(metadata.end_ts_ms as i64)
+    .bind(metadata.track_count as i32)
+    .bind(metadata.compressed_size_bytes as i64)
+    .bind(&metadata.storage_path)
+    .bind(&metadata.storage_backend)
+    .bind(viewport.min_lat)
+    .bind(viewport.min_lon)
+    .bind(viewport.max_lat)
+    .bind(viewport.max_lon)
+    .bind(sqlx::types::Json(&metadata.meta))
+    .execute(&mut *tx)
+    .await?;
+
+    let ids: Vec<&str> = snapshot
+        .tracks
+        .iter()
+        .map(|t| t.track_id.as_str())
+        .collect();
+    let kinds: Vec<&str> = snapshot
+        .tracks
+        .iter()
+        .map(|t| t.track_kind.as_str())
+        .collect();
+    let firsts: Vec<i64> = snapshot
+        .tracks
+        .iter()
+        .map(|t| t.first_ts_ms as i64)
+        .collect();
+    let lasts: Vec<i64> = snapshot
+        .tracks
+        .iter()
+        .map(|t| t.last_ts_ms as i64)
+        .collect();
+    let counts: Vec<i32> = snapshot
+        .tracks
+        .iter()
+        .map(|t| t.position_count as i32)
+        .collect();
+
+    sqlx::query(
+        r#"
+        INSERT INTO snapshot_tracks (snapshot_id, track_id, track_kind, first_ts_ms, last_ts_ms, position_count)
+        SELECT $1, * FROM UNNEST($2::text[], $3::text[], $4::int8[], $5::int8[], $6::int4[])
+        "#,
+    )
+    .bind(&metadata.id)
+    .bind(&ids)
+    .bind(&kinds)
+    .bind(&firsts)
+    .bind(&lasts)
+    .bind(&counts)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO delta_index (track_id, start_ts_ms, end_ts_ms, delta_count, storage_hint)
+        SELECT track_id, first_ts_ms, last_ts_ms, delta_count, $5
+        FROM UNNEST($1::text[], $2::int8[], $3::int8[], $4::int4[])
+            AS batch(track_id, first_ts_ms, last_ts_ms, delta_count)
+        WHERE delta_count > 0
+        "#,
+    )
+    .bind(&ids)
+    .bind(&firsts)
+    .bind(&lasts)
+    .bind(delta_counts)
+    .bind(DELTA_STORAGE_HINT)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Membership row for a track; without deltas in the window the current state
+/// is its only observation
+fn track_aggregate(
+    state: &TrackDelta,
+    kind: String,
+    deltas: Option<(u64, u64, usize)>,
+) -> SnapshotTrack {
+    let (first_ts_ms, last_ts_ms, position_count) = match deltas {
+        Some((first, last, count)) if count > 0 => {
+            (first.min(state.ts_ms), last.max(state.ts_ms), count)
+        }
+        _ => (state.ts_ms, state.ts_ms, 1),
+    };
+    SnapshotTrack {
+        track_id: state.id.clone(),
+        track_kind: kind,
+        first_ts_ms,
+        last_ts_ms,
+        position_count,
+    }
+}
+
+fn track_from_row(row: &PgRow) -> TrackDelta {
+    let meta: Option<serde_json::Value> = row.get("meta");
+    TrackDelta {
+        id: row.get("id"),
+        kind: kind_from_name(row.get("kind")),
+        position: Some(Position {
+            lat: row.get("lat"),
+            lon: row.get("lon"),
+            alt: row.get("alt"),
+        }),
+        heading: row.get::<Option<f64>, _>("heading").unwrap_or(0.0),
+        speed: row.get::<Option<f64>, _>("speed").unwrap_or(0.0),
+        ts_ms: row.get::<i64, _>("ts_ms") as u64,
+        provider_id: row.get("provider_id"),
+        meta: meta_from_json(meta),
+    }
+}
+
+fn kind_from_name(kind: &str) -> i32 {
+    match kind {
+        "aircraft" => 1,
+        "satellite" => 2,
+        "ground" => 3,
+        "vessel" => 4,
+        _ => 0,
+    }
+}
+
+fn meta_from_json(value: Option<serde_json::Value>) -> HashMap<String, String> {
+    let Some(serde_json::Value::Object(obj)) = value else {
+        return HashMap::new();
+    };
+    obj.into_iter()
+        .map(|(key, value)| {
+            let value = value
+                .as_str()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| value.to_string());
+            (key, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_track_aggregate_uses_window_deltas() {
+        let state = TrackDelta {
+            id: "a1".to_string(),
+            ts_ms: 5_000,
+            ..Default::default()
+        };
+
+        let with_deltas = track_aggregate(&state, "aircraft".to_string(), Some((1_000, 5_000, 12)));
+        assert_eq!(
+            (
+                with_deltas.first_ts_ms,
+                with_deltas.last_ts_ms,
+                with_deltas.position_count
+            ),
+            (1_000, 5_000, 12)
+        );
+
+        // Deltas already downsampled or not yet written: the state alone counts
+        let without = track_aggregate(&state, "aircraft".to_string(), None);
+        assert_eq!(
+            (
+                without.first_ts_ms,
+                without.last_ts_ms,
+                without.position_count
+            ),
+            (5_000, 5_000, 1)
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! H3 index backfill
+//!
+//! Postgres cannot compute H3 cells, so rows written before ingest populated
+//! `h3_index` (or at a different `H3_RESOLUTION`) are fixed up here. Progress
+//! is kept in `h3_backfill_state`, so a restart resumes where the last run
+//! stopped and a finished table is skipped until the resolution changes.
+
+use h3o::Resolution;
+use harpy_core::h3_cells;
+use sqlx::Row;
+
+use super::PostgresStore;
+
+/// Rows read and updated per statement
+const DEFAULT_BATCH_ROWS: i64 = 5_000;
+
+/// Saved progress for one table
+#[derive(Debug, Clone, PartialEq)]
+struct BackfillState {
+    resolution: i16,
+    last_id: Option<String>,
+    completed: bool,
+}
+
+/// Where a table's backfill starts: `None` when it is already done
+fn resume_from(state: Option<&BackfillState>, resolution: Resolution) -> Option<Option<String>> {
+    match state {
+        Some(state) if state.resolution == i16::from(u8::from(resolution)) => {
+            if state.completed {
+                None
+            } else {
+                Some(state.last_id.clone())
+            }
+        }
+        // Never run, or run at another resolution: start over
+        _ => Some(None),
+    }
+}
+
+/// Backfill `tracks` and `track_deltas`, unless disabled by `INGEST_H3_BACKFILL=false`
+pub async fn run(store: PostgresStore) {
+    let enabled = std::env::var("INGEST_H3_BACKFILL")
+        .map(|v| v != "false" && v != "0")
+        .unwrap_or(true);
+    if !enabled {
+        return;
+    }
+    let batch_rows = std::env::var("INGEST_H3_BACKFILL_BATCH")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_BATCH_ROWS);
+
+    for table in ["tracks", "track_deltas"] {
+        match backfill_table(&store, table, batch_rows).await {
+            Ok(0) => {}
+            Ok(updated) => tracing::info!("H3 backfill of {} updated {} rows", table, updated),
+            Err(e) => {
+                tracing::warn!("H3 backfill of {} stopped: {}", table, e);
+                return;
+            }
+        }
+    }
+}
+
+async fn backfill_table(
+    store: &PostgresStore,
+    table: &str,
+    batch_rows: i64,
+) -> anyhow::Result<u64> {
+    let resolution = store.h3_resolution;
+    let state = load_state(store, table).await?;
+    let Some(mut cursor) = resume_from(state.as_ref(), resolution) else {
+        return Ok(0);
+    };
+    tracing::info!(
+        "Backfilling {}.h3_index at resolution {} from {}",
+        table,
+        resolution,
+        cursor.as_deref().unwrap_or("the start")
+    );
+
+    let mut updated = 0u64;
+    loop {
+        let rows = match table {
+            "tracks" => fetch_tracks(store, cursor.as_deref(), batch_rows).await?,
+            _ => fetch_deltas(store, cursor.as_deref(), resolution, batch_rows).await?,
+        };
+        let Some((last_id, _, _)) = rows.last() else {
+            break;
+        };
+        cursor = Some(last_id.clone());
+
+        let mut ids = Vec::with_capacity(rows.len());
+        let mut lats = Vec::with_capacity(rows.len());
+        let mut lons = Vec::with_capacity(rows.len());
+        let mut cells = Vec::with_capacity(rows.len());
+        for (id, lat, lon) in &rows {
+            ids.push(id.clone());
+            lats.push(*lat);
+            lons.push(*lon);
+            cells.push(h3_cells::cell_index(*lat, *lon, resolution));
+        }
+
+        // Matching lat/lon skips rows moved by ingest since they were read;
+        // ingest already wrote the right cell for those.
+        let id_match = match table {
+            "tracks" => "t.id = b.id",
+            _ => "t.id = b.id::bigint",
+        };
+        let result = sqlx::query(&format!(
+            r#"
+            UPDATE {table} AS t SET h3_index = b.h3_index
+            FROM UNNEST($1::text[], $2::float8[], $3::float8[], $4::int8[])
+                AS b(id, lat, lon, h3_index)
+            WHERE {id_match} AND t.lat = b.lat AND t.lon = b.lon
+              AND t.h3_index IS DISTINCT FROM b.h3_index
+            "#
+        ))
+        .bind(ids)
+        .bind(lats)
+        .bind(lons)
+        .bind(cells)
+        .execute(&store.pool)
+        .await?;
+        updated += result.rows_affected();
+
+        save_state(store, table, resolution, cursor.as_deref(), false).await?;
+        if (rows.len() as i64) < batch_rows {
+            break;
+        }
+    }
+
+    save_state(store, table, resolution, cursor.as_deref(), true).await?;
+    Ok(updated)
+}
+
+async fn fetch_tracks(
+    store: &PostgresStore,
+    after: Option<&str>,
+    limit: i64,
+) -> anyhow::Result<Vec<(String, f64, f64)>> {
+    let rows = sqlx::query(
+        "SELECT id, lat, lon FROM tracks WHERE ($1::text IS NULL OR id > $1) ORDER BY id LIMIT $2",
+    )
+    .bind(after)
+    .bind(limit)
+    .fetch_all(&store.pool)
+    .await?;
+    Ok(rows
+        .iter()
+        .map(|row| (row.get("id"), row.get("lat"), row.get("lon")))
+        .collect())
+}
+
+/// Deltas are never moved, so only rows missing a cell at `resolution` are read
+async fn fetch_deltas(
+    store: &PostgresStore,
+    after: Option<&str>,
+    resolution: Resolution,
+    limit: i64,
+) -> anyhow::Result<Vec<(String, f64, f64)>> {
+    let after: i64 = after.and_then(|id| id.parse().ok()).unwrap_or(0);
+    let rows = sqlx::query(
+        r#"
+        SELECT id, lat, lon FROM track_deltas
+        WHERE id > $1 AND (h3_index IS NULL OR ((h3_index >> 52) & 15) <> $2)
+        ORDER BY id LIMIT $3
+        "#,
+    )
+    .bind(after)
+    .bind(i64::from(u8::from(resolution)))
+    .bind(limit)
+    .fetch_all(&store.pool)
+    .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let id: i64 = row.get("id");
+            (id.to_string(), row.get("lat"), row.get("lon"))
+        })
+        .collect())
+}
+
+async fn load_state(store: &PostgresStore, table: &str) -> anyhow::Result<Option<BackfillState>> {
+    let row = sqlx::query(
+        "SELECT resolution, last_id, completed_at IS NOT NULL AS completed FROM h3_backfill_state WHERE table_name = $1",
+    )
+    .bind(table)
+    .fetch_optional(&store.pool)
+    .await?;
+    Ok(row.map(|row| BackfillState {
+        resolution: row.get("resolution"),
+        last_id: row.get("last_id"),
+        completed: row.get("completed"),
+    }))
+}
+
+async fn save_state(
+    store: &PostgresStore,
+    table: &str,
+    resolution: Resolution,
+    last_id: Option<&str>,
+    completed: bool,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO h3_backfill_state (table_name, resolution, last_id, completed_at, updated_at)
+        VALUES ($1, $2, $3, CASE WHEN $4 THEN NOW() END, NOW())
+        ON CONFLICT (table_name) DO UPDATE SET
+            resolution = EXCLUDED.resolution,
+            last_id = EXCLUDED.last_id,
+            completed_at = EXCLUDED.completed_at,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(table)
+    .bind(i16::from(u8::from(resolution)))
+    .bind(last_id)
+    .bind(completed)
+    .execute(&store.pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_from_state() {
+        let res = Resolution::Eight;
+        assert_eq!(resume_from(None, res), Some(None));
+
+        let partial = BackfillState {
+            resolution: 8,
+            last_id: Some("1200".to_string()),
+            completed: false,
+        };
+        assert_eq!(
+            resume_from(Some(&partial), res),
+            Some(Some("1200".to_string()))
+        );
+
+        let done = BackfillState {
+            completed: true,
+            ..partial.clone()
+        };
+        assert_eq!(resume_from(Some(&done), res), None);
+        // A new resolution invalidates every stored cell
+        assert_eq!(resume_from(Some(&done), Resolution::Nine), Some(None));
+    }
+}
@@ -1,5 +1,9 @@
+pub mod h3_backfill;
+pub mod partitions;
 pub mod postgres_store;
 pub mod redis_store;
+pub mod write_queue;
 
 pub use postgres_store::PostgresStore;
 pub use redis_store::RedisStore;
+pub use write_queue::{WriteQueue, WriteQueueConfig};
@@ -0,0 +1,422 @@
+//! Daily partitions of `track_deltas`
+//!
+//! `track_deltas` is range-partitioned on `ts_ms` by UTC day (migration 004).
+//! The manager keeps a few days of partitions ahead of the clock, drops whole
+//! partitions once they are past retention, and downsamples history older than
+//! a few hours to one position per track per minute.
+
+use sqlx::Row;
+use std::time::Duration;
+
+use super::PostgresStore;
+
+const DAY_MS: i64 = 86_400_000;
+const HOUR_MS: i64 = 3_600_000;
+const MINUTE_MS: i64 = 60_000;
+
+/// Days of history kept when `INGEST_DELTA_RETENTION_DAYS` is unset
+const DEFAULT_RETENTION_DAYS: i64 = 90;
+/// Partitions created ahead of today
+const DEFAULT_PREMAKE_DAYS: i64 = 3;
+/// Age after which history is compacted to one position per track per minute
+const DEFAULT_DOWNSAMPLE_AFTER_HOURS: i64 = 24;
+const DEFAULT_MAINTENANCE_INTERVAL_SECS: u64 = 3600;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PartitionConfig {
+    pub retention_days: i64,
+    pub premake_days: i64,
+    /// `0` disables downsampling
+    pub downsample_after_hours: i64,
+    pub interval: Duration,
+}
+
+impl PartitionConfig {
+    pub fn from_env() -> Self {
+        let env = |name: &str, default: i64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v >= 0)
+                .unwrap_or(default)
+        };
+        Self {
+            retention_days: env("INGEST_DELTA_RETENTION_DAYS", DEFAULT_RETENTION_DAYS).max(1),
+            premake_days: env("INGEST_PARTITION_PREMAKE_DAYS", DEFAULT_PREMAKE_DAYS),
+            downsample_after_hours: env(
+                "INGEST_DOWNSAMPLE_AFTER_HOURS",
+                DEFAULT_DOWNSAMPLE_AFTER_HOURS,
+            ),
+            interval: Duration::from_secs(
+                env(
+                    "INGEST_PARTITION_MAINTENANCE_SECS",
+                    DEFAULT_MAINTENANCE_INTERVAL_SECS as i64,
+                )
+                .max(60) as u64,
+            ),
+        }
+    }
+}
+
+/// An attached partition of `track_deltas`; `None` bounds mark the default partition
+#[derive(Debug, Clone, PartialEq)]
+struct Partition {
+    name: String,
+    range: Option<(i64, i64)>,
+}
+
+/// Run maintenance every `config.interval`, starting immediately
+pub async fn run(store: PostgresStore, config: PartitionConfig) {
+    let mut interval = tokio::time::interval(config.interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = maintain(&store, &config).await {
+            tracing::error!("track_deltas partition maintenance failed: {}", e);
+        }
+    }
+}
+
+async fn maintain(store: &PostgresStore, config: &PartitionConfig) -> anyhow::Result<()> {
+    if !ensure_partitions(store, config.premake_days).await? {
+        return Ok(());
+    }
+    drop_expired(store, config.retention_days).await?;
+    if config.downsample_after_hours > 0 {
+        downsample(store, config.downsample_after_hours).await?;
+    }
+    Ok(())
+}
+
+/// Create daily partitions from today through `premake_days` ahead.
+///
+/// Returns `false` when `track_deltas` is not partitioned (migration 004 not applied).
+pub async fn ensure_partitions(store: &PostgresStore, premake_days: i64) -> anyhow::Result<bool> {
+    let partitioned: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM pg_partitioned_table WHERE partrelid = 'track_deltas'::regclass)",
+    )
+    .fetch_one(&store.pool)
+    .await?;
+    if !partitioned {
+        tracing::warn!(
+            "track_deltas is not partitioned; apply migrations/004_partition_track_deltas.sql"
+        );
+        return Ok(false);
+    }
+
+    let partitions = load_partitions(store).await?;
+    let default = partitions
+        .iter()
+        .find(|p| p.range.is_none())
+        .map(|p| p.name.clone());
+    for day in missing_days(&partitions, day_start(now_ms()), premake_days) {
+        create_partition(store, day, default.as_deref()).await?;
+    }
+    Ok(true)
+}
+
+async fn load_partitions(store: &PostgresStore) -> anyhow::Result<Vec<Partition>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT c.relname::text AS name, pg_get_expr(c.relpartbound, c.oid) AS bound
+        FROM pg_inherits i
+        JOIN pg_class c ON c.oid = i.inhrelid
+        WHERE i.inhparent = 'track_deltas'::regclass
+        "#,
+    )
+    .fetch_all(&store.pool)
+    .await?;
+
+    let mut partitions = Vec::with_capacity(rows.len());
+    for row in rows {
+        let name: String = row.get("name");
+        let bound: String = row.get("bound");
+        if bound.trim() == "DEFAULT" {
+            partitions.push(Partition { name, range: None });
+        } else if let Some(range) = parse_range_bound(&bound) {
+            partitions.push(Partition {
+                name,
+                range: Some(range),
+            });
+        } else {
+            tracing::warn!(
+                "Ignoring partition {} with unexpected bound {}",
+                name,
+                bound
+            );
+        }
+    }
+    Ok(partitions)
+}
+
+/// Create one day's partition, moving any rows the default partition holds for it.
+///
+/// `CREATE TABLE ... PARTITION OF` fails when the default partition already
+/// has rows in the new range, so the table is filled first and then attached.
+async fn create_partition(
+    store: &PostgresStore,
+    day: i64,
+    default: Option<&str>,
+) -> anyhow::Result<()> {
+    let name = partition_name(day);
+    let end = day + DAY_MS;
+    let mut tx = store.pool.begin().await?;
+    sqlx::query(&format!(
+        "CREATE TABLE {name} (LIKE track_deltas INCLUDING DEFAULTS INCLUDING CONSTRAINTS)"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    let moved = match default {
+        Some(default) => sqlx::query(&format!(
+            r#"
+            WITH moved AS (
+                DELETE FROM {default} WHERE ts_ms >= $1 AND ts_ms < $2 RETURNING *
+            )
+            INSERT INTO {name} SELECT * FROM moved
+            "#
+        ))
+        .bind(day)
+        .bind(end)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected(),
+        None => 0,
+    };
+    sqlx::query(&format!(
+        "ALTER TABLE track_deltas ATTACH PARTITION {name} FOR VALUES FROM ({day}) TO ({end})"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    tracing::info!(
+        "Created partition {} ({} rows moved from the default partition)",
+        name,
+        moved
+    );
+    Ok(())
+}
+
+/// Drop partitions entirely older than the retention window
+async fn drop_expired(store: &PostgresStore, retention_days: i64) -> anyhow::Result<()> {
+    let cutoff = day_start(now_ms()) - retention_days * DAY_MS;
+    let partitions = load_partitions(store).await?;
+    for partition in expired(&partitions, cutoff) {
+        sqlx::query(&format!("DROP TABLE {}", partition.name))
+            .execute(&store.pool)
+            .await?;
+        tracing::info!("Dropped expired partition {}", partition.name);
+    }
+    if let Some(default) = partitions.iter().find(|p| p.range.is_none()) {
+        let deleted = sqlx::query(&format!("DELETE FROM {} WHERE ts_ms < $1", default.name))
+            .bind(cutoff)
+            .execute(&store.pool)
+            .await?
+            .rows_affected();
+        if deleted > 0 {
+            tracing::info!("Deleted {} expired rows from {}", deleted, default.name);
+        }
+    }
+    Ok(())
+}
+
+/// Keep the newest position per track per minute for history older than
+/// `after_hours`, one hour per transaction, resuming from the saved watermark
+async fn downsample(store: &PostgresStore, after_hours: i64) -> anyhow::Result<()> {
+    let target = minute_floor(now_ms() - after_hours * HOUR_MS);
+    let watermark: Option<i64> = sqlx::query_scalar(
+        "SELECT compacted_through_ts_ms FROM track_delta_downsample_state WHERE id = 1",
+    )
+    .fetch_optional(&store.pool)
+    .await?;
+    let mut from = match watermark {
+        Some(ts) => ts,
+        None => {
+            let oldest: Option<i64> = sqlx::query_scalar("SELECT MIN(ts_ms) FROM track_deltas")
+                .fetch_one(&store.pool)
+                .await?;
+            match oldest {
+                Some(ts) => minute_floor(ts),
+                None => target,
+            }
+        }
+    };
+
+    let mut deleted = 0u64;
+    while from < target {
+        let to = (from + HOUR_MS).min(target);
+        let mut tx = store.pool.begin().await?;
+        deleted += sqlx::query(
+            r#"
+            DELETE FROM track_deltas td
+            USING (
+                SELECT id, ts_ms,
+                       ROW_NUMBER() OVER (
+                           PARTITION BY track_id, ts_ms / 60000
+                           ORDER BY ts_ms DESC, id DESC
+                       ) AS rn
+                FROM track_deltas
+                WHERE ts_ms >= $1 AND ts_ms < $2
+            ) ranked
+            WHERE td.ts_ms >= $1 AND td.ts_ms < $2
+              AND td.id = ranked.id AND td.ts_ms = ranked.ts_ms
+              AND ranked.rn > 1
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        save_watermark(&mut tx, to).await?;
+        tx.commit().await?;
+        from = to;
+    }
+    if watermark.is_none() {
+        // Record the starting point even when there was nothing to compact
+        save_watermark(&mut *store.pool.acquire().await?, from).await?;
+    }
+    if deleted > 0 {
+        tracing::info!("Downsampled track_deltas: removed {} rows", deleted);
+    }
+    Ok(())
+}
+
+async fn save_watermark(conn: &mut sqlx::PgConnection, ts_ms: i64) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO track_delta_downsample_state (id, compacted_through_ts_ms, updated_at)
+        VALUES (1, $1, NOW())
+        ON CONFLICT (id) DO UPDATE SET
+            compacted_through_ts_ms = EXCLUDED.compacted_through_ts_ms,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(ts_ms)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Parse `FOR VALUES FROM ('a') TO ('b')` as printed by `pg_get_expr`
+fn parse_range_bound(bound: &str) -> Option<(i64, i64)> {
+    let rest = bound.trim().strip_prefix("FOR VALUES FROM (")?;
+    let (from, to) = rest.split_once(") TO (")?;
+    let to = to.strip_suffix(')')?;
+    Some((parse_bound_value(from)?, parse_bound_value(to)?))
+}
+
+fn parse_bound_value(value: &str) -> Option<i64> {
+    match value.trim().trim_matches('\'') {
+        "MINVALUE" => Some(i64::MIN),
+        "MAXVALUE" => Some(i64::MAX),
+        v => v.parse().ok(),
+    }
+}
+
+/// Day starts from `today` through `premake_days` ahead not covered by any partition
+fn missing_days(partitions: &[Partition], today: i64, premake_days: i64) -> Vec<i64> {
+    (0..=premake_days)
+        .map(|offset| today + offset * DAY_MS)
+        .filter(|day| {
+            let end = day + DAY_MS;
+            !partitions
+                .iter()
+                .filter_map(|p| p.range)
+                .any(|(start, stop)| start < end && *day < stop)
+        })
+        .collect()
+}
+
+/// Ranged partitions whose every row is older than `cutoff`
+fn expired(partitions: &[Partition], cutoff: i64) -> Vec<&Partition> {
+    partitions
+        .iter()
+        .filter(|p| matches!(p.range, Some((_, end)) if end <= cutoff))
+        .collect()
+}
+
+fn partition_name(day_start_ms: i64) -> String {
+    let date = chrono::DateTime::from_timestamp_millis(day_start_ms)
+        .map(|dt| dt.format("%Y%m%d").to_string())
+        .unwrap_or_else(|| day_start_ms.to_string());
+    format!("track_deltas_p{}", date)
+}
+
+fn day_start(ts_ms: i64) -> i64 {
+    ts_ms.div_euclid(DAY_MS) * DAY_MS
+}
+
+fn minute_floor(ts_ms: i64) -> i64 {
+    ts_ms.div_euclid(MINUTE_MS) * MINUTE_MS
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2026-10-19T00:00:00Z
+    const DAY: i64 = 1_792_368_000_000;
+
+    fn ranged(name: &str, start: i64, end: i64) -> Partition {
+        Partition {
+            name: name.to_string(),
+            range: Some((start, end)),
+        }
+    }
+
+    #[test]
+    fn test_parse_range_bound() {
+        assert_eq!(
+            parse_range_bound("FOR VALUES FROM ('1792368000000') TO ('1792454400000')"),
+            Some((1_792_368_000_000, 1_792_454_400_000))
+        );
+        assert_eq!(
+            parse_range_bound("FOR VALUES FROM (MINVALUE) TO ('5')"),
+            Some((i64::MIN, 5))
+        );
+        assert_eq!(parse_range_bound("DEFAULT"), None);
+    }
+
+    #[test]
+    fn test_missing_days_skips_covered_ranges() {
+        let partitions = vec![
+            // Legacy history ending tomorrow
+            ranged("track_deltas_legacy", i64::MIN, DAY + DAY_MS),
+            ranged("track_deltas_p20261021", DAY + 2 * DAY_MS, DAY + 3 * DAY_MS),
+            Partition {
+                name: "track_deltas_default".to_string(),
+                range: None,
+            },
+        ];
+        assert_eq!(
+            missing_days(&partitions, DAY, 3),
+            vec![DAY + DAY_MS, DAY + 3 * DAY_MS]
+        );
+        assert_eq!(partition_name(DAY + DAY_MS), "track_deltas_p20261020");
+    }
+
+    #[test]
+    fn test_expired_partitions_end_before_cutoff() {
+        let partitions = vec![
+            ranged("old", DAY - 2 * DAY_MS, DAY - DAY_MS),
+            ranged("edge", DAY - DAY_MS, DAY),
+            ranged("current", DAY, DAY + DAY_MS),
+            Partition {
+                name: "track_deltas_default".to_string(),
+                range: None,
+            },
+        ];
+        let names: Vec<&str> = expired(&partitions, DAY)
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["old", "edge"]);
+    }
+}
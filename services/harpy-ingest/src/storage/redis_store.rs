@@ -1,63 +1,31 @@
-use harpy_proto::harpy::v1::TrackDelta;
+use harpy_core::provider_status::{self, ProviderStatusRecord, PROVIDER_STATUS_STREAM};
+use harpy_core::streams::{self, TRACKS_STREAM};
+use harpy_core::track_wire::{self, TrackJson, WireFormat};
+use harpy_proto::harpy::v1::{ProviderStatus, TrackDelta};
 use redis::{aio::ConnectionManager, AsyncCommands};
-use serde::{Deserialize, Serialize};
 
 #[derive(Clone)]
 pub struct RedisStore {
     client: ConnectionManager,
-}
-
-/// JSON-serializable representation of TrackDelta for Redis storage
-#[derive(Serialize, Deserialize)]
-struct TrackDeltaJson {
-    id: String,
-    kind: i32,
-    position: Option<PositionJson>,
-    heading: f64,
-    speed: f64,
-    ts_ms: u64,
-    provider_id: String,
-    #[serde(default)]
-    meta: std::collections::HashMap<String, String>,
-}
-
-#[derive(Serialize, Deserialize)]
-struct PositionJson {
-    lat: f64,
-    lon: f64,
-    alt: f64,
+    stream_maxlen: usize,
+    wire_format: WireFormat,
 }
 
 impl RedisStore {
     pub async fn new(redis_url: &str) -> anyhow::Result<Self> {
         let client = redis::Client::open(redis_url)?;
         let connection = ConnectionManager::new(client).await?;
-        Ok(Self { client: connection })
-    }
-
-    /// Convert TrackDelta to JSON representation
-    fn to_json(track: &TrackDelta) -> TrackDeltaJson {
-        TrackDeltaJson {
-            id: track.id.clone(),
-            kind: track.kind,
-            position: track.position.as_ref().map(|p| PositionJson {
-                lat: p.lat,
-                lon: p.lon,
-                alt: p.alt,
-            }),
-            heading: track.heading,
-            speed: track.speed,
-            ts_ms: track.ts_ms,
-            provider_id: track.provider_id.clone(),
-            meta: track.meta.clone(),
-        }
+        Ok(Self {
+            client: connection,
+            stream_maxlen: streams::maxlen_from_env(),
+            wire_format: WireFormat::from_env(),
+        })
     }
 
     /// Store a track in Redis with 1-hour TTL
     pub async fn store_track(&mut self, track: &TrackDelta) -> anyhow::Result<()> {
         let key = format!("track:{}", track.id);
-        let json_track = Self::to_json(track);
-        let value = serde_json::to_string(&json_track)?;
+        let value = serde_json::to_string(&TrackJson::from(track))?;
 
         // Store with 1-hour TTL (3600 seconds)
         self.client.set_ex::<_, _, ()>(&key, value, 3600).await?;
@@ -76,48 +44,67 @@ impl RedisStore {
         Ok(())
     }
 
-    /// Publish track batch to Redis pub/sub channel
+    /// Append track batch to the tracks Redis stream
     pub async fn publish_track_batch(&mut self, tracks: &[TrackDelta]) -> anyhow::Result<()> {
         if tracks.is_empty() {
             return Ok(());
         }
 
-        let channel = "tracks:updates";
-        let json_tracks: Vec<TrackDeltaJson> = tracks.iter().map(Self::to_json).collect();
-        let batch_json = serde_json::to_string(&json_tracks)?;
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_millis() as u64;
+        let payload = track_wire::encode_track_batch(tracks, now_ms, self.wire_format)?;
 
-        self.client.publish::<_, _, ()>(channel, batch_json).await?;
+        streams::publish(
+            &mut self.client,
+            TRACKS_STREAM,
+            self.stream_maxlen,
+            &payload,
+        )
+        .await?;
 
-        tracing::debug!("Published {} tracks to Redis channel", tracks.len());
+        tracing::debug!("Published {} tracks to Redis stream", tracks.len());
         Ok(())
     }
 
-    /// Update provider health status
+    /// Record provider health and append it to the status stream when it changed
+    ///
+    /// The `provider:status:{id}` key is refreshed on every poll; the stream
+    /// only carries transitions relative to `previous`.
     pub async fn update_provider_status(
         &mut self,
-        provider_id: &str,
-        circuit_state: &str,
-        freshness: &str,
-        success: bool,
+        previous: Option<&ProviderStatus>,
+        status: &ProviderStatus,
     ) -> anyhow::Result<()> {
-        let key = format!("provider:status:{}", provider_id);
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)?
             .as_millis() as u64;
 
-        let status = serde_json::json!({
-            "provider_id": provider_id,
-            "circuit_state": circuit_state,
-            "freshness": freshness,
-            "last_update_ts_ms": now,
-            "last_success": success,
-        });
-
+        let record = ProviderStatusRecord::new(status, now);
         self.client
-            .set::<_, _, ()>(&key, status.to_string())
+            .set::<_, _, ()>(
+                provider_status::status_key(&status.provider_id),
+                serde_json::to_string(&record)?,
+            )
             .await?;
 
-        tracing::debug!("Updated provider status for {}", provider_id);
+        if provider_status::is_transition(previous, status) {
+            streams::publish(
+                &mut self.client,
+                PROVIDER_STATUS_STREAM,
+                self.stream_maxlen,
+                &provider_status::encode_event(status, now),
+            )
+            .await?;
+            tracing::info!(
+                "Provider {} is now {} / {} ({} failures)",
+                status.provider_id,
+                record.circuit_state,
+                record.freshness,
+                status.failure_count
+            );
+        }
+
         Ok(())
     }
 }
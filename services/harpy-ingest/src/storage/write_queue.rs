@@ -0,0 +1,245 @@
+//! Bounded write queue between providers and Postgres
+//!
+//! Providers hand their polled batches to the queue and return to polling; a
+//! single writer drains it, merging whatever is waiting per provider and
+//! writing each provider's rows in its own transaction, so one provider's bad
+//! batch cannot take another's rows with it. When the writer falls behind,
+//! `submit` waits for room, so a slow database slows the pollers instead of
+//! growing memory.
+
+use harpy_proto::harpy::v1::TrackDelta;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+use super::PostgresStore;
+
+/// Batches waiting for the writer
+const DEFAULT_QUEUE_CAPACITY: usize = 64;
+/// Rows drained from the queue per writer pass
+const DEFAULT_MAX_BATCH_ROWS: usize = 20_000;
+/// Attempts per transaction before its rows are dropped; only transient
+/// failures are retried
+const MAX_WRITE_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WriteQueueConfig {
+    pub capacity: usize,
+    pub max_batch_rows: usize,
+}
+
+impl WriteQueueConfig {
+    pub fn from_env() -> Self {
+        let env = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(default)
+        };
+        Self {
+            capacity: env("INGEST_WRITE_QUEUE_CAPACITY", DEFAULT_QUEUE_CAPACITY),
+            max_batch_rows: env("INGEST_WRITE_BATCH_ROWS", DEFAULT_MAX_BATCH_ROWS),
+        }
+    }
+}
+
+/// One provider's tracks, written in a single transaction
+#[derive(Debug)]
+struct ProviderBatch {
+    provider_id: String,
+    tracks: Vec<TrackDelta>,
+}
+
+/// Handle for submitting batches to the Postgres writer
+#[derive(Clone)]
+pub struct WriteQueue {
+    tx: mpsc::Sender<ProviderBatch>,
+}
+
+impl WriteQueue {
+    /// Start the writer task for `store`
+    pub fn spawn(store: PostgresStore, config: WriteQueueConfig) -> Self {
+        let (tx, rx) = mpsc::channel(config.capacity);
+        tokio::spawn(run_writer(store, rx, config.max_batch_rows));
+        Self { tx }
+    }
+
+    /// Queue a batch, waiting while the queue is full
+    pub async fn submit(&self, provider_id: &str, tracks: Vec<TrackDelta>) {
+        if tracks.is_empty() {
+            return;
+        }
+        let batch = ProviderBatch {
+            provider_id: provider_id.to_string(),
+            tracks,
+        };
+        let batch = match self.tx.try_send(batch) {
+            Ok(()) => return,
+            Err(TrySendError::Full(batch)) => {
+                tracing::warn!(
+                    "Postgres write queue full; {} waits to queue {} tracks",
+                    provider_id,
+                    batch.tracks.len()
+                );
+                batch
+            }
+            Err(TrySendError::Closed(batch)) => batch,
+        };
+        if let Err(e) = self.tx.send(batch).await {
+            tracing::error!(
+                "Postgres writer stopped; dropping {} tracks from {}",
+                e.0.tracks.len(),
+                provider_id
+            );
+        }
+    }
+}
+
+async fn run_writer(
+    store: PostgresStore,
+    mut rx: mpsc::Receiver<ProviderBatch>,
+    max_batch_rows: usize,
+) {
+    while let Some(first) = rx.recv().await {
+        for batch in drain_batch(first, &mut rx, max_batch_rows) {
+            write_with_retry(&store, &batch).await;
+        }
+    }
+}
+
+/// Take batches already waiting behind `first`, up to `max_rows` rows, merged
+/// per provider in arrival order
+fn drain_batch(
+    first: ProviderBatch,
+    rx: &mut mpsc::Receiver<ProviderBatch>,
+    max_rows: usize,
+) -> Vec<ProviderBatch> {
+    let mut rows = first.tracks.len();
+    let mut batches = vec![first];
+    while rows < max_rows {
+        let Ok(more) = rx.try_recv() else {
+            break;
+        };
+        rows += more.tracks.len();
+        match batches
+            .iter_mut()
+            .find(|batch| batch.provider_id == more.provider_id)
+        {
+            Some(batch) => batch.tracks.extend(more.tracks),
+            None => batches.push(more),
+        }
+    }
+    batches
+}
+
+async fn write_with_retry(store: &PostgresStore, batch: &ProviderBatch) {
+    let started = std::time::Instant::now();
+    for attempt in 1..=MAX_WRITE_ATTEMPTS {
+        match store.write_batch(&batch.tracks).await {
+            Ok(()) => {
+                tracing::debug!(
+                    "Wrote {} tracks from {} to Postgres in {:?}",
+                    batch.tracks.len(),
+                    batch.provider_id,
+                    started.elapsed()
+                );
+                return;
+            }
+            Err(e) if attempt < MAX_WRITE_ATTEMPTS && is_transient(&e) => {
+                tracing::warn!(
+                    "Postgres batch write for {} failed (attempt {}/{}): {}",
+                    batch.provider_id,
+                    attempt,
+                    MAX_WRITE_ATTEMPTS,
+                    e
+                );
+                tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Dropping {} tracks from {} after {} Postgres write attempts: {}",
+                    batch.tracks.len(),
+                    batch.provider_id,
+                    attempt,
+                    e
+                );
+                return;
+            }
+        }
+    }
+}
+
+/// Whether retrying the same batch can succeed: lost connections, pool
+/// exhaustion, serialization failures and deadlocks
+fn is_transient(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed) => true,
+        Some(sqlx::Error::Database(e)) => e.code().is_some_and(|code| is_transient_sqlstate(&code)),
+        _ => false,
+    }
+}
+
+fn is_transient_sqlstate(code: &str) -> bool {
+    // 08: connection exception; 40001/40P01: serialization failure/deadlock;
+    // 53300: too many connections; 57P01-03: server shutting down or starting
+    code.starts_with("08")
+        || matches!(
+            code,
+            "40001" | "40P01" | "53300" | "57P01" | "57P02" | "57P03"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks(provider_id: &str, prefix: &str, count: usize) -> ProviderBatch {
+        ProviderBatch {
+            provider_id: provider_id.to_string(),
+            tracks: (0..count)
+                .map(|i| TrackDelta {
+                    id: format!("{}-{}", prefix, i),
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drain_batch_merges_waiting_batches_per_provider_up_to_limit() {
+        let (tx, mut rx) = mpsc::channel(8);
+        tx.send(tracks("tle", "b", 3)).await.unwrap();
+        tx.send(tracks("adsb", "c", 3)).await.unwrap();
+        tx.send(tracks("adsb", "d", 3)).await.unwrap();
+
+        let batches = drain_batch(tracks("adsb", "a", 2), &mut rx, 6);
+        // Whole batches are taken until the limit is reached, one per provider.
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].provider_id, "adsb");
+        assert_eq!(batches[0].tracks.len(), 5);
+        assert_eq!(batches[0].tracks[0].id, "a-0");
+        assert_eq!(batches[0].tracks[4].id, "c-2");
+        assert_eq!(batches[1].provider_id, "tle");
+        assert_eq!(batches[1].tracks.len(), 3);
+
+        let rest = drain_batch(rx.recv().await.unwrap(), &mut rx, 6);
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].tracks.len(), 3);
+    }
+
+    #[test]
+    fn test_only_transient_errors_are_retried() {
+        assert!(is_transient(&sqlx::Error::PoolTimedOut.into()));
+        assert!(is_transient(
+            &sqlx::Error::Io(std::io::ErrorKind::ConnectionReset.into()).into()
+        ));
+        assert!(!is_transient(&sqlx::Error::RowNotFound.into()));
+        assert!(!is_transient(&anyhow::anyhow!("Track a has no position")));
+
+        assert!(is_transient_sqlstate("40001"));
+        assert!(is_transient_sqlstate("08006"));
+        assert!(!is_transient_sqlstate("23505"));
+        assert!(!is_transient_sqlstate("22P02"));
+    }
+}
@@ -0,0 +1,492 @@
+//! Data Quality Validation
+//!
+//! Runs between `Provider::fetch` and the stores. Records with unusable
+//! coordinates or timestamps, duplicate IDs within a batch, or positions the
+//! track could not have reached since its last accepted one are held back and
+//! returned as rejections for the `quarantine` table.
+
+use harpy_proto::harpy::v1::{TrackDelta, TrackKind};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// Records passed to the stores, by provider
+pub const RECORDS_ACCEPTED: &str = "harpy_ingest_records_accepted_total";
+/// Records quarantined, by provider and reason
+pub const RECORDS_REJECTED: &str = "harpy_ingest_records_rejected_total";
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Consecutive jump rejections after which the new position is accepted
+/// anyway: either the track really moved or the anchor itself was bad.
+const MAX_CONSECUTIVE_JUMPS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    MissingPosition,
+    NonFiniteCoordinate,
+    CoordinateOutOfRange,
+    FutureTimestamp,
+    StaleTimestamp,
+    DuplicateId,
+    ImpossibleJump,
+}
+
+impl RejectReason {
+    /// Code stored in `quarantine.reason_code` and the metrics `reason` label
+    pub fn code(self) -> &'static str {
+        match self {
+            RejectReason::MissingPosition => "MISSING_POSITION",
+            RejectReason::NonFiniteCoordinate => "NON_FINITE_COORDINATE",
+            RejectReason::CoordinateOutOfRange => "COORDINATE_OUT_OF_RANGE",
+            RejectReason::FutureTimestamp => "FUTURE_TIMESTAMP",
+            RejectReason::StaleTimestamp => "STALE_TIMESTAMP",
+            RejectReason::DuplicateId => "DUPLICATE_ID",
+            RejectReason::ImpossibleJump => "IMPOSSIBLE_JUMP",
+        }
+    }
+}
+
+/// A record held back from the stores
+#[derive(Debug, Clone)]
+pub struct Rejection {
+    pub track: TrackDelta,
+    pub reason: RejectReason,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidationConfig {
+    /// Furthest ahead of the ingest clock a timestamp may be
+    pub max_future_ms: u64,
+    /// Furthest behind the ingest clock a timestamp may be
+    pub max_past_ms: u64,
+    /// Per-kind override of `max_past_ms`. Satellite `ts_ms` is the TLE epoch,
+    /// which is days to years old for valid element sets, so satellites have no
+    /// limit by default.
+    pub max_past_ms_by_kind: HashMap<i32, u64>,
+    /// Implied speed limit per track kind, m/s; kinds without one skip the jump check
+    pub max_speed_mps: HashMap<i32, f64>,
+    /// Distance allowed on top of the speed limit, for position noise and
+    /// coarse provider timestamps
+    pub jump_slack_m: f64,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            max_future_ms: 3_600_000,
+            max_past_ms: 30 * 86_400_000,
+            max_past_ms_by_kind: HashMap::from([(TrackKind::Satellite as i32, u64::MAX)]),
+            max_speed_mps: HashMap::from([
+                // Above any civil or military jet, well below orbital speed
+                (TrackKind::Aircraft as i32, 400.0),
+                (TrackKind::Satellite as i32, 12_000.0),
+                (TrackKind::Ground as i32, 100.0),
+                (TrackKind::Vessel as i32, 40.0),
+            ]),
+            jump_slack_m: 1_000.0,
+        }
+    }
+}
+
+impl ValidationConfig {
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        let env_f64 = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| v.is_finite() && *v >= 0.0)
+        };
+        if let Some(secs) = env_f64("INGEST_MAX_FUTURE_SECS") {
+            config.max_future_ms = (secs * 1000.0) as u64;
+        }
+        if let Some(secs) = env_f64("INGEST_MAX_PAST_SECS") {
+            config.max_past_ms = (secs * 1000.0) as u64;
+        }
+        if let Some(secs) = env_f64("INGEST_MAX_PAST_SATELLITE_SECS") {
+            config
+                .max_past_ms_by_kind
+                .insert(TrackKind::Satellite as i32, (secs * 1000.0) as u64);
+        }
+        if let Some(meters) = env_f64("INGEST_JUMP_SLACK_METERS") {
+            config.jump_slack_m = meters;
+        }
+        for (kind, name) in [
+            (TrackKind::Aircraft, "INGEST_MAX_SPEED_AIRCRAFT_MPS"),
+            (TrackKind::Satellite, "INGEST_MAX_SPEED_SATELLITE_MPS"),
+            (TrackKind::Ground, "INGEST_MAX_SPEED_GROUND_MPS"),
+            (TrackKind::Vessel, "INGEST_MAX_SPEED_VESSEL_MPS"),
+        ] {
+            if let Some(mps) = env_f64(name) {
+                config.max_speed_mps.insert(kind as i32, mps);
+            }
+        }
+        config
+    }
+
+    /// Furthest behind the ingest clock a timestamp of this kind may be
+    fn max_past_ms_for(&self, kind: i32) -> u64 {
+        self.max_past_ms_by_kind
+            .get(&kind)
+            .copied()
+            .unwrap_or(self.max_past_ms)
+    }
+}
+
+/// Last accepted position of a track
+#[derive(Debug, Clone, Copy)]
+struct LastKnown {
+    kind: i32,
+    lat: f64,
+    lon: f64,
+    ts_ms: u64,
+    /// Jump rejections since this position was accepted
+    jumps: u32,
+}
+
+/// Validation state for one provider's poll loop
+///
+/// Last known positions live in memory, so after a restart each track's
+/// first position is taken as given.
+pub struct Validator {
+    provider_id: String,
+    config: ValidationConfig,
+    last_known: HashMap<String, LastKnown>,
+}
+
+impl Validator {
+    pub fn new(provider_id: impl Into<String>, config: ValidationConfig) -> Self {
+        Self {
+            provider_id: provider_id.into(),
+            config,
+            last_known: HashMap::new(),
+        }
+    }
+
+    /// Split a fetched batch into records for the stores and rejections
+    pub fn validate(
+        &mut self,
+        tracks: Vec<TrackDelta>,
+        now_ms: u64,
+    ) -> (Vec<TrackDelta>, Vec<Rejection>) {
+        let mut rejected = Vec::new();
+
+        let mut candidates = Vec::with_capacity(tracks.len());
+        for track in tracks {
+            match self.check_record(&track, now_ms) {
+                Ok(()) => candidates.push(track),
+                Err((reason, detail)) => rejected.push(Rejection {
+                    track,
+                    reason,
+                    detail,
+                }),
+            }
+        }
+
+        // Of several records for one ID, the newest goes on (the first on ties)
+        let mut newest: HashMap<&str, usize> = HashMap::new();
+        for (index, track) in candidates.iter().enumerate() {
+            match newest.entry(track.id.as_str()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(index);
+                }
+                Entry::Occupied(mut entry) => {
+                    if track.ts_ms > candidates[*entry.get()].ts_ms {
+                        entry.insert(index);
+                    }
+                }
+            }
+        }
+        let keep: Vec<bool> = candidates
+            .iter()
+            .enumerate()
+            .map(|(index, track)| newest[track.id.as_str()] == index)
+            .collect();
+
+        let mut accepted = Vec::with_capacity(candidates.len());
+        for (track, keep) in candidates.into_iter().zip(keep) {
+            if !keep {
+                rejected.push(Rejection {
+                    detail: format!("{} appears more than once in the batch", track.id),
+                    track,
+                    reason: RejectReason::DuplicateId,
+                });
+                continue;
+            }
+            match self.check_jump(&track) {
+                Ok(()) => accepted.push(track),
+                Err(detail) => rejected.push(Rejection {
+                    track,
+                    reason: RejectReason::ImpossibleJump,
+                    detail,
+                }),
+            }
+        }
+
+        // Tracks gone quiet for longer than any accepted timestamp could be old
+        let config = &self.config;
+        self.last_known.retain(|_, last| {
+            last.ts_ms >= now_ms.saturating_sub(config.max_past_ms_for(last.kind))
+        });
+
+        metrics::counter!(RECORDS_ACCEPTED, "provider" => self.provider_id.clone())
+            .increment(accepted.len() as u64);
+        for rejection in &rejected {
+            metrics::counter!(
+                RECORDS_REJECTED,
+                "provider" => self.provider_id.clone(),
+                "reason" => rejection.reason.code()
+            )
+            .increment(1);
+        }
+        (accepted, rejected)
+    }
+
+    fn check_record(&self, track: &TrackDelta, now_ms: u64) -> Result<(), (RejectReason, String)> {
+        let Some(position) = track.position.as_ref() else {
+            return Err((RejectReason::MissingPosition, "no position".to_string()));
+        };
+        if !(position.lat.is_finite() && position.lon.is_finite() && position.alt.is_finite()) {
+            return Err((
+                RejectReason::NonFiniteCoordinate,
+                format!(
+                    "lat={} lon={} alt={}",
+                    position.lat, position.lon, position.alt
+                ),
+            ));
+        }
+        if !(-90.0..=90.0).contains(&position.lat) || !(-180.0..=180.0).contains(&position.lon) {
+            return Err((
+                RejectReason::CoordinateOutOfRange,
+                format!("lat={} lon={}", position.lat, position.lon),
+            ));
+        }
+        if track.ts_ms > now_ms.saturating_add(self.config.max_future_ms) {
+            return Err((
+                RejectReason::FutureTimestamp,
+                format!("ts_ms={} is {}ms ahead", track.ts_ms, track.ts_ms - now_ms),
+            ));
+        }
+        if track.ts_ms < now_ms.saturating_sub(self.config.max_past_ms_for(track.kind)) {
+            return Err((
+                RejectReason::StaleTimestamp,
+                format!("ts_ms={} is {}ms old", track.ts_ms, now_ms - track.ts_ms),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Compare against the last accepted position and move the anchor on success
+    fn check_jump(&mut self, track: &TrackDelta) -> Result<(), String> {
+        let position = track.position.clone().unwrap_or_default();
+        let current = LastKnown {
+            kind: track.kind,
+            lat: position.lat,
+            lon: position.lon,
+            ts_ms: track.ts_ms,
+            jumps: 0,
+        };
+        let Some(max_speed) = self.config.max_speed_mps.get(&track.kind).copied() else {
+            self.last_known.insert(track.id.clone(), current);
+            return Ok(());
+        };
+        let last = match self.last_known.entry(track.id.clone()) {
+            Entry::Vacant(entry) => {
+                entry.insert(current);
+                return Ok(());
+            }
+            Entry::Occupied(entry) => entry.into_mut(),
+        };
+
+        let distance_m = haversine_m(last.lat, last.lon, current.lat, current.lon);
+        let elapsed_s = last.ts_ms.abs_diff(current.ts_ms) as f64 / 1000.0;
+        let reachable_m = max_speed * elapsed_s + self.config.jump_slack_m;
+        if distance_m <= reachable_m || last.jumps + 1 >= MAX_CONSECUTIVE_JUMPS {
+            *last = current;
+            return Ok(());
+        }
+
+        last.jumps += 1;
+        Err(format!(
+            "moved {:.0}m in {:.1}s (implied {:.0} m/s, limit {:.0} m/s)",
+            distance_m,
+            elapsed_s,
+            distance_m / elapsed_s.max(1.0),
+            max_speed
+        ))
+    }
+}
+
+fn haversine_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let delta_lat = (lat2 - lat1).to_radians();
+    let delta_lon = (lon2 - lon1).to_radians();
+    let a = (delta_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (delta_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harpy_proto::harpy::v1::Position;
+
+    const NOW: u64 = 1_760_000_000_000;
+
+    fn aircraft(id: &str, lat: f64, lon: f64, ts_ms: u64) -> TrackDelta {
+        TrackDelta {
+            id: id.to_string(),
+            kind: TrackKind::Aircraft as i32,
+            position: Some(Position {
+                lat,
+                lon,
+                alt: 10_000.0,
+            }),
+            ts_ms,
+            provider_id: "opensky".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn reasons(rejected: &[Rejection]) -> Vec<(&str, RejectReason)> {
+        rejected
+            .iter()
+            .map(|r| (r.track.id.as_str(), r.reason))
+            .collect()
+    }
+
+    #[test]
+    fn test_rejects_bad_coordinates_and_timestamps() {
+        let mut validator = Validator::new("opensky", ValidationConfig::default());
+        let mut no_position = aircraft("none", 0.0, 0.0, NOW);
+        no_position.position = None;
+
+        let (accepted, rejected) = validator.validate(
+            vec![
+                aircraft("ok", 51.5, -0.1, NOW),
+                aircraft("nan", f64::NAN, -0.1, NOW),
+                aircraft("range", 95.0, -0.1, NOW),
+                aircraft("future", 51.5, -0.1, NOW + 2 * 3_600_000),
+                aircraft("past", 51.5, -0.1, NOW - 31 * 86_400_000),
+                no_position,
+            ],
+            NOW,
+        );
+
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].id, "ok");
+        assert_eq!(
+            reasons(&rejected),
+            vec![
+                ("nan", RejectReason::NonFiniteCoordinate),
+                ("range", RejectReason::CoordinateOutOfRange),
+                ("future", RejectReason::FutureTimestamp),
+                ("past", RejectReason::StaleTimestamp),
+                ("none", RejectReason::MissingPosition),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keeps_newest_of_duplicate_ids() {
+        let mut validator = Validator::new("opensky", ValidationConfig::default());
+        let (accepted, rejected) = validator.validate(
+            vec![
+                aircraft("a1", 51.5, -0.1, NOW - 10_000),
+                aircraft("a1", 51.5, -0.1, NOW),
+                aircraft("a2", 40.0, -74.0, NOW),
+            ],
+            NOW,
+        );
+
+        let ids: Vec<(&str, u64)> = accepted.iter().map(|t| (t.id.as_str(), t.ts_ms)).collect();
+        assert_eq!(ids, vec![("a1", NOW), ("a2", NOW)]);
+        assert_eq!(reasons(&rejected), vec![("a1", RejectReason::DuplicateId)]);
+        assert_eq!(rejected[0].track.ts_ms, NOW - 10_000);
+    }
+
+    #[test]
+    fn test_rejects_teleport_against_last_known_position() {
+        let mut validator = Validator::new("opensky", ValidationConfig::default());
+        validator.validate(vec![aircraft("a1", 51.5, -0.1, NOW)], NOW);
+
+        // ~2.2 km in 10 s is 220 m/s: fine for an aircraft
+        let (accepted, _) =
+            validator.validate(vec![aircraft("a1", 51.52, -0.1, NOW + 10_000)], NOW);
+        assert_eq!(accepted.len(), 1);
+
+        // London to New York in 10 s
+        let (accepted, rejected) =
+            validator.validate(vec![aircraft("a1", 40.6, -73.8, NOW + 20_000)], NOW);
+        assert!(accepted.is_empty());
+        assert_eq!(
+            reasons(&rejected),
+            vec![("a1", RejectReason::ImpossibleJump)]
+        );
+
+        // The anchor stays at the last good position
+        let (accepted, _) =
+            validator.validate(vec![aircraft("a1", 51.53, -0.1, NOW + 30_000)], NOW);
+        assert_eq!(accepted.len(), 1);
+    }
+
+    #[test]
+    fn test_reanchors_after_repeated_jumps() {
+        let mut validator = Validator::new("opensky", ValidationConfig::default());
+        // A bad first fix, then the track keeps reporting its real position
+        validator.validate(vec![aircraft("a1", 0.0, 0.0, NOW)], NOW);
+
+        let mut outcomes = Vec::new();
+        for step in 1..=4 {
+            let (accepted, _) =
+                validator.validate(vec![aircraft("a1", 51.5, -0.1, NOW + step * 10_000)], NOW);
+            outcomes.push(accepted.len());
+        }
+        assert_eq!(outcomes, vec![0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_kinds_without_speed_limit_skip_jump_check() {
+        let mut config = ValidationConfig::default();
+        config.max_speed_mps.remove(&(TrackKind::Aircraft as i32));
+        let mut validator = Validator::new("opensky", config);
+        validator.validate(vec![aircraft("a1", 51.5, -0.1, NOW)], NOW);
+        let (accepted, _) = validator.validate(vec![aircraft("a1", 40.6, -73.8, NOW)], NOW);
+        assert_eq!(accepted.len(), 1);
+    }
+
+    #[test]
+    fn test_old_tle_epoch_is_not_stale_for_satellites() {
+        // CelesTrak stamps satellites with the TLE epoch, often months old
+        let epoch = NOW - 400 * 86_400_000;
+        let mut satellite = aircraft("sat-25544", 51.6, 10.0, epoch);
+        satellite.kind = TrackKind::Satellite as i32;
+        satellite.provider_id = "celestrak".to_string();
+
+        let mut validator = Validator::new("celestrak", ValidationConfig::default());
+        let (accepted, rejected) = validator.validate(
+            vec![satellite.clone(), aircraft("a1", 51.5, -0.1, epoch)],
+            NOW,
+        );
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].id, "sat-25544");
+        assert_eq!(
+            reasons(&rejected),
+            vec![("a1", RejectReason::StaleTimestamp)]
+        );
+        // The anchor survives the horizon sweep, so jumps are still checked
+        assert!(validator.last_known.contains_key("sat-25544"));
+
+        let mut config = ValidationConfig::default();
+        config
+            .max_past_ms_by_kind
+            .insert(TrackKind::Satellite as i32, 365 * 86_400_000);
+        let (accepted, rejected) =
+            Validator::new("celestrak", config).validate(vec![satellite], NOW);
+        assert!(accepted.is_empty());
+        assert_eq!(
+            reasons(&rejected),
+            vec![("sat-25544", RejectReason::StaleTimestamp)]
+        );
+    }
+}
@@ -15,9 +15,11 @@ use axum::{
     Json, Router,
 };
 use dashmap::DashMap;
+use harpy_core::{enrichment::Enrichers, follow, track_filter};
 use harpy_proto::harpy::v1::{
-    envelope::Payload, BoundingBox, CircuitState, Envelope, Freshness, LayerType, ProviderStatus,
-    SubscriptionAck, SubscriptionMode, TrackDelta, TrackDeltaBatch, TrackKind,
+    envelope::Payload, BoundingBox, CircuitState, Envelope, FollowFilter, Freshness, LayerType,
+    ProviderStatus, SubscriptionAck, SubscriptionMode, TrackDelta, TrackDeltaBatch, TrackFilter,
+    TrackKind,
 };
 use metrics::{counter, gauge};
 use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
@@ -40,12 +42,18 @@ use uuid::Uuid;
 #[derive(Clone)]
 struct AppState {
     tx: broadcast::Sender<NodeEvent>,
-    subs: Arc<DashMap<String, ClientSub>>,
+    subs: Arc<DashMap<String, HashMap<String, ClientSub>>>,
     provider_snapshots: Arc<DashMap<String, ProviderSnapshot>>,
     metrics: PrometheusHandle,
     debug_counters: Arc<DebugCounters>,
+    enrichers: Enrichers,
 }
 
+/// Subscription ID used when a request does not name one
+const DEFAULT_SUBSCRIPTION_ID: &str = "default";
+/// Maximum number of named subscriptions a single connection may hold
+const MAX_SUBSCRIPTIONS_PER_CONNECTION: usize = 8;
+
 #[derive(Clone)]
 enum NodeEvent {
     TrackBatch(Arc<Vec<TrackDelta>>),
@@ -57,6 +65,8 @@ struct ClientSub {
     viewport: BoundingBox,
     layers: HashSet<i32>,
     mode: i32,
+    follow: Option<FollowFilter>,
+    filter: Option<TrackFilter>,
 }
 
 impl Default for ClientSub {
@@ -70,6 +80,8 @@ impl Default for ClientSub {
             },
             layers: default_layers(),
             mode: SubscriptionMode::Live as i32,
+            follow: None,
+            filter: None,
         }
     }
 }
@@ -144,6 +156,7 @@ async fn main() -> anyhow::Result<()> {
         provider_snapshots: Arc::new(DashMap::new()),
         metrics,
         debug_counters: Arc::new(DebugCounters::default()),
+        enrichers: Enrichers::from_env(),
     };
 
     tokio::spawn(provider_loop_adsb(state.clone()));
@@ -196,7 +209,12 @@ async fn debug_snapshot_handler(State(state): State<AppState>) -> impl IntoRespo
             playback_clients: state
                 .subs
                 .iter()
-                .filter(|entry| entry.value().mode == SubscriptionMode::Playback as i32)
+                .filter(|entry| {
+                    entry
+                        .value()
+                        .values()
+                        .any(|sub| sub.mode == SubscriptionMode::Playback as i32)
+                })
                 .count(),
             backpressure_totals: BackpressureTotals {
                 track_batches_dropped: 0,
@@ -221,10 +239,13 @@ async fn ws_handler(State(state): State<AppState>, ws: WebSocketUpgrade) -> impl
 
 async fn handle_socket(mut socket: WebSocket, state: AppState) {
     let client_id = format!("client-{}", Uuid::new_v4().simple());
-    state.subs.insert(client_id.clone(), ClientSub::default());
+    state.subs.insert(
+        client_id.clone(),
+        HashMap::from([(DEFAULT_SUBSCRIPTION_ID.to_string(), ClientSub::default())]),
+    );
     gauge!("harpy_ws_connections").increment(1.0);
 
-    if send_subscription_ack(&mut socket, &client_id, true, None)
+    if send_subscription_ack(&mut socket, DEFAULT_SUBSCRIPTION_ID, None)
         .await
         .is_err()
     {
@@ -240,11 +261,8 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
             incoming = socket.recv() => {
                 match incoming {
                     Some(Ok(Message::Binary(data))) => {
-                        if let Err(error) = handle_client_binary_message(&client_id, &state, data) {
-                            let _ = send_subscription_ack(&mut socket, &client_id, false, Some(error)).await;
-                        } else {
-                            let _ = send_subscription_ack(&mut socket, &client_id, true, None).await;
-                        }
+                        let (subscription_id, result) = handle_client_binary_message(&client_id, &state, data);
+                        let _ = send_subscription_ack(&mut socket, &subscription_id, result.err()).await;
                     }
                     Some(Ok(Message::Close(_))) => {
                         break;
@@ -262,16 +280,18 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
             outbound = rx.recv() => {
                 match outbound {
                     Ok(event) => {
-                        let sub = state
+                        let subs = state
                             .subs
                             .get(&client_id)
                             .map(|entry| entry.value().clone())
                             .unwrap_or_default();
 
-                        if let Some((envelope, track_count, provider_status_count)) = event_to_envelope(event, &sub) {
+                        let mut closed = false;
+                        for (envelope, track_count, provider_status_count) in event_to_envelopes(event, &subs) {
                             match encode_envelope(&envelope) {
                                 Ok(bytes) => {
                                     if socket.send(Message::Binary(bytes)).await.is_err() {
+                                        closed = true;
                                         break;
                                     }
                                     if track_count > 0 {
@@ -293,6 +313,9 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
                                 Err(err) => tracing::error!("failed to encode outgoing envelope: {}", err),
                             }
                         }
+                        if closed {
+                            break;
+                        }
                     }
                     Err(broadcast::error::RecvError::Lagged(skipped)) => {
                         tracing::warn!("client {} lagged {} broadcast messages", client_id, skipped);
@@ -307,24 +330,50 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
     gauge!("harpy_ws_connections").decrement(1.0);
 }
 
+/// Apply a client message, returning the subscription ID it targeted and the outcome
 fn handle_client_binary_message(
     client_id: &str,
     state: &AppState,
     data: Vec<u8>,
-) -> Result<(), String> {
-    let envelope = Envelope::decode(&*data).map_err(|err| format!("decode error: {err}"))?;
-    let Some(payload) = envelope.payload else {
-        return Ok(());
+) -> (String, Result<(), String>) {
+    let envelope = match Envelope::decode(&*data) {
+        Ok(envelope) => envelope,
+        Err(err) => return (String::new(), Err(format!("decode error: {err}"))),
+    };
+    let Some(Payload::SubscriptionRequest(req)) = envelope.payload else {
+        return (DEFAULT_SUBSCRIPTION_ID.to_string(), Ok(()));
     };
 
-    match payload {
-        Payload::SubscriptionRequest(req) => {
-            let sub = subscription_from_request(req);
-            state.subs.insert(client_id.to_string(), sub);
-            Ok(())
-        }
-        _ => Ok(()),
+    let subscription_id = if req.subscription_id.is_empty() {
+        DEFAULT_SUBSCRIPTION_ID.to_string()
+    } else {
+        req.subscription_id.clone()
+    };
+    let mut subs = state.subs.entry(client_id.to_string()).or_default();
+
+    if req.unsubscribe {
+        let result = match subs.remove(&subscription_id) {
+            Some(_) => Ok(()),
+            None => Err(format!("unknown subscription: {subscription_id}")),
+        };
+        return (subscription_id, result);
+    }
+
+    if let Some(Err(error)) = req.filter.as_ref().map(track_filter::validate) {
+        return (subscription_id, Err(format!("invalid filter: {error}")));
+    }
+
+    if !subs.contains_key(&subscription_id) && subs.len() >= MAX_SUBSCRIPTIONS_PER_CONNECTION {
+        return (
+            subscription_id,
+            Err(format!(
+                "too many subscriptions on this connection (max {MAX_SUBSCRIPTIONS_PER_CONNECTION})"
+            )),
+        );
     }
+
+    subs.insert(subscription_id.clone(), subscription_from_request(req));
+    (subscription_id, Ok(()))
 }
 
 fn subscription_from_request(req: harpy_proto::harpy::v1::SubscriptionRequest) -> ClientSub {
@@ -336,6 +385,9 @@ fn subscription_from_request(req: harpy_proto::harpy::v1::SubscriptionRequest) -
         viewport: req.viewport.unwrap_or_else(default_viewport),
         layers,
         mode: req.mode,
+        // The node keeps no history, so `trail_ms` is not honored here.
+        follow: req.follow.filter(follow::is_active),
+        filter: req.filter,
     }
 }
 
@@ -359,43 +411,63 @@ fn default_layers() -> HashSet<i32> {
     ])
 }
 
-fn event_to_envelope(event: NodeEvent, sub: &ClientSub) -> Option<(Envelope, u64, u64)> {
+/// Build the outbound envelopes for one connection: a tagged track batch per
+/// matching subscription, and connection-wide messages once.
+fn event_to_envelopes(
+    event: NodeEvent,
+    subs: &HashMap<String, ClientSub>,
+) -> Vec<(Envelope, u64, u64)> {
     match event {
-        NodeEvent::TrackBatch(tracks) => {
-            let filtered = filter_tracks_for_sub(&tracks, sub);
-            if filtered.is_empty() {
-                return None;
-            }
-            let filtered_len = filtered.len() as u64;
-            Some((
-                Envelope {
-                    schema_version: "1.0.0".to_string(),
-                    server_ts_ms: now_ms(),
-                    payload: Some(Payload::TrackDeltaBatch(TrackDeltaBatch {
-                        deltas: filtered,
-                    })),
-                },
-                filtered_len,
-                0,
-            ))
-        }
-        NodeEvent::ProviderStatus(provider_status) => Some((
+        NodeEvent::TrackBatch(tracks) => subs
+            .iter()
+            .filter_map(|(subscription_id, sub)| {
+                let filtered = filter_tracks_for_sub(&tracks, sub);
+                if filtered.is_empty() {
+                    return None;
+                }
+                let filtered_len = filtered.len() as u64;
+                Some((
+                    Envelope {
+                        schema_version: "1.0.0".to_string(),
+                        server_ts_ms: now_ms(),
+                        seq: 0,
+                        payload: Some(Payload::TrackDeltaBatch(TrackDeltaBatch {
+                            deltas: filtered,
+                            subscription_id: subscription_id.clone(),
+                        })),
+                    },
+                    filtered_len,
+                    0,
+                ))
+            })
+            .collect(),
+        NodeEvent::ProviderStatus(provider_status) => vec![(
             Envelope {
                 schema_version: "1.0.0".to_string(),
                 server_ts_ms: now_ms(),
+                seq: 0,
                 payload: Some(Payload::ProviderStatus(provider_status)),
             },
             0,
             1,
-        )),
+        )],
     }
 }
 
 fn filter_tracks_for_sub(tracks: &[TrackDelta], sub: &ClientSub) -> Vec<TrackDelta> {
     tracks
         .iter()
-        .filter(|track| layer_allowed(track.kind, &sub.layers))
-        .filter(|track| track_in_viewport(track, &sub.viewport))
+        .filter(|track| {
+            sub.follow
+                .as_ref()
+                .is_some_and(|follow| follow::follows(follow, track))
+                || (layer_allowed(track.kind, &sub.layers)
+                    && track_in_viewport(track, &sub.viewport)
+                    && sub
+                        .filter
+                        .as_ref()
+                        .map_or(true, |filter| track_filter::passes(filter, track)))
+        })
         .cloned()
         .collect()
 }
@@ -435,17 +507,18 @@ fn track_in_viewport(track: &TrackDelta, viewport: &BoundingBox) -> bool {
 
 async fn send_subscription_ack(
     socket: &mut WebSocket,
-    client_id: &str,
-    success: bool,
+    subscription_id: &str,
     error: Option<String>,
 ) -> Result<(), ()> {
     let ack = Envelope {
         schema_version: "1.0.0".to_string(),
         server_ts_ms: now_ms(),
+        seq: 0,
         payload: Some(Payload::SubscriptionAck(SubscriptionAck {
-            subscription_id: client_id.to_string(),
-            success,
+            subscription_id: subscription_id.to_string(),
+            success: error.is_none(),
             error,
+            ..Default::default()
         })),
     };
     let bytes = encode_envelope(&ack).map_err(|_| ())?;
@@ -534,12 +607,14 @@ async fn poll_provider(
     let mut interval = tokio::time::interval(Duration::from_secs(interval_secs.max(1)));
     let mut consecutive_failures = 0_u32;
     let mut last_success_ts_ms = 0_u64;
+    let enrichment = state.enrichers.pipeline_for(provider.provider_id());
 
     loop {
         interval.tick().await;
         let provider_id = provider.provider_id().to_string();
         match provider.fetch().await {
-            Ok(deltas) => {
+            Ok(mut deltas) => {
+                enrichment.enrich(&mut deltas);
                 let items = deltas.len();
                 consecutive_failures = 0;
                 last_success_ts_ms = now_ms();
@@ -669,7 +744,7 @@ fn now_ms() -> u64 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use harpy_proto::harpy::v1::Position;
+    use harpy_proto::harpy::v1::{MetaOp, MetaPredicate, Position};
 
     fn make_track(lat: f64, lon: f64, kind: i32) -> TrackDelta {
         TrackDelta {
@@ -714,6 +789,117 @@ mod tests {
         assert!(!track_in_viewport(&outside_mid, &viewport));
     }
 
+    #[test]
+    fn event_to_envelopes_tags_batches_per_subscription() {
+        let inset = ClientSub {
+            viewport: BoundingBox {
+                min_lat: 35.0,
+                min_lon: -125.0,
+                max_lat: 40.0,
+                max_lon: -120.0,
+            },
+            ..ClientSub::default()
+        };
+        let subs = HashMap::from([
+            (DEFAULT_SUBSCRIPTION_ID.to_string(), ClientSub::default()),
+            ("inset".to_string(), inset),
+        ]);
+        let tracks = Arc::new(vec![
+            make_track(37.77, -122.41, TrackKind::Aircraft as i32),
+            make_track(51.5, 0.0, TrackKind::Aircraft as i32),
+        ]);
+
+        let envelopes = event_to_envelopes(NodeEvent::TrackBatch(tracks), &subs);
+        let tagged: HashMap<String, usize> = envelopes
+            .into_iter()
+            .filter_map(|(envelope, _, _)| match envelope.payload {
+                Some(Payload::TrackDeltaBatch(batch)) => {
+                    Some((batch.subscription_id, batch.deltas.len()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tagged.get(DEFAULT_SUBSCRIPTION_ID), Some(&2));
+        assert_eq!(tagged.get("inset"), Some(&1));
+
+        let status = ProviderStatus {
+            provider_id: "p".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            event_to_envelopes(NodeEvent::ProviderStatus(status), &subs).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn followed_tracks_bypass_viewport_and_layers() {
+        let sub = ClientSub {
+            viewport: BoundingBox {
+                min_lat: 35.0,
+                min_lon: -125.0,
+                max_lat: 40.0,
+                max_lon: -120.0,
+            },
+            layers: HashSet::from([LayerType::Vessel as i32]),
+            follow: Some(FollowFilter {
+                callsign_patterns: vec!["mck00*".to_string()],
+                ..Default::default()
+            }),
+            ..ClientSub::default()
+        };
+        let mut followed = make_track(51.5, 0.0, TrackKind::Aircraft as i32);
+        followed
+            .meta
+            .insert("callsign".to_string(), "MCK007".to_string());
+        let unfollowed = make_track(51.5, 0.0, TrackKind::Aircraft as i32);
+
+        let filtered = filter_tracks_for_sub(&[followed, unfollowed], &sub);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].meta.get("callsign").unwrap(), "MCK007");
+    }
+
+    #[test]
+    fn track_filter_applies_provider_altitude_and_meta_predicates() {
+        let sub = ClientSub {
+            filter: Some(TrackFilter {
+                provider_deny: vec!["blocked".to_string()],
+                min_alt: Some(1000.0),
+                meta: vec![MetaPredicate {
+                    key: "squawk".to_string(),
+                    op: MetaOp::In as i32,
+                    values: vec!["7500".to_string(), "7600".to_string(), "7700".to_string()],
+                }],
+                ..Default::default()
+            }),
+            ..ClientSub::default()
+        };
+
+        let mut emergency = make_track(10.0, 10.0, TrackKind::Aircraft as i32);
+        emergency.position.as_mut().unwrap().alt = 9000.0;
+        emergency
+            .meta
+            .insert("squawk".to_string(), "7700".to_string());
+        let mut blocked = emergency.clone();
+        blocked.provider_id = "blocked".to_string();
+        let mut low = emergency.clone();
+        low.position.as_mut().unwrap().alt = 100.0;
+        let mut routine = emergency.clone();
+        routine
+            .meta
+            .insert("squawk".to_string(), "1200".to_string());
+
+        let filtered = filter_tracks_for_sub(&[emergency, blocked, low, routine], &sub);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].provider_id, "test");
+        assert!(track_filter::validate(&TrackFilter {
+            min_speed: Some(5.0),
+            max_speed: Some(1.0),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
     #[test]
     fn layer_allowed_maps_track_kinds_to_layer_types() {
         let layers = HashSet::from([
@@ -17,6 +17,7 @@ fn default_subscription() -> Envelope {
     Envelope {
         schema_version: "1.0.0".to_string(),
         server_ts_ms: 0,
+        seq: 0,
         payload: Some(Payload::SubscriptionRequest(SubscriptionRequest {
             viewport: Some(BoundingBox {
                 min_lat: -90.0,
@@ -29,6 +30,10 @@ fn default_subscription() -> Envelope {
                 range: Some(time_range::Range::Live(LiveMode {})),
             }),
             mode: SubscriptionMode::Live as i32,
+            subscription_id: String::new(),
+            unsubscribe: false,
+            follow: None,
+            filter: None,
         })),
     }
 }
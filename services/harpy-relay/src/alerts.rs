@@ -0,0 +1,290 @@
+//! Alert Scoping
+//!
+//! Locates alerts and links by the tracks they concern so fanout can honor
+//! each subscription's alert layer and viewport, and loads the open alerts
+//! sent to a client when it subscribes.
+
+use harpy_core::redis_contract::{
+    self, AlertMessage, AlertSeverity as ContractSeverity, AlertStatus as ContractStatus,
+    LinkMessage, NodeKind,
+};
+use harpy_proto::harpy::v1::{AlertSeverity, AlertStatus, AlertUpsert};
+use serde_json::Value;
+use sqlx::postgres::PgRow;
+use sqlx::Row;
+
+/// Maximum number of open alerts sent on subscribe, newest first
+const MAX_OPEN_ALERTS: i64 = 500;
+
+/// Tracks and positions an alert or link concerns
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlertScope {
+    /// Evidence track ids, with or without a known position
+    pub track_ids: Vec<String>,
+    /// Known evidence positions as `(lat, lon)`
+    pub points: Vec<(f64, f64)>,
+    /// Known callsigns of the evidence tracks
+    pub callsigns: Vec<String>,
+}
+
+impl AlertScope {
+    /// Scope of an alert from its evidence tracks
+    pub fn of_alert(message: &AlertMessage) -> Self {
+        Self {
+            track_ids: message
+                .evidence_tracks
+                .iter()
+                .map(|track| track.track_id.clone())
+                .collect(),
+            points: message
+                .evidence_tracks
+                .iter()
+                .map(|track| (track.position.lat, track.position.lon))
+                .collect(),
+            callsigns: message
+                .evidence_tracks
+                .iter()
+                .filter_map(|track| track.callsign.clone())
+                .collect(),
+        }
+    }
+
+    /// Scope of a link from its track endpoints
+    pub fn of_link(message: &LinkMessage) -> Self {
+        let mut scope = Self::default();
+        let endpoints = [
+            (message.from_type, &message.from_id, message.from_position),
+            (message.to_type, &message.to_id, message.to_position),
+        ];
+        for (kind, id, position) in endpoints {
+            if kind == NodeKind::Track {
+                scope.track_ids.push(id.clone());
+            }
+            if let Some(position) = position {
+                scope.points.push((position.lat, position.lon));
+            }
+        }
+        scope
+    }
+
+    /// Whether nothing locates the alert; such alerts reach every alert subscriber
+    pub fn is_unlocated(&self) -> bool {
+        self.track_ids.is_empty() && self.points.is_empty()
+    }
+}
+
+/// Map a contract severity to its protobuf value
+pub fn proto_severity(severity: ContractSeverity) -> AlertSeverity {
+    match severity {
+        ContractSeverity::Info => AlertSeverity::Info,
+        ContractSeverity::Medium => AlertSeverity::Medium,
+        ContractSeverity::Warning => AlertSeverity::Warning,
+        ContractSeverity::Critical => AlertSeverity::Critical,
+    }
+}
+
+/// Map a contract status to its protobuf value
+pub fn proto_status(status: ContractStatus) -> AlertStatus {
+    match status {
+        ContractStatus::Active => AlertStatus::Active,
+        ContractStatus::Acknowledged => AlertStatus::Acknowledged,
+        ContractStatus::Resolved => AlertStatus::Resolved,
+    }
+}
+
+/// Alert columns with evidence link ids and the current position and callsign of evidence tracks
+///
+/// Evidence tracks are those linked from the alert itself and the endpoints of
+/// its `alert_evidence` links.
+const ALERT_SELECT: &str = "SELECT a.id, a.severity, a.title, a.description, a.ts_ms, a.status, a.meta, \
+        (EXTRACT(EPOCH FROM a.updated_at) * 1000)::BIGINT AS updated_ts_ms, \
+        ARRAY(SELECT ae.link_id FROM alert_evidence ae WHERE ae.alert_id = a.id) AS evidence_link_ids, \
+        evidence.track_ids, evidence.lats, evidence.lons \
+ FROM alerts a \
+ LEFT JOIN LATERAL ( \
+     SELECT array_agg(ev.track_id) AS track_ids, \
+            array_agg(t.lat) FILTER (WHERE t.id IS NOT NULL) AS lats, \
+            array_agg(t.lon) FILTER (WHERE t.id IS NOT NULL) AS lons, \
+            array_agg(btrim(t.meta->>'callsign')) FILTER (WHERE t.meta->>'callsign' IS NOT NULL) AS callsigns \
+     FROM ( \
+         SELECT l.to_id FROM links l \
+         WHERE l.from_type = 'Alert' AND l.from_id = a.id AND l.to_type = 'Track' \
+         UNION \
+         SELECT l.from_id FROM alert_evidence ae JOIN links l ON l.id = ae.link_id \
+         WHERE ae.alert_id = a.id AND l.from_type = 'Track' \
+         UNION \
+         SELECT l.to_id FROM alert_evidence ae JOIN links l ON l.id = ae.link_id \
+         WHERE ae.alert_id = a.id AND l.to_type = 'Track' \
+     ) ev(track_id) \
+     LEFT JOIN tracks t ON t.id = ev.track_id \
+ ) evidence ON TRUE";
+
+/// An alert loaded for a time range
+#[derive(Debug, Clone)]
+pub struct HistoricalAlert {
+    pub alert: AlertUpsert,
+    pub scope: AlertScope,
+    /// When the alert row was last updated; a RESOLVED alert ends here
+    pub updated_ts_ms: u64,
+}
+
+/// Load ACTIVE and ACKNOWLEDGED alerts with the current position of their evidence tracks
+pub async fn fetch_open_alerts(
+    pool: &sqlx::PgPool,
+) -> anyhow::Result<Vec<(AlertUpsert, AlertScope)>> {
+    let sql = format!(
+        "{ALERT_SELECT} \
+         WHERE a.status IN ('ACTIVE', 'ACKNOWLEDGED') \
+         ORDER BY a.ts_ms DESC \
+         LIMIT $1"
+    );
+    let rows = sqlx::query(&sql)
+        .bind(MAX_OPEN_ALERTS)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows.iter().filter_map(alert_from_row).collect())
+}
+
+/// Load alerts raised before `end_ts_ms` that were still open at `start_ts_ms`, oldest first
+pub async fn fetch_alerts_in_range(
+    pool: &sqlx::PgPool,
+    start_ts_ms: u64,
+    end_ts_ms: u64,
+    limit: i64,
+) -> anyhow::Result<Vec<HistoricalAlert>> {
+    let sql = format!(
+        "{ALERT_SELECT} \
+         WHERE a.ts_ms <= $2 \
+           AND (a.status <> 'RESOLVED' OR a.updated_at >= to_timestamp($1::BIGINT / 1000.0)) \
+         ORDER BY a.ts_ms ASC \
+         LIMIT $3"
+    );
+    let rows = sqlx::query(&sql)
+        .bind(start_ts_ms as i64)
+        .bind(end_ts_ms as i64)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let (alert, scope) = alert_from_row(row)?;
+            Some(HistoricalAlert {
+                alert,
+                scope,
+                updated_ts_ms: row
+                    .try_get::<Option<i64>, _>("updated_ts_ms")
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default()
+                    .max(0) as u64,
+            })
+        })
+        .collect())
+}
+
+/// Convert an `alerts` row to an AlertUpsert and its scope; rows with unknown enums are skipped
+fn alert_from_row(row: &PgRow) -> Option<(AlertUpsert, AlertScope)> {
+    let id: String = row.get("id");
+    let severity: String = row.get("severity");
+    let status: String = row.get("status");
+    let (Some(severity), Some(status)) = (
+        ContractSeverity::parse(&severity),
+        ContractStatus::parse(&status),
+    ) else {
+        tracing::warn!("Skipping alert {} with unknown severity or status", id);
+        return None;
+    };
+
+    let meta = match row.get::<Option<Value>, _>("meta") {
+        Some(Value::Object(meta)) => redis_contract::flatten_meta(&meta),
+        _ => Default::default(),
+    };
+    let track_ids: Option<Vec<String>> = row.get("track_ids");
+    let lats: Option<Vec<f64>> = row.get("lats");
+    let lons: Option<Vec<f64>> = row.get("lons");
+    let callsigns: Option<Vec<String>> = row.get("callsigns");
+
+    let alert = AlertUpsert {
+        id,
+        severity: proto_severity(severity) as i32,
+        title: row.get("title"),
+        description: row
+            .get::<Option<String>, _>("description")
+            .unwrap_or_default(),
+        ts_ms: row.get::<i64, _>("ts_ms").max(0) as u64,
+        evidence_link_ids: row.get("evidence_link_ids"),
+        status: proto_status(status) as i32,
+        meta,
+    };
+    let scope = AlertScope {
+        track_ids: track_ids.unwrap_or_default(),
+        points: lats
+            .unwrap_or_default()
+            .into_iter()
+            .zip(lons.unwrap_or_default())
+            .collect(),
+        callsigns: callsigns.unwrap_or_default(),
+    };
+    Some((alert, scope))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_alert_scope_from_evidence_tracks() {
+        let payload = json!({
+            "id": "alert-1",
+            "severity": "WARNING",
+            "title": "Speed Anomaly",
+            "ts_ms": 1,
+            "status": "ACTIVE",
+            "evidence_tracks": [
+                {"track_id": "adsb-1", "position": {"lat": 10.0, "lon": 20.0}}
+            ]
+        })
+        .to_string();
+        let scope =
+            AlertScope::of_alert(&redis_contract::decode::<AlertMessage>(&payload).unwrap());
+        assert_eq!(scope.track_ids, vec!["adsb-1"]);
+        assert_eq!(scope.points, vec![(10.0, 20.0)]);
+        assert!(scope.callsigns.is_empty());
+
+        // Messages without evidence tracks are unlocated
+        let legacy = json!({
+            "id": "alert-2",
+            "severity": "INFO",
+            "title": "t",
+            "ts_ms": 1,
+            "status": "ACTIVE"
+        })
+        .to_string();
+        assert!(
+            AlertScope::of_alert(&redis_contract::decode::<AlertMessage>(&legacy).unwrap())
+                .is_unlocated()
+        );
+    }
+
+    #[test]
+    fn test_link_scope_uses_track_endpoints() {
+        let payload = json!({
+            "id": "link-1",
+            "from_type": "Alert",
+            "from_id": "alert-1",
+            "rel": "is_evidenced_by",
+            "to_type": "Track",
+            "to_id": "adsb-1",
+            "ts_ms": 1,
+            "to_position": {"lat": -5.0, "lon": 120.0}
+        })
+        .to_string();
+        let scope = AlertScope::of_link(&redis_contract::decode::<LinkMessage>(&payload).unwrap());
+        assert_eq!(scope.track_ids, vec!["adsb-1"]);
+        assert_eq!(scope.points, vec![(-5.0, 120.0)]);
+    }
+}
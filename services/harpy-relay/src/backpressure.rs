@@ -1,44 +1,79 @@
 //! Backpressure Semantics (B2-5)
 //!
-//! Implements differentiated backpressure handling:
-//! - TrackDeltaBatch: Droppable under load (high throughput, loss acceptable)
-//! - AlertUpsert: Never dropped (critical alerts)
-//! - ProviderStatus: Never dropped (health monitoring)
-//! - SnapshotMeta: Never dropped (control messages)
+//! Each connection has one outbound queue shared by all of its subscriptions:
+//! - TrackDeltaBatch: while the client is behind, pending batches of a
+//!   subscription coalesce to the newest update per track id, up to
+//!   `max_pending_tracks` tracks per connection
+//! - AlertUpsert, LinkUpsert, ProviderStatus, SnapshotMeta and acks: delivered
+//!   in order, never coalesced, up to `max_pending_control` envelopes
+//!
+//! A client that overflows the control queue, or leaves envelopes pending for
+//! longer than `stall_timeout`, is evicted: its queue is freed, further sends
+//! fail, and the receiver reports why so the socket can be closed with a reason.
 
 #![allow(dead_code)]
 
-use harpy_proto::harpy::v1::Envelope;
+use crate::telemetry;
+use harpy_proto::harpy::v1::{envelope::Payload, Envelope, TrackDelta, TrackDeltaBatch};
+use std::collections::{HashMap, VecDeque};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-use tokio::sync::mpsc;
-
-/// Maximum number of pending track batches per client before dropping
-const MAX_PENDING_TRACK_BATCHES: usize = 10;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
 
-/// Channel type for backpressure-aware message sending
-#[derive(Debug, Clone)]
-pub struct BackpressureChannel {
-    /// High priority channel (alerts, provider status, control messages)
-    /// Uses unbounded channel - never drops
-    high_priority_tx: mpsc::UnboundedSender<Envelope>,
+/// Per-connection queue limits
+#[derive(Debug, Clone, Copy)]
+pub struct BackpressureLimits {
+    /// Distinct tracks pending across all subscriptions of a connection
+    pub max_pending_tracks: usize,
+    /// Pending alerts, links, statuses and control messages
+    pub max_pending_control: usize,
+    /// How long envelopes may wait without the client taking any
+    pub stall_timeout: Duration,
+}
 
-    /// Normal priority channel (track batches)
-    /// Uses bounded channel - drops under pressure
-    normal_priority_tx: mpsc::Sender<Envelope>,
+impl Default for BackpressureLimits {
+    fn default() -> Self {
+        Self {
+            max_pending_tracks: 20_000,
+            max_pending_control: 1_024,
+            stall_timeout: Duration::from_secs(30),
+        }
+    }
+}
 
-    /// Statistics for monitoring
-    stats: Arc<BackpressureStats>,
+impl BackpressureLimits {
+    /// Limits from `RELAY_MAX_PENDING_TRACKS`, `RELAY_MAX_PENDING_CONTROL` and `RELAY_CLIENT_STALL_SECS`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let env = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+        Self {
+            max_pending_tracks: env("RELAY_MAX_PENDING_TRACKS")
+                .map_or(defaults.max_pending_tracks, |v| v as usize),
+            max_pending_control: env("RELAY_MAX_PENDING_CONTROL")
+                .map_or(defaults.max_pending_control, |v| v as usize),
+            stall_timeout: env("RELAY_CLIENT_STALL_SECS")
+                .map_or(defaults.stall_timeout, Duration::from_secs),
+        }
+    }
 }
 
-/// Backpressure statistics
+/// Backpressure counters, kept per connection and relay-wide
 #[derive(Debug, Default)]
 pub struct BackpressureStats {
-    /// Number of track batches dropped
-    pub track_batches_dropped: AtomicUsize,
-    /// Number of track batches sent
+    /// Track batches handed to the socket
     pub track_batches_sent: AtomicUsize,
-    /// Number of high priority messages sent
+    /// Track updates handed to the socket
+    pub track_updates_sent: AtomicUsize,
+    /// Track updates superseded by a newer update of the same track
+    pub track_updates_coalesced: AtomicUsize,
+    /// Track updates dropped because `max_pending_tracks` was reached
+    pub track_updates_dropped: AtomicUsize,
+    /// High priority envelopes handed to the socket
     pub high_priority_sent: AtomicUsize,
 }
 
@@ -47,254 +82,622 @@ impl BackpressureStats {
         Self::default()
     }
 
-    pub fn record_track_batch_dropped(&self) {
-        self.track_batches_dropped.fetch_add(1, Ordering::Relaxed);
-    }
-
-    pub fn record_track_batch_sent(&self) {
-        self.track_batches_sent.fetch_add(1, Ordering::Relaxed);
-    }
-
-    pub fn record_high_priority_sent(&self) {
-        self.high_priority_sent.fetch_add(1, Ordering::Relaxed);
-    }
-
     pub fn get_stats(&self) -> BackpressureSnapshot {
         BackpressureSnapshot {
-            track_batches_dropped: self.track_batches_dropped.load(Ordering::Relaxed),
             track_batches_sent: self.track_batches_sent.load(Ordering::Relaxed),
+            track_updates_sent: self.track_updates_sent.load(Ordering::Relaxed),
+            track_updates_coalesced: self.track_updates_coalesced.load(Ordering::Relaxed),
+            track_updates_dropped: self.track_updates_dropped.load(Ordering::Relaxed),
             high_priority_sent: self.high_priority_sent.load(Ordering::Relaxed),
         }
     }
 }
 
 /// Snapshot of backpressure statistics
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct BackpressureSnapshot {
-    pub track_batches_dropped: usize,
     pub track_batches_sent: usize,
+    pub track_updates_sent: usize,
+    pub track_updates_coalesced: usize,
+    pub track_updates_dropped: usize,
     pub high_priority_sent: usize,
 }
 
-impl BackpressureChannel {
-    /// Create a new backpressure channel
-    pub fn new() -> (Self, mpsc::UnboundedReceiver<Envelope>) {
-        let (high_priority_tx, mut high_priority_rx) = mpsc::unbounded_channel();
-        let (normal_priority_tx, mut normal_priority_rx) = mpsc::channel(MAX_PENDING_TRACK_BATCHES);
-
-        let stats = Arc::new(BackpressureStats::new());
-
-        // Merge the two receivers into one
-        // For simplicity, we use a single unbounded receiver that gets all messages
-        // The normal_priority channel is used for backpressure detection only
-        let (merged_tx, merged_rx) = mpsc::unbounded_channel();
-
-        // Forward high/normal priority messages into a single receiver.
-        // If created outside a Tokio runtime (e.g. sync unit tests), skip task spawn.
-        if let Ok(handle) = tokio::runtime::Handle::try_current() {
-            let merged_tx_high = merged_tx.clone();
-            handle.spawn(async move {
-                while let Some(envelope) = high_priority_rx.recv().await {
-                    if merged_tx_high.send(envelope).is_err() {
-                        break;
-                    }
-                }
-            });
+/// Pending control envelopes and track updates of one connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueDepth {
+    pub control: usize,
+    pub tracks: usize,
+}
 
-            let merged_tx_normal = merged_tx.clone();
-            handle.spawn(async move {
-                while let Some(envelope) = normal_priority_rx.recv().await {
-                    if merged_tx_normal.send(envelope).is_err() {
-                        break;
-                    }
-                }
-            });
-        } else {
-            tracing::debug!(
-                "BackpressureChannel created outside Tokio runtime; forwarders not started"
-            );
+/// Why a client was evicted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// More than `max_pending_control` envelopes were pending
+    ControlOverflow,
+    /// Envelopes were pending for longer than `stall_timeout`
+    Stalled,
+}
+
+impl Eviction {
+    /// Close reason sent to the client
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::ControlOverflow => "too many pending messages",
+            Self::Stalled => "client stalled",
         }
+    }
+}
 
-        let channel = Self {
-            high_priority_tx,
-            normal_priority_tx,
-            stats,
-        };
+/// Pending track updates of one subscription
+enum PendingTracks {
+    /// A fanout batch received while nothing else was pending, still shared
+    Shared(Arc<Envelope>),
+    /// Newest update per track id merged from several batches
+    Coalesced {
+        server_ts_ms: u64,
+        deltas: HashMap<String, TrackDelta>,
+    },
+}
 
-        (channel, merged_rx)
+impl PendingTracks {
+    fn len(&self) -> usize {
+        match self {
+            Self::Shared(envelope) => batch_len(envelope),
+            Self::Coalesced { deltas, .. } => deltas.len(),
+        }
+    }
+
+    fn into_envelope(self, subscription_id: String) -> Arc<Envelope> {
+        match self {
+            Self::Shared(envelope) => envelope,
+            Self::Coalesced {
+                server_ts_ms,
+                deltas,
+            } => Arc::new(Envelope {
+                schema_version: "1.0.0".to_string(),
+                server_ts_ms,
+                seq: 0,
+                payload: Some(Payload::TrackDeltaBatch(TrackDeltaBatch {
+                    deltas: deltas.into_values().collect(),
+                    subscription_id,
+                })),
+            }),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    control: VecDeque<Arc<Envelope>>,
+    /// Pending tracks by subscription id, in the order they became pending
+    tracks: VecDeque<(String, PendingTracks)>,
+    pending_tracks: usize,
+    /// When the oldest envelope still pending was queued or the client last took one
+    waiting_since: Option<Instant>,
+    evicted: Option<Eviction>,
+    receiver_dropped: bool,
+}
+
+impl Queue {
+    fn is_empty(&self) -> bool {
+        self.control.is_empty() && self.tracks.is_empty()
+    }
+
+    fn evict(&mut self, eviction: Eviction) {
+        self.evicted = Some(eviction);
+        self.control.clear();
+        self.tracks.clear();
+        self.pending_tracks = 0;
+    }
+}
+
+struct Inner {
+    queue: Mutex<Queue>,
+    notify: Notify,
+    limits: BackpressureLimits,
+    stats: BackpressureStats,
+    monitor: Arc<BackpressureMonitor>,
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn count(&self, counter: fn(&BackpressureStats) -> &AtomicUsize, n: usize) {
+        if n > 0 {
+            counter(&self.stats).fetch_add(n, Ordering::Relaxed);
+            counter(&self.monitor.totals).fetch_add(n, Ordering::Relaxed);
+        }
     }
+}
+
+/// Sending half of a connection's outbound queue; cloned into each subscription
+#[derive(Clone)]
+pub struct BackpressureChannel {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for BackpressureChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BackpressureChannel")
+            .field("limits", &self.inner.limits)
+            .finish_non_exhaustive()
+    }
+}
 
-    /// Send an envelope with appropriate backpressure handling
+/// Receiving half of a connection's outbound queue
+pub struct BackpressureReceiver {
+    inner: Arc<Inner>,
+}
+
+impl BackpressureChannel {
+    /// Create a channel with default limits and its own monitor
+    pub fn new() -> (Self, BackpressureReceiver) {
+        Self::with_limits(
+            BackpressureLimits::default(),
+            Arc::new(BackpressureMonitor::new()),
+        )
+    }
+
+    /// Create a channel reporting to a relay-wide monitor
+    pub fn with_limits(
+        limits: BackpressureLimits,
+        monitor: Arc<BackpressureMonitor>,
+    ) -> (Self, BackpressureReceiver) {
+        let inner = Arc::new(Inner {
+            queue: Mutex::new(Queue::default()),
+            notify: Notify::new(),
+            limits,
+            stats: BackpressureStats::new(),
+            monitor,
+        });
+        (
+            Self {
+                inner: inner.clone(),
+            },
+            BackpressureReceiver { inner },
+        )
+    }
+
+    /// Queue an envelope with appropriate backpressure handling
     #[allow(clippy::result_large_err)]
     pub fn send(&self, envelope: Envelope) -> Result<(), Envelope> {
-        // Determine message priority based on payload type
-        let is_high_priority = self.is_high_priority(&envelope);
-
-        if is_high_priority {
-            // High priority: never drop, use unbounded channel
-            match self.high_priority_tx.send(envelope) {
-                Ok(_) => {
-                    self.stats.record_high_priority_sent();
-                    Ok(())
-                }
-                Err(e) => Err(e.0),
+        self.send_shared(Arc::new(envelope))
+            .map_err(|unsent| Arc::try_unwrap(unsent).unwrap_or_else(|shared| (*shared).clone()))
+    }
+
+    /// Queue an envelope shared between several clients (fanout batches)
+    ///
+    /// Fails once the client is evicted or gone. Track updates beyond
+    /// `max_pending_tracks` are dropped without failing the send.
+    pub fn send_shared(&self, envelope: Arc<Envelope>) -> Result<(), Arc<Envelope>> {
+        let inner = &self.inner;
+        let mut queue = inner.lock();
+        if queue.evicted.is_some() || queue.receiver_dropped {
+            let reason = if queue.receiver_dropped {
+                "closed"
+            } else {
+                "evicted"
+            };
+            telemetry::record_drop(&envelope, reason);
+            return Err(envelope);
+        }
+        if queue
+            .waiting_since
+            .is_some_and(|since| since.elapsed() > inner.limits.stall_timeout)
+        {
+            queue.evict(Eviction::Stalled);
+            inner.monitor.record_eviction();
+            telemetry::record_drop(&envelope, "evicted");
+            return Err(envelope);
+        }
+
+        if let Some(Payload::TrackDeltaBatch(batch)) = &envelope.payload {
+            if batch.deltas.is_empty() {
+                return Ok(());
             }
+            self.queue_tracks(&mut queue, &envelope);
         } else {
-            // Normal priority: try bounded channel first
-            match self.normal_priority_tx.try_send(envelope) {
-                Ok(_) => {
-                    self.stats.record_track_batch_sent();
-                    Ok(())
-                }
-                Err(tokio::sync::mpsc::error::TrySendError::Full(envelope)) => {
-                    // Channel full - drop the track batch
-                    self.stats.record_track_batch_dropped();
-                    tracing::warn!("Dropping TrackDeltaBatch due to backpressure (queue full)");
-                    // Return error to indicate drop
-                    Err(envelope)
-                }
-                Err(tokio::sync::mpsc::error::TrySendError::Closed(envelope)) => Err(envelope),
+            if queue.control.len() >= inner.limits.max_pending_control {
+                queue.evict(Eviction::ControlOverflow);
+                inner.monitor.record_eviction();
+                telemetry::record_drop(&envelope, "evicted");
+                return Err(envelope);
             }
+            queue.control.push_back(envelope);
         }
+
+        queue.waiting_since.get_or_insert_with(Instant::now);
+        drop(queue);
+        inner.notify.notify_one();
+        Ok(())
     }
 
-    /// Check if an envelope is high priority (never dropped)
-    fn is_high_priority(&self, envelope: &Envelope) -> bool {
-        use harpy_proto::harpy::v1::envelope::Payload;
+    fn queue_tracks(&self, queue: &mut Queue, envelope: &Arc<Envelope>) {
+        let inner = &self.inner;
+        let max = inner.limits.max_pending_tracks;
+        let Some(Payload::TrackDeltaBatch(batch)) = &envelope.payload else {
+            return;
+        };
+
+        let Some(index) = queue
+            .tracks
+            .iter()
+            .position(|(subscription_id, _)| *subscription_id == batch.subscription_id)
+        else {
+            // Nothing pending for this subscription: keep the shared batch
+            let len = batch.deltas.len();
+            if queue.pending_tracks + len > max {
+                inner.count(|s| &s.track_updates_dropped, len);
+                telemetry::record_drop(envelope, "queue_full");
+                return;
+            }
+            queue.pending_tracks += len;
+            queue.tracks.push_back((
+                batch.subscription_id.clone(),
+                PendingTracks::Shared(envelope.clone()),
+            ));
+            return;
+        };
+
+        // Merge into what is already pending, newest update per track wins
+        let mut pending_tracks = queue.pending_tracks;
+        let mut coalesced = 0;
+        let mut dropped = 0;
+        let pending = &mut queue.tracks[index].1;
+        if let PendingTracks::Shared(previous) = pending {
+            let mut deltas = HashMap::new();
+            if let Some(Payload::TrackDeltaBatch(previous_batch)) = &previous.payload {
+                for delta in &previous_batch.deltas {
+                    deltas.insert(delta.id.clone(), delta.clone());
+                }
+                coalesced += previous_batch.deltas.len() - deltas.len();
+                pending_tracks -= previous_batch.deltas.len() - deltas.len();
+            }
+            *pending = PendingTracks::Coalesced {
+                server_ts_ms: previous.server_ts_ms,
+                deltas,
+            };
+        }
+        let PendingTracks::Coalesced {
+            server_ts_ms,
+            deltas,
+        } = pending
+        else {
+            return;
+        };
 
-        match &envelope.payload {
-            Some(Payload::TrackDeltaBatch(_)) => false, // Droppable
-            Some(Payload::AlertUpsert(_)) => true,      // Never drop
-            Some(Payload::ProviderStatus(_)) => true,   // Never drop
-            Some(Payload::SnapshotMeta(_)) => true,     // Never drop (control)
-            Some(Payload::LinkUpsert(_)) => true,       // Never drop (rare)
-            Some(Payload::SubscriptionAck(_)) => true,  // Never drop (control)
-            Some(Payload::SubscriptionRequest(_)) => true, // Never drop (control)
-            None => false,
+        // Keep the oldest receive time so fanout latency covers the wait
+        *server_ts_ms = (*server_ts_ms).min(envelope.server_ts_ms);
+        for delta in &batch.deltas {
+            match deltas.get_mut(&delta.id) {
+                Some(existing) => {
+                    if delta.ts_ms >= existing.ts_ms {
+                        *existing = delta.clone();
+                    }
+                    coalesced += 1;
+                }
+                None if pending_tracks < max => {
+                    deltas.insert(delta.id.clone(), delta.clone());
+                    pending_tracks += 1;
+                }
+                None => dropped += 1,
+            }
         }
+        queue.pending_tracks = pending_tracks;
+        inner.count(|s| &s.track_updates_coalesced, coalesced);
+        inner.count(|s| &s.track_updates_dropped, dropped);
     }
 
     /// Get current backpressure statistics
     pub fn stats(&self) -> BackpressureSnapshot {
-        self.stats.get_stats()
+        self.inner.stats.get_stats()
+    }
+
+    /// What is currently pending for the client
+    pub fn depth(&self) -> QueueDepth {
+        let queue = self.inner.lock();
+        QueueDepth {
+            control: queue.control.len(),
+            tracks: queue.pending_tracks,
+        }
     }
 }
 
-impl Default for BackpressureChannel {
-    fn default() -> Self {
-        let (channel, _) = Self::new();
-        channel
+impl BackpressureReceiver {
+    /// Wait for the next envelope; `None` once the client has been evicted
+    pub async fn recv(&mut self) -> Option<Arc<Envelope>> {
+        loop {
+            let notified = self.inner.notify.notified();
+            if let Some(next) = self.take() {
+                return next;
+            }
+            notified.await;
+        }
+    }
+
+    /// Take the next envelope if one is ready
+    pub fn try_recv(&mut self) -> Option<Arc<Envelope>> {
+        self.take().flatten()
+    }
+
+    /// Why the client was evicted, if it was
+    pub fn eviction(&self) -> Option<Eviction> {
+        self.inner.lock().evicted
+    }
+
+    /// `Some(None)` once evicted, `None` while nothing is pending
+    fn take(&self) -> Option<Option<Arc<Envelope>>> {
+        let inner = &self.inner;
+        let mut queue = inner.lock();
+        if queue.evicted.is_some() {
+            return Some(None);
+        }
+
+        // Control messages go first, then the oldest pending subscription batch
+        let next = if let Some(envelope) = queue.control.pop_front() {
+            inner.count(|s| &s.high_priority_sent, 1);
+            envelope
+        } else {
+            let (subscription_id, pending) = queue.tracks.pop_front()?;
+            let len = pending.len();
+            queue.pending_tracks -= len;
+            inner.count(|s| &s.track_batches_sent, 1);
+            inner.count(|s| &s.track_updates_sent, len);
+            pending.into_envelope(subscription_id)
+        };
+
+        queue.waiting_since = (!queue.is_empty()).then(Instant::now);
+        Some(Some(next))
     }
 }
 
-/// Global backpressure monitor for the relay service
+impl Drop for BackpressureReceiver {
+    fn drop(&mut self) {
+        let mut queue = self.inner.lock();
+        queue.receiver_dropped = true;
+        queue.control.clear();
+        queue.tracks.clear();
+        queue.pending_tracks = 0;
+    }
+}
+
+fn batch_len(envelope: &Envelope) -> usize {
+    match &envelope.payload {
+        Some(Payload::TrackDeltaBatch(batch)) => batch.deltas.len(),
+        _ => 0,
+    }
+}
+
+/// Relay-wide backpressure totals across all connections
+#[derive(Debug, Default)]
 pub struct BackpressureMonitor {
-    total_dropped: AtomicUsize,
-    total_sent: AtomicUsize,
+    totals: BackpressureStats,
+    clients_evicted: AtomicUsize,
 }
 
 impl BackpressureMonitor {
     pub fn new() -> Self {
-        Self {
-            total_dropped: AtomicUsize::new(0),
-            total_sent: AtomicUsize::new(0),
-        }
+        Self::default()
+    }
+
+    pub fn record_eviction(&self) {
+        self.clients_evicted.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn record_dropped(&self) {
-        self.total_dropped.fetch_add(1, Ordering::Relaxed);
+    pub fn totals(&self) -> BackpressureSnapshot {
+        self.totals.get_stats()
     }
 
-    pub fn record_sent(&self) {
-        self.total_sent.fetch_add(1, Ordering::Relaxed);
+    pub fn clients_evicted(&self) -> usize {
+        self.clients_evicted.load(Ordering::Relaxed)
     }
 
+    /// Share of track updates dropped rather than delivered or coalesced
     pub fn drop_rate(&self) -> f64 {
-        let dropped = self.total_dropped.load(Ordering::Relaxed) as f64;
-        let sent = self.total_sent.load(Ordering::Relaxed) as f64;
-        if sent + dropped > 0.0 {
-            dropped / (sent + dropped)
+        let totals = self.totals();
+        let dropped = totals.track_updates_dropped as f64;
+        let handled = (totals.track_updates_sent + totals.track_updates_coalesced) as f64;
+        if handled + dropped > 0.0 {
+            dropped / (handled + dropped)
         } else {
             0.0
         }
     }
-}
 
-impl Default for BackpressureMonitor {
-    fn default() -> Self {
-        Self::new()
+    /// Prometheus text for the relay-wide counters
+    pub fn render(&self) -> String {
+        let totals = self.totals();
+        let counters = [
+            (
+                "track_batches_sent_total",
+                "Track batches handed to client sockets",
+                totals.track_batches_sent,
+            ),
+            (
+                "track_updates_sent_total",
+                "Track updates handed to client sockets",
+                totals.track_updates_sent,
+            ),
+            (
+                "track_updates_coalesced_total",
+                "Track updates superseded by a newer update before delivery",
+                totals.track_updates_coalesced,
+            ),
+            (
+                "track_updates_dropped_total",
+                "Track updates dropped at the per-client pending limit",
+                totals.track_updates_dropped,
+            ),
+            (
+                "high_priority_sent_total",
+                "Alerts, links, statuses and control messages handed to client sockets",
+                totals.high_priority_sent,
+            ),
+            (
+                "clients_evicted_total",
+                "Clients disconnected for falling too far behind",
+                self.clients_evicted(),
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, help, value) in counters {
+            out.push_str(&format!(
+                "# HELP harpy_relay_{name} {help}\n\
+                 # TYPE harpy_relay_{name} counter\n\
+                 harpy_relay_{name} {value}\n"
+            ));
+        }
+        out.push_str(&format!(
+            "# HELP harpy_relay_track_drop_ratio Share of track updates dropped at the pending limit\n\
+             # TYPE harpy_relay_track_drop_ratio gauge\n\
+             harpy_relay_track_drop_ratio {}\n",
+            self.drop_rate()
+        ));
+        out
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use harpy_proto::harpy::v1::{AlertUpsert, ProviderStatus, TrackDeltaBatch};
+    use harpy_proto::harpy::v1::AlertUpsert;
 
-    #[test]
-    fn test_is_high_priority() {
-        let (channel, _rx) = BackpressureChannel::new();
+    fn track(id: &str, ts_ms: u64) -> TrackDelta {
+        TrackDelta {
+            id: id.to_string(),
+            ts_ms,
+            ..Default::default()
+        }
+    }
 
-        // TrackDeltaBatch is low priority (droppable)
-        let track_batch = Envelope {
+    fn batch(subscription_id: &str, deltas: Vec<TrackDelta>) -> Envelope {
+        Envelope {
             schema_version: "1.0.0".to_string(),
             server_ts_ms: 0,
-            payload: Some(harpy_proto::harpy::v1::envelope::Payload::TrackDeltaBatch(
-                TrackDeltaBatch { deltas: vec![] },
-            )),
-        };
-        assert!(!channel.is_high_priority(&track_batch));
+            seq: 0,
+            payload: Some(Payload::TrackDeltaBatch(TrackDeltaBatch {
+                deltas,
+                subscription_id: subscription_id.to_string(),
+            })),
+        }
+    }
 
-        // AlertUpsert is high priority (never drop)
-        let alert = Envelope {
+    fn alert(id: &str) -> Envelope {
+        Envelope {
             schema_version: "1.0.0".to_string(),
             server_ts_ms: 0,
-            payload: Some(harpy_proto::harpy::v1::envelope::Payload::AlertUpsert(
-                AlertUpsert {
-                    id: "test".to_string(),
-                    severity: 1,
-                    title: "Test".to_string(),
-                    description: "".to_string(),
-                    ts_ms: 0,
-                    evidence_link_ids: vec![],
-                    status: 1,
-                    meta: Default::default(),
-                },
-            )),
-        };
-        assert!(channel.is_high_priority(&alert));
+            seq: 0,
+            payload: Some(Payload::AlertUpsert(AlertUpsert {
+                id: id.to_string(),
+                ..Default::default()
+            })),
+        }
+    }
 
-        // ProviderStatus is high priority (never drop)
-        let status = Envelope {
-            schema_version: "1.0.0".to_string(),
-            server_ts_ms: 0,
-            payload: Some(harpy_proto::harpy::v1::envelope::Payload::ProviderStatus(
-                ProviderStatus {
-                    provider_id: "test".to_string(),
-                    circuit_state: 1,
-                    freshness: 1,
-                    last_success_ts_ms: 0,
-                    failure_count: 0,
-                    error_message: None,
-                    meta: Default::default(),
-                },
-            )),
+    fn deltas(envelope: &Envelope) -> Vec<(String, u64)> {
+        let Some(Payload::TrackDeltaBatch(batch)) = &envelope.payload else {
+            panic!("expected a track batch");
         };
-        assert!(channel.is_high_priority(&status));
+        let mut deltas: Vec<(String, u64)> = batch
+            .deltas
+            .iter()
+            .map(|delta| (delta.id.clone(), delta.ts_ms))
+            .collect();
+        deltas.sort();
+        deltas
     }
 
-    #[test]
-    fn test_backpressure_stats() {
-        let stats = BackpressureStats::new();
+    #[tokio::test]
+    async fn test_pending_batches_coalesce_per_track() {
+        let (tx, mut rx) = BackpressureChannel::new();
+        tx.send(batch("default", vec![track("a", 1), track("b", 1)]))
+            .unwrap();
+        tx.send(batch("default", vec![track("a", 2), track("c", 2)]))
+            .unwrap();
+        // Older updates never replace newer ones
+        tx.send(batch("default", vec![track("b", 0)])).unwrap();
+        tx.send(alert("x")).unwrap();
+
+        // Control messages overtake pending tracks
+        assert!(matches!(
+            rx.recv().await.unwrap().payload,
+            Some(Payload::AlertUpsert(_))
+        ));
+        assert_eq!(
+            deltas(&rx.recv().await.unwrap()),
+            vec![
+                ("a".to_string(), 2),
+                ("b".to_string(), 1),
+                ("c".to_string(), 2)
+            ]
+        );
+        assert!(rx.try_recv().is_none());
+
+        let stats = tx.stats();
+        assert_eq!(stats.track_batches_sent, 1);
+        assert_eq!(stats.track_updates_sent, 3);
+        assert_eq!(stats.track_updates_coalesced, 2);
+        assert_eq!(stats.high_priority_sent, 1);
+    }
 
-        stats.record_track_batch_sent();
-        stats.record_track_batch_sent();
-        stats.record_track_batch_dropped();
-        stats.record_high_priority_sent();
+    #[tokio::test]
+    async fn test_subscriptions_stay_separate_and_tracks_are_bounded() {
+        let limits = BackpressureLimits {
+            max_pending_tracks: 3,
+            ..Default::default()
+        };
+        let (tx, mut rx) = BackpressureChannel::with_limits(limits, Arc::default());
+        tx.send(batch("main", vec![track("a", 1), track("b", 1)]))
+            .unwrap();
+        tx.send(batch("inset", vec![track("a", 1)])).unwrap();
+        // Full: a new track is dropped, a pending one still updates
+        tx.send(batch("main", vec![track("c", 2), track("a", 2)]))
+            .unwrap();
+
+        assert_eq!(
+            deltas(&rx.recv().await.unwrap()),
+            vec![("a".to_string(), 2), ("b".to_string(), 1)]
+        );
+        assert_eq!(
+            deltas(&rx.recv().await.unwrap()),
+            vec![("a".to_string(), 1)]
+        );
+        assert_eq!(tx.stats().track_updates_dropped, 1);
+    }
 
-        let snapshot = stats.get_stats();
-        assert_eq!(snapshot.track_batches_sent, 2);
-        assert_eq!(snapshot.track_batches_dropped, 1);
-        assert_eq!(snapshot.high_priority_sent, 1);
+    #[tokio::test]
+    async fn test_overflow_and_stall_evict() {
+        let monitor = Arc::new(BackpressureMonitor::new());
+        let limits = BackpressureLimits {
+            max_pending_control: 2,
+            ..Default::default()
+        };
+        let (tx, mut rx) = BackpressureChannel::with_limits(limits, monitor.clone());
+        tx.send(alert("a")).unwrap();
+        tx.send(alert("b")).unwrap();
+        assert!(tx.send(alert("c")).is_err());
+        assert!(rx.recv().await.is_none());
+        assert_eq!(rx.eviction(), Some(Eviction::ControlOverflow));
+
+        let limits = BackpressureLimits {
+            stall_timeout: Duration::ZERO,
+            ..Default::default()
+        };
+        let (tx, rx) = BackpressureChannel::with_limits(limits, monitor.clone());
+        tx.send(alert("a")).unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(tx.send(alert("b")).is_err());
+        assert_eq!(rx.eviction(), Some(Eviction::Stalled));
+
+        assert_eq!(monitor.clients_evicted(), 2);
+        assert!(monitor
+            .render()
+            .contains("harpy_relay_clients_evicted_total 2"));
     }
 }
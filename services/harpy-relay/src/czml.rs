@@ -0,0 +1,794 @@
+//! CZML Export
+//!
+//! Turns a time range, viewport and layer set into a time-dynamic CZML
+//! document for Cesium. Each track becomes an entity with sampled positions,
+//! and each alert open during the range a time-bounded point or polygon. Reads
+//! the same snapshot and `track_deltas` data as playback.
+
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, SecondsFormat};
+use harpy_proto::harpy::v1::{AlertSeverity, AlertStatus};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{Postgres, QueryBuilder};
+use std::collections::BTreeMap;
+
+use crate::alerts::{self, HistoricalAlert};
+use crate::seek::{find_snapshot, parse_layers, SeekError};
+use crate::state_at_time::{
+    max_age_ms, parse_bbox, sample_from_row, wrap_lon, Sample, StateBbox, GROUND_MAX_AGE_MS,
+};
+use crate::AppState;
+
+/// Maximum exported range (24 hours, as for seek)
+const MAX_RANGE_MS: u64 = 24 * 60 * 60 * 1000;
+/// Default cap on exported track samples (override with RELAY_CZML_MAX_SAMPLES)
+const DEFAULT_MAX_SAMPLES: usize = 250_000;
+/// Maximum number of alerts in one export
+const MAX_ALERTS: i64 = 2_000;
+/// Seconds of trail drawn behind each track
+const PATH_TRAIL_SECONDS: f64 = 600.0;
+/// Degree used for satellite positions, which follow curved orbits between samples
+const SATELLITE_LAGRANGE_DEGREE: usize = 5;
+
+/// CZML export request parameters
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CzmlRequest {
+    /// Start timestamp (epoch milliseconds)
+    pub start_ts_ms: u64,
+    /// End timestamp (epoch milliseconds)
+    pub end_ts_ms: u64,
+    /// Optional viewport filter: `min_lat,min_lon,max_lat,max_lon`.
+    /// `min_lon > max_lon` selects a dateline-crossing box.
+    pub bbox: Option<String>,
+    /// Layer types to include (comma-separated: aircraft,satellite,ground,vessel,alert)
+    pub layers: Option<String>,
+}
+
+/// Time window and provenance of an export
+#[derive(Debug, Clone)]
+struct ExportWindow {
+    start_ts_ms: u64,
+    end_ts_ms: u64,
+    snapshot_id: Option<String>,
+}
+
+/// CZML export handler
+pub async fn czml_handler(
+    State(state): State<AppState>,
+    Query(params): Query<CzmlRequest>,
+) -> impl IntoResponse {
+    if params.start_ts_ms >= params.end_ts_ms {
+        return Json(Err::<Value, _>(SeekError {
+            error: "start_ts_ms must be less than end_ts_ms".to_string(),
+            code: "INVALID_RANGE".to_string(),
+        }))
+        .into_response();
+    }
+    if params.end_ts_ms - params.start_ts_ms > MAX_RANGE_MS {
+        return Json(Err::<Value, _>(SeekError {
+            error: "Time range exceeds maximum (24 hours)".to_string(),
+            code: "RANGE_TOO_LARGE".to_string(),
+        }))
+        .into_response();
+    }
+
+    let bbox = match params.bbox.as_deref().map(parse_bbox).transpose() {
+        Ok(bbox) => bbox,
+        Err(error) => return Json(Err::<Value, _>(error)).into_response(),
+    };
+
+    let Some(pool) = state.db_pool.as_ref() else {
+        return Json(Err::<Value, _>(SeekError {
+            error: "Database unavailable: CZML export requires Postgres".to_string(),
+            code: "DB_UNAVAILABLE".to_string(),
+        }))
+        .into_response();
+    };
+
+    let snapshot = match find_snapshot(pool, params.start_ts_ms).await {
+        Ok(snapshot) => snapshot,
+        Err(error) => return Json(Err::<Value, _>(error)).into_response(),
+    };
+
+    let layers = parse_layers(params.layers.as_deref());
+    let samples = match fetch_export_samples(pool, &params, &layers, bbox.as_ref()).await {
+        Ok(samples) => samples,
+        Err(error) => return Json(Err::<Value, _>(error)).into_response(),
+    };
+
+    let historical_alerts = if layers.is_empty() || layers.iter().any(|l| l == "alert") {
+        match alerts::fetch_alerts_in_range(pool, params.start_ts_ms, params.end_ts_ms, MAX_ALERTS)
+            .await
+        {
+            Ok(alerts) => alerts,
+            Err(e) => {
+                return Json(Err::<Value, _>(SeekError {
+                    error: format!("Failed to query alerts: {}", e),
+                    code: "DB_QUERY_FAILED".to_string(),
+                }))
+                .into_response();
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    let window = ExportWindow {
+        start_ts_ms: params.start_ts_ms,
+        end_ts_ms: params.end_ts_ms,
+        snapshot_id: snapshot.map(|s| s.id),
+    };
+    let document = build_document(&window, samples, &historical_alerts, bbox);
+
+    let disposition = format!(
+        "attachment; filename=\"harpy-{}-{}.czml\"",
+        params.start_ts_ms, params.end_ts_ms
+    );
+    ([(header::CONTENT_DISPOSITION, disposition)], Json(document)).into_response()
+}
+
+/// Fetch each track's last fix before the range (while still alive) and every fix inside it
+///
+/// With a bbox only tracks with a fix inside it during the range (or the
+/// ground lookback before it) are loaded, but those are loaded whole.
+async fn fetch_export_samples(
+    pool: &sqlx::PgPool,
+    params: &CzmlRequest,
+    layers: &[String],
+    bbox: Option<&StateBbox>,
+) -> Result<Vec<Sample>, SeekError> {
+    let max_samples = std::env::var("RELAY_CZML_MAX_SAMPLES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_SAMPLES);
+
+    let rows = last_fix_query(params, layers, bbox)
+        .build()
+        .fetch_all(pool)
+        .await
+        .map_err(|e| SeekError {
+            error: format!("Failed to query track deltas: {}", e),
+            code: "DB_QUERY_FAILED".to_string(),
+        })?;
+    let mut samples: Vec<Sample> = rows.iter().map(sample_from_row).collect();
+    samples.retain(|s| params.start_ts_ms - s.ts_ms <= max_age_ms(&s.kind));
+
+    let rows = in_range_query(params, layers, bbox, max_samples)
+        .build()
+        .fetch_all(pool)
+        .await
+        .map_err(|e| SeekError {
+            error: format!("Failed to query track deltas: {}", e),
+            code: "DB_QUERY_FAILED".to_string(),
+        })?;
+    if rows.len() > max_samples {
+        return Err(SeekError {
+            error: format!(
+                "Export exceeds {} track samples; narrow the range, bbox or layers",
+                max_samples
+            ),
+            code: "EXPORT_TOO_LARGE".to_string(),
+        });
+    }
+
+    samples.extend(rows.iter().map(sample_from_row));
+    Ok(samples)
+}
+
+/// Per track, the latest fix within the ground lookback before the range
+fn last_fix_query<'a>(
+    params: &CzmlRequest,
+    layers: &[String],
+    bbox: Option<&StateBbox>,
+) -> QueryBuilder<'a, Postgres> {
+    let mut qb = QueryBuilder::<Postgres>::new(
+        "SELECT DISTINCT ON (td.track_id) td.track_id, td.lat, td.lon, td.alt, td.heading, td.speed, td.ts_ms, td.provider_id, td.meta, COALESCE(t.kind, 'unknown') AS kind \
+         FROM track_deltas td \
+         LEFT JOIN tracks t ON t.id = td.track_id \
+         WHERE td.ts_ms >= ",
+    );
+    qb.push_bind(params.start_ts_ms.saturating_sub(GROUND_MAX_AGE_MS) as i64)
+        .push(" AND td.ts_ms <= ")
+        .push_bind(params.start_ts_ms as i64);
+    push_export_scope(&mut qb, params, layers, bbox);
+    qb.push(" ORDER BY td.track_id, td.ts_ms DESC");
+    qb
+}
+
+/// Every fix inside the range, capped one past `max_samples`
+fn in_range_query<'a>(
+    params: &CzmlRequest,
+    layers: &[String],
+    bbox: Option<&StateBbox>,
+    max_samples: usize,
+) -> QueryBuilder<'a, Postgres> {
+    let mut qb = QueryBuilder::<Postgres>::new(
+        "SELECT td.track_id, td.lat, td.lon, td.alt, td.heading, td.speed, td.ts_ms, td.provider_id, td.meta, COALESCE(t.kind, 'unknown') AS kind \
+         FROM track_deltas td \
+         LEFT JOIN tracks t ON t.id = td.track_id \
+         WHERE td.ts_ms > ",
+    );
+    qb.push_bind(params.start_ts_ms as i64)
+        .push(" AND td.ts_ms <= ")
+        .push_bind(params.end_ts_ms as i64);
+    push_export_scope(&mut qb, params, layers, bbox);
+    qb.push(" ORDER BY td.track_id, td.ts_ms ASC LIMIT ")
+        .push_bind(max_samples as i64 + 1);
+    qb
+}
+
+/// Push ` AND ...` restricting `td` rows to the layers and to tracks with a
+/// fix inside `bbox` between the ground lookback and the end of the range
+fn push_export_scope(
+    qb: &mut QueryBuilder<'_, Postgres>,
+    params: &CzmlRequest,
+    layers: &[String],
+    bbox: Option<&StateBbox>,
+) {
+    if !layers.is_empty() {
+        qb.push(" AND t.kind = ANY(")
+            .push_bind(layers.to_vec())
+            .push(")");
+    }
+    if let Some(bbox) = bbox {
+        qb.push(" AND td.track_id IN (SELECT DISTINCT td.track_id FROM track_deltas td WHERE td.ts_ms >= ")
+            .push_bind(params.start_ts_ms.saturating_sub(GROUND_MAX_AGE_MS) as i64)
+            .push(" AND td.ts_ms <= ")
+            .push_bind(params.end_ts_ms as i64);
+        bbox.push_predicate(qb);
+        qb.push(")");
+    }
+}
+
+/// Build the CZML packets: the document clock, then tracks, then alerts.
+///
+/// Tracks are kept whole when any of their samples falls inside `bbox`;
+/// alerts when any of their evidence positions does.
+fn build_document(
+    window: &ExportWindow,
+    samples: Vec<Sample>,
+    historical_alerts: &[HistoricalAlert],
+    bbox: Option<StateBbox>,
+) -> Vec<Value> {
+    let mut tracks: BTreeMap<String, Vec<Sample>> = BTreeMap::new();
+    for sample in samples {
+        tracks
+            .entry(sample.track_id.clone())
+            .or_default()
+            .push(sample);
+    }
+    for track in tracks.values_mut() {
+        track.sort_by_key(|s| s.ts_ms);
+        // Cesium requires strictly increasing sample times; keep the last report per instant.
+        track.dedup_by(|later, earlier| {
+            let same = later.ts_ms == earlier.ts_ms;
+            if same {
+                std::mem::swap(later, earlier);
+            }
+            same
+        });
+    }
+    if let Some(bbox) = bbox {
+        tracks.retain(|_, track| track.iter().any(|s| bbox.contains(s.lat, s.lon)));
+    }
+
+    let track_packets: Vec<Value> = tracks
+        .iter()
+        .filter_map(|(id, track)| track_packet(window, id, track))
+        .collect();
+    let alert_packets: Vec<Value> = historical_alerts
+        .iter()
+        .filter_map(|alert| alert_packet(window, alert, &tracks, bbox))
+        .collect();
+
+    let mut document = Vec::with_capacity(1 + track_packets.len() + alert_packets.len());
+    document.push(json!({
+        "id": "document",
+        "name": format!(
+            "HARPY export {} to {}",
+            iso8601(window.start_ts_ms),
+            iso8601(window.end_ts_ms)
+        ),
+        "version": "1.0",
+        "clock": {
+            "interval": interval(window.start_ts_ms, window.end_ts_ms),
+            "currentTime": iso8601(window.start_ts_ms),
+            "multiplier": 60,
+            "range": "LOOP_STOP",
+            "step": "SYSTEM_CLOCK_MULTIPLIER",
+        },
+        "harpy": {
+            "schema_version": "1.0.0",
+            "snapshot_id": window.snapshot_id,
+            "track_count": track_packets.len(),
+            "alert_count": alert_packets.len(),
+        },
+    }));
+    document.extend(track_packets);
+    document.extend(alert_packets);
+    document
+}
+
+/// Entity for one track: sampled positions, availability while alive, label and trail
+fn track_packet(window: &ExportWindow, id: &str, track: &[Sample]) -> Option<Value> {
+    let first = track.first()?;
+    let last = track.last()?;
+    let availability = availability(window, track);
+    if availability.is_empty() {
+        return None;
+    }
+
+    let mut positions = Vec::with_capacity(track.len() * 4);
+    for sample in track {
+        positions.push(json!((sample.ts_ms - first.ts_ms) as f64 / 1000.0));
+        positions.push(json!(sample.lon));
+        positions.push(json!(sample.lat));
+        positions.push(json!(sample.alt));
+    }
+    let (algorithm, degree) = interpolation(&last.kind, track.len());
+    let label = track_label(id, track);
+    let availability = match &availability[..] {
+        [single] => json!(single),
+        intervals => json!(intervals),
+    };
+
+    Some(json!({
+        "id": format!("track/{}", id),
+        "name": label,
+        "description": format!("{} track from {}", last.kind, last.provider_id),
+        "availability": availability,
+        "position": {
+            "epoch": iso8601(first.ts_ms),
+            "cartographicDegrees": positions,
+            "interpolationAlgorithm": algorithm,
+            "interpolationDegree": degree,
+            "forwardExtrapolationType": "HOLD",
+            "backwardExtrapolationType": "HOLD",
+        },
+        "point": {
+            "pixelSize": 8,
+            "color": { "rgba": kind_color(&last.kind) },
+            "outlineColor": { "rgba": [0, 0, 0, 255] },
+            "outlineWidth": 1,
+        },
+        "label": {
+            "text": label,
+            "font": "12px sans-serif",
+            "horizontalOrigin": "LEFT",
+            "pixelOffset": { "cartesian2": [10, 0] },
+            "fillColor": { "rgba": [255, 255, 255, 255] },
+            "showBackground": true,
+        },
+        "path": {
+            "leadTime": 0,
+            "trailTime": PATH_TRAIL_SECONDS,
+            "width": 1,
+            "material": { "solidColor": { "color": { "rgba": kind_color(&last.kind) } } },
+        },
+        "properties": {
+            "kind": last.kind,
+            "provider_id": last.provider_id,
+        },
+    }))
+}
+
+/// Intervals during which a track is alive, clipped to the export window.
+///
+/// A track stays alive for its kind's staleness cutoff after each fix, the
+/// same rule the state API uses; longer gaps split the availability.
+fn availability(window: &ExportWindow, track: &[Sample]) -> Vec<String> {
+    let mut spans: Vec<(u64, u64)> = Vec::new();
+    for sample in track {
+        let alive_until = sample.ts_ms.saturating_add(max_age_ms(&sample.kind));
+        match spans.last_mut() {
+            Some((_, end)) if sample.ts_ms <= *end => *end = (*end).max(alive_until),
+            _ => spans.push((sample.ts_ms, alive_until)),
+        }
+    }
+
+    spans
+        .into_iter()
+        .map(|(start, end)| (start.max(window.start_ts_ms), end.min(window.end_ts_ms)))
+        .filter(|(start, end)| start <= end)
+        .map(|(start, end)| interval(start, end))
+        .collect()
+}
+
+/// Interpolation algorithm and degree for a track kind with `samples` positions
+fn interpolation(kind: &str, samples: usize) -> (&'static str, usize) {
+    if kind == "satellite" && samples > 2 {
+        ("LAGRANGE", SATELLITE_LAGRANGE_DEGREE.min(samples - 1))
+    } else {
+        ("LINEAR", 1)
+    }
+}
+
+/// Label from the latest `callsign` or `name` in the track's meta, else its id
+fn track_label(id: &str, track: &[Sample]) -> String {
+    track
+        .iter()
+        .rev()
+        .find_map(|sample| {
+            ["callsign", "name"]
+                .iter()
+                .filter_map(|key| sample.meta.get(*key))
+                .map(|value| value.trim())
+                .find(|value| !value.is_empty())
+        })
+        .unwrap_or(id)
+        .to_string()
+}
+
+/// Time-bounded entity for an alert: a point for one or two evidence positions,
+/// otherwise the polygon around them
+fn alert_packet(
+    window: &ExportWindow,
+    historical: &HistoricalAlert,
+    tracks: &BTreeMap<String, Vec<Sample>>,
+    bbox: Option<StateBbox>,
+) -> Option<Value> {
+    let alert = &historical.alert;
+    let start = alert.ts_ms.max(window.start_ts_ms);
+    let end = if alert.status == AlertStatus::Resolved as i32 {
+        historical.updated_ts_ms.min(window.end_ts_ms)
+    } else {
+        window.end_ts_ms
+    };
+    if start > end {
+        return None;
+    }
+
+    let points = evidence_points(historical, tracks);
+    if points.is_empty() {
+        return None;
+    }
+    if let Some(bbox) = bbox {
+        if !points.iter().any(|&(lat, lon)| bbox.contains(lat, lon)) {
+            return None;
+        }
+    }
+
+    let color = severity_color(alert.severity);
+    let hull = convex_hull(&points);
+    let (center_lat, center_lon) = centroid(&hull);
+
+    let mut packet = json!({
+        "id": format!("alert/{}", alert.id),
+        "name": alert.title,
+        "description": alert.description,
+        "availability": interval(start, end),
+        "position": { "cartographicDegrees": [center_lon, center_lat, 0.0] },
+        "label": {
+            "text": alert.title,
+            "font": "12px sans-serif",
+            "verticalOrigin": "BOTTOM",
+            "pixelOffset": { "cartesian2": [0, -12] },
+            "fillColor": { "rgba": color },
+            "showBackground": true,
+        },
+        "properties": {
+            "severity": AlertSeverity::try_from(alert.severity)
+                .unwrap_or(AlertSeverity::Unspecified)
+                .as_str_name(),
+            "status": AlertStatus::try_from(alert.status)
+                .unwrap_or(AlertStatus::Unspecified)
+                .as_str_name(),
+            "evidence_track_ids": historical.scope.track_ids,
+        },
+    });
+
+    if hull.len() >= 3 {
+        let outline: Vec<f64> = hull
+            .iter()
+            .flat_map(|&(lat, lon)| [lon, lat, 0.0])
+            .collect();
+        let [r, g, b, _] = color;
+        packet["polygon"] = json!({
+            "positions": { "cartographicDegrees": outline },
+            "material": { "solidColor": { "color": { "rgba": [r, g, b, 96] } } },
+            "outline": true,
+            "outlineColor": { "rgba": color },
+            "height": 0,
+        });
+    } else {
+        packet["point"] = json!({
+            "pixelSize": 14,
+            "color": { "rgba": color },
+            "outlineColor": { "rgba": [255, 255, 255, 255] },
+            "outlineWidth": 2,
+        });
+    }
+    Some(packet)
+}
+
+/// Evidence positions at the time the alert was raised.
+///
+/// Uses each evidence track's exported fix at or before the alert (or its
+/// first fix after it); falls back to the tracks' current positions when none
+/// of them were exported.
+fn evidence_points(
+    historical: &HistoricalAlert,
+    tracks: &BTreeMap<String, Vec<Sample>>,
+) -> Vec<(f64, f64)> {
+    let points: Vec<(f64, f64)> = historical
+        .scope
+        .track_ids
+        .iter()
+        .filter_map(|id| tracks.get(id))
+        .filter_map(|track| {
+            track
+                .iter()
+                .rev()
+                .find(|s| s.ts_ms <= historical.alert.ts_ms)
+                .or_else(|| track.first())
+                .map(|s| (s.lat, s.lon))
+        })
+        .collect();
+    if points.is_empty() {
+        historical.scope.points.clone()
+    } else {
+        points
+    }
+}
+
+/// Convex hull of `(lat, lon)` points, counter-clockwise.
+///
+/// Longitudes are unwrapped around the first point so hulls spanning the
+/// antimeridian stay small; Cesium accepts the out-of-range longitudes.
+fn convex_hull(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let Some(&(_, origin_lon)) = points.first() else {
+        return Vec::new();
+    };
+    let mut unwrapped: Vec<(f64, f64)> = points
+        .iter()
+        .map(|&(lat, lon)| (lat, origin_lon + wrap_lon(lon - origin_lon)))
+        .collect();
+    unwrapped.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.total_cmp(&b.0)));
+    unwrapped.dedup();
+    if unwrapped.len() < 3 {
+        return unwrapped;
+    }
+
+    // Andrew's monotone chain over (x = lon, y = lat).
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        (a.1 - o.1) * (b.0 - o.0) - (a.0 - o.0) * (b.1 - o.1)
+    };
+    let mut hull: Vec<(f64, f64)> = Vec::with_capacity(unwrapped.len() * 2);
+    for pass in [unwrapped.clone(), unwrapped.into_iter().rev().collect()] {
+        let floor = hull.len();
+        for point in pass {
+            while hull.len() >= floor + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        hull.pop();
+    }
+    hull
+}
+
+/// Mean of `(lat, lon)` points, with the longitude wrapped back into range
+fn centroid(points: &[(f64, f64)]) -> (f64, f64) {
+    let n = points.len().max(1) as f64;
+    let (lat, lon) = points
+        .iter()
+        .fold((0.0, 0.0), |(lat, lon), p| (lat + p.0, lon + p.1));
+    (lat / n, wrap_lon(lon / n))
+}
+
+fn kind_color(kind: &str) -> [u8; 4] {
+    match kind {
+        "aircraft" => [0, 200, 255, 255],
+        "satellite" => [200, 120, 255, 255],
+        "vessel" => [0, 220, 120, 255],
+        "ground" => [255, 200, 0, 255],
+        _ => [200, 200, 200, 255],
+    }
+}
+
+fn severity_color(severity: i32) -> [u8; 4] {
+    match AlertSeverity::try_from(severity).unwrap_or(AlertSeverity::Unspecified) {
+        AlertSeverity::Critical => [255, 40, 40, 255],
+        AlertSeverity::Warning => [255, 140, 0, 255],
+        AlertSeverity::Medium => [255, 210, 0, 255],
+        AlertSeverity::Info | AlertSeverity::Unspecified => [80, 160, 255, 255],
+    }
+}
+
+fn interval(start_ts_ms: u64, end_ts_ms: u64) -> String {
+    format!("{}/{}", iso8601(start_ts_ms), iso8601(end_ts_ms))
+}
+
+fn iso8601(ts_ms: u64) -> String {
+    DateTime::from_timestamp_millis(ts_ms as i64)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::AlertScope;
+    use harpy_proto::harpy::v1::AlertUpsert;
+    use std::collections::HashMap;
+
+    fn window() -> ExportWindow {
+        ExportWindow {
+            start_ts_ms: 1_700_000_000_000,
+            end_ts_ms: 1_700_003_600_000,
+            snapshot_id: Some("snap-1".to_string()),
+        }
+    }
+
+    fn sample(track_id: &str, kind: &str, lat: f64, lon: f64, offset_ms: u64) -> Sample {
+        Sample {
+            track_id: track_id.to_string(),
+            kind: kind.to_string(),
+            lat,
+            lon,
+            alt: 1000.0,
+            heading: 0.0,
+            speed: 0.0,
+            ts_ms: window().start_ts_ms + offset_ms,
+            provider_id: "test".to_string(),
+            meta: HashMap::new(),
+        }
+    }
+
+    fn historical(id: &str, track_ids: &[&str], status: AlertStatus) -> HistoricalAlert {
+        HistoricalAlert {
+            alert: AlertUpsert {
+                id: id.to_string(),
+                severity: AlertSeverity::Critical as i32,
+                title: "Rendezvous".to_string(),
+                ts_ms: window().start_ts_ms + 10_000,
+                status: status as i32,
+                ..Default::default()
+            },
+            scope: AlertScope {
+                track_ids: track_ids.iter().map(|id| id.to_string()).collect(),
+                ..Default::default()
+            },
+            updated_ts_ms: window().start_ts_ms + 20_000,
+        }
+    }
+
+    #[test]
+    fn test_track_entity_samples_and_label() {
+        let mut first = sample("a-1", "aircraft", 10.0, 20.0, 0);
+        first
+            .meta
+            .insert("callsign".to_string(), "UAL1".to_string());
+        let second = sample("a-1", "aircraft", 11.0, 21.0, 5_000);
+        // A duplicate report at the same instant keeps the later one.
+        let duplicate = sample("a-1", "aircraft", 11.5, 21.5, 5_000);
+
+        let document = build_document(&window(), vec![first, second, duplicate], &[], None);
+        assert_eq!(document[0]["id"], "document");
+        assert_eq!(document[0]["harpy"]["snapshot_id"], "snap-1");
+
+        let track = &document[1];
+        assert_eq!(track["id"], "track/a-1");
+        assert_eq!(track["label"]["text"], "UAL1");
+        assert_eq!(track["position"]["epoch"], "2023-11-14T22:13:20.000Z");
+        assert_eq!(track["position"]["interpolationAlgorithm"], "LINEAR");
+        assert_eq!(
+            track["position"]["cartographicDegrees"],
+            json!([0.0, 20.0, 10.0, 1000.0, 5.0, 21.5, 11.5, 1000.0])
+        );
+        assert_eq!(
+            track["availability"],
+            "2023-11-14T22:13:20.000Z/2023-11-14T22:14:25.000Z"
+        );
+    }
+
+    #[test]
+    fn test_availability_splits_on_stale_gap() {
+        let track = vec![
+            sample("a-1", "aircraft", 0.0, 0.0, 0),
+            sample("a-1", "aircraft", 0.0, 0.0, 30_000),
+            sample("a-1", "aircraft", 0.0, 0.0, 600_000),
+        ];
+        let intervals = availability(&window(), &track);
+        assert_eq!(intervals.len(), 2);
+        assert!(intervals[0].ends_with("22:14:50.000Z"));
+        assert!(intervals[1].starts_with("2023-11-14T22:23:20.000Z"));
+    }
+
+    #[test]
+    fn test_bbox_keeps_tracks_that_enter_it() {
+        let samples = vec![
+            sample("in", "vessel", 0.0, 175.0, 0),
+            sample("in", "vessel", 0.0, -179.0, 60_000),
+            sample("out", "vessel", 40.0, 0.0, 0),
+        ];
+        let bbox = parse_bbox("-10,178,10,-170").unwrap();
+
+        let document = build_document(&window(), samples, &[], Some(bbox));
+        assert_eq!(document.len(), 2);
+        assert_eq!(document[1]["id"], "track/in");
+    }
+
+    #[test]
+    fn test_export_queries_scope_tracks_to_bbox() {
+        let params = CzmlRequest {
+            start_ts_ms: window().start_ts_ms,
+            end_ts_ms: window().end_ts_ms,
+            bbox: Some("-10,178,10,-170".to_string()),
+            layers: None,
+        };
+        let bbox = parse_bbox("-10,178,10,-170").unwrap();
+
+        for sql in [
+            last_fix_query(&params, &[], Some(&bbox)).sql().to_string(),
+            in_range_query(&params, &[], Some(&bbox), 10)
+                .sql()
+                .to_string(),
+        ] {
+            assert!(sql.contains("td.track_id IN (SELECT DISTINCT td.track_id"));
+            assert!(sql.contains(" OR td.lon <= "));
+            assert!(sql.contains("td.h3_index BETWEEN"));
+        }
+        let sql = in_range_query(&params, &[], None, 10).sql().to_string();
+        assert!(!sql.contains("td.track_id IN"));
+    }
+
+    #[test]
+    fn test_alert_geometry_from_evidence() {
+        let samples = vec![
+            sample("t-1", "vessel", 0.0, 0.0, 0),
+            sample("t-2", "vessel", 0.0, 1.0, 0),
+            sample("t-3", "vessel", 1.0, 0.5, 0),
+            // Reported after the alert was raised; the earlier fix is used.
+            sample("t-3", "vessel", 5.0, 5.0, 60_000),
+        ];
+        let alerts = vec![
+            historical("polygon", &["t-1", "t-2", "t-3"], AlertStatus::Active),
+            historical("point", &["t-1"], AlertStatus::Resolved),
+            historical("unlocated", &[], AlertStatus::Active),
+        ];
+
+        let document = build_document(&window(), samples, &alerts, None);
+        assert_eq!(document[0]["harpy"]["alert_count"], 2);
+
+        let polygon = &document[4];
+        assert_eq!(polygon["id"], "alert/polygon");
+        assert_eq!(
+            polygon["polygon"]["positions"]["cartographicDegrees"]
+                .as_array()
+                .unwrap()
+                .len(),
+            9
+        );
+        assert!(polygon["availability"]
+            .as_str()
+            .unwrap()
+            .ends_with("23:13:20.000Z"));
+
+        let point = &document[5];
+        assert_eq!(point["id"], "alert/point");
+        assert!(point.get("polygon").is_none());
+        assert_eq!(point["point"]["color"]["rgba"], json!([255, 40, 40, 255]));
+        assert_eq!(
+            point["availability"],
+            "2023-11-14T22:13:30.000Z/2023-11-14T22:13:40.000Z"
+        );
+    }
+
+    #[test]
+    fn test_convex_hull_across_dateline() {
+        let hull = convex_hull(&[(0.0, 179.0), (0.0, -179.0), (2.0, 180.0), (1.0, 179.9)]);
+        assert_eq!(hull.len(), 3);
+        assert!(hull.iter().all(|&(_, lon)| (179.0..=181.0).contains(&lon)));
+        let (_, lon) = centroid(&hull);
+        assert!(lon.abs() > 179.0);
+    }
+}
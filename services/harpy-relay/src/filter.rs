@@ -0,0 +1,129 @@
+//! Track Filter SQL
+//!
+//! Translates a `TrackFilter` into predicates for playback queries. Validation
+//! and in-memory evaluation live in `harpy_core::track_filter`, shared with
+//! the node.
+
+use harpy_proto::harpy::v1::{MetaOp, TrackFilter};
+use sqlx::{Postgres, QueryBuilder};
+
+/// Escape `LIKE` wildcards in a literal
+fn escape_like(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Push ` AND ...` clauses for every predicate of the filter against `td`
+///
+/// A NULL speed counts as 0, as it does for live tracks.
+pub(crate) fn push_filter_predicates(qb: &mut QueryBuilder<'_, Postgres>, filter: &TrackFilter) {
+    if !filter.provider_allow.is_empty() {
+        qb.push(" AND td.provider_id = ANY(")
+            .push_bind(filter.provider_allow.clone())
+            .push(")");
+    }
+    if !filter.provider_deny.is_empty() {
+        qb.push(" AND td.provider_id <> ALL(")
+            .push_bind(filter.provider_deny.clone())
+            .push(")");
+    }
+    if let Some(min_alt) = filter.min_alt {
+        qb.push(" AND td.alt >= ").push_bind(min_alt);
+    }
+    if let Some(max_alt) = filter.max_alt {
+        qb.push(" AND td.alt <= ").push_bind(max_alt);
+    }
+    if let Some(min_speed) = filter.min_speed {
+        qb.push(" AND COALESCE(td.speed, 0) >= ")
+            .push_bind(min_speed);
+    }
+    if let Some(max_speed) = filter.max_speed {
+        qb.push(" AND COALESCE(td.speed, 0) <= ")
+            .push_bind(max_speed);
+    }
+
+    for predicate in &filter.meta {
+        let key = predicate.key.trim().to_string();
+        let values: Vec<String> = predicate
+            .values
+            .iter()
+            .map(|v| v.trim().to_lowercase())
+            .collect();
+
+        match MetaOp::try_from(predicate.op).unwrap_or(MetaOp::Unspecified) {
+            MetaOp::Exists => {
+                qb.push(" AND td.meta->>")
+                    .push_bind(key)
+                    .push(" IS NOT NULL");
+            }
+            MetaOp::Equals => {
+                qb.push(" AND lower(btrim(td.meta->>")
+                    .push_bind(key)
+                    .push(")) = ")
+                    .push_bind(values.into_iter().next().unwrap_or_default());
+            }
+            MetaOp::In => {
+                qb.push(" AND lower(btrim(td.meta->>")
+                    .push_bind(key)
+                    .push(")) = ANY(")
+                    .push_bind(values)
+                    .push(")");
+            }
+            MetaOp::NotIn => {
+                qb.push(" AND (td.meta->>")
+                    .push_bind(key.clone())
+                    .push(" IS NULL OR lower(btrim(td.meta->>")
+                    .push_bind(key)
+                    .push(")) <> ALL(")
+                    .push_bind(values)
+                    .push("))");
+            }
+            MetaOp::Prefix => {
+                let patterns: Vec<String> = values
+                    .iter()
+                    .map(|v| format!("{}%", escape_like(v)))
+                    .collect();
+                qb.push(" AND lower(btrim(td.meta->>")
+                    .push_bind(key)
+                    .push(")) LIKE ANY(")
+                    .push_bind(patterns)
+                    .push(")");
+            }
+            MetaOp::Unspecified => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("a_b%c"), "a\\_b\\%c");
+    }
+
+    #[test]
+    fn test_speed_band_treats_null_speed_as_zero() {
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT 1 FROM track_deltas td WHERE TRUE");
+        push_filter_predicates(
+            &mut qb,
+            &TrackFilter {
+                min_speed: Some(0.0),
+                max_speed: Some(50.0),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            qb.sql(),
+            "SELECT 1 FROM track_deltas td WHERE TRUE \
+             AND COALESCE(td.speed, 0) >= $1 AND COALESCE(td.speed, 0) <= $2"
+        );
+    }
+}
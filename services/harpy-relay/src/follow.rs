@@ -0,0 +1,120 @@
+//! Follow-Track Filters
+//!
+//! Pins specific track ids or callsign patterns to a subscription so they are
+//! delivered regardless of viewport or layer, and loads their recent trail
+//! when the subscription is created.
+
+pub use harpy_core::follow::{follows, follows_callsign, is_active};
+use harpy_proto::harpy::v1::{FollowFilter, TrackDelta};
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::playback::delta_from_row;
+
+/// Upper bound on the trail window a client may request (6 hours)
+pub const MAX_TRAIL_MS: u64 = 6 * 60 * 60 * 1000;
+/// Maximum number of trail samples sent on subscribe
+const MAX_TRAIL_ROWS: i64 = 5000;
+
+/// Translate a callsign glob into an `ILIKE` pattern
+fn glob_to_like(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    for c in pattern.trim().chars() {
+        match c {
+            '*' => out.push('%'),
+            '?' => out.push('_'),
+            '%' | '_' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Push a SQL predicate selecting rows of `td` pinned by the filter
+///
+/// An inactive filter pushes `FALSE`, selecting nothing. Stored callsigns are
+/// often space-padded, so they are trimmed before matching like `follows`.
+pub(crate) fn push_follow_predicate(qb: &mut QueryBuilder<'_, Postgres>, filter: &FollowFilter) {
+    if !is_active(filter) {
+        qb.push("FALSE");
+        return;
+    }
+
+    let like_patterns: Vec<String> = filter
+        .callsign_patterns
+        .iter()
+        .map(|p| glob_to_like(p))
+        .collect();
+
+    qb.push("(td.track_id = ANY(")
+        .push_bind(filter.track_ids.clone())
+        .push(") OR btrim(td.meta->>'callsign') ILIKE ANY(")
+        .push_bind(like_patterns)
+        .push("))");
+}
+
+/// Load the recent trail of followed tracks, oldest first
+pub async fn fetch_trail(
+    pool: &sqlx::PgPool,
+    filter: &FollowFilter,
+    now_ms: u64,
+) -> anyhow::Result<Vec<TrackDelta>> {
+    if !is_active(filter) || filter.trail_ms == 0 {
+        return Ok(Vec::new());
+    }
+    let since_ts_ms = now_ms.saturating_sub(filter.trail_ms.min(MAX_TRAIL_MS));
+
+    let mut qb = QueryBuilder::<Postgres>::new(
+        "SELECT * FROM (\
+         SELECT td.track_id, td.lat, td.lon, td.alt, td.heading, td.speed, td.ts_ms, td.provider_id, td.meta, COALESCE(t.kind, 'unknown') AS kind \
+         FROM track_deltas td \
+         LEFT JOIN tracks t ON t.id = td.track_id \
+         WHERE td.ts_ms >= ",
+    );
+    qb.push_bind(since_ts_ms as i64).push(" AND ");
+    push_follow_predicate(&mut qb, filter);
+    qb.push(" ORDER BY td.ts_ms DESC LIMIT ")
+        .push_bind(MAX_TRAIL_ROWS)
+        .push(") trail ORDER BY ts_ms ASC");
+
+    let rows = qb.build().fetch_all(pool).await?;
+    Ok(rows.iter().map(delta_from_row).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_to_like_escapes_wildcards() {
+        assert_eq!(glob_to_like("UAL*"), "UAL%");
+        assert_eq!(glob_to_like("A?_%"), "A_\\_\\%");
+    }
+
+    #[test]
+    fn test_follow_predicate_trims_padded_callsigns() {
+        let filter = FollowFilter {
+            callsign_patterns: vec![" MCK00? ".to_string()],
+            ..Default::default()
+        };
+        // A space-padded callsign matches live, so the trail query must too
+        assert!(follows(
+            &filter,
+            &TrackDelta {
+                meta: [("callsign".to_string(), "MCK007  ".to_string())].into(),
+                ..Default::default()
+            }
+        ));
+
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT 1 FROM track_deltas td WHERE ");
+        push_follow_predicate(&mut qb, &filter);
+        assert_eq!(
+            qb.sql(),
+            "SELECT 1 FROM track_deltas td WHERE (td.track_id = ANY($1) \
+             OR btrim(td.meta->>'callsign') ILIKE ANY($2))"
+        );
+        assert_eq!(glob_to_like(&filter.callsign_patterns[0]), "MCK00_");
+    }
+}
@@ -4,22 +4,23 @@
 //! and fans out track updates from Redis pub/sub.
 
 use axum::{
-    extract::ws::{Message, WebSocket, WebSocketUpgrade},
-    extract::State,
+    extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+    extract::{Query, State},
     response::{IntoResponse, Response},
     routing::get,
     Json, Router,
 };
 use dashmap::DashMap;
-use harpy_core::types::HealthResponse;
+use harpy_core::{track_filter, types::HealthResponse};
 use harpy_proto::harpy::v1::{
     BoundingBox, Envelope, LayerType, SubscriptionMode, SubscriptionRequest,
 };
+use metrics_exporter_prometheus::PrometheusHandle;
 use prost::Message as ProstMessage;
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
@@ -27,15 +28,31 @@ use tokio::task::JoinHandle;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use uuid::Uuid;
 
+mod alerts;
 mod backpressure;
+mod czml;
+mod filter;
+mod follow;
 mod playback;
+mod playback_session;
+mod provider_status;
 mod redis_subscriber;
 mod seek;
+mod session;
+mod snapshot_blob;
+mod spatial_index;
+mod state_at_time;
 mod subscription;
+mod telemetry;
+mod wire;
 
-use subscription::{Subscription, SubscriptionManager};
+use subscription::{
+    ClientId, Subscription, SubscriptionId, SubscriptionManager, DEFAULT_SUBSCRIPTION_ID,
+};
+
+/// Maximum number of named subscriptions a single connection may hold
+const MAX_SUBSCRIPTIONS_PER_CONNECTION: usize = 8;
 
 /// Application state shared across handlers
 #[derive(Clone)]
@@ -44,7 +61,14 @@ pub(crate) struct AppState {
     pub(crate) connection_counter: Arc<AtomicU64>,
     pub(crate) db_pool: Option<PgPool>,
     pub(crate) redis_client: Option<redis::Client>,
-    pub(crate) playback_tasks: Arc<DashMap<String, JoinHandle<()>>>,
+    pub(crate) playback_tasks: Arc<DashMap<(ClientId, SubscriptionId), JoinHandle<()>>>,
+    pub(crate) playback_sessions: Arc<playback_session::PlaybackSessions>,
+    pub(crate) stream_metrics: Arc<redis_subscriber::StreamMetrics>,
+    pub(crate) provider_statuses: Arc<provider_status::ProviderStatusMap>,
+    pub(crate) sessions: Arc<session::SessionStore>,
+    pub(crate) snapshot_blobs: snapshot_blob::SnapshotBlobs,
+    pub(crate) backpressure: Arc<backpressure::BackpressureMonitor>,
+    pub(crate) metrics: PrometheusHandle,
 }
 
 #[derive(Debug, Serialize)]
@@ -65,9 +89,11 @@ struct RelayDebugSnapshot {
 
 #[derive(Debug, Serialize)]
 struct BackpressureTotals {
-    track_batches_dropped: usize,
     track_batches_sent: usize,
+    track_updates_coalesced: usize,
+    track_updates_dropped: usize,
     high_priority_sent: usize,
+    clients_evicted: usize,
 }
 
 #[derive(Debug, Serialize)]
@@ -114,6 +140,8 @@ async fn main() -> anyhow::Result<()> {
         .with(tracing_subscriber::fmt::layer().json())
         .init();
 
+    let metrics = telemetry::install_recorder()?;
+
     // Get configuration from environment
     let port = std::env::var("WS_PORT")
         .unwrap_or_else(|_| "8080".to_string())
@@ -146,18 +174,51 @@ async fn main() -> anyhow::Result<()> {
     };
 
     // Create app state
+    let backpressure = Arc::new(backpressure::BackpressureMonitor::new());
+    let playback_sessions = Arc::new(playback_session::PlaybackSessions::from_env(
+        db_pool.clone(),
+    ));
     let state = AppState {
         subscription_manager: subscription_manager.clone(),
         connection_counter: Arc::new(AtomicU64::new(0)),
         db_pool,
         redis_client,
         playback_tasks: Arc::new(DashMap::new()),
+        playback_sessions: playback_sessions.clone(),
+        stream_metrics: Arc::new(redis_subscriber::StreamMetrics::default()),
+        provider_statuses: Arc::new(provider_status::ProviderStatusMap::default()),
+        sessions: Arc::new(session::SessionStore::from_env(backpressure.clone())),
+        snapshot_blobs: snapshot_blob::SnapshotBlobs::from_env(),
+        backpressure,
+        metrics,
     };
 
+    // Delete playback sessions nobody has touched within their TTL
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            tick.tick().await;
+            match playback_sessions.expire().await {
+                Ok(0) => {}
+                Ok(expired) => tracing::info!("Expired {} playback sessions", expired),
+                Err(e) => tracing::warn!("Failed to expire playback sessions: {}", e),
+            }
+        }
+    });
+
     // Start Redis subscriber in background
     let sub_manager_clone = subscription_manager.clone();
+    let stream_metrics = state.stream_metrics.clone();
+    let provider_statuses = state.provider_statuses.clone();
     tokio::spawn(async move {
-        if let Err(e) = redis_subscriber::run_subscriber(redis_url, sub_manager_clone).await {
+        if let Err(e) = redis_subscriber::run_subscriber(
+            redis_url,
+            sub_manager_clone,
+            stream_metrics,
+            provider_statuses,
+        )
+        .await
+        {
             tracing::error!("Redis subscriber error: {}", e);
         }
     });
@@ -168,6 +229,8 @@ async fn main() -> anyhow::Result<()> {
         .route("/health", get(health_check))
         .route("/metrics", get(metrics_handler))
         .route("/seek", get(seek::seek_handler))
+        .route("/state", get(state_at_time::state_handler))
+        .route("/export/czml", get(czml::czml_handler))
         .route("/api/debug/snapshot", get(debug_snapshot_handler))
         .layer(
             CorsLayer::new()
@@ -189,70 +252,120 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn ws_handler(State(state): State<AppState>, ws: WebSocketUpgrade) -> Response {
-    let client_id = format!("client-{}", Uuid::new_v4().simple());
+/// Query parameters of the WebSocket upgrade
+#[derive(Debug, Default, Deserialize)]
+struct ConnectParams {
+    /// Token from a previous connection's SubscriptionAck
+    resume_token: Option<String>,
+    /// Highest `seq` the client received on that connection
+    last_seq: Option<u64>,
+    /// Frame compression, `deflate` (see `wire`)
+    compression: Option<String>,
+    /// Track batch encoding, `compact` (see `wire`)
+    encoding: Option<String>,
+}
+
+async fn ws_handler(
+    State(state): State<AppState>,
+    Query(params): Query<ConnectParams>,
+    ws: WebSocketUpgrade,
+) -> Response {
     let client_num = state.connection_counter.fetch_add(1, Ordering::SeqCst);
 
-    ws.on_upgrade(move |socket| handle_socket(socket, state, client_id, client_num))
+    ws.on_upgrade(move |socket| handle_socket(socket, state, params, client_num))
 }
 
-async fn handle_socket(mut socket: WebSocket, state: AppState, client_id: String, client_num: u64) {
+async fn handle_socket(
+    mut socket: WebSocket,
+    state: AppState,
+    params: ConnectParams,
+    client_num: u64,
+) {
+    let resumed = params
+        .resume_token
+        .as_deref()
+        .and_then(|token| state.sessions.resume(token));
+    let is_resumed = resumed.is_some();
+    let mut session = match resumed {
+        Some(session) => session,
+        None => new_session(&state).await,
+    };
+    let client_id = session.client_id.clone();
+    let stall_timeout = state.sessions.limits().stall_timeout;
+    let mut wire = wire::FrameEncoder::from_env(wire::WireOptions::negotiate(
+        params.compression.as_deref(),
+        params.encoding.as_deref(),
+    ));
     tracing::info!(
-        "WebSocket connection established: {} (client #{})",
+        "WebSocket connection established: {} (client #{}, resumed: {}, wire: {:?})",
         client_id,
-        client_num
+        client_num,
+        is_resumed,
+        wire.options()
     );
 
-    // Create backpressure-aware channel for this client.
-    let (tx, mut rx) = backpressure::BackpressureChannel::new();
-
-    // Default subscription (world viewport, all layers)
-    let default_viewport = BoundingBox {
-        min_lat: -90.0,
-        max_lat: 90.0,
-        min_lon: -180.0,
-        max_lon: 180.0,
-    };
-    let default_layers = vec![
-        LayerType::Aircraft,
-        LayerType::Satellite,
-        LayerType::Ground,
-        LayerType::Vessel,
-    ];
-
-    // Create initial subscription
-    let initial_subscription = Subscription {
-        viewport: default_viewport,
-        layers: default_layers,
-        sender: tx.clone(),
-    };
-
-    // Register subscription
-    state
-        .subscription_manager
-        .subscribe(client_id.clone(), initial_subscription)
-        .await;
-
-    // Send subscription acknowledgment
-    let ack = Envelope {
-        schema_version: "1.0.0".to_string(),
-        server_ts_ms: now_ms(),
-        payload: Some(harpy_proto::harpy::v1::envelope::Payload::SubscriptionAck(
-            harpy_proto::harpy::v1::SubscriptionAck {
-                subscription_id: client_id.clone(),
-                success: true,
-                error: None,
-            },
-        )),
-    };
-
-    if let Ok(ack_bytes) = encode_envelope(&ack) {
-        if socket.send(Message::Binary(ack_bytes)).await.is_err() {
+    // A resumed client first gets what it missed, then the ack for the session
+    let mut replay_complete = false;
+    if is_resumed {
+        let (frames, complete) = session.replay_after(params.last_seq.unwrap_or(0));
+        replay_complete = complete;
+        for frame in frames {
+            match send_message(
+                &mut socket,
+                Message::Binary(wire.frame(frame)),
+                stall_timeout,
+            )
+            .await
+            {
+                Ok(()) => {}
+                Err(SendFailure::Stalled) => {
+                    tracing::warn!("Evicting {}: socket write stalled during replay", client_id);
+                    release_session(&state, session).await;
+                    return;
+                }
+                Err(SendFailure::Closed) => break,
+            }
+        }
+    }
+    let ack = Arc::new(session_ack(
+        &session.resume_token,
+        is_resumed,
+        replay_complete,
+        wire.options(),
+    ));
+    let ack_frame = Message::Binary(wire.frame(session.sequence(&ack)));
+    match send_message(&mut socket, ack_frame, stall_timeout).await {
+        Ok(()) => {}
+        Err(SendFailure::Stalled) => {
+            tracing::warn!(
+                "Evicting {}: socket write stalled on session ack",
+                client_id
+            );
+            release_session(&state, session).await;
+            return;
+        }
+        Err(SendFailure::Closed) => {
             tracing::warn!("Failed to send subscription ack to {}", client_id);
         }
     }
 
+    if !is_resumed {
+        // Send the current status of every provider; later changes arrive via fanout
+        for status in state.provider_statuses.snapshot().await {
+            let _ = session.tx.send(Envelope {
+                schema_version: "1.0.0".to_string(),
+                server_ts_ms: now_ms(),
+                seq: 0,
+                payload: Some(harpy_proto::harpy::v1::envelope::Payload::ProviderStatus(
+                    status,
+                )),
+            });
+        }
+    }
+
     // Main message loop
+    let mut closed_by_client = false;
+    let mut evicted = false;
     loop {
         tokio::select! {
             // Handle incoming WebSocket messages from client
@@ -263,9 +376,11 @@ async fn handle_socket(mut socket: WebSocket, state: AppState, client_id: String
                             msg,
                             &state,
                             &client_id,
-                            &tx,
+                            &session.tx,
+                            &mut session.subscription_ids,
                         ).await {
                             if should_disconnect {
+                                closed_by_client = true;
                                 break;
                             }
                         }
@@ -282,28 +397,140 @@ async fn handle_socket(mut socket: WebSocket, state: AppState, client_id: String
             }
 
             // Handle outgoing messages from subscription manager
-            Some(envelope) = rx.recv() => {
-                match encode_envelope(&envelope) {
-                    Ok(bytes) => {
-                        if socket.send(Message::Binary(bytes)).await.is_err() {
-                            tracing::warn!("Failed to send message to {}, closing", client_id);
-                            break;
-                        }
+            envelope = session.rx.recv() => {
+                let Some(envelope) = envelope else {
+                    let reason = session
+                        .rx
+                        .eviction()
+                        .map_or("evicted", backpressure::Eviction::reason);
+                    tracing::warn!("Evicting {}: {}", client_id, reason);
+                    let close = Message::Close(Some(CloseFrame {
+                        code: close_code::AGAIN,
+                        reason: reason.into(),
+                    }));
+                    let _ = send_message(&mut socket, close, stall_timeout).await;
+                    evicted = true;
+                    break;
+                };
+                let envelope = wire.prepare(envelope);
+                let bytes = wire.frame(session.sequence(&envelope));
+                let len = bytes.len();
+                match send_message(&mut socket, Message::Binary(bytes), stall_timeout).await {
+                    Ok(()) => {}
+                    Err(SendFailure::Stalled) => {
+                        // The client stopped reading; nothing more can reach it
+                        tracing::warn!("Evicting {}: socket write stalled", client_id);
+                        evicted = true;
+                        break;
                     }
-                    Err(e) => {
-                        tracing::error!("Failed to encode envelope: {}", e);
+                    Err(SendFailure::Closed) => {
+                        tracing::warn!("Failed to send message to {}, closing", client_id);
+                        break;
                     }
                 }
+                telemetry::record_fanout_latency(&envelope, now_ms());
+                telemetry::record_bytes_sent(len, wire.options());
             }
         }
     }
 
-    // Clean up subscription
-    state.subscription_manager.unsubscribe(&client_id).await;
-    if let Some((_, handle)) = state.playback_tasks.remove(&client_id) {
-        handle.abort();
+    // A close frame or eviction ends the session; anything else may be a network blip
+    if closed_by_client || evicted || state.sessions.grace().is_zero() {
+        release_session(&state, session).await;
+        tracing::info!("WebSocket connection closed: {}", client_id);
+        return;
+    }
+
+    let token = session.resume_token.clone();
+    let generation = state.sessions.park(session);
+    tracing::info!(
+        "WebSocket connection closed: {}, session kept for {:?}",
+        client_id,
+        state.sessions.grace()
+    );
+    tokio::spawn(async move {
+        tokio::time::sleep(state.sessions.grace()).await;
+        if let Some(session) = state.sessions.expire(&token, generation) {
+            tracing::info!("Session of {} expired", session.client_id);
+            release_session(&state, session).await;
+        }
+    });
+}
+
+/// Why a socket write failed
+enum SendFailure {
+    /// The connection is gone
+    Closed,
+    /// The client took nothing for `stall_timeout`
+    Stalled,
+}
+
+/// Write one message, giving up when the client's window stays full
+async fn send_message(
+    socket: &mut WebSocket,
+    message: Message,
+    timeout: std::time::Duration,
+) -> Result<(), SendFailure> {
+    match tokio::time::timeout(timeout, socket.send(message)).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(_)) => Err(SendFailure::Closed),
+        Err(_) => Err(SendFailure::Stalled),
+    }
+}
+
+/// Start a session with the default subscription (world viewport, all layers)
+async fn new_session(state: &AppState) -> session::Session {
+    let mut session = state.sessions.new_session();
+
+    let default_viewport = BoundingBox {
+        min_lat: -90.0,
+        max_lat: 90.0,
+        min_lon: -180.0,
+        max_lon: 180.0,
+    };
+    let default_layers = vec![
+        LayerType::Aircraft,
+        LayerType::Satellite,
+        LayerType::Ground,
+        LayerType::Vessel,
+    ];
+
+    let initial_subscription = Subscription {
+        viewport: default_viewport,
+        layers: default_layers,
+        sender: session.tx.clone(),
+        follow: None,
+        filter: None,
+    };
+
+    state
+        .subscription_manager
+        .subscribe(
+            session.client_id.clone(),
+            DEFAULT_SUBSCRIPTION_ID.to_string(),
+            initial_subscription,
+        )
+        .await;
+    session
+        .subscription_ids
+        .insert(DEFAULT_SUBSCRIPTION_ID.to_string());
+    session
+}
+
+/// Drop a session's subscriptions and playback tasks
+async fn release_session(state: &AppState, session: session::Session) {
+    state
+        .subscription_manager
+        .remove_client(&session.client_id)
+        .await;
+    for subscription_id in session.subscription_ids {
+        if let Some((_, handle)) = state
+            .playback_tasks
+            .remove(&(session.client_id.clone(), subscription_id))
+        {
+            handle.abort();
+        }
     }
-    tracing::info!("WebSocket connection closed: {}", client_id);
 }
 
 /// Handle a message from the client
@@ -313,6 +540,7 @@ async fn handle_client_message(
     state: &AppState,
     client_id: &str,
     tx: &backpressure::BackpressureChannel,
+    subscription_ids: &mut HashSet<SubscriptionId>,
 ) -> Result<(), bool> {
     match msg {
         Message::Binary(data) => {
@@ -324,7 +552,26 @@ async fn handle_client_message(
                             harpy_proto::harpy::v1::envelope::Payload::SubscriptionRequest(
                                 sub_req,
                             ) => {
-                                handle_subscription_update(sub_req, state, client_id, tx).await;
+                                handle_subscription_update(
+                                    sub_req,
+                                    state,
+                                    client_id,
+                                    tx,
+                                    subscription_ids,
+                                )
+                                .await;
+                            }
+                            harpy_proto::harpy::v1::envelope::Payload::PlaybackControl(control) => {
+                                if let Err(e) =
+                                    state.playback_sessions.control(client_id, &control).await
+                                {
+                                    tracing::warn!(
+                                        "Playback control from {} failed: {}",
+                                        client_id,
+                                        e
+                                    );
+                                    let _ = tx.send(subscription_ack("", Some(e.to_string())));
+                                }
                             }
                             _ => {
                                 tracing::debug!(
@@ -342,17 +589,7 @@ async fn handle_client_message(
                         e
                     );
                     // Send error response
-                    let error_ack = Envelope {
-                        schema_version: "1.0.0".to_string(),
-                        server_ts_ms: now_ms(),
-                        payload: Some(harpy_proto::harpy::v1::envelope::Payload::SubscriptionAck(
-                            harpy_proto::harpy::v1::SubscriptionAck {
-                                subscription_id: client_id.to_string(),
-                                success: false,
-                                error: Some(format!("Decode error: {}", e)),
-                            },
-                        )),
-                    };
+                    let error_ack = subscription_ack("", Some(format!("Decode error: {}", e)));
                     let _ = tx.send(error_ack);
                 }
             }
@@ -380,18 +617,81 @@ async fn handle_client_message(
 }
 
 /// Handle a subscription update request from a client
+///
+/// Each request targets one named subscription of the connection; other
+/// subscriptions held by the same connection are left untouched.
 async fn handle_subscription_update(
     sub_req: SubscriptionRequest,
     state: &AppState,
     client_id: &str,
     tx: &backpressure::BackpressureChannel,
+    subscription_ids: &mut HashSet<SubscriptionId>,
 ) {
+    let subscription_id = if sub_req.subscription_id.is_empty() {
+        DEFAULT_SUBSCRIPTION_ID.to_string()
+    } else {
+        sub_req.subscription_id.clone()
+    };
+
     tracing::info!(
-        "Updating subscription for {}: {:?} layers",
+        "Updating subscription {} for {}: {:?} layers",
+        subscription_id,
         client_id,
         sub_req.layers.len()
     );
 
+    let task_key = (client_id.to_string(), subscription_id.clone());
+
+    if sub_req.unsubscribe {
+        let was_live = state
+            .subscription_manager
+            .unsubscribe(client_id, &subscription_id)
+            .await;
+        let was_playback = match state.playback_tasks.remove(&task_key) {
+            Some((_, handle)) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        };
+        subscription_ids.remove(&subscription_id);
+
+        let error = (!was_live && !was_playback)
+            .then(|| format!("Unknown subscription: {}", subscription_id));
+        if tx.send(subscription_ack(&subscription_id, error)).is_err() {
+            tracing::warn!("Failed to send unsubscribe ack to {}", client_id);
+        }
+        return;
+    }
+
+    if let Some(Err(error)) = sub_req.filter.as_ref().map(track_filter::validate) {
+        let error = format!("Invalid filter: {}", error);
+        if tx
+            .send(subscription_ack(&subscription_id, Some(error)))
+            .is_err()
+        {
+            tracing::warn!("Failed to send subscription ack to {}", client_id);
+        }
+        return;
+    }
+
+    if !subscription_ids.contains(&subscription_id)
+        && subscription_ids.len() >= MAX_SUBSCRIPTIONS_PER_CONNECTION
+    {
+        let error = format!(
+            "Too many subscriptions on this connection (max {})",
+            MAX_SUBSCRIPTIONS_PER_CONNECTION
+        );
+        if tx
+            .send(subscription_ack(&subscription_id, Some(error)))
+            .is_err()
+        {
+            tracing::warn!("Failed to send subscription ack to {}", client_id);
+        }
+        return;
+    }
+    subscription_ids.insert(subscription_id.clone());
+
     // Extract viewport and layers from request
     let viewport = sub_req.viewport.unwrap_or_else(|| BoundingBox {
         min_lat: -90.0,
@@ -416,6 +716,9 @@ async fn handle_subscription_update(
         })
         .collect();
 
+    let follow = sub_req.follow.filter(follow::is_active);
+    let track_filter = sub_req.filter;
+
     // Log subscription mode and handle playback
     let is_playback = match sub_req.mode {
         mode if mode == SubscriptionMode::Live as i32 => {
@@ -432,99 +735,115 @@ async fn handle_subscription_update(
         }
     };
 
-    // Stop any existing playback task when a new subscription request arrives.
-    if let Some((_, handle)) = state.playback_tasks.remove(client_id) {
+    // Stop any existing playback task for this subscription.
+    if let Some((_, handle)) = state.playback_tasks.remove(&task_key) {
         handle.abort();
     }
 
     // Handle playback mode
     if is_playback {
-        // Remove from live fanout while playback mode is active.
-        state.subscription_manager.unsubscribe(client_id).await;
-
-        if let Some(time_range) = sub_req.time_range {
-            match time_range.range {
-                Some(harpy_proto::harpy::v1::time_range::Range::Playback(playback)) => {
-                    let start_ts_ms = playback.start_ts_ms;
-                    let end_ts_ms = playback.end_ts_ms;
-                    let speed = 1.0; // Default speed
-
-                    tracing::info!(
-                        "Starting playback for {}: {} to {} at {}x speed",
-                        client_id,
-                        start_ts_ms,
-                        end_ts_ms,
-                        speed
-                    );
+        // Remove this subscription from live fanout while playback mode is active.
+        state
+            .subscription_manager
+            .unsubscribe(client_id, &subscription_id)
+            .await;
+
+        let playback = match sub_req.time_range.and_then(|time_range| time_range.range) {
+            Some(harpy_proto::harpy::v1::time_range::Range::Playback(playback)) => playback,
+            _ => {
+                tracing::warn!("Playback mode requested but no playback range provided");
+                if tx
+                    .send(subscription_ack(
+                        &subscription_id,
+                        Some("Playback mode requires a playback range".to_string()),
+                    ))
+                    .is_err()
+                {
+                    tracing::warn!("Failed to send playback ack to {}", client_id);
+                }
+                return;
+            }
+        };
 
-                    // Start playback task
-                    let subscription = Subscription {
-                        viewport: viewport.clone(),
-                        layers: layers.clone(),
-                        sender: tx.clone(),
-                    };
-                    let client_id_clone = client_id.to_string();
-                    let tx_clone = tx.clone();
-                    let db_pool = state.db_pool.clone();
-
-                    let playback_handle = tokio::spawn(async move {
-                        let mut playback_rx = playback::start_playback(
-                            start_ts_ms,
-                            end_ts_ms,
-                            speed,
-                            subscription,
-                            db_pool,
-                        )
-                        .await;
-
-                        while let Some(envelope) = playback_rx.recv().await {
-                            if let Err(unsent) = tx_clone.send(envelope) {
-                                match unsent.payload {
-                                    Some(
-                                        harpy_proto::harpy::v1::envelope::Payload::TrackDeltaBatch(
-                                            _,
-                                        ),
-                                    ) => {
-                                        tracing::debug!(
-                                            "Dropped playback TrackDeltaBatch for {} due to backpressure",
-                                            client_id_clone
-                                        );
-                                    }
-                                    _ => {
-                                        tracing::debug!(
-                                            "Playback client {} disconnected",
-                                            client_id_clone
-                                        );
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    });
-                    state
-                        .playback_tasks
-                        .insert(client_id.to_string(), playback_handle);
+        // Start a shared playback session or join the one named in the request
+        let clock = if playback.session_id.is_empty() {
+            state
+                .playback_sessions
+                .create(playback_session::NewSession {
+                    client_id,
+                    start_ts_ms: playback.start_ts_ms,
+                    end_ts_ms: playback.end_ts_ms,
+                    speed: playback.speed,
+                    viewport: &viewport,
+                    layers: &layers,
+                })
+                .await
+        } else {
+            state
+                .playback_sessions
+                .join(&playback.session_id, client_id)
+                .await
+        };
+        let (clock, membership) = match clock {
+            Ok(follower) => follower,
+            Err(e) => {
+                tracing::warn!("Playback for {} failed: {}", client_id, e);
+                if tx
+                    .send(subscription_ack(&subscription_id, Some(e.to_string())))
+                    .is_err()
+                {
+                    tracing::warn!("Failed to send playback ack to {}", client_id);
                 }
-                _ => {
-                    tracing::warn!("Playback mode requested but no playback range provided");
+                return;
+            }
+        };
+        tracing::info!(
+            "Starting playback for {} in session {}",
+            client_id,
+            clock.borrow().session_id
+        );
+
+        // Start playback task
+        let subscription = Subscription {
+            viewport,
+            layers,
+            sender: tx.clone(),
+            follow,
+            filter: track_filter,
+        };
+        let client_id_clone = client_id.to_string();
+        let tx_clone = tx.clone();
+        let db_pool = state.db_pool.clone();
+        let playback_subscription_id = subscription_id.clone();
+
+        let playback_handle = tokio::spawn(async move {
+            // Following ends when this task is aborted or finishes
+            let _membership = membership;
+            let mut playback_rx =
+                playback::start_playback(clock, subscription, playback_subscription_id, db_pool)
+                    .await;
+
+            while let Some(envelope) = playback_rx.recv().await {
+                if let Err(unsent) = tx_clone.send(envelope) {
+                    match unsent.payload {
+                        Some(harpy_proto::harpy::v1::envelope::Payload::TrackDeltaBatch(_)) => {
+                            tracing::debug!(
+                                "Dropped playback TrackDeltaBatch for {} due to backpressure",
+                                client_id_clone
+                            );
+                        }
+                        _ => {
+                            tracing::debug!("Playback client {} disconnected", client_id_clone);
+                            break;
+                        }
+                    }
                 }
             }
-        }
+        });
+        state.playback_tasks.insert(task_key, playback_handle);
 
         // Send ack for playback mode
-        let ack = Envelope {
-            schema_version: "1.0.0".to_string(),
-            server_ts_ms: now_ms(),
-            payload: Some(harpy_proto::harpy::v1::envelope::Payload::SubscriptionAck(
-                harpy_proto::harpy::v1::SubscriptionAck {
-                    subscription_id: client_id.to_string(),
-                    success: true,
-                    error: None,
-                },
-            )),
-        };
-
-        if tx.send(ack).is_err() {
+        if tx.send(subscription_ack(&subscription_id, None)).is_err() {
             tracing::warn!("Failed to send playback ack to {}", client_id);
         }
 
@@ -536,37 +855,137 @@ async fn handle_subscription_update(
         viewport,
         layers,
         sender: tx.clone(),
+        follow: follow.clone(),
+        filter: track_filter,
     };
 
+    let alert_subscription = subscription
+        .layers
+        .contains(&LayerType::Alert)
+        .then(|| subscription.clone());
+
     // Update subscription
-    state
+    let replaced = state
         .subscription_manager
-        .subscribe(client_id.to_string(), subscription)
+        .subscribe(client_id.to_string(), subscription_id.clone(), subscription)
         .await;
+    // Viewport changes resend the subscription; only a subscription that just
+    // gained the alert layer needs the open alerts, later ones arrive live
+    let alert_subscription = alert_subscription.filter(|_| {
+        replaced.map_or(true, |replaced| {
+            !replaced.layers.contains(&LayerType::Alert)
+        })
+    });
 
     // Send success acknowledgment
-    let ack = Envelope {
+    if tx.send(subscription_ack(&subscription_id, None)).is_err() {
+        tracing::warn!("Failed to send subscription ack to {}", client_id);
+    }
+
+    // Bring the client up to date with alerts raised before it subscribed
+    if let (Some(subscription), Some(pool)) = (alert_subscription, state.db_pool.clone()) {
+        let client_id = client_id.to_string();
+        tokio::spawn(async move {
+            let open_alerts = match alerts::fetch_open_alerts(&pool).await {
+                Ok(open_alerts) => open_alerts,
+                Err(e) => {
+                    tracing::error!("Open alert query failed for {}: {}", client_id, e);
+                    return;
+                }
+            };
+            for (alert, scope) in open_alerts {
+                if !subscription.wants_alert(&scope) {
+                    continue;
+                }
+                let envelope = Envelope {
+                    schema_version: "1.0.0".to_string(),
+                    server_ts_ms: now_ms(),
+                    seq: 0,
+                    payload: Some(harpy_proto::harpy::v1::envelope::Payload::AlertUpsert(
+                        alert,
+                    )),
+                };
+                if subscription.sender.send(envelope).is_err() {
+                    tracing::debug!(
+                        "Stopped sending open alerts to {}, channel closed",
+                        client_id
+                    );
+                    return;
+                }
+            }
+        });
+    }
+
+    // Send the recent trail of followed tracks after the ack
+    if let (Some(follow), Some(pool)) = (follow, state.db_pool.clone()) {
+        if follow.trail_ms > 0 {
+            let client_id = client_id.to_string();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let deltas = match follow::fetch_trail(&pool, &follow, now_ms()).await {
+                    Ok(deltas) => deltas,
+                    Err(e) => {
+                        tracing::error!("Follow trail query failed for {}: {}", client_id, e);
+                        return;
+                    }
+                };
+                if deltas.is_empty() {
+                    return;
+                }
+                let trail = Envelope {
+                    schema_version: "1.0.0".to_string(),
+                    server_ts_ms: now_ms(),
+                    seq: 0,
+                    payload: Some(harpy_proto::harpy::v1::envelope::Payload::TrackDeltaBatch(
+                        harpy_proto::harpy::v1::TrackDeltaBatch {
+                            deltas,
+                            subscription_id,
+                        },
+                    )),
+                };
+                if tx.send(trail).is_err() {
+                    tracing::debug!("Dropped follow trail for {} due to backpressure", client_id);
+                }
+            });
+        }
+    }
+}
+
+/// Build a subscription acknowledgment; `error` marks it as failed
+fn subscription_ack(subscription_id: &str, error: Option<String>) -> Envelope {
+    Envelope {
         schema_version: "1.0.0".to_string(),
         server_ts_ms: now_ms(),
+        seq: 0,
         payload: Some(harpy_proto::harpy::v1::envelope::Payload::SubscriptionAck(
             harpy_proto::harpy::v1::SubscriptionAck {
-                subscription_id: client_id.to_string(),
-                success: true,
-                error: None,
+                subscription_id: subscription_id.to_string(),
+                success: error.is_none(),
+                error,
+                ..Default::default()
             },
         )),
-    };
-
-    if tx.send(ack).is_err() {
-        tracing::warn!("Failed to send subscription ack to {}", client_id);
     }
 }
 
-/// Encode an envelope to bytes
-fn encode_envelope(envelope: &Envelope) -> anyhow::Result<Vec<u8>> {
-    let mut buf = Vec::new();
-    envelope.encode(&mut buf)?;
-    Ok(buf)
+/// Acknowledge the default subscription of a new or resumed session
+fn session_ack(
+    resume_token: &str,
+    resumed: bool,
+    replay_complete: bool,
+    wire: wire::WireOptions,
+) -> Envelope {
+    let mut ack = subscription_ack(DEFAULT_SUBSCRIPTION_ID, None);
+    if let Some(harpy_proto::harpy::v1::envelope::Payload::SubscriptionAck(inner)) =
+        ack.payload.as_mut()
+    {
+        inner.resume_token = resume_token.to_string();
+        inner.resumed = resumed;
+        inner.replay_complete = replay_complete;
+        inner.compression = wire.compression().to_string();
+        inner.encoding = wire.encoding().to_string();
+    }
+    ack
 }
 
 /// Get current timestamp in milliseconds
@@ -589,36 +1008,44 @@ async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
     format!(
         "# HELP harpy_relay_connected_clients Number of connected WebSocket clients\n\
          # TYPE harpy_relay_connected_clients gauge\n\
-         harpy_relay_connected_clients {}\n",
-        client_count
+         harpy_relay_connected_clients {}\n\
+         # HELP harpy_relay_parked_sessions Disconnected sessions waiting to be resumed\n\
+         # TYPE harpy_relay_parked_sessions gauge\n\
+         harpy_relay_parked_sessions {}\n\
+         # HELP harpy_relay_playback_sessions Shared playback sessions followed on this relay\n\
+         # TYPE harpy_relay_playback_sessions gauge\n\
+         harpy_relay_playback_sessions {}\n{}{}{}{}",
+        client_count,
+        state.sessions.parked_count(),
+        state.playback_sessions.live_count(),
+        state.backpressure.render(),
+        state.stream_metrics.render(),
+        telemetry::render_state(&state.subscription_manager, state.playback_tasks.len()).await,
+        state.metrics.render()
     )
 }
 
 async fn debug_snapshot_handler(State(state): State<AppState>) -> impl IntoResponse {
     let subscriptions = state.subscription_manager.debug_subscriptions().await;
     let mut subscriptions_by_layer: HashMap<String, usize> = HashMap::new();
-    let mut track_batches_dropped = 0usize;
-    let mut track_batches_sent = 0usize;
-    let mut high_priority_sent = 0usize;
-
     for subscription in &subscriptions {
         for layer in &subscription.layers {
             *subscriptions_by_layer.entry(layer.clone()).or_insert(0) += 1;
         }
-        track_batches_dropped += subscription.backpressure.track_batches_dropped;
-        track_batches_sent += subscription.backpressure.track_batches_sent;
-        high_priority_sent += subscription.backpressure.high_priority_sent;
     }
 
+    let totals = state.backpressure.totals();
     let relay = RelayDebugSnapshot {
-        connected_clients: subscriptions.len(),
+        connected_clients: state.subscription_manager.client_count().await,
         playback_clients: state.playback_tasks.len(),
         subscriptions,
         subscriptions_by_layer,
         backpressure_totals: BackpressureTotals {
-            track_batches_dropped,
-            track_batches_sent,
-            high_priority_sent,
+            track_batches_sent: totals.track_batches_sent,
+            track_updates_coalesced: totals.track_updates_coalesced,
+            track_updates_dropped: totals.track_updates_dropped,
+            high_priority_sent: totals.high_priority_sent,
+            clients_evicted: state.backpressure.clients_evicted(),
         },
     };
 
@@ -5,15 +5,20 @@
 
 #![allow(dead_code)]
 
+use crate::filter;
+use crate::follow;
+use crate::playback_session::PlaybackClock;
 use crate::subscription::Subscription;
+use crate::telemetry;
+use harpy_core::h3_cells;
 use harpy_proto::harpy::v1::{
-    envelope::Payload, Envelope, LayerType, Position, TrackDelta, TrackDeltaBatch,
+    envelope::Payload, BoundingBox, Envelope, LayerType, Position, TrackDelta, TrackDeltaBatch,
 };
+use sqlx::postgres::PgRow;
 use sqlx::{Postgres, QueryBuilder, Row};
 use std::collections::HashMap;
-use std::time::Duration;
-use tokio::sync::mpsc;
-use tokio::time::{interval, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio::time::Instant;
 
 /// Playback state for a client
 #[derive(Debug, Clone)]
@@ -92,84 +97,110 @@ pub struct PlaybackHandle {
     pub sender: mpsc::UnboundedSender<Envelope>,
 }
 
-/// Start playback streaming for a subscription
+/// Stream a subscription's view of a shared playback session
 ///
-/// Queries historical deltas from Postgres and streams them at playback speed.
+/// Sends the session state when the subscription starts following and after
+/// every control change, and the historical deltas matching the subscription
+/// as the session clock advances. A seek skips to the new position without
+/// streaming what lies in between.
 pub async fn start_playback(
-    start_ts_ms: u64,
-    end_ts_ms: u64,
-    speed: f32,
+    mut clock: watch::Receiver<PlaybackClock>,
     subscription: Subscription,
+    subscription_id: String,
     db_pool: Option<sqlx::PgPool>,
 ) -> mpsc::UnboundedReceiver<Envelope> {
     let (tx, rx) = mpsc::unbounded_channel();
-    let mut state = PlaybackState::new(start_ts_ms, end_ts_ms, speed);
 
-    // Spawn playback task
     tokio::spawn(async move {
-        let mut tick = interval(Duration::from_millis(100)); // 10fps
-        let mut last_tick = Instant::now();
+        let initial = clock.borrow_and_update().clone();
+        let mut revision = initial.revision;
+        let mut seek_revision = initial.seek_revision;
+        let mut position = initial.state.current_ts_ms;
+        let mut completed = false;
+        if tx.send(session_state(&initial, &subscription_id)).is_err() {
+            return;
+        }
 
         loop {
-            tick.tick().await;
-
-            if !state.is_playing {
+            // Stop following once the client's forwarding task is gone
+            tokio::select! {
+                changed = clock.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                }
+                _ = tx.closed() => break,
+            }
+            let snapshot = clock.borrow_and_update().clone();
+            if snapshot.revision != revision {
+                revision = snapshot.revision;
+                if tx.send(session_state(&snapshot, &subscription_id)).is_err() {
+                    tracing::debug!("Playback client disconnected");
+                    break;
+                }
+            }
+            if snapshot.seek_revision != seek_revision {
+                seek_revision = snapshot.seek_revision;
+                position = snapshot.state.current_ts_ms;
+                completed = false;
                 continue;
             }
 
-            let now = Instant::now();
-            let elapsed_ms = now.duration_since(last_tick).as_millis() as u64;
-            last_tick = now;
-
-            // Advance playback position
-            let prev_ts_ms = state.current_ts_ms;
-            let still_playing = state.advance(elapsed_ms);
-            let current_ts_ms = state.current_ts_ms;
-
-            let deltas = if let Some(pool) = db_pool.as_ref() {
-                match fetch_playback_deltas(pool, &subscription, prev_ts_ms, current_ts_ms).await {
-                    Ok(deltas) => deltas,
-                    Err(e) => {
-                        tracing::error!("Playback delta query failed: {}", e);
-                        Vec::new()
+            let current_ts_ms = snapshot.state.current_ts_ms;
+            if current_ts_ms > position {
+                let deltas = if let Some(pool) = db_pool.as_ref() {
+                    match fetch_playback_deltas(pool, &subscription, position, current_ts_ms).await
+                    {
+                        Ok(deltas) => deltas,
+                        Err(e) => {
+                            tracing::error!("Playback delta query failed: {}", e);
+                            Vec::new()
+                        }
                     }
+                } else {
+                    tracing::debug!("Playback requested without database pool");
+                    Vec::new()
+                };
+                position = current_ts_ms;
+
+                let batch = TrackDeltaBatch {
+                    deltas,
+                    subscription_id: subscription_id.clone(),
+                };
+                let envelope = Envelope {
+                    schema_version: "1.0.0".to_string(),
+                    server_ts_ms: now_ms(),
+                    seq: 0,
+                    payload: Some(Payload::TrackDeltaBatch(batch)),
+                };
+                if tx.send(envelope).is_err() {
+                    tracing::debug!("Playback client disconnected");
+                    break;
                 }
-            } else {
-                tracing::debug!("Playback requested without database pool");
-                Vec::new()
-            };
-
-            let batch = TrackDeltaBatch { deltas };
-            let envelope = Envelope {
-                schema_version: "1.0.0".to_string(),
-                server_ts_ms: now_ms(),
-                payload: Some(Payload::TrackDeltaBatch(batch)),
-            };
-
-            if tx.send(envelope).is_err() {
-                tracing::debug!("Playback client disconnected");
-                break;
             }
 
-            if !still_playing {
+            if !completed && position >= snapshot.state.end_ts_ms {
                 tracing::info!("Playback complete: reached end timestamp");
+                completed = true;
                 // Send completion indicator
                 let completion = Envelope {
                     schema_version: "1.0.0".to_string(),
                     server_ts_ms: now_ms(),
+                    seq: 0,
                     payload: Some(Payload::SnapshotMeta(
                         harpy_proto::harpy::v1::SnapshotMeta {
                             snapshot_id: "playback-complete".to_string(),
-                            start_ts_ms,
-                            end_ts_ms,
+                            start_ts_ms: snapshot.start_ts_ms,
+                            end_ts_ms: snapshot.state.end_ts_ms,
                             s3_url: "".to_string(),
                             track_count: 0,
                             compressed_size_bytes: 0,
                         },
                     )),
                 };
-                let _ = tx.send(completion);
-                break;
+                if tx.send(completion).is_err() {
+                    break;
+                }
             }
         }
     });
@@ -177,6 +208,17 @@ pub async fn start_playback(
     rx
 }
 
+fn session_state(clock: &PlaybackClock, subscription_id: &str) -> Envelope {
+    Envelope {
+        schema_version: "1.0.0".to_string(),
+        server_ts_ms: now_ms(),
+        seq: 0,
+        payload: Some(Payload::PlaybackSessionState(
+            clock.to_proto(subscription_id),
+        )),
+    }
+}
+
 fn layer_kind_strings(layers: &[LayerType]) -> Vec<&'static str> {
     let mut out = Vec::with_capacity(layers.len());
     for layer in layers {
@@ -191,7 +233,7 @@ fn layer_kind_strings(layers: &[LayerType]) -> Vec<&'static str> {
     out
 }
 
-fn parse_meta(value: Option<serde_json::Value>) -> HashMap<String, String> {
+pub(crate) fn parse_meta(value: Option<serde_json::Value>) -> HashMap<String, String> {
     let mut map = HashMap::new();
     let Some(serde_json::Value::Object(obj)) = value else {
         return map;
@@ -236,14 +278,8 @@ async fn fetch_playback_deltas(
     qb.push_bind(start_ts_ms as i64)
         .push(" AND td.ts_ms <= ")
         .push_bind(end_ts_ms as i64)
-        .push(" AND td.lat >= ")
-        .push_bind(subscription.viewport.min_lat)
-        .push(" AND td.lat <= ")
-        .push_bind(subscription.viewport.max_lat)
-        .push(" AND td.lon >= ")
-        .push_bind(subscription.viewport.min_lon)
-        .push(" AND td.lon <= ")
-        .push_bind(subscription.viewport.max_lon);
+        .push(" AND ((");
+    push_viewport_predicate(&mut qb, &subscription.viewport);
 
     let layer_kinds = layer_kind_strings(&subscription.layers);
     if !layer_kinds.is_empty() {
@@ -251,33 +287,100 @@ async fn fetch_playback_deltas(
             .push_bind(layer_kinds)
             .push(")");
     }
+    if let Some(track_filter) = subscription.filter.as_ref() {
+        filter::push_filter_predicates(&mut qb, track_filter);
+    }
+    qb.push(")");
+
+    // Followed tracks are included regardless of viewport and layer.
+    if let Some(follow) = subscription
+        .follow
+        .as_ref()
+        .filter(|f| follow::is_active(f))
+    {
+        qb.push(" OR ");
+        follow::push_follow_predicate(&mut qb, follow);
+    }
+    qb.push(")");
 
     qb.push(" ORDER BY td.ts_ms ASC LIMIT 5000");
 
-    let rows = qb.build().fetch_all(pool).await?;
-    let mut out = Vec::with_capacity(rows.len());
-
-    for row in rows {
-        let kind: String = row.get("kind");
-        let meta: Option<serde_json::Value> = row.try_get("meta").ok();
-        let delta = TrackDelta {
-            id: row.get("track_id"),
-            kind: kind_to_proto(&kind),
-            position: Some(Position {
-                lat: row.get("lat"),
-                lon: row.get("lon"),
-                alt: row.get("alt"),
-            }),
-            heading: row.get("heading"),
-            speed: row.get("speed"),
-            ts_ms: row.get::<i64, _>("ts_ms") as u64,
-            provider_id: row.get("provider_id"),
-            meta: parse_meta(meta),
-        };
-        out.push(delta);
+    let started = Instant::now();
+    let rows = qb.build().fetch_all(pool).await;
+    metrics::histogram!(telemetry::PLAYBACK_QUERY).record(started.elapsed().as_secs_f64());
+    let rows = rows?;
+    Ok(rows.iter().map(delta_from_row).collect())
+}
+
+/// Push the viewport bounds on `td`, matching `viewport_contains` on the live
+/// path: `min_lon > max_lon` crosses the antimeridian and selects the two
+/// longitude ranges on either side of it.
+fn push_viewport_predicate(qb: &mut QueryBuilder<'_, Postgres>, viewport: &BoundingBox) {
+    let min_lat = viewport.min_lat.min(viewport.max_lat);
+    let max_lat = viewport.min_lat.max(viewport.max_lat);
+    qb.push("td.lat >= ")
+        .push_bind(min_lat)
+        .push(" AND td.lat <= ")
+        .push_bind(max_lat)
+        .push(" AND (td.lon >= ")
+        .push_bind(viewport.min_lon)
+        .push(if viewport.min_lon <= viewport.max_lon {
+            " AND td.lon <= "
+        } else {
+            " OR td.lon <= "
+        })
+        .push_bind(viewport.max_lon)
+        .push(")");
+    push_h3_cover(qb, min_lat, viewport.min_lon, max_lat, viewport.max_lon);
+}
+
+/// Narrow a `track_deltas` viewport filter to the H3 cells covering it, so
+/// Postgres scans `idx_track_deltas_h3_ts` instead of every row in the window.
+/// Rows the migration 003 backfill has not reached (`h3_index IS NULL`) still
+/// match, so history is complete while it runs; the exact lat/lon bounds
+/// remain the real filter. Very large viewports get no cover.
+pub(crate) fn push_h3_cover(
+    qb: &mut QueryBuilder<'_, Postgres>,
+    min_lat: f64,
+    min_lon: f64,
+    max_lat: f64,
+    max_lon: f64,
+) {
+    let resolution = h3_cells::storage_resolution();
+    if let Some(ranges) = h3_cells::cover_bbox(min_lat, min_lon, max_lat, max_lon, resolution) {
+        qb.push(" AND ")
+            .push(h3_cells::sql_predicate("td.h3_index", &ranges));
     }
+}
 
-    Ok(out)
+/// Build a track delta from a `track_deltas` row joined with its track kind
+///
+/// `heading` and `speed` are nullable and default to 0.
+pub(crate) fn delta_from_row(row: &PgRow) -> TrackDelta {
+    let kind: String = row.get("kind");
+    let meta: Option<serde_json::Value> = row.try_get("meta").ok();
+    TrackDelta {
+        id: row.get("track_id"),
+        kind: kind_to_proto(&kind),
+        position: Some(Position {
+            lat: row.get("lat"),
+            lon: row.get("lon"),
+            alt: row.get("alt"),
+        }),
+        heading: row
+            .try_get::<Option<f64>, _>("heading")
+            .ok()
+            .flatten()
+            .unwrap_or(0.0),
+        speed: row
+            .try_get::<Option<f64>, _>("speed")
+            .ok()
+            .flatten()
+            .unwrap_or(0.0),
+        ts_ms: row.get::<i64, _>("ts_ms") as u64,
+        provider_id: row.get("provider_id"),
+        meta: parse_meta(meta),
+    }
 }
 
 fn now_ms() -> u64 {
@@ -326,4 +429,39 @@ mod tests {
         let state = PlaybackState::new(1000, 5000, 0.1);
         assert_eq!(state.speed, 0.25); // Clamped to min
     }
+
+    #[test]
+    fn test_h3_cover_only_for_bounded_viewports() {
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT 1 FROM track_deltas td WHERE true");
+        push_h3_cover(&mut qb, 37.0, -123.0, 38.0, -122.0);
+        let sql = qb.sql();
+        assert!(sql.contains(" AND (td.h3_index BETWEEN "));
+        // Unindexed rows are not dropped while the backfill runs
+        assert!(sql.ends_with(" OR td.h3_index IS NULL)"));
+
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT 1 FROM track_deltas td WHERE true");
+        push_h3_cover(&mut qb, -90.0, -180.0, 90.0, 180.0);
+        assert!(!qb.sql().contains("h3_index"));
+    }
+
+    #[test]
+    fn test_viewport_predicate_splits_at_antimeridian() {
+        let viewport = |min_lon, max_lon| BoundingBox {
+            min_lat: -20.0,
+            min_lon,
+            max_lat: 10.0,
+            max_lon,
+        };
+
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT 1 FROM track_deltas td WHERE ");
+        push_viewport_predicate(&mut qb, &viewport(170.0, -170.0));
+        assert!(qb.sql().starts_with(
+            "SELECT 1 FROM track_deltas td WHERE td.lat >= $1 AND td.lat <= $2 \
+             AND (td.lon >= $3 OR td.lon <= $4)"
+        ));
+
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT 1 FROM track_deltas td WHERE ");
+        push_viewport_predicate(&mut qb, &viewport(-10.0, 10.0));
+        assert!(qb.sql().contains("(td.lon >= $3 AND td.lon <= $4)"));
+    }
 }
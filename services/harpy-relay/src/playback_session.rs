@@ -0,0 +1,618 @@
+//! Shared Playback Sessions
+//!
+//! Every playback subscription follows a playback session: a clock over a
+//! historical time range with a shareable id. Other clients join the session
+//! by passing its id in `PlaybackMode.session_id`. The client that created the
+//! session controls it: its `PlaybackControl` (pause, resume, seek, speed)
+//! moves the clock for everyone in it, while controls from other clients are
+//! rejected. Once the controller stops following, the next follower to send a
+//! control takes over. Each client keeps its own viewport, layers and filters;
+//! only the clock is shared.
+//!
+//! Sessions are persisted in `playback_sessions` on every control change and
+//! every few seconds while playing, so a relay restart resumes them from the
+//! last saved position. A session nobody follows is dropped from memory and
+//! reloaded from Postgres when someone joins again; rows untouched for
+//! `RELAY_PLAYBACK_SESSION_TTL_HOURS` (default 24) are deleted.
+
+use crate::playback::PlaybackState;
+use dashmap::DashMap;
+use harpy_proto::harpy::v1::{BoundingBox, LayerType, PlaybackControl, PlaybackSessionState};
+use sqlx::{PgPool, Row};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{interval, Instant};
+use uuid::Uuid;
+
+/// Clock resolution
+const TICK: Duration = Duration::from_millis(100);
+/// How often a playing session's position is saved
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Shared clock of a session as seen by its followers
+#[derive(Debug, Clone)]
+pub struct PlaybackClock {
+    pub session_id: String,
+    /// Client whose controls drive the session
+    pub controller: String,
+    pub start_ts_ms: u64,
+    pub state: PlaybackState,
+    /// Bumped by every control change
+    pub revision: u64,
+    /// Bumped by every seek; followers skip the jump instead of streaming it
+    pub seek_revision: u64,
+}
+
+impl PlaybackClock {
+    fn new(
+        session_id: String,
+        controller: String,
+        start_ts_ms: u64,
+        end_ts_ms: u64,
+        speed: f32,
+    ) -> Self {
+        Self {
+            session_id,
+            controller,
+            start_ts_ms,
+            state: PlaybackState::new(start_ts_ms, end_ts_ms, speed),
+            revision: 0,
+            seek_revision: 0,
+        }
+    }
+
+    /// Apply a control, returning whether anything changed
+    fn apply(&mut self, control: &PlaybackControl) -> bool {
+        let before = (
+            self.state.current_ts_ms,
+            self.state.speed,
+            self.state.is_playing,
+        );
+        if let Some(speed) = control.speed {
+            self.state.set_speed(speed);
+        }
+        if let Some(ts_ms) = control.seek_ts_ms {
+            let ts_ms = ts_ms.clamp(self.start_ts_ms, self.state.end_ts_ms);
+            if ts_ms != self.state.current_ts_ms {
+                self.state.current_ts_ms = ts_ms;
+                self.seek_revision += 1;
+            }
+        }
+        match control.playing {
+            Some(true) => self.state.resume(),
+            Some(false) => self.state.pause(),
+            None => {}
+        }
+        let changed = before
+            != (
+                self.state.current_ts_ms,
+                self.state.speed,
+                self.state.is_playing,
+            );
+        if changed {
+            self.revision += 1;
+        }
+        changed
+    }
+
+    /// State message for a follower's subscription
+    pub fn to_proto(&self, subscription_id: &str) -> PlaybackSessionState {
+        PlaybackSessionState {
+            session_id: self.session_id.clone(),
+            subscription_id: subscription_id.to_string(),
+            start_ts_ms: self.start_ts_ms,
+            end_ts_ms: self.state.end_ts_ms,
+            current_ts_ms: self.state.current_ts_ms,
+            speed: self.state.speed,
+            is_playing: self.state.is_playing,
+        }
+    }
+}
+
+/// A live session; followers hold receivers of `clock`
+struct PlaybackSession {
+    clock: watch::Sender<PlaybackClock>,
+    /// Following clients and how many of their subscriptions follow
+    members: HashMap<String, usize>,
+}
+
+/// A client following a session; dropping it leaves the session
+pub struct Membership {
+    sessions: Arc<PlaybackSessions>,
+    session_id: String,
+    client_id: String,
+}
+
+impl Drop for Membership {
+    fn drop(&mut self) {
+        if let Some(mut session) = self.sessions.sessions.get_mut(&self.session_id) {
+            if let Entry::Occupied(mut entry) = session.members.entry(self.client_id.clone()) {
+                *entry.get_mut() -= 1;
+                if *entry.get() == 0 {
+                    entry.remove();
+                }
+            }
+        }
+    }
+}
+
+/// What the creator asked for, stored with the session
+pub struct NewSession<'a> {
+    pub client_id: &'a str,
+    pub start_ts_ms: u64,
+    pub end_ts_ms: u64,
+    pub speed: f32,
+    pub viewport: &'a BoundingBox,
+    pub layers: &'a [LayerType],
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error("Unknown playback session: {0}")]
+    NotFound(String),
+    #[error("Not following playback session: {0}")]
+    NotMember(String),
+    #[error("Playback session {0} is controlled by another client")]
+    NotController(String),
+    #[error("Invalid playback range: {0}..{1}")]
+    InvalidRange(u64, u64),
+    #[error("Playback session store unavailable: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Registry of live playback sessions
+pub struct PlaybackSessions {
+    sessions: DashMap<String, PlaybackSession>,
+    db_pool: Option<PgPool>,
+    ttl: Duration,
+}
+
+impl PlaybackSessions {
+    pub fn new(db_pool: Option<PgPool>, ttl: Duration) -> Self {
+        Self {
+            sessions: DashMap::new(),
+            db_pool,
+            ttl,
+        }
+    }
+
+    /// Row TTL from `RELAY_PLAYBACK_SESSION_TTL_HOURS`
+    pub fn from_env(db_pool: Option<PgPool>) -> Self {
+        let ttl_hours = std::env::var("RELAY_PLAYBACK_SESSION_TTL_HOURS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(24);
+        Self::new(db_pool, Duration::from_secs(ttl_hours * 3600))
+    }
+
+    /// Sessions currently followed on this relay
+    pub fn live_count(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Start a new session controlled by its creator and follow it
+    pub async fn create(
+        self: &Arc<Self>,
+        new: NewSession<'_>,
+    ) -> Result<(watch::Receiver<PlaybackClock>, Membership), SessionError> {
+        if new.start_ts_ms >= new.end_ts_ms {
+            return Err(SessionError::InvalidRange(new.start_ts_ms, new.end_ts_ms));
+        }
+        let speed = if new.speed > 0.0 { new.speed } else { 1.0 };
+        let clock = PlaybackClock::new(
+            Uuid::new_v4().simple().to_string(),
+            new.client_id.to_string(),
+            new.start_ts_ms,
+            new.end_ts_ms,
+            speed,
+        );
+        if let Some(pool) = self.db_pool.as_ref() {
+            insert(pool, &clock, &new).await?;
+        }
+        tracing::info!(
+            "Playback session {} created by {}",
+            clock.session_id,
+            new.client_id
+        );
+        Ok(self.follow_or_insert(clock, new.client_id))
+    }
+
+    /// Follow an existing session, loading it from Postgres if it is not live
+    pub async fn join(
+        self: &Arc<Self>,
+        session_id: &str,
+        client_id: &str,
+    ) -> Result<(watch::Receiver<PlaybackClock>, Membership), SessionError> {
+        if let Some(follower) = self.follow_live(session_id, client_id) {
+            return Ok(follower);
+        }
+        let Some(pool) = self.db_pool.as_ref() else {
+            return Err(SessionError::NotFound(session_id.to_string()));
+        };
+        let clock = load(pool, session_id)
+            .await?
+            .ok_or_else(|| SessionError::NotFound(session_id.to_string()))?;
+        Ok(self.follow_or_insert(clock, client_id))
+    }
+
+    /// Apply a control from a following client to a live session and save it
+    ///
+    /// Only the controller may steer the session; if it no longer follows, the
+    /// sending client becomes the controller.
+    pub async fn control(
+        &self,
+        client_id: &str,
+        control: &PlaybackControl,
+    ) -> Result<(), SessionError> {
+        let clock = {
+            let session = self
+                .sessions
+                .get(&control.session_id)
+                .ok_or_else(|| SessionError::NotFound(control.session_id.clone()))?;
+            if !session.members.contains_key(client_id) {
+                return Err(SessionError::NotMember(control.session_id.clone()));
+            }
+            let controller = session.clock.borrow().controller.clone();
+            let take_over = controller != client_id;
+            if take_over && session.members.contains_key(&controller) {
+                return Err(SessionError::NotController(control.session_id.clone()));
+            }
+            let changed = session.clock.send_if_modified(|clock| {
+                if take_over {
+                    clock.controller = client_id.to_string();
+                }
+                clock.apply(control)
+            });
+            if !changed && !take_over {
+                return Ok(());
+            }
+            let clock = session.clock.borrow().clone();
+            clock
+        };
+
+        tracing::info!(
+            "Playback session {} at {} ({}x, playing: {})",
+            clock.session_id,
+            clock.state.current_ts_ms,
+            clock.state.speed,
+            clock.state.is_playing
+        );
+        self.persist(&clock).await;
+        Ok(())
+    }
+
+    /// Delete rows of sessions nobody touched within the TTL
+    pub async fn expire(&self) -> Result<u64, sqlx::Error> {
+        let Some(pool) = self.db_pool.as_ref() else {
+            return Ok(0);
+        };
+        let result = sqlx::query(
+            "DELETE FROM playback_sessions \
+             WHERE updated_at < NOW() - make_interval(secs => $1)",
+        )
+        .bind(self.ttl.as_secs_f64())
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Subscribe to a live session with the same id, or make `clock` live
+    fn follow_or_insert(
+        self: &Arc<Self>,
+        clock: PlaybackClock,
+        client_id: &str,
+    ) -> (watch::Receiver<PlaybackClock>, Membership) {
+        // Subscribing under the entry lock keeps `run_clock` from retiring the
+        // session between the lookup and the subscription.
+        let session_id = clock.session_id.clone();
+        let mut inserted = false;
+        let receiver = {
+            let mut session = self.sessions.entry(session_id.clone()).or_insert_with(|| {
+                inserted = true;
+                PlaybackSession {
+                    clock: watch::Sender::new(clock),
+                    members: HashMap::new(),
+                }
+            });
+            *session.members.entry(client_id.to_string()).or_default() += 1;
+            session.clock.subscribe()
+        };
+        if inserted {
+            tokio::spawn(run_clock(self.clone(), session_id.clone()));
+        }
+        (receiver, self.membership(session_id, client_id))
+    }
+
+    /// Subscribe to a session if it is live
+    fn follow_live(
+        self: &Arc<Self>,
+        session_id: &str,
+        client_id: &str,
+    ) -> Option<(watch::Receiver<PlaybackClock>, Membership)> {
+        let receiver = {
+            let mut session = self.sessions.get_mut(session_id)?;
+            *session.members.entry(client_id.to_string()).or_default() += 1;
+            session.clock.subscribe()
+        };
+        Some((receiver, self.membership(session_id.to_string(), client_id)))
+    }
+
+    fn membership(self: &Arc<Self>, session_id: String, client_id: &str) -> Membership {
+        Membership {
+            sessions: self.clone(),
+            session_id,
+            client_id: client_id.to_string(),
+        }
+    }
+
+    async fn persist(&self, clock: &PlaybackClock) {
+        let Some(pool) = self.db_pool.as_ref() else {
+            return;
+        };
+        if let Err(e) = update(pool, clock).await {
+            tracing::warn!(
+                "Failed to save playback session {}: {}",
+                clock.session_id,
+                e
+            );
+        }
+    }
+}
+
+/// Advance a session's clock until nobody follows it
+async fn run_clock(sessions: Arc<PlaybackSessions>, session_id: String) {
+    let mut tick = interval(TICK);
+    let mut last_tick = Instant::now();
+    let mut last_persist = Instant::now();
+
+    loop {
+        tick.tick().await;
+        let now = Instant::now();
+        let elapsed_ms = now.duration_since(last_tick).as_millis() as u64;
+        last_tick = now;
+
+        let Some(session) = sessions.sessions.get(&session_id) else {
+            return;
+        };
+        if session.clock.receiver_count() == 0 {
+            let clock = session.clock.borrow().clone();
+            drop(session);
+            sessions.persist(&clock).await;
+            let retired = sessions
+                .sessions
+                .remove_if(&session_id, |_, session| {
+                    session.clock.receiver_count() == 0
+                })
+                .is_some();
+            if retired {
+                tracing::info!("Playback session {} has no followers", session_id);
+                return;
+            }
+            continue;
+        }
+
+        let mut finished = false;
+        let advanced = session.clock.send_if_modified(|clock| {
+            if !clock.state.is_playing {
+                return false;
+            }
+            if !clock.state.advance(elapsed_ms) {
+                // Reached the end: followers see the session stop
+                clock.revision += 1;
+                finished = true;
+            }
+            true
+        });
+        let clock = (finished || (advanced && last_persist.elapsed() >= PERSIST_INTERVAL))
+            .then(|| session.clock.borrow().clone());
+        drop(session);
+        if let Some(clock) = clock {
+            sessions.persist(&clock).await;
+            last_persist = Instant::now();
+        }
+    }
+}
+
+async fn insert(
+    pool: &PgPool,
+    clock: &PlaybackClock,
+    new: &NewSession<'_>,
+) -> Result<(), sqlx::Error> {
+    let layers: Vec<&str> = new.layers.iter().map(|layer| layer.as_str_name()).collect();
+    sqlx::query(
+        "INSERT INTO playback_sessions \
+         (id, client_id, start_ts_ms, end_ts_ms, viewport_min_lat, viewport_min_lon, \
+          viewport_max_lat, viewport_max_lon, layers, current_ts_ms, playback_speed, is_playing) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+    )
+    .bind(&clock.session_id)
+    .bind(&clock.controller)
+    .bind(clock.start_ts_ms as i64)
+    .bind(clock.state.end_ts_ms as i64)
+    .bind(new.viewport.min_lat)
+    .bind(new.viewport.min_lon)
+    .bind(new.viewport.max_lat)
+    .bind(new.viewport.max_lon)
+    .bind(serde_json::json!(layers))
+    .bind(clock.state.current_ts_ms as i64)
+    .bind(clock.state.speed as f64)
+    .bind(clock.state.is_playing)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn update(pool: &PgPool, clock: &PlaybackClock) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE playback_sessions \
+         SET current_ts_ms = $2, playback_speed = $3, is_playing = $4, client_id = $5, \
+             updated_at = NOW() \
+         WHERE id = $1",
+    )
+    .bind(&clock.session_id)
+    .bind(clock.state.current_ts_ms as i64)
+    .bind(clock.state.speed as f64)
+    .bind(clock.state.is_playing)
+    .bind(&clock.controller)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn load(pool: &PgPool, session_id: &str) -> Result<Option<PlaybackClock>, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT client_id, start_ts_ms, end_ts_ms, current_ts_ms, \
+                COALESCE(playback_speed, 1.0) AS playback_speed, \
+                COALESCE(is_playing, TRUE) AS is_playing \
+         FROM playback_sessions WHERE id = $1",
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|row| {
+        let mut clock = PlaybackClock::new(
+            session_id.to_string(),
+            row.get("client_id"),
+            row.get::<i64, _>("start_ts_ms") as u64,
+            row.get::<i64, _>("end_ts_ms") as u64,
+            row.get::<f64, _>("playback_speed") as f32,
+        );
+        clock.state.current_ts_ms = row.get::<i64, _>("current_ts_ms") as u64;
+        if !row.get::<bool, _>("is_playing") {
+            clock.state.pause();
+        }
+        clock
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control(
+        playing: Option<bool>,
+        seek_ts_ms: Option<u64>,
+        speed: Option<f32>,
+    ) -> PlaybackControl {
+        PlaybackControl {
+            session_id: "s".to_string(),
+            playing,
+            seek_ts_ms,
+            speed,
+        }
+    }
+
+    #[test]
+    fn test_controls_bump_revisions() {
+        let mut clock = PlaybackClock::new("s".to_string(), "c".to_string(), 1_000, 5_000, 1.0);
+
+        assert!(clock.apply(&control(Some(false), None, None)));
+        assert!(!clock.state.is_playing);
+        assert_eq!((clock.revision, clock.seek_revision), (1, 0));
+
+        // Seeking while paused stays paused and is clamped to the range
+        assert!(clock.apply(&control(None, Some(9_000), Some(4.0))));
+        assert_eq!(clock.state.current_ts_ms, 5_000);
+        assert_eq!(clock.state.speed, 4.0);
+        assert!(!clock.state.is_playing);
+        assert_eq!((clock.revision, clock.seek_revision), (2, 1));
+
+        assert!(!clock.apply(&control(Some(false), Some(5_000), None)));
+        assert_eq!(clock.revision, 2);
+
+        assert!(clock.apply(&control(Some(true), Some(2_000), None)));
+        assert!(clock.state.is_playing);
+        assert_eq!(clock.to_proto("default").current_ts_ms, 2_000);
+    }
+
+    #[tokio::test]
+    async fn test_followers_share_the_clock() {
+        let sessions = Arc::new(PlaybackSessions::new(None, Duration::from_secs(60)));
+        let viewport = BoundingBox::default();
+        let (first, first_membership) = sessions
+            .create(NewSession {
+                client_id: "client-1",
+                start_ts_ms: 0,
+                end_ts_ms: 60_000,
+                speed: 0.0,
+                viewport: &viewport,
+                layers: &[LayerType::Aircraft],
+            })
+            .await
+            .unwrap();
+        let session_id = first.borrow().session_id.clone();
+        let (mut second, second_membership) = sessions.join(&session_id, "client-2").await.unwrap();
+        assert!(matches!(
+            sessions.join("missing", "client-2").await,
+            Err(SessionError::NotFound(_))
+        ));
+
+        sessions
+            .control(
+                "client-1",
+                &PlaybackControl {
+                    session_id: session_id.clone(),
+                    playing: Some(false),
+                    seek_ts_ms: Some(30_000),
+                    speed: None,
+                },
+            )
+            .await
+            .unwrap();
+        second.changed().await.unwrap();
+        let clock = second.borrow_and_update().clone();
+        assert_eq!(clock.state.current_ts_ms, 30_000);
+        assert!(!clock.state.is_playing);
+        assert_eq!(first.borrow().state.current_ts_ms, 30_000);
+
+        drop(first);
+        drop(first_membership);
+        drop(second);
+        drop(second_membership);
+        tokio::time::sleep(TICK * 3).await;
+        assert_eq!(sessions.live_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_only_the_controller_steers_the_session() {
+        let sessions = Arc::new(PlaybackSessions::new(None, Duration::from_secs(60)));
+        let viewport = BoundingBox::default();
+        let (briefer, briefer_membership) = sessions
+            .create(NewSession {
+                client_id: "briefer",
+                start_ts_ms: 0,
+                end_ts_ms: 60_000,
+                speed: 1.0,
+                viewport: &viewport,
+                layers: &[LayerType::Aircraft],
+            })
+            .await
+            .unwrap();
+        let session_id = briefer.borrow().session_id.clone();
+        let (_viewer, _viewer_membership) = sessions.join(&session_id, "viewer").await.unwrap();
+        let pause = PlaybackControl {
+            session_id: session_id.clone(),
+            playing: Some(false),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            sessions.control("stranger", &pause).await,
+            Err(SessionError::NotMember(_))
+        ));
+        assert!(matches!(
+            sessions.control("viewer", &pause).await,
+            Err(SessionError::NotController(_))
+        ));
+        assert!(briefer.borrow().state.is_playing);
+
+        // Once the briefer stops following, a remaining viewer takes over
+        drop(briefer_membership);
+        sessions.control("viewer", &pause).await.unwrap();
+        assert!(!briefer.borrow().state.is_playing);
+        assert_eq!(briefer.borrow().controller, "viewer");
+    }
+}
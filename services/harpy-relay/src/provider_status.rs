@@ -0,0 +1,97 @@
+//! Provider Status Map
+//!
+//! Authoritative view of every provider's health. Seeded from the
+//! `provider:status:{id}` keys at startup and kept current from the provider
+//! status stream; clients get the full map when they connect and only
+//! transitions afterwards.
+
+use harpy_core::provider_status::{self, ProviderStatusRecord, PROVIDER_STATUS_KEY_PREFIX};
+use harpy_proto::harpy::v1::ProviderStatus;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+#[derive(Debug, Default)]
+pub struct ProviderStatusMap {
+    statuses: RwLock<HashMap<String, ProviderStatus>>,
+}
+
+impl ProviderStatusMap {
+    /// Store `status`, returning whether it changed what clients have seen
+    pub async fn apply(&self, status: ProviderStatus) -> bool {
+        let mut statuses = self.statuses.write().await;
+        let changed = provider_status::is_transition(statuses.get(&status.provider_id), &status);
+        statuses.insert(status.provider_id.clone(), status);
+        changed
+    }
+
+    /// All known statuses ordered by provider id
+    pub async fn snapshot(&self) -> Vec<ProviderStatus> {
+        let mut statuses: Vec<ProviderStatus> =
+            self.statuses.read().await.values().cloned().collect();
+        statuses.sort_by(|a, b| a.provider_id.cmp(&b.provider_id));
+        statuses
+    }
+
+    /// Load the statuses ingest last wrote to Redis
+    pub async fn seed(&self, conn: &mut redis::aio::MultiplexedConnection) -> anyhow::Result<()> {
+        let mut keys: Vec<String> = Vec::new();
+        {
+            let mut iter: redis::AsyncIter<String> = redis::cmd("SCAN")
+                .cursor_arg(0)
+                .arg("MATCH")
+                .arg(format!("{}*", PROVIDER_STATUS_KEY_PREFIX))
+                .clone()
+                .iter_async(conn)
+                .await?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+        }
+
+        for key in keys {
+            let value: Option<String> = redis::cmd("GET").arg(&key).query_async(conn).await?;
+            match value.map(|value| serde_json::from_str::<ProviderStatusRecord>(&value)) {
+                Some(Ok(record)) => {
+                    self.apply(record.to_proto()).await;
+                }
+                Some(Err(e)) => tracing::debug!("Skipping provider status {}: {}", key, e),
+                None => {}
+            }
+        }
+
+        tracing::info!(
+            "Seeded {} provider statuses from Redis",
+            self.statuses.read().await.len()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harpy_proto::harpy::v1::CircuitState;
+
+    fn status(provider_id: &str, circuit_state: CircuitState, ts_ms: u64) -> ProviderStatus {
+        ProviderStatus {
+            provider_id: provider_id.to_string(),
+            circuit_state: circuit_state as i32,
+            last_success_ts_ms: ts_ms,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_reports_only_transitions() {
+        let map = ProviderStatusMap::default();
+        assert!(map.apply(status("tle", CircuitState::Closed, 1)).await);
+        assert!(!map.apply(status("tle", CircuitState::Closed, 2)).await);
+        assert!(map.apply(status("tle", CircuitState::Open, 2)).await);
+        assert!(map.apply(status("adsb", CircuitState::Closed, 3)).await);
+
+        let snapshot = map.snapshot().await;
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].provider_id, "adsb");
+        assert_eq!(snapshot[1].circuit_state(), CircuitState::Open);
+    }
+}
@@ -1,252 +1,154 @@
-//! Redis Pub/Sub Subscriber
+//! Redis Stream Subscriber
 //!
-//! Subscribes to Redis channels and forwards messages to the subscription manager
-//! for fanout to WebSocket clients.
+//! Consumes the tracks, alerts, links and provider status Redis streams and
+//! forwards messages to the subscription manager for fanout to WebSocket clients.
 
+use crate::alerts::{proto_severity, proto_status, AlertScope};
+use crate::provider_status::ProviderStatusMap;
 use crate::subscription::SubscriptionManager;
-use futures::StreamExt;
-use harpy_proto::harpy::v1::{Envelope, ProviderStatus, TrackDelta};
-use serde::Deserialize;
+use harpy_core::provider_status::{self, PROVIDER_STATUS_STREAM};
+use harpy_core::redis_contract::{
+    self, AlertMessage, LinkMessage, NodeKind, ALERTS_STREAM, LINKS_STREAM,
+};
+use harpy_core::streams::{self, ConsumerConfig, StreamStats, TRACKS_STREAM};
+use harpy_core::track_wire;
+use harpy_proto::harpy::v1::{AlertUpsert, Envelope, LinkUpsert, NodeRef, NodeType};
 use std::sync::Arc;
 
-/// JSON representation of TrackDelta for deserialization from Redis
-#[derive(Debug, Deserialize)]
-struct TrackDeltaJson {
-    id: String,
-    #[serde(rename = "kind")]
-    kind: i32,
-    position: Option<PositionJson>,
-    heading: f64,
-    speed: f64,
-    #[serde(rename = "ts_ms")]
-    ts_ms: u64,
-    #[serde(rename = "provider_id")]
-    provider_id: String,
-    #[serde(default)]
-    meta: std::collections::HashMap<String, String>,
+/// Consumer counters for each stream the relay reads
+#[derive(Debug)]
+pub struct StreamMetrics {
+    pub tracks: StreamStats,
+    pub alerts: StreamStats,
+    pub links: StreamStats,
+    pub providers: StreamStats,
 }
 
-#[derive(Debug, Deserialize)]
-struct PositionJson {
-    lat: f64,
-    lon: f64,
-    alt: f64,
+impl Default for StreamMetrics {
+    fn default() -> Self {
+        Self {
+            tracks: StreamStats::new(TRACKS_STREAM),
+            alerts: StreamStats::new(ALERTS_STREAM),
+            links: StreamStats::new(LINKS_STREAM),
+            providers: StreamStats::new(PROVIDER_STATUS_STREAM),
+        }
+    }
 }
 
-/// JSON representation of ProviderStatus
-#[derive(Debug, Deserialize)]
-struct ProviderStatusJson {
-    #[serde(rename = "provider_id")]
-    provider_id: String,
-    #[serde(rename = "circuit_state")]
-    circuit_state: String,
-    freshness: String,
-    #[serde(rename = "last_update_ts_ms")]
-    last_update_ts_ms: u64,
-    #[serde(rename = "last_success")]
-    last_success: bool,
+impl StreamMetrics {
+    /// Prometheus text for all relay stream consumers
+    pub fn render(&self) -> String {
+        StreamStats::render_prometheus(
+            &[&self.tracks, &self.alerts, &self.links, &self.providers],
+            "harpy_relay",
+        )
+    }
 }
 
-/// Start the Redis subscriber loop
+/// Start the Redis stream consumers
+///
+/// Every relay replica must deliver every message, so each replica needs its
+/// own consumer group: `RELAY_STREAM_GROUP`, or one named after the consumer.
 pub async fn run_subscriber(
     redis_url: String,
     subscription_manager: Arc<SubscriptionManager>,
+    metrics: Arc<StreamMetrics>,
+    provider_statuses: Arc<ProviderStatusMap>,
 ) -> anyhow::Result<()> {
     tracing::info!("Starting Redis subscriber on {}", redis_url);
 
-    // Create a regular async connection for pub/sub (not ConnectionManager)
     let client = redis::Client::open(redis_url)?;
 
-    // Clone for the provider status polling task
-    let manager_for_polling = subscription_manager.clone();
-    let client_for_polling = client.clone();
-
-    // Spawn provider status polling task
-    tokio::spawn(async move {
-        poll_provider_status(client_for_polling, manager_for_polling).await;
-    });
-
-    // Set up pub/sub connection for tracks and alerts
-    let pubsub_conn = client.get_async_connection().await?;
-    let mut pubsub = pubsub_conn.into_pubsub();
-
-    // Subscribe to channels
-    pubsub.subscribe("tracks:updates").await?;
-    pubsub.subscribe("alerts:updates").await?;
-    pubsub.subscribe("links:updates").await?;
-
-    tracing::info!("Subscribed to Redis channels: tracks:updates, alerts:updates, links:updates");
-
-    // Get message stream
-    let mut msg_stream = pubsub.on_message();
-
-    loop {
-        match msg_stream.next().await {
-            Some(msg) => {
-                let channel: String = match msg.get_channel::<String>() {
-                    Ok(c) => c,
-                    Err(e) => {
-                        tracing::warn!("Failed to get channel: {}", e);
-                        continue;
-                    }
-                };
-                let payload: String = match msg.get_payload() {
-                    Ok(p) => p,
-                    Err(e) => {
-                        tracing::warn!("Failed to get payload: {}", e);
-                        continue;
-                    }
-                };
-
-                tracing::debug!("Received message on channel: {}", channel);
-
-                match channel.as_str() {
-                    "tracks:updates" => {
-                        handle_track_batch(payload, &subscription_manager).await;
-                    }
-                    "alerts:updates" => {
-                        handle_alert(payload, &subscription_manager).await;
-                    }
-                    "links:updates" => {
-                        handle_link(payload, &subscription_manager).await;
-                    }
-                    _ => {
-                        tracing::debug!("Unknown channel: {}", channel);
-                    }
-                }
-            }
-            None => {
-                tracing::warn!("Redis pub/sub stream closed, reconnecting...");
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    match client.get_multiplexed_async_connection().await {
+        Ok(mut conn) => {
+            if let Err(e) = provider_statuses.seed(&mut conn).await {
+                tracing::warn!("Failed to seed provider statuses: {}", e);
             }
         }
+        Err(e) => tracing::warn!("Failed to seed provider statuses: {}", e),
     }
-}
 
-/// Poll provider status from Redis and broadcast to all clients
-async fn poll_provider_status(
-    client: redis::Client,
-    subscription_manager: Arc<SubscriptionManager>,
-) {
-    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(5));
+    let group = relay_group(
+        std::env::var("RELAY_STREAM_GROUP").ok(),
+        streams::consumer_name(),
+    );
+    tracing::info!("Reading Redis streams as consumer group {}", group);
+    let manager = &subscription_manager;
 
-    loop {
-        interval.tick().await;
+    tokio::join!(
+        streams::run_consumer(
+            client.clone(),
+            ConsumerConfig::new(TRACKS_STREAM, &group),
+            &metrics.tracks,
+            |payload| handle_track_batch(payload, manager),
+        ),
+        streams::run_consumer(
+            client.clone(),
+            ConsumerConfig::new(ALERTS_STREAM, &group),
+            &metrics.alerts,
+            |payload| handle_alert(payload, manager),
+        ),
+        streams::run_consumer(
+            client.clone(),
+            ConsumerConfig::new(LINKS_STREAM, &group),
+            &metrics.links,
+            |payload| handle_link(payload, manager),
+        ),
+        streams::run_consumer(
+            client,
+            ConsumerConfig::new(PROVIDER_STATUS_STREAM, &group),
+            &metrics.providers,
+            |payload| handle_provider_status(payload, manager, &provider_statuses),
+        ),
+    );
 
-        // Get a new connection each time
-        let mut conn = match client.get_async_connection().await {
-            Ok(conn) => conn,
-            Err(e) => {
-                tracing::warn!("Failed to get Redis connection: {}", e);
-                continue;
-            }
-        };
-
-        // Fetch all provider status keys
-        let pattern = "provider:status:*";
+    Ok(())
+}
 
-        match redis::cmd("KEYS")
-            .arg(pattern)
-            .query_async::<_, Vec<String>>(&mut conn)
-            .await
-        {
-            Ok(keys) => {
-                for key in keys {
-                    match redis::cmd("GET")
-                        .arg(&key)
-                        .query_async::<_, String>(&mut conn)
-                        .await
-                    {
-                        Ok(status_json) => {
-                            match serde_json::from_str::<ProviderStatusJson>(&status_json) {
-                                Ok(status) => {
-                                    let provider_status = convert_to_proto_status(status);
-                                    let envelope = Envelope {
-                                        schema_version: "1.0.0".to_string(),
-                                        server_ts_ms: now_ms(),
-                                        payload: Some(
-                                            harpy_proto::harpy::v1::envelope::Payload::ProviderStatus(
-                                                provider_status,
-                                            ),
-                                        ),
-                                    };
-                                    subscription_manager.broadcast_to_all(envelope).await;
-                                }
-                                Err(e) => {
-                                    tracing::debug!("Failed to parse provider status: {}", e);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            tracing::debug!("Failed to get provider status for {}: {}", key, e);
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                tracing::debug!("Failed to fetch provider status keys: {}", e);
-            }
-        }
+/// Consumer group for this replica
+///
+/// Replicas sharing a group would split the streams between them, so without
+/// an explicit group each replica gets one derived from its consumer name.
+fn relay_group(explicit: Option<String>, consumer: Option<String>) -> String {
+    match (explicit.filter(|group| !group.trim().is_empty()), consumer) {
+        (Some(group), _) => group,
+        (None, Some(consumer)) => format!("harpy-relay-{}", consumer),
+        (None, None) => "harpy-relay".to_string(),
     }
 }
 
 /// Handle track batch from Redis
-async fn handle_track_batch(payload: String, subscription_manager: &Arc<SubscriptionManager>) {
-    // Parse the JSON array of TrackDelta
-    match serde_json::from_str::<Vec<TrackDeltaJson>>(&payload) {
-        Ok(tracks_json) => {
-            // Convert JSON tracks to protobuf tracks
-            let tracks: Vec<TrackDelta> = tracks_json
-                .into_iter()
-                .map(convert_to_proto_track)
-                .collect();
+async fn handle_track_batch(payload: Vec<u8>, subscription_manager: &Arc<SubscriptionManager>) {
+    let received_ms = now_ms();
+    match track_wire::decode_track_batch(&payload) {
+        Ok(tracks) => {
             tracing::debug!("Forwarding {} tracks to subscription manager", tracks.len());
-            subscription_manager.broadcast_tracks(tracks).await;
+            subscription_manager
+                .broadcast_tracks(tracks, received_ms)
+                .await;
         }
         Err(e) => {
-            tracing::warn!("Failed to parse track batch from Redis: {}", e);
+            tracing::warn!("Failed to decode track batch from Redis: {}", e);
         }
     }
 }
 
 /// Handle alert from Redis
-async fn handle_alert(payload: String, subscription_manager: &Arc<SubscriptionManager>) {
-    // Parse the alert JSON
-    match serde_json::from_str::<serde_json::Value>(&payload) {
-        Ok(alert_json) => {
-            let alert = harpy_proto::harpy::v1::AlertUpsert {
-                id: alert_json
-                    .get("id")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("unknown")
-                    .to_string(),
-                severity: 1, // Info as default
-                title: alert_json
-                    .get("title")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Alert")
-                    .to_string(),
-                description: alert_json
-                    .get("description")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string(),
-                ts_ms: alert_json
-                    .get("ts_ms")
-                    .and_then(|v| v.as_u64())
-                    .unwrap_or_else(now_ms),
-                evidence_link_ids: vec![],
-                status: 1, // Active
-                meta: Default::default(),
-            };
+async fn handle_alert(payload: Vec<u8>, subscription_manager: &Arc<SubscriptionManager>) {
+    match redis_contract::decode::<AlertMessage>(&String::from_utf8_lossy(&payload)) {
+        Ok(message) => {
+            let scope = AlertScope::of_alert(&message);
             let envelope = Envelope {
                 schema_version: "1.0.0".to_string(),
                 server_ts_ms: now_ms(),
+                seq: 0,
                 payload: Some(harpy_proto::harpy::v1::envelope::Payload::AlertUpsert(
-                    alert,
+                    convert_to_proto_alert(message),
                 )),
             };
-            subscription_manager.broadcast_to_all(envelope).await;
+            subscription_manager
+                .broadcast_scoped(envelope, &scope)
+                .await;
         }
         Err(e) => {
             tracing::warn!("Failed to parse alert from Redis: {}", e);
@@ -254,110 +156,62 @@ async fn handle_alert(payload: String, subscription_manager: &Arc<SubscriptionMa
     }
 }
 
-/// Convert JSON track to protobuf TrackDelta
-fn convert_to_proto_track(json: TrackDeltaJson) -> TrackDelta {
-    use harpy_proto::harpy::v1::Position;
-
-    let position = json.position.map(|p| Position {
-        lat: p.lat,
-        lon: p.lon,
-        alt: p.alt,
-    });
-
-    TrackDelta {
-        id: json.id,
-        kind: json.kind,
-        position,
-        heading: json.heading,
-        speed: json.speed,
-        ts_ms: json.ts_ms,
-        provider_id: json.provider_id,
-        meta: json.meta,
+/// Convert a contract alert to protobuf AlertUpsert
+fn convert_to_proto_alert(message: AlertMessage) -> AlertUpsert {
+    AlertUpsert {
+        id: message.id,
+        severity: proto_severity(message.severity) as i32,
+        title: message.title,
+        description: message.description,
+        ts_ms: message.ts_ms,
+        evidence_link_ids: message.evidence_link_ids,
+        status: proto_status(message.status) as i32,
+        meta: redis_contract::flatten_meta(&message.meta),
     }
 }
 
-/// Convert JSON provider status to protobuf ProviderStatus
-fn convert_to_proto_status(json: ProviderStatusJson) -> ProviderStatus {
-    use harpy_proto::harpy::v1::{CircuitState, Freshness};
-
-    let circuit_state = match json.circuit_state.as_str() {
-        "CIRCUIT_STATE_CLOSED" => CircuitState::Closed,
-        "CIRCUIT_STATE_OPEN" => CircuitState::Open,
-        "CIRCUIT_STATE_HALF_OPEN" => CircuitState::HalfOpen,
-        _ => CircuitState::Unspecified,
-    };
-
-    let freshness = match json.freshness.as_str() {
-        "FRESHNESS_FRESH" => Freshness::Fresh,
-        "FRESHNESS_AGING" => Freshness::Aging,
-        "FRESHNESS_STALE" => Freshness::Stale,
-        "FRESHNESS_CRITICAL" => Freshness::Critical,
-        _ => Freshness::Unspecified,
-    };
-
-    ProviderStatus {
-        provider_id: json.provider_id,
-        circuit_state: circuit_state as i32,
-        freshness: freshness as i32,
-        last_success_ts_ms: json.last_update_ts_ms,
-        failure_count: if json.last_success { 0 } else { 1 },
-        error_message: None,
-        meta: Default::default(),
+/// Handle a provider status transition from Redis
+async fn handle_provider_status(
+    payload: Vec<u8>,
+    subscription_manager: &Arc<SubscriptionManager>,
+    provider_statuses: &ProviderStatusMap,
+) {
+    match provider_status::decode_event(&payload) {
+        Ok(status) => {
+            if provider_statuses.apply(status.clone()).await {
+                let envelope = Envelope {
+                    schema_version: "1.0.0".to_string(),
+                    server_ts_ms: now_ms(),
+                    seq: 0,
+                    payload: Some(harpy_proto::harpy::v1::envelope::Payload::ProviderStatus(
+                        status,
+                    )),
+                };
+                subscription_manager.broadcast_to_all(envelope).await;
+            }
+        }
+        Err(e) => {
+            tracing::warn!("Failed to decode provider status from Redis: {}", e);
+        }
     }
 }
 
 /// Handle link from Redis
-async fn handle_link(payload: String, subscription_manager: &Arc<SubscriptionManager>) {
-    // Parse the link JSON
-    match serde_json::from_str::<serde_json::Value>(&payload) {
-        Ok(link_json) => {
-            let link = harpy_proto::harpy::v1::LinkUpsert {
-                id: link_json
-                    .get("id")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("unknown")
-                    .to_string(),
-                from: Some(harpy_proto::harpy::v1::NodeRef {
-                    node_type: harpy_proto::harpy::v1::NodeType::Track as i32,
-                    node_id: link_json
-                        .get("from_id")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("")
-                        .to_string(),
-                }),
-                rel: link_json
-                    .get("rel")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string(),
-                to: Some(harpy_proto::harpy::v1::NodeRef {
-                    node_type: harpy_proto::harpy::v1::NodeType::Track as i32,
-                    node_id: link_json
-                        .get("to_id")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("")
-                        .to_string(),
-                }),
-                ts_ms: link_json
-                    .get("ts_ms")
-                    .and_then(|v| v.as_u64())
-                    .unwrap_or_else(now_ms),
-                meta: link_json
-                    .get("meta")
-                    .and_then(|v| v.as_object())
-                    .map(|o| {
-                        o.iter()
-                            .filter_map(|(k, v)| v.as_str().map(|s| (k.clone(), s.to_string())))
-                            .collect()
-                    })
-                    .unwrap_or_default(),
-            };
+async fn handle_link(payload: Vec<u8>, subscription_manager: &Arc<SubscriptionManager>) {
+    match redis_contract::decode::<LinkMessage>(&String::from_utf8_lossy(&payload)) {
+        Ok(message) => {
+            let scope = AlertScope::of_link(&message);
             let envelope = Envelope {
                 schema_version: "1.0.0".to_string(),
                 server_ts_ms: now_ms(),
-                payload: Some(harpy_proto::harpy::v1::envelope::Payload::LinkUpsert(link)),
+                seq: 0,
+                payload: Some(harpy_proto::harpy::v1::envelope::Payload::LinkUpsert(
+                    convert_to_proto_link(message),
+                )),
             };
-            subscription_manager.broadcast_to_all(envelope).await;
+            subscription_manager
+                .broadcast_scoped(envelope, &scope)
+                .await;
         }
         Err(e) => {
             tracing::warn!("Failed to parse link from Redis: {}", e);
@@ -365,6 +219,33 @@ async fn handle_link(payload: String, subscription_manager: &Arc<SubscriptionMan
     }
 }
 
+/// Convert a contract link to protobuf LinkUpsert
+fn convert_to_proto_link(message: LinkMessage) -> LinkUpsert {
+    LinkUpsert {
+        id: message.id,
+        from: Some(NodeRef {
+            node_type: proto_node_type(message.from_type) as i32,
+            node_id: message.from_id,
+        }),
+        rel: message.rel,
+        to: Some(NodeRef {
+            node_type: proto_node_type(message.to_type) as i32,
+            node_id: message.to_id,
+        }),
+        ts_ms: message.ts_ms,
+        meta: redis_contract::flatten_meta(&message.meta),
+    }
+}
+
+fn proto_node_type(kind: NodeKind) -> NodeType {
+    match kind {
+        NodeKind::Track => NodeType::Track,
+        NodeKind::Sensor => NodeType::Sensor,
+        NodeKind::Detection => NodeType::Detection,
+        NodeKind::Alert => NodeType::Alert,
+    }
+}
+
 /// Get current timestamp in milliseconds
 fn now_ms() -> u64 {
     std::time::SystemTime::now()
@@ -372,3 +253,80 @@ fn now_ms() -> u64 {
         .unwrap_or_default()
         .as_millis() as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harpy_proto::harpy::v1::{AlertSeverity, AlertStatus};
+    use serde_json::json;
+
+    #[test]
+    fn test_alert_conversion_preserves_fusion_fields() {
+        let payload = json!({
+            "version": 1,
+            "id": "alert-1",
+            "severity": "CRITICAL",
+            "title": "Proximity Alert",
+            "description": "Tracks a and b are 400m apart",
+            "ts_ms": 1_700_000_000_000u64,
+            "status": "ACKNOWLEDGED",
+            "evidence_link_ids": ["link-1", "link-2"],
+            "meta": {"rule": "proximity", "distance_m": 400.5, "providers": ["a", "b"]}
+        })
+        .to_string();
+
+        let alert =
+            convert_to_proto_alert(redis_contract::decode::<AlertMessage>(&payload).unwrap());
+        assert_eq!(alert.severity, AlertSeverity::Critical as i32);
+        assert_eq!(alert.status, AlertStatus::Acknowledged as i32);
+        assert_eq!(alert.evidence_link_ids, vec!["link-1", "link-2"]);
+        assert_eq!(alert.meta.get("rule").unwrap(), "proximity");
+        assert_eq!(alert.meta.get("distance_m").unwrap(), "400.5");
+        assert_eq!(alert.meta.get("providers").unwrap(), "[\"a\",\"b\"]");
+    }
+
+    #[test]
+    fn test_link_conversion_maps_node_types() {
+        let payload = json!({
+            "id": "link-1",
+            "from_type": "Alert",
+            "from_id": "alert-1",
+            "rel": "is_evidenced_by",
+            "to_type": "Track",
+            "to_id": "track-1",
+            "ts_ms": 42,
+            "meta": {"convergence_link": "link-0"}
+        })
+        .to_string();
+
+        let link = convert_to_proto_link(redis_contract::decode::<LinkMessage>(&payload).unwrap());
+        assert_eq!(link.from.unwrap().node_type, NodeType::Alert as i32);
+        assert_eq!(link.to.unwrap().node_type, NodeType::Track as i32);
+        assert_eq!(link.meta.get("convergence_link").unwrap(), "link-0");
+    }
+
+    #[test]
+    fn test_unknown_severity_rejected() {
+        let payload = json!({
+            "id": "alert-1",
+            "severity": "APOCALYPTIC",
+            "title": "t",
+            "ts_ms": 0,
+            "status": "ACTIVE"
+        })
+        .to_string();
+        assert!(redis_contract::decode::<AlertMessage>(&payload).is_err());
+    }
+
+    #[test]
+    fn test_relay_group_defaults_per_replica() {
+        let named = |v: &str| Some(v.to_string());
+        assert_eq!(relay_group(named("relay-a"), named("host-1")), "relay-a");
+        assert_eq!(relay_group(None, named("host-1")), "harpy-relay-host-1");
+        assert_eq!(
+            relay_group(named(" "), named("host-2")),
+            "harpy-relay-host-2"
+        );
+        assert_eq!(relay_group(None, None), "harpy-relay");
+    }
+}
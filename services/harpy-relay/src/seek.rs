@@ -166,7 +166,7 @@ pub async fn seek_handler(
     Json(Ok::<_, SeekError>(response)).into_response()
 }
 
-fn parse_layers(layers: Option<&str>) -> Vec<String> {
+pub(crate) fn parse_layers(layers: Option<&str>) -> Vec<String> {
     layers
         .map(|l| {
             l.split(',')
@@ -177,7 +177,7 @@ fn parse_layers(layers: Option<&str>) -> Vec<String> {
         .unwrap_or_default()
 }
 
-async fn find_snapshot(
+pub(crate) async fn find_snapshot(
     pool: &sqlx::PgPool,
     start_ts_ms: u64,
 ) -> Result<Option<SnapshotRef>, SeekError> {
@@ -270,6 +270,7 @@ async fn count_deltas(
             .push_bind(min_lon)
             .push(" AND td.lon <= ")
             .push_bind(max_lon);
+        crate::playback::push_h3_cover(&mut qb, min_lat, min_lon, max_lat, max_lon);
     }
 
     if !layers.is_empty() {
@@ -0,0 +1,285 @@
+//! Session Resume
+//!
+//! Every WebSocket connection belongs to a session that numbers outgoing
+//! envelopes with `seq` and keeps recent alerts, links, provider statuses and
+//! snapshot metadata in a replay buffer. When a socket drops, the session is
+//! parked for a grace period with its subscriptions still registered. A client
+//! reconnecting with the session's resume token gets its client id and
+//! subscriptions back, everything queued while it was away, and a replay of
+//! buffered envelopes sent after the last `seq` it saw.
+
+use crate::backpressure::{
+    BackpressureChannel, BackpressureLimits, BackpressureMonitor, BackpressureReceiver,
+};
+use crate::subscription::{ClientId, SubscriptionId};
+use dashmap::DashMap;
+use harpy_proto::harpy::v1::{envelope::Payload, Envelope};
+use prost::Message;
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Field number of `Envelope.seq`
+const SEQ_FIELD: u32 = 3;
+
+/// State of one client across reconnects
+pub struct Session {
+    pub client_id: ClientId,
+    pub resume_token: String,
+    pub tx: BackpressureChannel,
+    pub rx: BackpressureReceiver,
+    /// Named subscriptions currently held by the session
+    pub subscription_ids: HashSet<SubscriptionId>,
+    last_seq: u64,
+    replay: VecDeque<(u64, Arc<Envelope>)>,
+    replay_capacity: usize,
+    /// Highest seq dropped from the replay buffer
+    evicted_seq: u64,
+}
+
+impl Session {
+    pub fn new(
+        replay_capacity: usize,
+        limits: BackpressureLimits,
+        monitor: Arc<BackpressureMonitor>,
+    ) -> Self {
+        let (tx, rx) = BackpressureChannel::with_limits(limits, monitor);
+        Self {
+            client_id: format!("client-{}", Uuid::new_v4().simple()),
+            resume_token: Uuid::new_v4().simple().to_string(),
+            tx,
+            rx,
+            subscription_ids: HashSet::new(),
+            last_seq: 0,
+            replay: VecDeque::new(),
+            replay_capacity,
+            evicted_seq: 0,
+        }
+    }
+
+    /// Encode `envelope` with the next seq, keeping replayable payloads in the buffer
+    pub fn sequence(&mut self, envelope: &Arc<Envelope>) -> Vec<u8> {
+        self.last_seq += 1;
+        if is_replayable(envelope) && self.replay_capacity > 0 {
+            if self.replay.len() == self.replay_capacity {
+                if let Some((seq, _)) = self.replay.pop_front() {
+                    self.evicted_seq = seq;
+                }
+            }
+            self.replay.push_back((self.last_seq, envelope.clone()));
+        }
+        encode_with_seq(envelope, self.last_seq)
+    }
+
+    /// Buffered envelopes sent after `last_seq`, and whether none were evicted
+    pub fn replay_after(&self, last_seq: u64) -> (Vec<Vec<u8>>, bool) {
+        let frames = self
+            .replay
+            .iter()
+            .filter(|(seq, _)| *seq > last_seq)
+            .map(|(seq, envelope)| encode_with_seq(envelope, *seq))
+            .collect();
+        (frames, last_seq >= self.evicted_seq)
+    }
+}
+
+/// Alerts, links, provider statuses, snapshot metadata and playback session
+/// states are replayed; track batches are superseded by the next update and
+/// acks describe the old socket
+fn is_replayable(envelope: &Envelope) -> bool {
+    matches!(
+        envelope.payload,
+        Some(Payload::AlertUpsert(_))
+            | Some(Payload::LinkUpsert(_))
+            | Some(Payload::ProviderStatus(_))
+            | Some(Payload::SnapshotMeta(_))
+            | Some(Payload::PlaybackSessionState(_))
+    )
+}
+
+/// Encode an envelope with `seq` set
+///
+/// Fanout envelopes are shared between clients, so instead of cloning them the
+/// field is appended to the encoding; protobuf decoders keep the last value of
+/// a repeated scalar field.
+fn encode_with_seq(envelope: &Envelope, seq: u64) -> Vec<u8> {
+    let mut buf = envelope.encode_to_vec();
+    prost::encoding::uint64::encode(SEQ_FIELD, &seq, &mut buf);
+    buf
+}
+
+/// Sessions whose socket dropped, waiting to be resumed
+pub struct SessionStore {
+    parked: DashMap<String, (u64, Session)>,
+    generation: AtomicU64,
+    grace: Duration,
+    replay_capacity: usize,
+    limits: BackpressureLimits,
+    monitor: Arc<BackpressureMonitor>,
+}
+
+impl SessionStore {
+    pub fn new(
+        grace: Duration,
+        replay_capacity: usize,
+        limits: BackpressureLimits,
+        monitor: Arc<BackpressureMonitor>,
+    ) -> Self {
+        Self {
+            parked: DashMap::new(),
+            generation: AtomicU64::new(0),
+            grace,
+            replay_capacity,
+            limits,
+            monitor,
+        }
+    }
+
+    /// Grace period from `RELAY_RESUME_GRACE_SECS`, buffer size from
+    /// `RELAY_REPLAY_BUFFER` and queue limits from `BackpressureLimits::from_env`
+    pub fn from_env(monitor: Arc<BackpressureMonitor>) -> Self {
+        let grace_secs = std::env::var("RELAY_RESUME_GRACE_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(30);
+        let replay_capacity = std::env::var("RELAY_REPLAY_BUFFER")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(256);
+        Self::new(
+            Duration::from_secs(grace_secs),
+            replay_capacity,
+            BackpressureLimits::from_env(),
+            monitor,
+        )
+    }
+
+    pub fn new_session(&self) -> Session {
+        Session::new(self.replay_capacity, self.limits, self.monitor.clone())
+    }
+
+    /// How long a parked session waits for its client
+    pub fn grace(&self) -> Duration {
+        self.grace
+    }
+
+    /// Queue limits of new sessions
+    pub fn limits(&self) -> BackpressureLimits {
+        self.limits
+    }
+
+    /// Take a parked session back for a reconnecting client
+    pub fn resume(&self, resume_token: &str) -> Option<Session> {
+        self.parked
+            .remove(resume_token)
+            .map(|(_, (_, session))| session)
+    }
+
+    /// Park a session, returning the generation to pass to `expire`
+    pub fn park(&self, session: Session) -> u64 {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed);
+        self.parked
+            .insert(session.resume_token.clone(), (generation, session));
+        generation
+    }
+
+    /// Remove a session still parked since `generation`
+    pub fn expire(&self, resume_token: &str, generation: u64) -> Option<Session> {
+        self.parked
+            .remove_if(resume_token, |_, (parked, _)| *parked == generation)
+            .map(|(_, (_, session))| session)
+    }
+
+    pub fn parked_count(&self) -> usize {
+        self.parked.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harpy_proto::harpy::v1::{AlertUpsert, TrackDeltaBatch};
+
+    fn envelope(payload: Payload) -> Arc<Envelope> {
+        Arc::new(Envelope {
+            schema_version: "1.0.0".to_string(),
+            server_ts_ms: 0,
+            seq: 0,
+            payload: Some(payload),
+        })
+    }
+
+    fn session(replay_capacity: usize) -> Session {
+        Session::new(
+            replay_capacity,
+            BackpressureLimits::default(),
+            Arc::default(),
+        )
+    }
+
+    fn alert(id: &str) -> Arc<Envelope> {
+        envelope(Payload::AlertUpsert(AlertUpsert {
+            id: id.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_sequence_numbers_frames() {
+        let mut session = session(4);
+        let first = session.sequence(&envelope(Payload::TrackDeltaBatch(
+            TrackDeltaBatch::default(),
+        )));
+        let second = session.sequence(&alert("a"));
+
+        assert_eq!(Envelope::decode(&*first).unwrap().seq, 1);
+        let decoded = Envelope::decode(&*second).unwrap();
+        assert_eq!(decoded.seq, 2);
+        assert!(matches!(decoded.payload, Some(Payload::AlertUpsert(_))));
+    }
+
+    #[tokio::test]
+    async fn test_replay_after_skips_tracks_and_reports_eviction() {
+        let mut session = session(2);
+        session.sequence(&alert("a")); // seq 1
+        session.sequence(&envelope(Payload::TrackDeltaBatch(
+            TrackDeltaBatch::default(),
+        ))); // seq 2
+        session.sequence(&alert("b")); // seq 3
+
+        let (frames, complete) = session.replay_after(1);
+        assert!(complete);
+        let seqs: Vec<u64> = frames
+            .iter()
+            .map(|frame| Envelope::decode(&**frame).unwrap().seq)
+            .collect();
+        assert_eq!(seqs, vec![3]);
+
+        session.sequence(&alert("c")); // seq 4 evicts seq 1
+        assert!(!session.replay_after(0).1);
+        assert_eq!(session.replay_after(1).0.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expire_ignores_resumed_and_reparked_sessions() {
+        let store = SessionStore::new(
+            Duration::from_secs(30),
+            8,
+            BackpressureLimits::default(),
+            Arc::default(),
+        );
+        let session = store.new_session();
+        let token = session.resume_token.clone();
+
+        let generation = store.park(session);
+        let session = store.resume(&token).unwrap();
+        assert!(store.resume(&token).is_none());
+
+        let reparked = store.park(session);
+        assert!(store.expire(&token, generation).is_none());
+        assert!(store.expire(&token, reparked).is_some());
+        assert_eq!(store.parked_count(), 0);
+    }
+}
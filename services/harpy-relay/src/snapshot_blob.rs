@@ -0,0 +1,121 @@
+//! Snapshot Blob Client
+//!
+//! Reads the track states stored in a snapshot blob through harpy-ingest's
+//! `GET /snapshots/:id?format=ndjson`, so the relay needs no access to
+//! snapshot storage itself.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::state_at_time::Sample;
+
+/// Time allowed for fetching one snapshot's track states
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One line of the ndjson snapshot stream
+#[derive(Debug, Deserialize)]
+struct TrackLine {
+    id: String,
+    kind: String,
+    lat: f64,
+    lon: f64,
+    #[serde(default)]
+    alt: f64,
+    #[serde(default)]
+    heading: f64,
+    #[serde(default)]
+    speed: f64,
+    ts_ms: u64,
+    #[serde(default)]
+    provider_id: String,
+    #[serde(default)]
+    meta: HashMap<String, String>,
+}
+
+/// Client for harpy-ingest's snapshot download API
+#[derive(Debug, Clone)]
+pub(crate) struct SnapshotBlobs {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl SnapshotBlobs {
+    /// Configure from `RELAY_SNAPSHOT_API_URL` (default `http://harpy-ingest:8081`)
+    pub(crate) fn from_env() -> Self {
+        let base_url = std::env::var("RELAY_SNAPSHOT_API_URL")
+            .unwrap_or_else(|_| "http://harpy-ingest:8081".to_string());
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Track states stored in the snapshot's blob
+    pub(crate) async fn tracks(&self, snapshot_id: &str) -> anyhow::Result<Vec<Sample>> {
+        let body = self
+            .client
+            .get(format!("{}/snapshots/{}", self.base_url, snapshot_id))
+            .query(&[("format", "ndjson")])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(parse_track_line)
+            .collect()
+    }
+}
+
+/// Parse one ndjson track state into a sample
+fn parse_track_line(line: &str) -> anyhow::Result<Sample> {
+    let track: TrackLine = serde_json::from_str(line)?;
+    Ok(Sample {
+        track_id: track.id,
+        kind: kind_name(&track.kind),
+        lat: track.lat,
+        lon: track.lon,
+        alt: track.alt,
+        heading: track.heading,
+        speed: track.speed,
+        ts_ms: track.ts_ms,
+        provider_id: track.provider_id,
+        meta: track.meta,
+    })
+}
+
+/// Map a proto `TrackKind` name to the `tracks.kind` value
+fn kind_name(kind: &str) -> String {
+    match kind.strip_prefix("TRACK_KIND_") {
+        Some("UNSPECIFIED") | None => "unknown".to_string(),
+        Some(name) => name.to_ascii_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_track_line() {
+        let sample = parse_track_line(
+            r#"{"id":"ac-1","kind":"TRACK_KIND_AIRCRAFT","lat":1.5,"lon":-2.0,"alt":900.0,"heading":90.0,"speed":120.0,"ts_ms":1700000000000,"provider_id":"adsb","meta":{"callsign":"UAL1"}}"#,
+        )
+        .expect("valid line");
+        assert_eq!(sample.track_id, "ac-1");
+        assert_eq!(sample.kind, "aircraft");
+        assert_eq!(sample.ts_ms, 1_700_000_000_000);
+        assert_eq!(
+            sample.meta.get("callsign").map(String::as_str),
+            Some("UAL1")
+        );
+
+        assert_eq!(kind_name("TRACK_KIND_UNSPECIFIED"), "unknown");
+        assert!(parse_track_line("{}").is_err());
+    }
+}
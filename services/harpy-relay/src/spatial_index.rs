@@ -0,0 +1,196 @@
+//! Viewport Spatial Index
+//!
+//! Fixed lat/lon grid mapping each cell to the subscription slots whose
+//! viewport overlaps it. Fanout looks up a track's cell and only tests the
+//! subscriptions registered there, instead of every subscription.
+//! Viewports spanning a large share of the globe are kept in a separate
+//! `wide` list that is tested for every track.
+
+use harpy_proto::harpy::v1::BoundingBox;
+
+/// Grid cell size in degrees
+const CELL_DEGREES: f64 = 10.0;
+/// Number of latitude rows (-90..90)
+const CELL_ROWS: usize = 18;
+/// Number of longitude columns (-180..180)
+const CELL_COLS: usize = 36;
+/// Viewports covering more cells than this are tracked as wide subscriptions
+const WIDE_VIEWPORT_CELLS: usize = CELL_ROWS * CELL_COLS / 4;
+
+/// Slot identifier of a registered subscription
+pub type Slot = u32;
+
+/// Grid index from cells to subscription slots
+#[derive(Debug)]
+pub struct ViewportIndex {
+    cells: Vec<Vec<Slot>>,
+    wide: Vec<Slot>,
+}
+
+/// Cells a viewport was registered under, kept for removal
+#[derive(Debug, Clone, Default)]
+pub struct Placement {
+    cells: Vec<usize>,
+    wide: bool,
+}
+
+impl ViewportIndex {
+    pub fn new() -> Self {
+        Self {
+            cells: vec![Vec::new(); CELL_ROWS * CELL_COLS],
+            wide: Vec::new(),
+        }
+    }
+
+    /// Register a slot under every cell its viewport overlaps
+    pub fn insert(&mut self, slot: Slot, viewport: &BoundingBox) -> Placement {
+        let cells = viewport_cells(viewport);
+        if cells.len() > WIDE_VIEWPORT_CELLS {
+            return self.insert_wide(slot);
+        }
+
+        for &cell in &cells {
+            self.cells[cell].push(slot);
+        }
+        Placement { cells, wide: false }
+    }
+
+    /// Register a slot that must be tested for every track
+    pub fn insert_wide(&mut self, slot: Slot) -> Placement {
+        self.wide.push(slot);
+        Placement {
+            cells: Vec::new(),
+            wide: true,
+        }
+    }
+
+    /// Remove a slot using the placement returned by `insert`
+    pub fn remove(&mut self, slot: Slot, placement: &Placement) {
+        if placement.wide {
+            self.wide.retain(|s| *s != slot);
+        }
+        for &cell in &placement.cells {
+            self.cells[cell].retain(|s| *s != slot);
+        }
+    }
+
+    /// Candidate slots whose viewport may contain the given point
+    pub fn candidates(&self, lat: f64, lon: f64) -> impl Iterator<Item = Slot> + '_ {
+        let cell = cell_of(lat, lon);
+        self.wide
+            .iter()
+            .chain(cell.map(|c| self.cells[c].as_slice()).unwrap_or(&[]))
+            .copied()
+    }
+}
+
+impl Default for ViewportIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether a viewport contains a point, honoring dateline-crossing boxes
+/// (`min_lon > max_lon`).
+pub fn viewport_contains(viewport: &BoundingBox, lat: f64, lon: f64) -> bool {
+    let south = viewport.min_lat.min(viewport.max_lat);
+    let north = viewport.min_lat.max(viewport.max_lat);
+    if lat < south || lat > north {
+        return false;
+    }
+
+    if viewport.min_lon <= viewport.max_lon {
+        lon >= viewport.min_lon && lon <= viewport.max_lon
+    } else {
+        // Dateline-crossing bbox.
+        lon >= viewport.min_lon || lon <= viewport.max_lon
+    }
+}
+
+fn cell_of(lat: f64, lon: f64) -> Option<usize> {
+    if !lat.is_finite() || !lon.is_finite() {
+        return None;
+    }
+    let row = row_of(lat);
+    let col = col_of(lon);
+    Some(row * CELL_COLS + col)
+}
+
+fn row_of(lat: f64) -> usize {
+    (((lat.clamp(-90.0, 90.0) + 90.0) / CELL_DEGREES) as usize).min(CELL_ROWS - 1)
+}
+
+fn col_of(lon: f64) -> usize {
+    (((lon.clamp(-180.0, 180.0) + 180.0) / CELL_DEGREES) as usize).min(CELL_COLS - 1)
+}
+
+fn viewport_cells(viewport: &BoundingBox) -> Vec<usize> {
+    let south = viewport.min_lat.min(viewport.max_lat);
+    let north = viewport.min_lat.max(viewport.max_lat);
+    let rows = row_of(south)..=row_of(north);
+
+    let col_ranges = if viewport.min_lon <= viewport.max_lon {
+        vec![col_of(viewport.min_lon)..=col_of(viewport.max_lon)]
+    } else {
+        vec![
+            col_of(viewport.min_lon)..=CELL_COLS - 1,
+            0..=col_of(viewport.max_lon),
+        ]
+    };
+
+    let mut cells = Vec::new();
+    for row in rows {
+        for cols in &col_ranges {
+            for col in cols.clone() {
+                cells.push(row * CELL_COLS + col);
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbox(min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> BoundingBox {
+        BoundingBox {
+            min_lat,
+            min_lon,
+            max_lat,
+            max_lon,
+        }
+    }
+
+    #[test]
+    fn test_regional_viewport_candidates() {
+        let mut index = ViewportIndex::new();
+        index.insert(1, &bbox(37.0, -123.0, 38.0, -121.0));
+        index.insert(2, &bbox(50.0, 0.0, 52.0, 2.0));
+
+        let near_sf: Vec<Slot> = index.candidates(37.5, -122.0).collect();
+        assert_eq!(near_sf, vec![1]);
+        let near_london: Vec<Slot> = index.candidates(51.5, 0.5).collect();
+        assert_eq!(near_london, vec![2]);
+    }
+
+    #[test]
+    fn test_dateline_viewport_registered_on_both_sides() {
+        let mut index = ViewportIndex::new();
+        index.insert(7, &bbox(-10.0, 170.0, 10.0, -170.0));
+
+        assert_eq!(index.candidates(0.0, 175.0).collect::<Vec<_>>(), vec![7]);
+        assert_eq!(index.candidates(0.0, -175.0).collect::<Vec<_>>(), vec![7]);
+        assert!(index.candidates(0.0, 0.0).next().is_none());
+    }
+
+    #[test]
+    fn test_world_viewport_is_wide_and_removable() {
+        let mut index = ViewportIndex::new();
+        let placement = index.insert(3, &bbox(-90.0, -180.0, 90.0, 180.0));
+        assert_eq!(index.candidates(-45.0, 100.0).collect::<Vec<_>>(), vec![3]);
+
+        index.remove(3, &placement);
+        assert!(index.candidates(-45.0, 100.0).next().is_none());
+    }
+}
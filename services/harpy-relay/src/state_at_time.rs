@@ -0,0 +1,689 @@
+//! State-at-Time API
+//!
+//! Reconstructs the picture at a single instant: every track alive at `ts_ms`,
+//! positioned by interpolating between the surrounding track deltas, on top of
+//! the track states in the nearest snapshot. Used by the HUD "jump to time"
+//! control and AIP `seek_to_time`.
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgRow;
+use sqlx::{Postgres, QueryBuilder, Row};
+use std::collections::HashMap;
+
+use crate::playback::{parse_meta, push_h3_cover};
+use crate::seek::{find_snapshot, parse_layers, SeekError, SnapshotRef};
+use crate::AppState;
+
+/// Staleness cutoff for aircraft (fast movers, frequent reports)
+const AIRCRAFT_MAX_AGE_MS: u64 = 60 * 1000;
+/// Staleness cutoff for vessels (AIS reports are sparse)
+const VESSEL_MAX_AGE_MS: u64 = 10 * 60 * 1000;
+/// Staleness cutoff for satellites (TLE propagation cadence)
+const SATELLITE_MAX_AGE_MS: u64 = 5 * 60 * 1000;
+/// Staleness cutoff for ground sensors and other static kinds (the longest cutoff)
+pub(crate) const GROUND_MAX_AGE_MS: u64 = 30 * 60 * 1000;
+
+/// Margin added around a requested bbox in SQL, so a track whose fixes lie
+/// just outside it but whose interpolated position falls inside is still
+/// found. Covers an aircraft at 350 m/s over its 60 s cutoff (and a vessel
+/// at 25 m/s over 10 minutes).
+const INTERPOLATION_PAD_DEG: f64 = 0.25;
+
+/// State request parameters
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StateRequest {
+    /// Instant to reconstruct (epoch milliseconds)
+    pub ts_ms: u64,
+    /// Optional viewport filter: `min_lat,min_lon,max_lat,max_lon`.
+    /// `min_lon > max_lon` selects a dateline-crossing box.
+    pub bbox: Option<String>,
+    /// Layer types to include (comma-separated: aircraft,satellite,ground,vessel)
+    pub layers: Option<String>,
+}
+
+/// Reconstructed state of a single track at the requested instant
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackState {
+    pub id: String,
+    pub kind: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub alt: f64,
+    pub heading: f64,
+    pub speed: f64,
+    pub provider_id: String,
+    pub meta: HashMap<String, String>,
+    /// Timestamp of the observation the state was derived from
+    pub source_ts_ms: u64,
+    /// Age of that observation relative to the requested instant
+    pub age_ms: u64,
+    /// Whether the position was interpolated between two observations
+    pub interpolated: bool,
+}
+
+/// State response
+#[derive(Debug, Serialize)]
+pub struct StateResponse {
+    /// Request parameters echoed back
+    pub request: StateRequest,
+    /// Snapshot whose track states seeded the reconstruction
+    pub snapshot: Option<SnapshotRef>,
+    /// Tracks alive at the requested instant
+    pub tracks: Vec<TrackState>,
+    /// Number of tracks returned
+    pub track_count: usize,
+    /// Schema version
+    pub schema_version: String,
+}
+
+/// A raw observation read from `track_deltas`
+#[derive(Debug, Clone)]
+pub(crate) struct Sample {
+    pub(crate) track_id: String,
+    pub(crate) kind: String,
+    pub(crate) lat: f64,
+    pub(crate) lon: f64,
+    pub(crate) alt: f64,
+    pub(crate) heading: f64,
+    pub(crate) speed: f64,
+    pub(crate) ts_ms: u64,
+    pub(crate) provider_id: String,
+    pub(crate) meta: HashMap<String, String>,
+}
+
+/// Geographic filter parsed from the `bbox` parameter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct StateBbox {
+    min_lat: f64,
+    min_lon: f64,
+    max_lat: f64,
+    max_lon: f64,
+}
+
+impl StateBbox {
+    pub(crate) fn contains(&self, lat: f64, lon: f64) -> bool {
+        if lat < self.min_lat || lat > self.max_lat {
+            return false;
+        }
+        if self.min_lon <= self.max_lon {
+            lon >= self.min_lon && lon <= self.max_lon
+        } else {
+            // Dateline-crossing bbox.
+            lon >= self.min_lon || lon <= self.max_lon
+        }
+    }
+
+    /// The box widened by `pad_deg` of latitude and about the same ground
+    /// distance of longitude; `None` when that covers the whole globe
+    fn padded(&self, pad_deg: f64) -> Option<StateBbox> {
+        let min_lat = (self.min_lat - pad_deg).max(-90.0);
+        let max_lat = (self.max_lat + pad_deg).min(90.0);
+        let widest_lat = min_lat.abs().max(max_lat.abs()).min(89.0);
+        let lon_pad = pad_deg / widest_lat.to_radians().cos();
+        let lon_span = if self.min_lon <= self.max_lon {
+            self.max_lon - self.min_lon
+        } else {
+            self.max_lon + 360.0 - self.min_lon
+        };
+        if lon_span + 2.0 * lon_pad >= 360.0 {
+            return None;
+        }
+        Some(StateBbox {
+            min_lat,
+            min_lon: wrap_lon(self.min_lon - lon_pad),
+            max_lat,
+            max_lon: wrap_lon(self.max_lon + lon_pad),
+        })
+    }
+
+    /// Push ` AND ...` restricting `td` rows to the box
+    pub(crate) fn push_predicate(&self, qb: &mut QueryBuilder<'_, Postgres>) {
+        qb.push(" AND td.lat >= ")
+            .push_bind(self.min_lat)
+            .push(" AND td.lat <= ")
+            .push_bind(self.max_lat)
+            .push(" AND (td.lon >= ")
+            .push_bind(self.min_lon)
+            .push(if self.min_lon <= self.max_lon {
+                " AND td.lon <= "
+            } else {
+                " OR td.lon <= "
+            })
+            .push_bind(self.max_lon)
+            .push(")");
+        push_h3_cover(qb, self.min_lat, self.min_lon, self.max_lat, self.max_lon);
+    }
+}
+
+/// State-at-time handler
+pub async fn state_handler(
+    State(state): State<AppState>,
+    Query(params): Query<StateRequest>,
+) -> impl IntoResponse {
+    let bbox = match params.bbox.as_deref().map(parse_bbox).transpose() {
+        Ok(bbox) => bbox,
+        Err(error) => return Json(Err::<StateResponse, _>(error)).into_response(),
+    };
+
+    let Some(pool) = state.db_pool.as_ref() else {
+        return Json(Err::<StateResponse, _>(SeekError {
+            error: "Database unavailable: state requires Postgres".to_string(),
+            code: "DB_UNAVAILABLE".to_string(),
+        }))
+        .into_response();
+    };
+
+    let snapshot = match find_snapshot(pool, params.ts_ms).await {
+        Ok(snapshot) => snapshot,
+        Err(error) => return Json(Err::<StateResponse, _>(error)).into_response(),
+    };
+
+    let layers = parse_layers(params.layers.as_deref());
+    let sql_bbox = bbox.and_then(|bbox| bbox.padded(INTERPOLATION_PAD_DEG));
+    let (base, snapshot) = match snapshot {
+        Some(snapshot) => {
+            match snapshot_base(&state, pool, &snapshot, params.ts_ms, &layers, sql_bbox).await {
+                Ok(Some(base)) => (base, Some(snapshot)),
+                Ok(None) => (Vec::new(), None),
+                Err(error) => return Json(Err::<StateResponse, _>(error)).into_response(),
+            }
+        }
+        None => (Vec::new(), None),
+    };
+    let (before, after) =
+        match fetch_surrounding_samples(pool, params.ts_ms, layers, sql_bbox.as_ref()).await {
+            Ok(samples) => samples,
+            Err(error) => return Json(Err::<StateResponse, _>(error)).into_response(),
+        };
+
+    let before = merge_base(base, before);
+    let mut tracks = reconstruct_state(before, after, params.ts_ms);
+    if let Some(bbox) = bbox {
+        tracks.retain(|track| bbox.contains(track.lat, track.lon));
+    }
+    tracks.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let response = StateResponse {
+        request: params,
+        snapshot,
+        track_count: tracks.len(),
+        tracks,
+        schema_version: "1.0.0".to_string(),
+    };
+
+    Json(Ok::<_, SeekError>(response)).into_response()
+}
+
+pub(crate) fn parse_bbox(raw: &str) -> Result<StateBbox, SeekError> {
+    let invalid = || SeekError {
+        error: "bbox must be min_lat,min_lon,max_lat,max_lon".to_string(),
+        code: "INVALID_BBOX".to_string(),
+    };
+
+    let values = raw
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid())?;
+    let [min_lat, min_lon, max_lat, max_lon] = values[..] else {
+        return Err(invalid());
+    };
+
+    if !(-90.0..=90.0).contains(&min_lat)
+        || !(-90.0..=90.0).contains(&max_lat)
+        || !(-180.0..=180.0).contains(&min_lon)
+        || !(-180.0..=180.0).contains(&max_lon)
+        || min_lat > max_lat
+    {
+        return Err(invalid());
+    }
+
+    Ok(StateBbox {
+        min_lat,
+        min_lon,
+        max_lat,
+        max_lon,
+    })
+}
+
+/// Staleness cutoff for a track kind; beyond it a track is no longer alive.
+pub(crate) fn max_age_ms(kind: &str) -> u64 {
+    match kind {
+        "aircraft" => AIRCRAFT_MAX_AGE_MS,
+        "vessel" => VESSEL_MAX_AGE_MS,
+        "satellite" => SATELLITE_MAX_AGE_MS,
+        _ => GROUND_MAX_AGE_MS,
+    }
+}
+
+/// Whether positions for a kind may be linearly interpolated between fixes.
+pub(crate) fn interpolates(kind: &str) -> bool {
+    matches!(kind, "aircraft" | "vessel")
+}
+
+/// Longest staleness cutoff among the requested layers (all kinds when empty)
+fn window_ms(layers: &[String]) -> u64 {
+    if layers.is_empty() {
+        return GROUND_MAX_AGE_MS;
+    }
+    layers
+        .iter()
+        .map(|kind| max_age_ms(kind))
+        .max()
+        .unwrap_or(GROUND_MAX_AGE_MS)
+}
+
+/// Track states from the snapshot's blob that can stand as a track's last fix
+/// at `ts_ms`: no later than it, still alive, in the requested layers and
+/// (padded) bbox, and with no newer delta up to `ts_ms`.
+///
+/// `None` when the blob cannot be read, e.g. for a pre-2.0 snapshot; the
+/// state is then rebuilt from deltas alone.
+async fn snapshot_base(
+    state: &AppState,
+    pool: &sqlx::PgPool,
+    snapshot: &SnapshotRef,
+    ts_ms: u64,
+    layers: &[String],
+    bbox: Option<StateBbox>,
+) -> Result<Option<Vec<Sample>>, SeekError> {
+    let tracks = match state.snapshot_blobs.tracks(&snapshot.id).await {
+        Ok(tracks) => tracks,
+        Err(e) => {
+            tracing::warn!("Failed to load snapshot {}: {}", snapshot.id, e);
+            return Ok(None);
+        }
+    };
+    let base: Vec<Sample> = tracks
+        .into_iter()
+        .filter(|s| s.ts_ms <= ts_ms && ts_ms - s.ts_ms <= max_age_ms(&s.kind))
+        .filter(|s| layers.is_empty() || layers.contains(&s.kind))
+        .filter(|s| bbox.map_or(true, |bbox| bbox.contains(s.lat, s.lon)))
+        .collect();
+    if base.is_empty() {
+        return Ok(Some(base));
+    }
+
+    let ids: Vec<&str> = base.iter().map(|s| s.track_id.as_str()).collect();
+    let ts: Vec<i64> = base.iter().map(|s| s.ts_ms as i64).collect();
+    let superseded: Vec<String> = sqlx::query_scalar(
+        "SELECT b.track_id FROM UNNEST($1::text[], $2::int8[]) AS b(track_id, ts_ms) \
+         WHERE EXISTS (SELECT 1 FROM track_deltas td \
+         WHERE td.track_id = b.track_id AND td.ts_ms > b.ts_ms AND td.ts_ms <= $3)",
+    )
+    .bind(&ids)
+    .bind(&ts)
+    .bind(ts_ms as i64)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| SeekError {
+        error: format!("Failed to query track deltas: {}", e),
+        code: "DB_QUERY_FAILED".to_string(),
+    })?;
+
+    Ok(Some(
+        base.into_iter()
+            .filter(|s| !superseded.contains(&s.track_id))
+            .collect(),
+    ))
+}
+
+/// Per track, the newer of its snapshot state and its latest delta; a delta
+/// wins a tie
+fn merge_base(base: Vec<Sample>, before: Vec<Sample>) -> Vec<Sample> {
+    let mut latest: HashMap<String, Sample> = base
+        .into_iter()
+        .map(|sample| (sample.track_id.clone(), sample))
+        .collect();
+    for sample in before {
+        match latest.get(&sample.track_id) {
+            Some(existing) if existing.ts_ms > sample.ts_ms => {}
+            _ => {
+                latest.insert(sample.track_id.clone(), sample);
+            }
+        }
+    }
+    latest.into_values().collect()
+}
+
+/// Fetch, per track, the latest observation at or before `ts_ms` and the
+/// earliest observation after it, both bounded by the requested layers'
+/// staleness cutoff and, when given, the (padded) bbox.
+async fn fetch_surrounding_samples(
+    pool: &sqlx::PgPool,
+    ts_ms: u64,
+    layers: Vec<String>,
+    bbox: Option<&StateBbox>,
+) -> Result<(Vec<Sample>, Vec<Sample>), SeekError> {
+    let window_ms = window_ms(&layers);
+
+    let before = fetch_samples(
+        pool,
+        ts_ms.saturating_sub(window_ms),
+        ts_ms,
+        true,
+        &layers,
+        bbox,
+    )
+    .await?;
+    let after = fetch_samples(
+        pool,
+        ts_ms.saturating_add(1),
+        ts_ms.saturating_add(window_ms),
+        false,
+        &layers,
+        bbox,
+    )
+    .await?;
+
+    Ok((before, after))
+}
+
+/// Per track, the latest (or earliest) observation in `[start, end]`
+///
+/// With a bbox only observations inside it are scanned; a track is still
+/// skipped when its latest (earliest) observation in the window lies outside,
+/// so an older fix inside the box never stands in for a newer one.
+pub(crate) async fn fetch_samples(
+    pool: &sqlx::PgPool,
+    start_ts_ms: u64,
+    end_ts_ms: u64,
+    latest: bool,
+    layers: &[String],
+    bbox: Option<&StateBbox>,
+) -> Result<Vec<Sample>, SeekError> {
+    let mut qb = QueryBuilder::<Postgres>::new(
+        "SELECT DISTINCT ON (td.track_id) td.track_id, td.lat, td.lon, td.alt, td.heading, td.speed, td.ts_ms, td.provider_id, td.meta, COALESCE(t.kind, 'unknown') AS kind \
+         FROM track_deltas td \
+         LEFT JOIN tracks t ON t.id = td.track_id \
+         WHERE td.ts_ms >= ",
+    );
+    qb.push_bind(start_ts_ms as i64)
+        .push(" AND td.ts_ms <= ")
+        .push_bind(end_ts_ms as i64);
+
+    if !layers.is_empty() {
+        qb.push(" AND t.kind = ANY(")
+            .push_bind(layers.to_vec())
+            .push(")");
+    }
+
+    if let Some(bbox) = bbox {
+        bbox.push_predicate(&mut qb);
+        if latest {
+            qb.push(
+                " AND NOT EXISTS (SELECT 1 FROM track_deltas n \
+                 WHERE n.track_id = td.track_id AND n.ts_ms > td.ts_ms AND n.ts_ms <= ",
+            )
+            .push_bind(end_ts_ms as i64)
+            .push(")");
+        } else {
+            qb.push(
+                " AND NOT EXISTS (SELECT 1 FROM track_deltas n \
+                 WHERE n.track_id = td.track_id AND n.ts_ms < td.ts_ms AND n.ts_ms >= ",
+            )
+            .push_bind(start_ts_ms as i64)
+            .push(")");
+        }
+    }
+
+    if latest {
+        qb.push(" ORDER BY td.track_id, td.ts_ms DESC");
+    } else {
+        qb.push(" ORDER BY td.track_id, td.ts_ms ASC");
+    }
+
+    let rows = qb.build().fetch_all(pool).await.map_err(|e| SeekError {
+        error: format!("Failed to query track deltas: {}", e),
+        code: "DB_QUERY_FAILED".to_string(),
+    })?;
+
+    Ok(rows.iter().map(sample_from_row).collect())
+}
+
+/// Build a sample from a `track_deltas` row joined with its track kind
+pub(crate) fn sample_from_row(row: &PgRow) -> Sample {
+    Sample {
+        track_id: row.get("track_id"),
+        kind: row.get("kind"),
+        lat: row.get("lat"),
+        lon: row.get("lon"),
+        alt: row.get("alt"),
+        heading: row
+            .try_get::<Option<f64>, _>("heading")
+            .ok()
+            .flatten()
+            .unwrap_or(0.0),
+        speed: row
+            .try_get::<Option<f64>, _>("speed")
+            .ok()
+            .flatten()
+            .unwrap_or(0.0),
+        ts_ms: row.get::<i64, _>("ts_ms") as u64,
+        provider_id: row.get("provider_id"),
+        meta: parse_meta(row.try_get("meta").ok()),
+    }
+}
+
+/// Combine per-track before/after samples into the state at `ts_ms`.
+fn reconstruct_state(before: Vec<Sample>, after: Vec<Sample>, ts_ms: u64) -> Vec<TrackState> {
+    let mut after_by_track: HashMap<String, Sample> = after
+        .into_iter()
+        .map(|sample| (sample.track_id.clone(), sample))
+        .collect();
+
+    before
+        .into_iter()
+        .filter_map(|sample| {
+            let next = after_by_track.remove(&sample.track_id);
+            interpolate(sample, next.as_ref(), ts_ms)
+        })
+        .collect()
+}
+
+/// Derive a track's state at `ts_ms` from its last fix and (optionally) its next fix.
+///
+/// Returns `None` when the last fix is older than the kind's staleness cutoff.
+fn interpolate(before: Sample, after: Option<&Sample>, ts_ms: u64) -> Option<TrackState> {
+    let max_age = max_age_ms(&before.kind);
+    let age_ms = ts_ms.saturating_sub(before.ts_ms);
+    if age_ms > max_age {
+        return None;
+    }
+
+    let next = after.filter(|next| {
+        interpolates(&before.kind)
+            && next.ts_ms > before.ts_ms
+            && next.ts_ms - before.ts_ms <= max_age
+    });
+
+    let Some(next) = next else {
+        return Some(TrackState {
+            id: before.track_id,
+            kind: before.kind,
+            lat: before.lat,
+            lon: before.lon,
+            alt: before.alt,
+            heading: before.heading,
+            speed: before.speed,
+            provider_id: before.provider_id,
+            meta: before.meta,
+            source_ts_ms: before.ts_ms,
+            age_ms,
+            interpolated: false,
+        });
+    };
+
+    let t = (ts_ms - before.ts_ms) as f64 / (next.ts_ms - before.ts_ms) as f64;
+    let lerp = |a: f64, b: f64| a + (b - a) * t;
+
+    // Take the short way around the antimeridian.
+    let mut lon_delta = next.lon - before.lon;
+    if lon_delta > 180.0 {
+        lon_delta -= 360.0;
+    } else if lon_delta < -180.0 {
+        lon_delta += 360.0;
+    }
+    let lon = wrap_lon(before.lon + lon_delta * t);
+
+    let mut heading_delta = next.heading - before.heading;
+    if heading_delta > 180.0 {
+        heading_delta -= 360.0;
+    } else if heading_delta < -180.0 {
+        heading_delta += 360.0;
+    }
+    let heading = (before.heading + heading_delta * t).rem_euclid(360.0);
+
+    Some(TrackState {
+        lat: lerp(before.lat, next.lat),
+        lon,
+        alt: lerp(before.alt, next.alt),
+        heading,
+        speed: lerp(before.speed, next.speed),
+        id: before.track_id,
+        kind: before.kind,
+        provider_id: before.provider_id,
+        meta: before.meta,
+        source_ts_ms: before.ts_ms,
+        age_ms,
+        interpolated: true,
+    })
+}
+
+pub(crate) fn wrap_lon(lon: f64) -> f64 {
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(kind: &str, lat: f64, lon: f64, ts_ms: u64) -> Sample {
+        Sample {
+            track_id: "t-1".to_string(),
+            kind: kind.to_string(),
+            lat,
+            lon,
+            alt: 1000.0,
+            heading: 90.0,
+            speed: 200.0,
+            ts_ms,
+            provider_id: "test".to_string(),
+            meta: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_aircraft_interpolated_linearly() {
+        let before = sample("aircraft", 10.0, 20.0, 1_000);
+        let after = sample("aircraft", 12.0, 22.0, 11_000);
+
+        let state = interpolate(before, Some(&after), 6_000).expect("alive");
+        assert!(state.interpolated);
+        assert!((state.lat - 11.0).abs() < 1e-9);
+        assert!((state.lon - 21.0).abs() < 1e-9);
+        assert_eq!(state.source_ts_ms, 1_000);
+        assert_eq!(state.age_ms, 5_000);
+    }
+
+    #[test]
+    fn test_interpolation_crosses_dateline() {
+        let before = sample("vessel", 0.0, 179.0, 0);
+        let after = sample("vessel", 0.0, -179.0, 10_000);
+
+        let state = interpolate(before, Some(&after), 7_500).expect("alive");
+        assert!((state.lon - (-179.5)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_stale_track_excluded() {
+        let before = sample("aircraft", 10.0, 20.0, 0);
+        assert!(interpolate(before.clone(), None, AIRCRAFT_MAX_AGE_MS).is_some());
+        assert!(interpolate(before, None, AIRCRAFT_MAX_AGE_MS + 1).is_none());
+    }
+
+    #[test]
+    fn test_satellite_holds_last_fix() {
+        let before = sample("satellite", 10.0, 20.0, 0);
+        let after = sample("satellite", 30.0, 40.0, 10_000);
+
+        let state = interpolate(before, Some(&after), 5_000).expect("alive");
+        assert!(!state.interpolated);
+        assert_eq!(state.lat, 10.0);
+    }
+
+    #[test]
+    fn test_snapshot_state_fills_in_for_missing_deltas() {
+        let snapshot_only = Sample {
+            track_id: "t-2".to_string(),
+            ..sample("vessel", 5.0, 5.0, 2_000)
+        };
+        let base = vec![sample("aircraft", 10.0, 20.0, 3_000), snapshot_only];
+        let before = vec![sample("aircraft", 11.0, 21.0, 3_000)];
+
+        let mut merged = merge_base(base, before);
+        merged.sort_by(|a, b| a.track_id.cmp(&b.track_id));
+        assert_eq!(merged.len(), 2);
+        // The delta wins over a snapshot state from the same instant
+        assert_eq!(merged[0].lat, 11.0);
+        assert_eq!(merged[1].track_id, "t-2");
+        assert_eq!(merged[1].lat, 5.0);
+    }
+
+    #[test]
+    fn test_parse_bbox_dateline() {
+        let bbox = parse_bbox("-10,170,10,-170").expect("valid bbox");
+        assert!(bbox.contains(0.0, 175.0));
+        assert!(bbox.contains(0.0, -175.0));
+        assert!(!bbox.contains(0.0, 0.0));
+        assert!(parse_bbox("1,2,3").is_err());
+        assert!(parse_bbox("10,0,-10,5").is_err());
+    }
+
+    #[test]
+    fn test_padded_bbox_keeps_dateline_and_gives_up_on_the_globe() {
+        let bbox = parse_bbox("-10,170,10,179.9").expect("valid bbox");
+        let padded = bbox.padded(INTERPOLATION_PAD_DEG).expect("bounded");
+        assert!((padded.min_lat + 10.25).abs() < 1e-9);
+        // Padding past 180 wraps into a dateline-crossing box
+        assert!(padded.max_lon < padded.min_lon);
+        assert!(padded.contains(0.0, -179.9));
+        assert!(!padded.contains(0.0, 0.0));
+
+        let world = parse_bbox("-80,-180,80,180").expect("valid bbox");
+        assert!(world.padded(INTERPOLATION_PAD_DEG).is_none());
+    }
+
+    #[test]
+    fn test_bbox_predicate_in_sql() {
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT 1 FROM track_deltas td WHERE true");
+        parse_bbox("-10,170,10,-170")
+            .expect("valid bbox")
+            .push_predicate(&mut qb);
+        let sql = qb.sql();
+        assert!(sql.contains("td.lat >= $1 AND td.lat <= $2 AND (td.lon >= $3 OR td.lon <= $4)"));
+
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT 1 FROM track_deltas td WHERE true");
+        parse_bbox("37,-123,38,-122")
+            .expect("valid bbox")
+            .push_predicate(&mut qb);
+        let sql = qb.sql();
+        assert!(sql.contains("(td.lon >= $3 AND td.lon <= $4)"));
+        assert!(sql.contains("td.h3_index BETWEEN"));
+    }
+
+    #[test]
+    fn test_window_follows_requested_layers() {
+        assert_eq!(window_ms(&[]), GROUND_MAX_AGE_MS);
+        assert_eq!(window_ms(&["aircraft".to_string()]), AIRCRAFT_MAX_AGE_MS);
+        assert_eq!(
+            window_ms(&["aircraft".to_string(), "vessel".to_string()]),
+            VESSEL_MAX_AGE_MS
+        );
+    }
+}
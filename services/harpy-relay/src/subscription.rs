@@ -3,27 +3,41 @@
 //! Manages client subscriptions, filters tracks by viewport/layers,
 //! and handles fanout of messages to connected clients.
 
-use harpy_proto::harpy::v1::{BoundingBox, Envelope, LayerType, TrackDelta, TrackDeltaBatch};
+use harpy_core::track_filter;
+use harpy_proto::harpy::v1::{
+    BoundingBox, Envelope, FollowFilter, LayerType, TrackDelta, TrackDeltaBatch, TrackFilter,
+};
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use crate::backpressure::BackpressureChannel;
+use crate::alerts::AlertScope;
+use crate::backpressure::{BackpressureChannel, QueueDepth};
+use crate::follow;
+use crate::spatial_index::{viewport_contains, Placement, Slot, ViewportIndex};
 
 /// Unique client ID
 pub type ClientId = String;
 
+/// Client-chosen subscription ID, unique within a connection
+pub type SubscriptionId = String;
+
+/// Subscription ID used when a request does not name one
+pub const DEFAULT_SUBSCRIPTION_ID: &str = "default";
+
 #[derive(Debug, Clone, Serialize)]
 pub struct BackpressureDebugStats {
-    pub track_batches_dropped: usize,
     pub track_batches_sent: usize,
+    pub track_updates_coalesced: usize,
+    pub track_updates_dropped: usize,
     pub high_priority_sent: usize,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SubscriptionDebugInfo {
     pub client_id: String,
+    pub subscription_id: String,
     pub viewport: ViewportDebug,
     pub layers: Vec<String>,
     pub backpressure: BackpressureDebugStats,
@@ -44,6 +58,10 @@ pub struct Subscription {
     pub viewport: BoundingBox,
     /// Layer types to include
     pub layers: Vec<LayerType>,
+    /// Tracks delivered regardless of viewport and layers
+    pub follow: Option<FollowFilter>,
+    /// Provider, altitude, speed and meta predicates
+    pub filter: Option<TrackFilter>,
     /// Channel to send messages to this client
     pub sender: BackpressureChannel,
 }
@@ -51,13 +69,12 @@ pub struct Subscription {
 impl Subscription {
     /// Check if a track matches this subscription's filters
     pub fn matches(&self, track: &TrackDelta) -> bool {
-        // Check layer type match
-        let track_layer = match track.kind {
-            1 => LayerType::Aircraft,  // TRACK_KIND_AIRCRAFT
-            2 => LayerType::Satellite, // TRACK_KIND_SATELLITE
-            3 => LayerType::Ground,    // TRACK_KIND_GROUND
-            4 => LayerType::Vessel,    // TRACK_KIND_VESSEL
-            _ => return false,
+        if self.follows(track) {
+            return true;
+        }
+
+        let Some(track_layer) = track_layer(track.kind) else {
+            return false;
         };
 
         if !self.layers.contains(&track_layer) {
@@ -65,30 +82,173 @@ impl Subscription {
         }
 
         // Check viewport bounds
-        let pos = track.position.as_ref().unwrap();
-        if pos.lat < self.viewport.min_lat
-            || pos.lat > self.viewport.max_lat
-            || pos.lon < self.viewport.min_lon
-            || pos.lon > self.viewport.max_lon
-        {
+        let Some(pos) = track.position.as_ref() else {
             return false;
+        };
+        if !viewport_contains(&self.viewport, pos.lat, pos.lon) {
+            return false;
+        }
+
+        self.filter
+            .as_ref()
+            .map_or(true, |filter| track_filter::passes(filter, track))
+    }
+
+    /// Check if a track is pinned by this subscription's follow filter
+    pub fn follows(&self, track: &TrackDelta) -> bool {
+        self.follow
+            .as_ref()
+            .is_some_and(|filter| follow::follows(filter, track))
+    }
+
+    /// Whether this subscription pins any tracks
+    pub fn has_follow(&self) -> bool {
+        self.follow.as_ref().is_some_and(follow::is_active)
+    }
+
+    /// Check if an alert or link with the given scope should reach this subscription
+    ///
+    /// Requires the alert layer. Located alerts must have an evidence position
+    /// inside the viewport or an evidence track followed by id or callsign.
+    pub fn wants_alert(&self, scope: &AlertScope) -> bool {
+        if !self.layers.contains(&LayerType::Alert) {
+            return false;
+        }
+        if scope.is_unlocated() {
+            return true;
+        }
+        if scope
+            .points
+            .iter()
+            .any(|&(lat, lon)| viewport_contains(&self.viewport, lat, lon))
+        {
+            return true;
+        }
+        self.follow.as_ref().is_some_and(|filter| {
+            scope
+                .track_ids
+                .iter()
+                .any(|id| filter.track_ids.contains(id))
+                || scope
+                    .callsigns
+                    .iter()
+                    .any(|callsign| follow::follows_callsign(filter, callsign))
+        })
+    }
+}
+
+/// Map a track kind to the layer it is rendered on
+fn track_layer(kind: i32) -> Option<LayerType> {
+    match kind {
+        1 => Some(LayerType::Aircraft),  // TRACK_KIND_AIRCRAFT
+        2 => Some(LayerType::Satellite), // TRACK_KIND_SATELLITE
+        3 => Some(LayerType::Ground),    // TRACK_KIND_GROUND
+        4 => Some(LayerType::Vessel),    // TRACK_KIND_VESSEL
+        _ => None,
+    }
+}
+
+/// A subscription registered in a slot of the manager
+#[derive(Debug)]
+struct Registered {
+    client_id: ClientId,
+    subscription_id: SubscriptionId,
+    subscription: Subscription,
+    placement: Placement,
+}
+
+/// Slot-based subscription registry with a viewport spatial index
+#[derive(Debug, Default)]
+struct Registry {
+    slots: Vec<Option<Registered>>,
+    free: Vec<Slot>,
+    by_client: HashMap<ClientId, HashMap<SubscriptionId, Slot>>,
+    index: ViewportIndex,
+}
+
+impl Registry {
+    fn insert(
+        &mut self,
+        client_id: ClientId,
+        subscription_id: SubscriptionId,
+        subscription: Subscription,
+    ) -> Option<Subscription> {
+        let replaced = self.take(&client_id, &subscription_id);
+
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(None);
+                (self.slots.len() - 1) as Slot
+            }
+        };
+        // Followed tracks may appear anywhere, so test them against every track.
+        let placement = if subscription.has_follow() {
+            self.index.insert_wide(slot)
+        } else {
+            self.index.insert(slot, &subscription.viewport)
+        };
+        self.by_client
+            .entry(client_id.clone())
+            .or_default()
+            .insert(subscription_id.clone(), slot);
+        self.slots[slot as usize] = Some(Registered {
+            client_id,
+            subscription_id,
+            subscription,
+            placement,
+        });
+        replaced
+    }
+
+    fn remove(&mut self, client_id: &str, subscription_id: &str) -> bool {
+        self.take(client_id, subscription_id).is_some()
+    }
+
+    /// Remove a named subscription and return it
+    fn take(&mut self, client_id: &str, subscription_id: &str) -> Option<Subscription> {
+        let subscriptions = self.by_client.get_mut(client_id)?;
+        let slot = subscriptions.remove(subscription_id)?;
+        if subscriptions.is_empty() {
+            self.by_client.remove(client_id);
+        }
+        self.release(slot)
+    }
+
+    fn remove_client(&mut self, client_id: &str) {
+        let Some(subscriptions) = self.by_client.remove(client_id) else {
+            return;
+        };
+        for slot in subscriptions.into_values() {
+            self.release(slot);
         }
+    }
 
-        true
+    fn release(&mut self, slot: Slot) -> Option<Subscription> {
+        let registered = self.slots[slot as usize].take();
+        if let Some(registered) = registered.as_ref() {
+            self.index.remove(slot, &registered.placement);
+        }
+        self.free.push(slot);
+        registered.map(|registered| registered.subscription)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Registered> {
+        self.slots.iter().flatten()
     }
 }
 
 /// Manages all active subscriptions
 #[derive(Debug, Default)]
 pub struct SubscriptionManager {
-    subscriptions: RwLock<HashMap<ClientId, Subscription>>,
+    registry: RwLock<Registry>,
 }
 
 impl SubscriptionManager {
     /// Create a new subscription manager
     pub fn new() -> Self {
         Self {
-            subscriptions: RwLock::new(HashMap::new()),
+            registry: RwLock::new(Registry::default()),
         }
     }
 
@@ -97,34 +257,92 @@ impl SubscriptionManager {
         Arc::new(Self::new())
     }
 
-    /// Add or update a client subscription
-    pub async fn subscribe(&self, client_id: ClientId, subscription: Subscription) {
-        let mut subs = self.subscriptions.write().await;
-        subs.insert(client_id, subscription);
-        tracing::info!("Client subscribed, total clients: {}", subs.len());
+    /// Add or update a named subscription for a client, returning the one it replaced
+    pub async fn subscribe(
+        &self,
+        client_id: ClientId,
+        subscription_id: SubscriptionId,
+        subscription: Subscription,
+    ) -> Option<Subscription> {
+        let mut registry = self.registry.write().await;
+        let replaced = registry.insert(client_id, subscription_id, subscription);
+        tracing::info!(
+            "Client subscribed, total clients: {}",
+            registry.by_client.len()
+        );
+        replaced
+    }
+
+    /// Remove a single named subscription; returns false if it did not exist
+    pub async fn unsubscribe(&self, client_id: &str, subscription_id: &str) -> bool {
+        let mut registry = self.registry.write().await;
+        let removed = registry.remove(client_id, subscription_id);
+        tracing::info!(
+            "Client unsubscribed, total clients: {}",
+            registry.by_client.len()
+        );
+        removed
     }
 
-    /// Remove a client subscription
-    pub async fn unsubscribe(&self, client_id: &str) {
-        let mut subs = self.subscriptions.write().await;
-        subs.remove(client_id);
-        tracing::info!("Client unsubscribed, total clients: {}", subs.len());
+    /// Remove every subscription held by a client
+    pub async fn remove_client(&self, client_id: &str) {
+        let mut registry = self.registry.write().await;
+        registry.remove_client(client_id);
+        tracing::info!(
+            "Client removed, total clients: {}",
+            registry.by_client.len()
+        );
     }
 
-    /// Get count of active subscriptions
+    /// Get count of clients with at least one live subscription
     pub async fn client_count(&self) -> usize {
-        self.subscriptions.read().await.len()
+        self.registry.read().await.by_client.len()
+    }
+
+    /// Get count of live subscriptions across all clients
+    pub async fn subscription_count(&self) -> usize {
+        self.registry.read().await.iter().count()
+    }
+
+    /// Live subscriptions including each layer, ordered by layer name
+    pub async fn layer_counts(&self) -> BTreeMap<String, usize> {
+        let registry = self.registry.read().await;
+        let mut counts = BTreeMap::new();
+        for registered in registry.iter() {
+            for layer in &registered.subscription.layers {
+                *counts.entry(layer_type_name(layer)).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// Outbound queue depth of every client, ordered by client id
+    pub async fn queue_depths(&self) -> BTreeMap<ClientId, QueueDepth> {
+        let registry = self.registry.read().await;
+        // All subscriptions of a client share its connection channel.
+        registry
+            .by_client
+            .iter()
+            .filter_map(|(client_id, subscriptions)| {
+                let slot = *subscriptions.values().next()?;
+                let registered = registry.slots[slot as usize].as_ref()?;
+                Some((client_id.clone(), registered.subscription.sender.depth()))
+            })
+            .collect()
     }
 
     /// Return a snapshot of active subscriptions for debug endpoints.
     pub async fn debug_subscriptions(&self) -> Vec<SubscriptionDebugInfo> {
-        let subs = self.subscriptions.read().await;
+        let registry = self.registry.read().await;
 
-        subs.iter()
-            .map(|(client_id, subscription)| {
+        registry
+            .iter()
+            .map(|registered| {
+                let subscription = &registered.subscription;
                 let stats = subscription.sender.stats();
                 SubscriptionDebugInfo {
-                    client_id: client_id.clone(),
+                    client_id: registered.client_id.clone(),
+                    subscription_id: registered.subscription_id.clone(),
                     viewport: ViewportDebug {
                         min_lat: subscription.viewport.min_lat,
                         min_lon: subscription.viewport.min_lon,
@@ -133,8 +351,9 @@ impl SubscriptionManager {
                     },
                     layers: subscription.layers.iter().map(layer_type_name).collect(),
                     backpressure: BackpressureDebugStats {
-                        track_batches_dropped: stats.track_batches_dropped,
                         track_batches_sent: stats.track_batches_sent,
+                        track_updates_coalesced: stats.track_updates_coalesced,
+                        track_updates_dropped: stats.track_updates_dropped,
                         high_priority_sent: stats.high_priority_sent,
                     },
                 }
@@ -143,61 +362,143 @@ impl SubscriptionManager {
     }
 
     /// Broadcast track batch to all matching subscriptions
-    pub async fn broadcast_tracks(&self, tracks: Vec<TrackDelta>) {
+    ///
+    /// Each track is only tested against the subscriptions indexed under its
+    /// grid cell. Subscriptions with the same ID whose filters select the same
+    /// set of tracks share a single `Arc<Envelope>`; each batch is tagged with
+    /// the subscription ID it satisfies.
+    ///
+    /// `received_ms` (when the batch arrived from Redis) becomes the batches'
+    /// `server_ts_ms`, so fanout latency covers matching and queueing.
+    pub async fn broadcast_tracks(&self, tracks: Vec<TrackDelta>, received_ms: u64) {
         if tracks.is_empty() {
             return;
         }
 
-        let subs = self.subscriptions.read().await;
-        if subs.is_empty() {
+        let registry = self.registry.read().await;
+        if registry.by_client.is_empty() {
             return;
         }
 
-        // Group tracks by matching subscriptions
-        let mut client_batches: HashMap<ClientId, Vec<TrackDelta>> = HashMap::new();
+        // Matching track indices per slot
+        let mut matched: Vec<Vec<u32>> = vec![Vec::new(); registry.slots.len()];
 
-        for track in tracks {
-            for (client_id, subscription) in subs.iter() {
-                if subscription.matches(&track) {
-                    client_batches
-                        .entry(client_id.clone())
-                        .or_default()
-                        .push(track.clone());
+        for (track_idx, track) in tracks.iter().enumerate() {
+            let Some(pos) = track.position.as_ref() else {
+                continue;
+            };
+
+            for slot in registry.index.candidates(pos.lat, pos.lon) {
+                let Some(registered) = registry.slots[slot as usize].as_ref() else {
+                    continue;
+                };
+                if registered.subscription.matches(track) {
+                    matched[slot as usize].push(track_idx as u32);
                 }
             }
         }
 
-        // Send filtered batches to each client
-        for (client_id, batch_tracks) in client_batches {
-            if let Some(subscription) = subs.get(&client_id) {
-                let batch = TrackDeltaBatch {
-                    deltas: batch_tracks,
-                };
-                let envelope = Envelope {
-                    schema_version: "1.0.0".to_string(),
-                    server_ts_ms: now_ms(),
-                    payload: Some(harpy_proto::harpy::v1::envelope::Payload::TrackDeltaBatch(
-                        batch,
-                    )),
-                };
+        // Group slots that selected an identical set of tracks under the same ID
+        let mut groups: HashMap<(&str, &[u32]), Vec<Slot>> = HashMap::new();
+        for (slot, indices) in matched.iter().enumerate() {
+            if indices.is_empty() {
+                continue;
+            }
+            let Some(registered) = registry.slots[slot].as_ref() else {
+                continue;
+            };
+            groups
+                .entry((registered.subscription_id.as_str(), indices.as_slice()))
+                .or_default()
+                .push(slot as Slot);
+        }
 
-                if subscription.sender.send(envelope).is_err() {
+        // Send one shared batch per group
+        for ((subscription_id, indices), slots) in groups {
+            let batch = TrackDeltaBatch {
+                deltas: indices
+                    .iter()
+                    .map(|&idx| tracks[idx as usize].clone())
+                    .collect(),
+                subscription_id: subscription_id.to_string(),
+            };
+            let envelope = Arc::new(Envelope {
+                schema_version: "1.0.0".to_string(),
+                server_ts_ms: received_ms,
+                seq: 0,
+                payload: Some(harpy_proto::harpy::v1::envelope::Payload::TrackDeltaBatch(
+                    batch,
+                )),
+            });
+
+            for slot in slots {
+                let Some(registered) = registry.slots[slot as usize].as_ref() else {
+                    continue;
+                };
+                if registered
+                    .subscription
+                    .sender
+                    .send_shared(envelope.clone())
+                    .is_err()
+                {
                     tracing::debug!(
-                        "Dropped TrackDeltaBatch for client {} due to backpressure/closed channel",
-                        client_id
+                        "Dropped TrackDeltaBatch for client {}, evicted or closed",
+                        registered.client_id
                     );
                 }
             }
         }
     }
 
-    /// Send a message to all connected clients (used for provider status, alerts)
+    /// Send an alert or link once to every client with a subscription that wants it
+    pub async fn broadcast_scoped(&self, envelope: Envelope, scope: &AlertScope) {
+        let registry = self.registry.read().await;
+        let envelope = Arc::new(envelope);
+
+        for subscriptions in registry.by_client.values() {
+            let Some(registered) = subscriptions
+                .values()
+                .filter_map(|&slot| registry.slots[slot as usize].as_ref())
+                .find(|registered| registered.subscription.wants_alert(scope))
+            else {
+                continue;
+            };
+            if registered
+                .subscription
+                .sender
+                .send_shared(envelope.clone())
+                .is_err()
+            {
+                tracing::warn!(
+                    "Failed to send to client {}, channel closed",
+                    registered.client_id
+                );
+            }
+        }
+    }
+
+    /// Send a message once to every connected client (used for provider status)
     pub async fn broadcast_to_all(&self, envelope: Envelope) {
-        let subs = self.subscriptions.read().await;
+        let registry = self.registry.read().await;
+        let envelope = Arc::new(envelope);
 
-        for (client_id, subscription) in subs.iter() {
-            if subscription.sender.send(envelope.clone()).is_err() {
-                tracing::warn!("Failed to send to client {}, channel closed", client_id);
+        // All subscriptions of a client share its connection channel.
+        let clients = registry
+            .by_client
+            .values()
+            .filter_map(|subscriptions| subscriptions.values().next())
+            .filter_map(|&slot| registry.slots[slot as usize].as_ref());
+        for registered in clients {
+            if registered
+                .subscription
+                .sender
+                .send_shared(envelope.clone())
+                .is_err()
+            {
+                tracing::warn!(
+                    "Failed to send to client {}, channel closed",
+                    registered.client_id
+                );
             }
         }
     }
@@ -217,25 +518,26 @@ fn layer_type_name(layer: &LayerType) -> String {
     .to_string()
 }
 
-/// Get current timestamp in milliseconds
-fn now_ms() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use harpy_proto::harpy::v1::{Position, TrackKind};
 
+    fn now_ms() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+
     fn create_test_subscription(viewport: BoundingBox, layers: Vec<LayerType>) -> Subscription {
-        let sender = BackpressureChannel::default();
+        let (sender, _) = BackpressureChannel::new();
         Subscription {
             viewport,
             layers,
             sender,
+            follow: None,
+            filter: None,
         }
     }
 
@@ -297,4 +599,451 @@ mod tests {
         let satellite = create_test_track(37.5, -122.0, TrackKind::Satellite);
         assert!(!sub.matches(&satellite));
     }
+
+    #[test]
+    fn test_track_filter_applies_after_viewport() {
+        let viewport = BoundingBox {
+            min_lat: 37.0,
+            max_lat: 38.0,
+            min_lon: -123.0,
+            max_lon: -121.0,
+        };
+        let mut sub = create_test_subscription(viewport, vec![LayerType::Aircraft]);
+        sub.filter = Some(TrackFilter {
+            min_alt: Some(5000.0),
+            ..Default::default()
+        });
+
+        // Test tracks fly at 1000m
+        assert!(!sub.matches(&create_test_track(37.5, -122.0, TrackKind::Aircraft)));
+
+        let mut high = create_test_track(37.5, -122.0, TrackKind::Aircraft);
+        high.position.as_mut().unwrap().alt = 10000.0;
+        assert!(sub.matches(&high));
+    }
+
+    #[test]
+    fn test_dateline_viewport_and_missing_position() {
+        let viewport = BoundingBox {
+            min_lat: -10.0,
+            max_lat: 10.0,
+            min_lon: 170.0,
+            max_lon: -170.0,
+        };
+        let sub = create_test_subscription(viewport, vec![LayerType::Vessel]);
+
+        assert!(sub.matches(&create_test_track(0.0, 175.0, TrackKind::Vessel)));
+        assert!(sub.matches(&create_test_track(0.0, -175.0, TrackKind::Vessel)));
+        assert!(!sub.matches(&create_test_track(0.0, 0.0, TrackKind::Vessel)));
+
+        let mut no_position = create_test_track(0.0, 175.0, TrackKind::Vessel);
+        no_position.position = None;
+        assert!(!sub.matches(&no_position));
+    }
+
+    #[tokio::test]
+    async fn test_followed_track_ignores_viewport_and_layers() {
+        let manager = SubscriptionManager::new();
+        let (tx, mut rx) = BackpressureChannel::new();
+        manager
+            .subscribe(
+                "ops".to_string(),
+                DEFAULT_SUBSCRIPTION_ID.to_string(),
+                Subscription {
+                    viewport: BoundingBox {
+                        min_lat: 37.0,
+                        max_lat: 38.0,
+                        min_lon: -123.0,
+                        max_lon: -121.0,
+                    },
+                    layers: vec![LayerType::Vessel],
+                    sender: tx,
+                    follow: Some(FollowFilter {
+                        track_ids: vec!["followed".to_string()],
+                        ..Default::default()
+                    }),
+                    filter: None,
+                },
+            )
+            .await;
+
+        let mut followed = create_test_track(-33.9, 151.2, TrackKind::Aircraft);
+        followed.id = "followed".to_string();
+        let other = create_test_track(-33.9, 151.2, TrackKind::Aircraft);
+        manager
+            .broadcast_tracks(vec![followed, other], now_ms())
+            .await;
+
+        let envelope = rx.recv().await.expect("followed batch");
+        match &envelope.payload {
+            Some(harpy_proto::harpy::v1::envelope::Payload::TrackDeltaBatch(batch)) => {
+                assert_eq!(batch.deltas.len(), 1);
+                assert_eq!(batch.deltas[0].id, "followed");
+            }
+            other => panic!("unexpected payload: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_routes_tracks_by_viewport() {
+        let manager = SubscriptionManager::new();
+        let (sf_tx, mut sf_rx) = BackpressureChannel::new();
+        let (world_tx, mut world_rx) = BackpressureChannel::new();
+
+        manager
+            .subscribe(
+                "sf".to_string(),
+                DEFAULT_SUBSCRIPTION_ID.to_string(),
+                Subscription {
+                    viewport: BoundingBox {
+                        min_lat: 37.0,
+                        max_lat: 38.0,
+                        min_lon: -123.0,
+                        max_lon: -121.0,
+                    },
+                    layers: vec![LayerType::Aircraft],
+                    sender: sf_tx,
+                    follow: None,
+                    filter: None,
+                },
+            )
+            .await;
+        manager
+            .subscribe(
+                "world".to_string(),
+                DEFAULT_SUBSCRIPTION_ID.to_string(),
+                Subscription {
+                    viewport: BoundingBox {
+                        min_lat: -90.0,
+                        max_lat: 90.0,
+                        min_lon: -180.0,
+                        max_lon: 180.0,
+                    },
+                    layers: vec![LayerType::Aircraft],
+                    sender: world_tx,
+                    follow: None,
+                    filter: None,
+                },
+            )
+            .await;
+
+        manager
+            .broadcast_tracks(
+                vec![
+                    create_test_track(37.5, -122.0, TrackKind::Aircraft),
+                    create_test_track(51.5, 0.0, TrackKind::Aircraft),
+                ],
+                now_ms(),
+            )
+            .await;
+
+        let batch_len = |envelope: Arc<Envelope>| match &envelope.payload {
+            Some(harpy_proto::harpy::v1::envelope::Payload::TrackDeltaBatch(batch)) => {
+                batch.deltas.len()
+            }
+            _ => 0,
+        };
+        assert_eq!(batch_len(sf_rx.recv().await.expect("sf batch")), 1);
+        assert_eq!(batch_len(world_rx.recv().await.expect("world batch")), 2);
+
+        assert!(manager.unsubscribe("sf", DEFAULT_SUBSCRIPTION_ID).await);
+        assert_eq!(manager.client_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_named_subscriptions_share_connection() {
+        let manager = SubscriptionManager::new();
+        let (tx, mut rx) = BackpressureChannel::new();
+
+        let subscription = |viewport: BoundingBox| Subscription {
+            viewport,
+            layers: vec![LayerType::Aircraft],
+            sender: tx.clone(),
+            follow: None,
+            filter: None,
+        };
+        manager
+            .subscribe(
+                "hud".to_string(),
+                "main".to_string(),
+                subscription(BoundingBox {
+                    min_lat: -90.0,
+                    max_lat: 90.0,
+                    min_lon: -180.0,
+                    max_lon: 180.0,
+                }),
+            )
+            .await;
+        manager
+            .subscribe(
+                "hud".to_string(),
+                "inset".to_string(),
+                subscription(BoundingBox {
+                    min_lat: 37.0,
+                    max_lat: 38.0,
+                    min_lon: -123.0,
+                    max_lon: -121.0,
+                }),
+            )
+            .await;
+        assert_eq!(manager.client_count().await, 1);
+
+        manager
+            .broadcast_tracks(
+                vec![
+                    create_test_track(37.5, -122.0, TrackKind::Aircraft),
+                    create_test_track(51.5, 0.0, TrackKind::Aircraft),
+                ],
+                now_ms(),
+            )
+            .await;
+
+        let mut tagged = HashMap::new();
+        for _ in 0..2 {
+            let envelope = rx.recv().await.expect("tagged batch");
+            if let Some(harpy_proto::harpy::v1::envelope::Payload::TrackDeltaBatch(batch)) =
+                &envelope.payload
+            {
+                tagged.insert(batch.subscription_id.clone(), batch.deltas.len());
+            }
+        }
+        assert_eq!(tagged.get("main"), Some(&2));
+        assert_eq!(tagged.get("inset"), Some(&1));
+
+        // Connection-wide messages are delivered once per client.
+        manager
+            .broadcast_to_all(Envelope {
+                schema_version: "1.0.0".to_string(),
+                server_ts_ms: now_ms(),
+                seq: 0,
+                payload: None,
+            })
+            .await;
+        assert!(rx.recv().await.is_some());
+        assert!(rx.try_recv().is_none());
+
+        assert!(manager.unsubscribe("hud", "inset").await);
+        assert!(!manager.unsubscribe("hud", "inset").await);
+        assert_eq!(manager.client_count().await, 1);
+        manager.remove_client("hud").await;
+        assert_eq!(manager.client_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_returns_the_replaced_subscription() {
+        let manager = SubscriptionManager::new();
+        let world = BoundingBox {
+            min_lat: -90.0,
+            max_lat: 90.0,
+            min_lon: -180.0,
+            max_lon: 180.0,
+        };
+        let first = manager
+            .subscribe(
+                "hud".to_string(),
+                "main".to_string(),
+                create_test_subscription(world.clone(), vec![LayerType::Alert]),
+            )
+            .await;
+        assert!(first.is_none());
+
+        let replaced = manager
+            .subscribe(
+                "hud".to_string(),
+                "main".to_string(),
+                create_test_subscription(world, vec![LayerType::Aircraft]),
+            )
+            .await;
+        assert_eq!(replaced.map(|s| s.layers), Some(vec![LayerType::Alert]));
+        assert_eq!(manager.subscription_count().await, 1);
+    }
+
+    #[test]
+    fn test_alert_scope_requires_layer_and_viewport() {
+        let viewport = BoundingBox {
+            min_lat: 37.0,
+            max_lat: 38.0,
+            min_lon: -123.0,
+            max_lon: -121.0,
+        };
+        let in_view = AlertScope {
+            track_ids: vec!["a".to_string()],
+            points: vec![(37.5, -122.0)],
+            ..Default::default()
+        };
+        let out_of_view = AlertScope {
+            track_ids: vec!["b".to_string()],
+            points: vec![(51.5, 0.0)],
+            ..Default::default()
+        };
+
+        let tracks_only = create_test_subscription(viewport.clone(), vec![LayerType::Aircraft]);
+        assert!(!tracks_only.wants_alert(&in_view));
+        assert!(!tracks_only.wants_alert(&AlertScope::default()));
+
+        let mut alerts = create_test_subscription(viewport, vec![LayerType::Alert]);
+        assert!(alerts.wants_alert(&in_view));
+        assert!(!alerts.wants_alert(&out_of_view));
+        assert!(alerts.wants_alert(&AlertScope::default()));
+
+        // Alerts on followed tracks reach the subscription wherever they are
+        alerts.follow = Some(FollowFilter {
+            track_ids: vec!["b".to_string()],
+            ..Default::default()
+        });
+        assert!(alerts.wants_alert(&out_of_view));
+
+        // Including tracks followed by callsign pattern
+        alerts.follow = Some(FollowFilter {
+            callsign_patterns: vec!["UAL*".to_string()],
+            ..Default::default()
+        });
+        assert!(!alerts.wants_alert(&out_of_view));
+        assert!(alerts.wants_alert(&AlertScope {
+            callsigns: vec!["UAL123".to_string()],
+            ..out_of_view.clone()
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_scoped_delivers_once_per_wanting_client() {
+        let manager = SubscriptionManager::new();
+        let (alert_tx, mut alert_rx) = BackpressureChannel::new();
+        let (track_tx, mut track_rx) = BackpressureChannel::new();
+        let world = BoundingBox {
+            min_lat: -90.0,
+            max_lat: 90.0,
+            min_lon: -180.0,
+            max_lon: 180.0,
+        };
+
+        for subscription_id in ["main", "inset"] {
+            manager
+                .subscribe(
+                    "ops".to_string(),
+                    subscription_id.to_string(),
+                    Subscription {
+                        viewport: world.clone(),
+                        layers: vec![LayerType::Aircraft, LayerType::Alert],
+                        sender: alert_tx.clone(),
+                        follow: None,
+                        filter: None,
+                    },
+                )
+                .await;
+        }
+        manager
+            .subscribe(
+                "viewer".to_string(),
+                DEFAULT_SUBSCRIPTION_ID.to_string(),
+                Subscription {
+                    viewport: world,
+                    layers: vec![LayerType::Aircraft],
+                    sender: track_tx,
+                    follow: None,
+                    filter: None,
+                },
+            )
+            .await;
+
+        manager
+            .broadcast_scoped(
+                Envelope {
+                    schema_version: "1.0.0".to_string(),
+                    server_ts_ms: now_ms(),
+                    seq: 0,
+                    payload: None,
+                },
+                &AlertScope {
+                    track_ids: vec!["a".to_string()],
+                    points: vec![(10.0, 10.0)],
+                    ..Default::default()
+                },
+            )
+            .await;
+
+        assert!(alert_rx.recv().await.is_some());
+        assert!(alert_rx.try_recv().is_none());
+        assert!(track_rx.try_recv().is_none());
+    }
+
+    /// Fanout throughput benchmark.
+    ///
+    /// Run with: cargo test --release -p harpy-relay -- --ignored bench_fanout --nocapture
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[ignore = "benchmark"]
+    async fn bench_fanout_throughput() {
+        const CLIENTS: usize = 300;
+        const TRACKS_PER_BATCH: usize = 5_000;
+        const BATCHES: usize = 10;
+
+        let manager = SubscriptionManager::new();
+        let mut receivers = Vec::with_capacity(CLIENTS);
+        for i in 0..CLIENTS {
+            let (tx, rx) = BackpressureChannel::new();
+            receivers.push(rx);
+            // Mix of world viewports and regional viewports spread over the globe.
+            let viewport = if i % 10 == 0 {
+                BoundingBox {
+                    min_lat: -90.0,
+                    max_lat: 90.0,
+                    min_lon: -180.0,
+                    max_lon: 180.0,
+                }
+            } else {
+                let lat = -60.0 + (i * 37 % 120) as f64;
+                let lon = -170.0 + (i * 53 % 340) as f64;
+                BoundingBox {
+                    min_lat: lat,
+                    max_lat: lat + 8.0,
+                    min_lon: lon,
+                    max_lon: lon + 12.0,
+                }
+            };
+            manager
+                .subscribe(
+                    format!("client-{}", i),
+                    DEFAULT_SUBSCRIPTION_ID.to_string(),
+                    Subscription {
+                        viewport,
+                        layers: vec![LayerType::Aircraft, LayerType::Vessel],
+                        sender: tx,
+                        follow: None,
+                        filter: None,
+                    },
+                )
+                .await;
+        }
+
+        let batch: Vec<TrackDelta> = (0..TRACKS_PER_BATCH)
+            .map(|i| {
+                let lat = -80.0 + (i * 7919 % 160_000) as f64 / 1000.0;
+                let lon = -180.0 + (i * 104_729 % 360_000) as f64 / 1000.0;
+                let mut track = create_test_track(lat, lon, TrackKind::Aircraft);
+                track.id = format!("t-{}", i);
+                track
+            })
+            .collect();
+
+        let started = std::time::Instant::now();
+        for _ in 0..BATCHES {
+            manager.broadcast_tracks(batch.clone(), now_ms()).await;
+        }
+        let elapsed = started.elapsed();
+
+        let tracks_per_sec = (TRACKS_PER_BATCH * BATCHES) as f64 / elapsed.as_secs_f64();
+        println!(
+            "fanout: {} tracks x {} clients in {:?} ({:.0} tracks/s)",
+            TRACKS_PER_BATCH * BATCHES,
+            CLIENTS,
+            elapsed,
+            tracks_per_sec
+        );
+        assert!(
+            tracks_per_sec > 20_000.0,
+            "fanout throughput below target: {:.0} tracks/s",
+            tracks_per_sec
+        );
+        drop(receivers);
+    }
 }
@@ -0,0 +1,169 @@
+//! Fanout Metrics
+//!
+//! Event metrics (latency histograms, drops) go through the `metrics` crate
+//! and its Prometheus recorder; queue depths and subscription counts are read
+//! from live state when `/metrics` is scraped.
+
+use crate::subscription::SubscriptionManager;
+use crate::wire::WireOptions;
+use harpy_proto::harpy::v1::{envelope::Payload, Envelope};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::fmt::Write;
+
+/// Redis receive (or playback read) to socket write, by payload
+pub const FANOUT_LATENCY: &str = "harpy_relay_fanout_latency_seconds";
+/// Duration of one playback `track_deltas` query
+pub const PLAYBACK_QUERY: &str = "harpy_relay_playback_query_seconds";
+/// Envelopes not queued for a client, by payload and reason (`evicted`, `closed`, `queue_full`)
+pub const MESSAGES_DROPPED: &str = "harpy_relay_messages_dropped_total";
+/// Bytes written to WebSocket clients by compression and encoding
+pub const BYTES_SENT: &str = "harpy_relay_ws_bytes_sent_total";
+
+/// Histogram buckets for every `*_seconds` metric
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Install the Prometheus recorder backing the `metrics` macros
+pub fn install_recorder() -> anyhow::Result<PrometheusHandle> {
+    Ok(PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), LATENCY_BUCKETS)?
+        .install_recorder()?)
+}
+
+/// Payload label of an envelope
+pub fn payload_kind(envelope: &Envelope) -> &'static str {
+    match &envelope.payload {
+        Some(Payload::TrackDeltaBatch(_)) => "track_delta_batch",
+        Some(Payload::AlertUpsert(_)) => "alert_upsert",
+        Some(Payload::ProviderStatus(_)) => "provider_status",
+        Some(Payload::SnapshotMeta(_)) => "snapshot_meta",
+        Some(Payload::LinkUpsert(_)) => "link_upsert",
+        Some(Payload::CompactTrackBatch(_)) => "compact_track_batch",
+        Some(Payload::PlaybackSessionState(_)) => "playback_session_state",
+        Some(Payload::SubscriptionRequest(_)) => "subscription_request",
+        Some(Payload::SubscriptionAck(_)) => "subscription_ack",
+        Some(Payload::PlaybackControl(_)) => "playback_control",
+        None => "none",
+    }
+}
+
+/// Record the time from `server_ts_ms` until the envelope was written to a socket
+pub fn record_fanout_latency(envelope: &Envelope, now_ms: u64) {
+    if envelope.server_ts_ms == 0 {
+        return;
+    }
+    let seconds = now_ms.saturating_sub(envelope.server_ts_ms) as f64 / 1000.0;
+    metrics::histogram!(FANOUT_LATENCY, "payload" => payload_kind(envelope)).record(seconds);
+}
+
+/// Count the bytes of a frame written to a client
+pub fn record_bytes_sent(len: usize, wire: WireOptions) {
+    metrics::counter!(
+        BYTES_SENT,
+        "compression" => if wire.deflate { "deflate" } else { "none" },
+        "encoding" => if wire.compact { "compact" } else { "full" }
+    )
+    .increment(len as u64);
+}
+
+/// Count an envelope that was not queued for a client
+pub fn record_drop(envelope: &Envelope, reason: &'static str) {
+    metrics::counter!(MESSAGES_DROPPED, "payload" => payload_kind(envelope), "reason" => reason)
+        .increment(1);
+}
+
+/// Prometheus text for queue depths and subscription counts
+pub async fn render_state(manager: &SubscriptionManager, playback_subscriptions: usize) -> String {
+    let mut out = String::new();
+
+    let depths = manager.queue_depths().await;
+    let _ = writeln!(
+        out,
+        "# HELP harpy_relay_client_queue_depth Envelopes pending for a client\n\
+         # TYPE harpy_relay_client_queue_depth gauge"
+    );
+    let (mut control_total, mut tracks_total) = (0, 0);
+    for (client_id, depth) in &depths {
+        control_total += depth.control;
+        tracks_total += depth.tracks;
+        let _ = writeln!(
+            out,
+            "harpy_relay_client_queue_depth{{client_id=\"{client_id}\",queue=\"control\"}} {}\n\
+             harpy_relay_client_queue_depth{{client_id=\"{client_id}\",queue=\"tracks\"}} {}",
+            depth.control, depth.tracks
+        );
+    }
+    let _ = writeln!(
+        out,
+        "# HELP harpy_relay_queue_depth Envelopes and track updates pending across all clients\n\
+         # TYPE harpy_relay_queue_depth gauge\n\
+         harpy_relay_queue_depth{{queue=\"control\"}} {control_total}\n\
+         harpy_relay_queue_depth{{queue=\"tracks\"}} {tracks_total}"
+    );
+
+    let layers = manager.layer_counts().await;
+    let live = manager.subscription_count().await;
+    let _ = writeln!(
+        out,
+        "# HELP harpy_relay_subscriptions Subscriptions by mode\n\
+         # TYPE harpy_relay_subscriptions gauge\n\
+         harpy_relay_subscriptions{{mode=\"live\"}} {live}\n\
+         harpy_relay_subscriptions{{mode=\"playback\"}} {playback_subscriptions}\n\
+         # HELP harpy_relay_subscriptions_by_layer Live subscriptions including each layer\n\
+         # TYPE harpy_relay_subscriptions_by_layer gauge"
+    );
+    for (layer, count) in layers {
+        let _ = writeln!(
+            out,
+            "harpy_relay_subscriptions_by_layer{{layer=\"{layer}\"}} {count}"
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backpressure::BackpressureChannel;
+    use crate::subscription::Subscription;
+    use harpy_proto::harpy::v1::{AlertUpsert, BoundingBox, LayerType};
+
+    #[tokio::test]
+    async fn test_render_state_reports_depth_and_layers() {
+        let manager = SubscriptionManager::new();
+        let (tx, _rx) = BackpressureChannel::new();
+        manager
+            .subscribe(
+                "client-1".to_string(),
+                "default".to_string(),
+                Subscription {
+                    viewport: BoundingBox {
+                        min_lat: -90.0,
+                        max_lat: 90.0,
+                        min_lon: -180.0,
+                        max_lon: 180.0,
+                    },
+                    layers: vec![LayerType::Aircraft, LayerType::Alert],
+                    sender: tx.clone(),
+                    follow: None,
+                    filter: None,
+                },
+            )
+            .await;
+        tx.send(Envelope {
+            payload: Some(Payload::AlertUpsert(AlertUpsert::default())),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let text = render_state(&manager, 2).await;
+        assert!(text.contains(
+            "harpy_relay_client_queue_depth{client_id=\"client-1\",queue=\"control\"} 1"
+        ));
+        assert!(text.contains("harpy_relay_queue_depth{queue=\"tracks\"} 0"));
+        assert!(text.contains("harpy_relay_subscriptions{mode=\"live\"} 1"));
+        assert!(text.contains("harpy_relay_subscriptions{mode=\"playback\"} 2"));
+        assert!(text.contains("harpy_relay_subscriptions_by_layer{layer=\"ALERT\"} 1"));
+    }
+}
@@ -0,0 +1,390 @@
+//! Wire Options
+//!
+//! Clients opt into smaller frames with query parameters on `/ws`:
+//!
+//! - `compression=deflate` sends every binary frame as a raw deflate stream
+//!   (browsers inflate it with `DecompressionStream("deflate-raw")`). The
+//!   WebSocket stack does not implement the permessage-deflate extension, so
+//!   compression is negotiated here instead of in the handshake.
+//! - `encoding=compact` replaces each `TrackDeltaBatch` with a
+//!   `CompactTrackBatch`: fixed-point coordinates sent as differences from the
+//!   last state sent for the track on the same socket, with unchanged fields
+//!   and meta entries omitted.
+//!
+//! Compact state belongs to the socket, not the session: a resumed connection
+//! starts over with keyframes because frames in flight when the old socket
+//! dropped may never have arrived.
+
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use harpy_proto::harpy::v1::{
+    envelope::Payload, CompactTrackBatch, CompactTrackDelta, Envelope, TrackDelta, TrackDeltaBatch,
+};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+
+/// `compression` value enabling deflate
+pub const DEFLATE: &str = "deflate";
+/// `encoding` value enabling compact track batches
+pub const COMPACT: &str = "compact";
+
+/// Largest magnitude of a quantized 64-bit field, keeping differences exact in JavaScript
+const LIMIT_I64: f64 = 4.0e15;
+/// Largest magnitude of a quantized 32-bit field, keeping differences within `i32`
+const LIMIT_I32: f64 = 1.0e9;
+
+/// Frame options in effect for a connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WireOptions {
+    pub deflate: bool,
+    pub compact: bool,
+}
+
+impl WireOptions {
+    /// Options from the upgrade's query parameters; unknown values are ignored
+    pub fn negotiate(compression: Option<&str>, encoding: Option<&str>) -> Self {
+        Self {
+            deflate: compression.is_some_and(|value| value.eq_ignore_ascii_case(DEFLATE)),
+            compact: encoding.is_some_and(|value| value.eq_ignore_ascii_case(COMPACT)),
+        }
+    }
+
+    /// `SubscriptionAck.compression`
+    pub fn compression(self) -> &'static str {
+        if self.deflate {
+            DEFLATE
+        } else {
+            ""
+        }
+    }
+
+    /// `SubscriptionAck.encoding`
+    pub fn encoding(self) -> &'static str {
+        if self.compact {
+            COMPACT
+        } else {
+            ""
+        }
+    }
+}
+
+/// Applies a connection's wire options to outgoing envelopes and frames
+pub struct FrameEncoder {
+    options: WireOptions,
+    compact: CompactEncoder,
+}
+
+impl FrameEncoder {
+    pub fn new(options: WireOptions, max_tracks: usize) -> Self {
+        Self {
+            options,
+            compact: CompactEncoder::new(max_tracks),
+        }
+    }
+
+    /// Track state limit from `RELAY_COMPACT_MAX_TRACKS` (default 50000)
+    pub fn from_env(options: WireOptions) -> Self {
+        let max_tracks = std::env::var("RELAY_COMPACT_MAX_TRACKS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(50_000);
+        Self::new(options, max_tracks)
+    }
+
+    pub fn options(&self) -> WireOptions {
+        self.options
+    }
+
+    /// The envelope to sequence and send in place of `envelope`
+    pub fn prepare(&mut self, envelope: Arc<Envelope>) -> Arc<Envelope> {
+        if !self.options.compact {
+            return envelope;
+        }
+        let Some(Payload::TrackDeltaBatch(batch)) = &envelope.payload else {
+            return envelope;
+        };
+        Arc::new(Envelope {
+            schema_version: envelope.schema_version.clone(),
+            server_ts_ms: envelope.server_ts_ms,
+            seq: envelope.seq,
+            payload: Some(Payload::CompactTrackBatch(self.compact.encode(batch))),
+        })
+    }
+
+    /// The bytes to write for an encoded envelope
+    pub fn frame(&self, encoded: Vec<u8>) -> Vec<u8> {
+        if !self.options.deflate {
+            return encoded;
+        }
+        let mut encoder = DeflateEncoder::new(
+            Vec::with_capacity(encoded.len() / 2),
+            Compression::default(),
+        );
+        // Writing to a Vec cannot fail
+        encoder
+            .write_all(&encoded)
+            .and_then(|_| encoder.finish())
+            .expect("deflate into memory")
+    }
+}
+
+/// Last state sent for a track, in quantized units
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TrackState {
+    kind: i32,
+    lat_e7: i64,
+    lon_e7: i64,
+    alt_dm: i64,
+    heading_cdeg: i32,
+    speed_cms: i32,
+    ts_ms: i64,
+    provider_id: String,
+    meta: HashMap<String, String>,
+}
+
+impl TrackState {
+    fn of(delta: &TrackDelta) -> Self {
+        let position = delta.position.clone().unwrap_or_default();
+        Self {
+            kind: delta.kind,
+            lat_e7: quantize(position.lat, 1e7, LIMIT_I64),
+            lon_e7: quantize(position.lon, 1e7, LIMIT_I64),
+            alt_dm: quantize(position.alt, 10.0, LIMIT_I64),
+            heading_cdeg: quantize(delta.heading, 100.0, LIMIT_I32) as i32,
+            speed_cms: quantize(delta.speed, 100.0, LIMIT_I32) as i32,
+            ts_ms: delta.ts_ms.min(LIMIT_I64 as u64) as i64,
+            provider_id: delta.provider_id.clone(),
+            meta: delta.meta.clone(),
+        }
+    }
+}
+
+fn quantize(value: f64, scale: f64, limit: f64) -> i64 {
+    let scaled = (value * scale).round();
+    if scaled.is_nan() {
+        0
+    } else {
+        scaled.clamp(-limit, limit) as i64
+    }
+}
+
+/// `Some(next - previous)` when the value changed
+fn change<T>(previous: T, next: T) -> Option<T>
+where
+    T: PartialEq + std::ops::Sub<Output = T> + Copy,
+{
+    (previous != next).then(|| next - previous)
+}
+
+/// Per-socket delta encoder for track batches
+pub struct CompactEncoder {
+    tracks: HashMap<String, TrackState>,
+    max_tracks: usize,
+}
+
+impl CompactEncoder {
+    pub fn new(max_tracks: usize) -> Self {
+        Self {
+            tracks: HashMap::new(),
+            max_tracks,
+        }
+    }
+
+    pub fn encode(&mut self, batch: &TrackDeltaBatch) -> CompactTrackBatch {
+        CompactTrackBatch {
+            deltas: batch
+                .deltas
+                .iter()
+                .map(|delta| self.encode_delta(delta))
+                .collect(),
+            subscription_id: batch.subscription_id.clone(),
+        }
+    }
+
+    fn encode_delta(&mut self, delta: &TrackDelta) -> CompactTrackDelta {
+        if !self.tracks.contains_key(&delta.id) && self.tracks.len() >= self.max_tracks {
+            // Forget everything; each track's next update becomes a keyframe
+            self.tracks.clear();
+        }
+        let next = TrackState::of(delta);
+        let previous = self.tracks.remove(&delta.id);
+        let keyframe = previous.is_none();
+        let previous = previous.unwrap_or_default();
+
+        let mut meta = HashMap::new();
+        for (key, value) in &next.meta {
+            if previous.meta.get(key) != Some(value) {
+                meta.insert(key.clone(), value.clone());
+            }
+        }
+        let mut meta_removed: Vec<String> = previous
+            .meta
+            .keys()
+            .filter(|key| !next.meta.contains_key(*key))
+            .cloned()
+            .collect();
+        meta_removed.sort();
+
+        let compact = CompactTrackDelta {
+            id: delta.id.clone(),
+            keyframe,
+            kind: (previous.kind != next.kind).then_some(next.kind),
+            lat_e7: change(previous.lat_e7, next.lat_e7),
+            lon_e7: change(previous.lon_e7, next.lon_e7),
+            alt_dm: change(previous.alt_dm, next.alt_dm),
+            heading_cdeg: change(previous.heading_cdeg, next.heading_cdeg),
+            speed_cms: change(previous.speed_cms, next.speed_cms),
+            ts_ms: change(previous.ts_ms, next.ts_ms),
+            provider_id: (previous.provider_id != next.provider_id)
+                .then(|| next.provider_id.clone()),
+            meta,
+            meta_removed,
+        };
+        self.tracks.insert(delta.id.clone(), next);
+        compact
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::DeflateDecoder;
+    use harpy_proto::harpy::v1::{Position, TrackKind};
+    use prost::Message;
+    use std::io::Read;
+
+    fn track(id: &str, lat: f64, lon: f64, ts_ms: u64, meta: &[(&str, &str)]) -> TrackDelta {
+        TrackDelta {
+            id: id.to_string(),
+            kind: TrackKind::Aircraft as i32,
+            position: Some(Position {
+                lat,
+                lon,
+                alt: 10_000.0,
+            }),
+            heading: 90.0,
+            speed: 230.5,
+            ts_ms,
+            provider_id: "adsb-opensky".to_string(),
+            meta: meta
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn batch(deltas: Vec<TrackDelta>) -> TrackDeltaBatch {
+        TrackDeltaBatch {
+            deltas,
+            subscription_id: "default".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_first_update_is_keyframe_then_only_changes() {
+        let mut encoder = CompactEncoder::new(16);
+        let first = encoder.encode(&batch(vec![track(
+            "a1",
+            37.123_456_78,
+            -122.5,
+            1_000,
+            &[("callsign", "UAL1"), ("squawk", "1200")],
+        )]));
+        let key = &first.deltas[0];
+        assert!(key.keyframe);
+        assert_eq!(key.kind, Some(TrackKind::Aircraft as i32));
+        assert_eq!(key.lat_e7, Some(371_234_568));
+        assert_eq!(key.lon_e7, Some(-1_225_000_000));
+        assert_eq!(key.alt_dm, Some(100_000));
+        assert_eq!(key.speed_cms, Some(23_050));
+        assert_eq!(key.meta.len(), 2);
+
+        let second = encoder.encode(&batch(vec![track(
+            "a1",
+            37.123_466_78,
+            -122.5,
+            2_000,
+            &[("callsign", "UAL1")],
+        )]));
+        let delta = &second.deltas[0];
+        assert!(!delta.keyframe);
+        assert_eq!(delta.kind, None);
+        assert_eq!(delta.lat_e7, Some(100));
+        assert_eq!(delta.lon_e7, None);
+        assert_eq!(delta.heading_cdeg, None);
+        assert_eq!(delta.ts_ms, Some(1_000));
+        assert_eq!(delta.provider_id, None);
+        assert!(delta.meta.is_empty());
+        assert_eq!(delta.meta_removed, vec!["squawk".to_string()]);
+    }
+
+    #[test]
+    fn test_state_limit_resets_to_keyframes() {
+        let mut encoder = CompactEncoder::new(1);
+        encoder.encode(&batch(vec![track("a1", 1.0, 1.0, 1, &[])]));
+        encoder.encode(&batch(vec![track("a2", 2.0, 2.0, 1, &[])]));
+        let again = encoder.encode(&batch(vec![track("a1", 1.0, 1.0, 2, &[])]));
+        assert!(again.deltas[0].keyframe);
+    }
+
+    #[test]
+    fn test_compact_deflate_frames_are_smaller() {
+        let deltas: Vec<TrackDelta> = (0..200)
+            .map(|i| {
+                track(
+                    &format!("track-{i}"),
+                    40.0 + i as f64 * 0.01,
+                    -74.0,
+                    1_700_000_000_000,
+                    &[("callsign", "DAL123"), ("registration", "N12345")],
+                )
+            })
+            .collect();
+        let envelope = |deltas: Vec<TrackDelta>| {
+            Arc::new(Envelope {
+                schema_version: "1.0.0".to_string(),
+                server_ts_ms: 1,
+                seq: 0,
+                payload: Some(Payload::TrackDeltaBatch(batch(deltas))),
+            })
+        };
+        let plain = envelope(deltas.clone()).encode_to_vec();
+
+        let mut encoder = FrameEncoder::new(
+            WireOptions::negotiate(Some("deflate"), Some("compact")),
+            1_000,
+        );
+        let first = encoder.prepare(envelope(deltas.clone()));
+        encoder.frame(first.encode_to_vec());
+        let moved: Vec<TrackDelta> = deltas
+            .into_iter()
+            .map(|mut delta| {
+                delta.ts_ms += 1_000;
+                if let Some(position) = delta.position.as_mut() {
+                    position.lon += 0.001;
+                }
+                delta
+            })
+            .collect();
+        let moved = encoder.prepare(envelope(moved));
+        let frame = encoder.frame(moved.encode_to_vec());
+        assert!(
+            frame.len() * 5 < plain.len(),
+            "{} vs {}",
+            frame.len(),
+            plain.len()
+        );
+
+        let mut inflated = Vec::new();
+        DeflateDecoder::new(&frame[..])
+            .read_to_end(&mut inflated)
+            .unwrap();
+        let decoded = Envelope::decode(&inflated[..]).unwrap();
+        let Some(Payload::CompactTrackBatch(compact)) = decoded.payload else {
+            panic!("expected a compact batch");
+        };
+        assert_eq!(compact.deltas.len(), 200);
+        assert_eq!(compact.deltas[0].lon_e7, Some(10_000));
+    }
+}